@@ -0,0 +1,160 @@
+//! Forwarding of command line arguments to an underlying command, for wrapper binaries.
+//!
+//! Arguments are quoted for POSIX shells so that they reach the command verbatim, whatever
+//! characters they contain.
+//!
+//! ```rust
+//! use shells::args::Forward;
+//!
+//! let args = vec!["-v", "--jobs", "4", "--token", "s3cr3t", "my file.txt"];
+//!
+//! // Forward everything.
+//! assert_eq!(shells::args::forward(&args).unwrap(), "-v --jobs 4 --token s3cr3t 'my file.txt'");
+//!
+//! // Forward a selection: unselected arguments are dropped, the values of the options declared
+//! // with `skip_option` along with them.
+//! let forward = Forward::new().flag("-v").option("--jobs").skip_option("--token").positionals();
+//! assert_eq!(forward.render(&args).unwrap(), "-v --jobs 4 'my file.txt'");
+//! ```
+//!
+
+use std::ffi::{OsStr, OsString};
+
+/// Error returned when arguments cannot be forwarded.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The argument is not valid Unicode and cannot be embedded in a command string.
+    NotUnicode(OsString),
+
+    /// A forwarded option was last on the command line, without its value.
+    MissingValue(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Argument cannot be forwarded."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::NotUnicode(ref arg) => write!(f, "argument is not valid unicode: {:?}", arg),
+            Error::MissingValue(ref option) => write!(f, "option `{}` requires a value", option),
+        }
+    }
+}
+
+/// Quotes every argument and joins them with spaces.
+///
+pub fn forward<I>(args: I) -> Result<String, Error>
+    where I: IntoIterator, I::Item: AsRef<OsStr>
+{
+    let quoted: Result<Vec<String>, Error> = args.into_iter()
+        .map(|arg| to_str(arg.as_ref()).map(|arg| ::quote::posix(arg).into_owned()))
+        .collect();
+
+    quoted.map(|quoted| quoted.join(" "))
+}
+
+/// Selection of the arguments to forward: flags, options taking a value (as `--name value` or
+/// `--name=value`) and optionally the positional arguments. Everything else is dropped.
+///
+/// The value of an option which is not forwarded is only known to be one when the option is
+/// declared with `skip_option`; otherwise it is taken for a positional argument.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Forward {
+    flags: Vec<String>,
+    options: Vec<String>,
+    skipped: Vec<String>,
+    positionals: bool,
+}
+
+impl Forward {
+    pub fn new() -> Forward {
+        Forward::default()
+    }
+
+    /// Forwards the given flag.
+    ///
+    pub fn flag(mut self, flag: &str) -> Forward {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// Forwards the given option along with its value.
+    ///
+    pub fn option(mut self, option: &str) -> Forward {
+        self.options.push(option.to_string());
+        self
+    }
+
+    /// Drops the given option along with its value, e.g. a secret the command must not see.
+    ///
+    pub fn skip_option(mut self, option: &str) -> Forward {
+        self.skipped.push(option.to_string());
+        self
+    }
+
+    /// Forwards the positional arguments, i.e. those not starting with `-` and everything after
+    /// `--`.
+    ///
+    pub fn positionals(mut self) -> Forward {
+        self.positionals = true;
+        self
+    }
+
+    /// Returns the selected arguments, quoted and joined with spaces. Typically called with
+    /// `std::env::args_os().skip(1)`.
+    ///
+    pub fn render<I>(&self, args: I) -> Result<String, Error>
+        where I: IntoIterator, I::Item: AsRef<OsStr>
+    {
+        let args: Vec<I::Item> = args.into_iter().collect();
+        let mut selected: Vec<&OsStr> = Vec::new();
+        let mut i = 0;
+
+        while i < args.len() {
+            let arg = args[i].as_ref();
+            let text = arg.to_string_lossy();
+
+            if text == "--" {
+                if self.positionals {
+                    selected.extend(args[i..].iter().map(|arg| arg.as_ref()));
+                }
+
+                break;
+            }
+
+            let name = text.split('=').next().unwrap_or("");
+
+            if self.flags.iter().any(|flag| *flag == text) {
+                selected.push(arg);
+            } else if self.options.iter().any(|option| option == name) {
+                selected.push(arg);
+
+                if !text.contains('=') {
+                    i += 1;
+                    selected.push(args.get(i).ok_or_else(|| Error::MissingValue(name.to_string()))?
+                        .as_ref());
+                }
+            } else if self.skipped.iter().any(|option| option == name) {
+                if !text.contains('=') {
+                    i += 1;
+                }
+            } else if self.positionals && !text.starts_with('-') {
+                selected.push(arg);
+            }
+
+            i += 1;
+        }
+
+        forward(selected)
+    }
+}
+
+fn to_str(arg: &OsStr) -> Result<&str, Error> {
+    arg.to_str().ok_or_else(|| Error::NotUnicode(arg.to_os_string()))
+}
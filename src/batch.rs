@@ -0,0 +1,120 @@
+//! Many commands run concurrently on a bounded number of threads, like `xargs -P`.
+//!
+//! A batch holds a list of commands, each a string run with `sh` or a `pipeline::Stage` for
+//! another shell or configuration. `Batch::run` waits for all of them and returns their outcomes
+//! in the order of the commands, whichever finished first.
+//!
+//! ```rust
+//! use shells::pipeline::bash;
+//!
+//! let report = shells::batch(["echo a", "sleep 0.1; echo b", "exit 3"])
+//!     .command(bash("echo c"))
+//!     .max_parallel(4)
+//!     .run();
+//!
+//! let stdout: Vec<&str> = report.results.iter().map(|result| &result.stdout[..]).collect();
+//!
+//! assert_eq!(stdout, ["a\n", "b\n", "", "c\n"]);
+//! assert_eq!(report.failures().map(|result| result.code).collect::<Vec<_>>(), [3]);
+//! ```
+//!
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use pipeline::Stage;
+use report::{self, Outcome};
+
+/// Creates a batch of the given commands, see the module documentation.
+///
+pub fn batch<I>(commands: I) -> Batch
+    where I: IntoIterator,
+          I::Item: Into<Stage>
+{
+    Batch { commands: commands.into_iter().map(Into::into).collect(), ..Batch::new() }
+}
+
+/// Commands run together, see the module documentation.
+///
+#[derive(Debug, Clone)]
+pub struct Batch {
+    commands: Vec<Stage>,
+    max_parallel: usize,
+}
+
+impl Default for Batch {
+    fn default() -> Batch {
+        Batch::new()
+    }
+}
+
+impl Batch {
+    /// Creates an empty batch running as many commands at once as the machine has processors.
+    ///
+    pub fn new() -> Batch {
+        let processors = ::std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        Batch { commands: Vec::new(), max_parallel: processors }
+    }
+
+    /// Appends a command to the batch.
+    ///
+    pub fn command<S: Into<Stage>>(mut self, command: S) -> Batch {
+        self.commands.push(command.into());
+        self
+    }
+
+    /// Maximum number of commands running at the same time.
+    ///
+    pub fn max_parallel(mut self, max: usize) -> Batch {
+        self.max_parallel = max.max(1);
+        self
+    }
+
+    /// Runs every command and waits for all of them.
+    ///
+    pub fn run(&self) -> Report {
+        let results = parallel(self.commands.len(), self.max_parallel, |i| {
+            let command = &self.commands[i];
+            Outcome::timed(command.cmd().to_string(), |_| command.run())
+        });
+
+        Report { results, total: self.commands.len() }
+    }
+}
+
+/// Outcome of a command of a batch, named by its command line.
+///
+pub type CommandResult = Outcome<String>;
+
+/// Outcomes of the commands of a batch, in the order of the commands.
+///
+pub type Report = report::Report<String>;
+
+/// Calls `f` with every index below `len`, on up to `max_parallel` threads taking the next index
+/// once done with theirs, and returns the results in the order of the indices.
+///
+pub(crate) fn parallel<R, F>(len: usize, max_parallel: usize, f: F) -> Vec<R>
+    where R: Send,
+          F: Fn(usize) -> R + Sync
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..len).map(|_| None).collect());
+
+    ::std::thread::scope(|scope| {
+        for _ in 0..max_parallel.min(len) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+
+                if i >= len {
+                    break;
+                }
+
+                let result = f(i);
+                results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    results.into_iter().flatten().collect()
+}
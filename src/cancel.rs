@@ -0,0 +1,125 @@
+//! Cancellation of commands in flight, from a Ctrl-C handler or another thread.
+//!
+//! A `CancellationToken` is shared between the code running the commands and the code deciding
+//! to stop them. Blocking executions of a shell given the token with `Shell::cancel_on` are killed
+//! with the processes of their group once it is cancelled, and fail with `Error::Cancelled`; the
+//! commands started by `Shell::start` are killed as well.
+//!
+//! ```rust
+//! use std::thread;
+//! use std::time::{Duration, Instant};
+//! use shells::{CancellationToken, Error, Shell};
+//!
+//! let token = CancellationToken::new();
+//! let shell = Shell::new("sh").cancel_on(token.clone());
+//!
+//! let canceller = token.clone();
+//! thread::spawn(move || {
+//!     thread::sleep(Duration::from_millis(100));
+//!     canceller.cancel();
+//! });
+//!
+//! let start = Instant::now();
+//!
+//! match shell.try_run("echo started; sleep 10") {
+//!     Err(Error::Cancelled { partial_stdout, .. }) => assert_eq!(partial_stdout, "started\n"),
+//!     other => panic!("unexpected result: {:?}", other),
+//! }
+//!
+//! assert!(start.elapsed() < Duration::from_secs(5));
+//! ```
+//!
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Handle cancelling the commands it was given to, see the module documentation. Clones share the
+/// same state: cancelling one cancels them all, for good.
+///
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+
+    #[cfg_attr(not(unix), allow(dead_code))]
+    next: AtomicU64,
+    callbacks: Mutex<Vec<(u64, Callback)>>,
+}
+
+impl Inner {
+    fn callbacks(&self) -> MutexGuard<'_, Vec<(u64, Callback)>> {
+        self.callbacks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CancellationToken {
+    /// Creates a token which is not cancelled.
+    ///
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the commands given the token, those running and those started afterwards.
+    ///
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let callbacks: Vec<(u64, Callback)> = self.inner.callbacks().drain(..).collect();
+
+        for (_, callback) in callbacks {
+            callback();
+        }
+    }
+
+    /// Whether the token was cancelled.
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Calls `f` once the token is cancelled, right away if it already is, unless the returned
+    /// registration was dropped before.
+    ///
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub(crate) fn on_cancel<F: FnOnce() + Send + 'static>(&self, f: F) -> Registration {
+        let id = self.inner.next.fetch_add(1, Ordering::SeqCst);
+        let mut callbacks = self.inner.callbacks();
+
+        match self.is_cancelled() {
+            true => {
+                drop(callbacks);
+                f();
+            },
+
+            false => callbacks.push((id, Box::new(f))),
+        }
+
+        Registration { inner: self.inner.clone(), id }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+/// Callback registered on a token, unregistered when dropped.
+///
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) struct Registration {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.inner.callbacks().retain(|&(id, _)| id != self.id);
+    }
+}
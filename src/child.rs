@@ -0,0 +1,474 @@
+//! Commands running in the background, see `Shell::spawn`.
+//!
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::SocketAddr;
+use std::process::{Child, ChildStderr, ChildStdout, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cancel::CancellationToken;
+use output::ExitStatus;
+use probe::Probe;
+use shell::{PreparedCommand, Stream};
+
+/// Handle on a command running in the background, for daemons and long-lived watchers. stdout
+/// and stderr are piped: take them to read the output, or it may block once the pipes are full.
+///
+/// Unlike the blocking executions, the timeout of the shell does not apply and mocks do not
+/// intercept the command. Dropping the handle leaves the command running, unlike `Running`.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use std::io::Read;
+///
+/// fn main() {
+///     let mut child = spawn_sh!("sleep 10").unwrap();
+///
+///     assert_eq!(child.try_wait().unwrap(), None);
+///     child.kill().unwrap();
+///     assert_eq!(child.wait().unwrap().signal(), Some(9));
+///
+///     let mut child = spawn_sh!("echo ready").unwrap();
+///     let mut stdout = String::new();
+///
+///     child.take_stdout().unwrap().read_to_string(&mut stdout).unwrap();
+///     assert_eq!((child.wait().unwrap().code(), &stdout[..]), (0, "ready\n"));
+/// }
+/// ```
+///
+#[derive(Debug)]
+pub struct ShellChild {
+    prepared: PreparedCommand,
+    child: Child,
+    status: Option<ExitStatus>,
+}
+
+impl ShellChild {
+    /// Starts the command.
+    ///
+    pub(crate) fn spawn(prepared: PreparedCommand) -> io::Result<ShellChild> {
+        ::trace::intercept_start(&prepared)?;
+        prepared.setup().map_err(io::Error::other)?;
+
+        let mut command = prepared.command();
+        command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        match prepared.start(command) {
+            Ok(child) => Ok(ShellChild { prepared, child, status: None }),
+
+            Err(e) => {
+                prepared.teardown();
+                Err(e)
+            },
+        }
+    }
+
+    /// Starts the command and waits until it is ready, see `Shell::spawn_until`.
+    ///
+    pub(crate) fn spawn_until(prepared: PreparedCommand, ready: Ready, timeout: Duration)
+        -> io::Result<ShellChild>
+    {
+        let mut child = ShellChild::spawn(prepared.in_own_group())?;
+        let (sender, printed) = mpsc::channel();
+        let line = match ready.condition {
+            Condition::Line(ref text) => Some(text.clone()),
+            _ => None,
+        };
+
+        if let Some(stdout) = child.take_stdout() {
+            let sinks = child.prepared.sinks(Stream::Stdout);
+            drain(stdout, Stream::Stdout, sinks, line.clone(), sender.clone());
+        }
+
+        if let Some(stderr) = child.take_stderr() {
+            drain(stderr, Stream::Stderr, child.prepared.sinks(Stream::Stderr), line, sender);
+        }
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let is_ready = match ready.condition {
+                Condition::Line(_) => printed.try_recv().is_ok(),
+                Condition::Probe(ref probe) => probe.check(),
+            };
+
+            if is_ready {
+                return Ok(child);
+            }
+
+            if let Some(status) = child.try_wait()? {
+                let reason = format!("the command exited with code {} before it was ready",
+                                     status.code());
+                return Err(io::Error::other(reason));
+            }
+
+            if Instant::now() >= deadline {
+                let _ = child.kill_tree();
+                let _ = child.wait();
+                let reason = format!("the command was not {} in time", ready);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, reason));
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// The process identifier of the shell running the command.
+    ///
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Takes the stdout of the command, `None` once taken.
+    ///
+    pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+        self.child.stdout.take()
+    }
+
+    /// Takes the stderr of the command, `None` once taken.
+    ///
+    pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+        self.child.stderr.take()
+    }
+
+    /// Waits for the command to exit and returns how it terminated.
+    ///
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self.status {
+            Some(status) => Ok(status),
+            None => self.child.wait().map(|status| self.exited(status.into())),
+        }
+    }
+
+    /// Returns how the command terminated if it already exited, without blocking.
+    ///
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self.status {
+            Some(status) => Ok(Some(status)),
+            None => Ok(self.child.try_wait()?.map(|status| self.exited(status.into()))),
+        }
+    }
+
+    /// Liveness check: whether the command is still running and passes the probe.
+    ///
+    pub fn is_alive(&mut self, probe: &Probe) -> io::Result<bool> {
+        Ok(self.try_wait()?.is_none() && probe.check())
+    }
+
+    /// Kills the command, with `SIGKILL` on Unix, like `kill_tree`: along with the processes it
+    /// started when it runs in a process group of its own.
+    ///
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.kill_tree()
+    }
+
+    /// Kills the command with every process of its group, with `SIGKILL`, when it runs in a group
+    /// of its own, see `Shell::process_group`. Otherwise, and elsewhere than on Unix, kills the
+    /// command alone.
+    ///
+    /// ```rust
+    /// use std::io::Read;
+    /// use shells::Shell;
+    ///
+    /// let mut child = Shell::new("sh").process_group().spawn("sleep 10 | sleep 10").unwrap();
+    /// let mut stdout = String::new();
+    ///
+    /// child.kill_tree().unwrap();
+    ///
+    /// // Both `sleep` would keep the pipe open for 10 seconds if they were still running.
+    /// child.take_stdout().unwrap().read_to_string(&mut stdout).unwrap();
+    /// assert_ne!(child.wait().unwrap().code(), 0);
+    /// ```
+    ///
+    pub fn kill_tree(&mut self) -> io::Result<()> {
+        if self.status.is_none() {
+            ::shell::kill(&mut self.child);
+        }
+
+        Ok(())
+    }
+
+    fn exited(&mut self, status: ExitStatus) -> ExitStatus {
+        self.status = Some(status);
+
+        if let Some(note) = self.prepared.teardown() {
+            eprintln!("{}", note);
+        }
+
+        status
+    }
+}
+
+/// Guard on a command running in the background, killing it with the processes of its group
+/// when dropped, for the commands which must not outlive the code which started them. Returned
+/// by `Shell::start`, which runs the command in a group of its own, or converted from the
+/// `ShellChild` of `spawn_*`, whose group may hold the command alone.
+///
+/// The token given to `Shell::cancel_on` kills the command as well. On Unix, it is killed as soon
+/// as the token is cancelled; elsewhere, once the guard notices it, when waited for or dropped.
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+/// use shells::{CancellationToken, Shell};
+///
+/// let token = CancellationToken::new();
+/// let shell = Shell::new("sh").cancel_on(token.clone());
+/// let start = Instant::now();
+///
+/// {
+///     let _server = shell.start("sleep 10 & sleep 10").unwrap();
+/// }
+///
+/// let mut watcher = shell.start("sleep 10").unwrap();
+/// token.cancel();
+///
+/// assert_ne!(watcher.wait().unwrap().code(), 0);
+/// assert!(start.elapsed() < Duration::from_secs(5));
+/// ```
+///
+pub struct Running {
+    child: ShellChild,
+    token: Option<CancellationToken>,
+
+    /// Whether the command was not reaped yet, so that its identifier is still its own.
+    #[cfg(unix)]
+    alive: ::std::sync::Arc<::std::sync::Mutex<bool>>,
+
+    #[cfg(unix)]
+    _registration: Option<::cancel::Registration>,
+}
+
+impl Running {
+    /// Starts the command, see `Shell::start`.
+    ///
+    pub(crate) fn start(prepared: PreparedCommand) -> io::Result<Running> {
+        ShellChild::spawn(prepared).map(Running::from)
+    }
+
+    /// The process identifier of the shell running the command.
+    ///
+    pub fn pid(&self) -> u32 {
+        self.child.pid()
+    }
+
+    /// Takes the stdout of the command, `None` once taken.
+    ///
+    pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+        self.child.take_stdout()
+    }
+
+    /// Takes the stderr of the command, `None` once taken.
+    ///
+    pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+        self.child.take_stderr()
+    }
+
+    /// Waits for the command to exit and returns how it terminated.
+    ///
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        if self.token.is_none() {
+            return self.child.wait();
+        }
+
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(status);
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Returns how the command terminated if it already exited, without blocking.
+    ///
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            self.kill()?;
+        }
+
+        // Holding the lock while reaping keeps the cancellation from killing a reused identifier.
+        #[cfg(unix)]
+        let mut alive = self.alive.lock().unwrap_or_else(|e| e.into_inner());
+
+        let status = self.child.try_wait()?;
+
+        #[cfg(unix)]
+        {
+            *alive = status.is_none();
+        }
+
+        Ok(status)
+    }
+
+    /// The command, as prepared by its shell.
+    ///
+    pub(crate) fn prepared(&self) -> &PreparedCommand {
+        &self.child.prepared
+    }
+
+    /// Copies the output of the command to the sinks of its shell until it exits, so that it never
+    /// blocks on a full pipe.
+    ///
+    pub(crate) fn forward_output(&mut self) {
+        let (sender, _) = mpsc::channel();
+
+        if let Some(stdout) = self.take_stdout() {
+            let sinks = self.child.prepared.sinks(Stream::Stdout);
+            drain(stdout, Stream::Stdout, sinks, None, sender.clone());
+        }
+
+        if let Some(stderr) = self.take_stderr() {
+            drain(stderr, Stream::Stderr, self.child.prepared.sinks(Stream::Stderr), None, sender);
+        }
+    }
+
+    /// Kills the command with the processes of its group, with `SIGKILL` on Unix.
+    ///
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill_tree()
+    }
+}
+
+impl From<ShellChild> for Running {
+    fn from(child: ShellChild) -> Running {
+        let token = child.prepared.cancel_token().cloned();
+
+        #[cfg(unix)]
+        {
+            let alive = ::std::sync::Arc::new(::std::sync::Mutex::new(true));
+            let (pid, killed) = (child.pid() as i32, alive.clone());
+
+            let registration = token.as_ref().map(|token| token.on_cancel(move || {
+                if *killed.lock().unwrap_or_else(|e| e.into_inner()) {
+                    unsafe {
+                        ::sys::kill(-pid, ::sys::SIGKILL);
+                        ::sys::kill(pid, ::sys::SIGKILL);
+                    }
+                }
+            }));
+
+            Running { child, token, alive, _registration: registration }
+        }
+
+        #[cfg(not(unix))]
+        Running { child, token }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Ok(None) = self.try_wait() {
+            let _ = self.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+impl fmt::Debug for Running {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Running").field("child", &self.child).field("token", &self.token).finish()
+    }
+}
+
+/// The condition telling a command started by `Shell::spawn_until` is ready.
+///
+#[derive(Clone)]
+pub struct Ready {
+    condition: Condition,
+}
+
+#[derive(Clone)]
+enum Condition {
+    Line(String),
+    Probe(Probe),
+}
+
+impl Ready {
+    /// Ready once the command prints a line containing `text`, on stdout or stderr.
+    ///
+    pub fn line(text: &str) -> Ready {
+        Ready { condition: Condition::Line(text.to_string()) }
+    }
+
+    /// Ready once a TCP connection to the given port of the local host succeeds.
+    ///
+    pub fn port(port: u16) -> Ready {
+        Ready::from(Probe::port(port))
+    }
+
+    /// Ready once a TCP connection to the given address succeeds.
+    ///
+    pub fn addr(addr: SocketAddr) -> Ready {
+        Ready::from(Probe::tcp(addr))
+    }
+
+    /// Ready once `probe` returns `true`. It is called repeatedly until then, and must not block
+    /// for long. See `probe::Probe` for the usual checks, an HTTP endpoint or a unix socket.
+    ///
+    pub fn probe<F: Fn() -> bool + Send + Sync + 'static>(probe: F) -> Ready {
+        Ready::from(Probe::custom(probe))
+    }
+}
+
+impl From<Probe> for Ready {
+    /// Ready once the probe passes.
+    ///
+    fn from(probe: Probe) -> Ready {
+        Ready { condition: Condition::Probe(probe) }
+    }
+}
+
+impl fmt::Debug for Ready {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ready({})", self)
+    }
+}
+
+impl fmt::Display for Ready {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.condition {
+            Condition::Line(ref text) => write!(f, "printing `{}`", text),
+            Condition::Probe(ref probe) => write!(f, "{}", probe),
+        }
+    }
+}
+
+/// Reads the pipe until it is closed, from a separate thread, copying what is read to the sinks
+/// and telling when a line contains `text`.
+///
+fn drain<R>(pipe: R, stream: Stream, sinks: Vec<::sink::Attached>, text: Option<String>,
+            printed: Sender<()>)
+    where R: Read + Send + 'static
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buffer = Vec::new();
+        let mut text = text;
+
+        loop {
+            buffer.clear();
+
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+
+                Ok(_) => {
+                    for sink in &sinks {
+                        sink.write(stream, &buffer);
+                    }
+
+                    let line = String::from_utf8_lossy(&buffer);
+
+                    if text.as_ref().is_some_and(|text| line.contains(&text[..])) {
+                        let _ = printed.send(());
+                        text = None;
+                    }
+                },
+            }
+        }
+    });
+}
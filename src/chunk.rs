@@ -0,0 +1,167 @@
+//! Execution of huge generated scripts in chunks, one shell invocation per chunk.
+//!
+//! A script of thousands of statements passed as a single `-c` argument can exceed the limits of
+//! the system (128 KiB per argument on Linux) and fails as a whole, without telling how far it
+//! went. `Chunked` groups the statements into chunks of bounded size instead, reports the
+//! progress after each of them and which statements a failing chunk held.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::chunk::Chunked;
+//!
+//! let statements: Vec<String> = (0..1000).map(|i| format!("echo {}", i)).collect();
+//! let report = Chunked::new(Shell::new("sh")).max_statements(300)
+//!     .run_with_progress(&statements, |progress| {
+//!         eprintln!("{}/{} statements", progress.done, progress.total);
+//!     });
+//!
+//! assert!(report.is_success());
+//! assert_eq!(report.results.len(), 4);
+//! assert_eq!(report.results[3].subject, 900..1000);
+//! ```
+//!
+//! Each chunk runs in a new shell: variables, functions and the working directory do not carry
+//! over from one chunk to the next. Put what every chunk needs in `Shell::preamble`, along with
+//! `set -e` for a chunk to stop at its first failing statement.
+//!
+
+use std::ops::Range;
+
+use report::{self, Outcome};
+use shell::Shell;
+
+/// Executor splitting a script into chunks, see the module documentation.
+///
+#[derive(Debug, Clone)]
+pub struct Chunked {
+    shell: Shell,
+    max_statements: usize,
+    max_bytes: usize,
+    keep_going: bool,
+}
+
+impl Chunked {
+    /// Creates an executor running the chunks with the given shell, of up to 1000 statements and
+    /// 64 KiB each, stopping at the first failing chunk.
+    ///
+    pub fn new(shell: Shell) -> Chunked {
+        Chunked { shell, max_statements: 1000, max_bytes: 64 * 1024, keep_going: false }
+    }
+
+    /// Maximum number of statements of a chunk.
+    ///
+    pub fn max_statements(mut self, max: usize) -> Chunked {
+        self.max_statements = max.max(1);
+        self
+    }
+
+    /// Maximum size of a chunk in bytes. A statement larger than this still gets a chunk of its
+    /// own.
+    ///
+    pub fn max_bytes(mut self, max: usize) -> Chunked {
+        self.max_bytes = max;
+        self
+    }
+
+    /// Runs the remaining chunks after a chunk failed.
+    ///
+    pub fn keep_going(mut self) -> Chunked {
+        self.keep_going = true;
+        self
+    }
+
+    /// Runs the statements chunk by chunk and returns the outcome of each chunk run.
+    ///
+    pub fn run<S: AsRef<str>>(&self, statements: &[S]) -> Report {
+        self.run_with_progress(statements, |_| {})
+    }
+
+    /// Runs the statements like `run`, calling `progress` after each chunk.
+    ///
+    pub fn run_with_progress<S, F>(&self, statements: &[S], mut progress: F) -> Report
+        where S: AsRef<str>, F: FnMut(&Progress)
+    {
+        let plan = self.plan(statements);
+        let mut report = Report { results: Vec::with_capacity(plan.len()), total: plan.len() };
+
+        for (index, range) in plan.iter().enumerate() {
+            let script: Vec<&str> = statements[range.clone()].iter().map(AsRef::as_ref).collect();
+            let chunk = Outcome::timed(range.clone(), |_| self.shell.run(&script.join("\n")));
+            let succeeded = chunk.succeeded();
+            report.results.push(chunk);
+
+            progress(&Progress {
+                chunk: index + 1,
+                chunks: plan.len(),
+                done: range.end,
+                total: statements.len(),
+            });
+
+            if !succeeded && !self.keep_going {
+                break;
+            }
+        }
+
+        report
+    }
+
+    /// Splits the statements into the ranges of the chunks.
+    ///
+    fn plan<S: AsRef<str>>(&self, statements: &[S]) -> Vec<Range<usize>> {
+        let mut plan = Vec::new();
+        let mut start = 0;
+        let mut bytes = 0;
+
+        for (index, statement) in statements.iter().enumerate() {
+            let size = statement.as_ref().len() + 1;
+            let full = index - start == self.max_statements || bytes + size > self.max_bytes;
+
+            if index > start && full {
+                plan.push(start..index);
+                start = index;
+                bytes = 0;
+            }
+
+            bytes += size;
+        }
+
+        if start < statements.len() {
+            plan.push(start..statements.len());
+        }
+
+        plan
+    }
+}
+
+/// Progress of a chunked execution, after a chunk exited.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of chunks run so far, starting at 1.
+    pub chunk: usize,
+
+    /// Number of chunks of the script.
+    pub chunks: usize,
+
+    /// Number of statements run so far.
+    pub done: usize,
+
+    /// Number of statements of the script.
+    pub total: usize,
+}
+
+/// Outcome of a chunk, named by the indices of its statements.
+///
+pub type Chunk = Outcome<Range<usize>>;
+
+/// Outcomes of the chunks which ran, in order.
+///
+pub type Report = report::Report<Range<usize>>;
+
+impl Report {
+    /// Number of statements of the chunks which ran.
+    ///
+    pub fn executed(&self) -> usize {
+        self.results.last().map(|chunk| chunk.subject.end).unwrap_or(0)
+    }
+}
@@ -0,0 +1,316 @@
+//! Integration with CI systems: reports of command batches displaying each command as a test case,
+//! and collapsible groups of the log.
+//!
+//! `junit` renders cases as JUnit XML, the format GitLab, Jenkins and most GitHub Actions
+//! reporters import, and `tap` as the Test Anything Protocol. The reports of `Fleet`, `Batch`,
+//! `Chunked` and the library tests have `junit` and `tap` methods rendering their outcomes.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::library::Library;
+//!
+//! let library = Library::new(Shell::new("sh")).source(r#"
+//! test_true() { true; }
+//! test_false() { echo "expected failure" >&2; false; }
+//! "#);
+//!
+//! let xml = library.test().junit("library");
+//!
+//! assert!(xml.contains(r#"<testsuite name="library" tests="2" failures="1""#));
+//! assert!(xml.contains(r#"<failure message="exit code 1">expected failure"#));
+//! ```
+//!
+//! `Groups` echoes the output of each command in a collapsible group of the CI log, detected from
+//! the environment variables of GitHub Actions and GitLab CI. `group` does the same for any step
+//! of the program.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::ci::{self, Groups};
+//!
+//! let shell = Shell::new("sh").sink(Groups::detect());
+//!
+//! ci::group("Build", || {
+//!     shell.run("echo compiling");
+//! });
+//! ```
+//!
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use shell::Stream;
+use sink::Sink;
+
+/// Outcome of a command, rendered as a test case.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case<'a> {
+    pub name: String,
+    pub code: i32,
+    pub stdout: &'a str,
+    pub stderr: &'a str,
+    pub duration: Duration,
+}
+
+impl<'a> Case<'a> {
+    pub fn passed(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// Renders the cases as a JUnit XML document holding a single test suite. Failed cases get a
+/// `failure` element with their stderr; the output of every case is kept in `system-out` and
+/// `system-err`.
+///
+pub fn junit(suite: &str, cases: &[Case]) -> String {
+    let failures = cases.iter().filter(|case| !case.passed()).count();
+    let time: Duration = cases.iter().map(|case| case.duration).sum();
+    let suite = escape(suite);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+
+    xml.push_str(&format!("  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+                          suite, cases.len(), failures, time.as_secs_f64()));
+
+    for case in cases {
+        xml.push_str(&format!("    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+                              escape(&case.name), suite, case.duration.as_secs_f64()));
+
+        if !case.passed() {
+            xml.push_str(&format!("      <failure message=\"exit code {}\">{}</failure>\n",
+                                  case.code, escape(case.stderr)));
+        }
+
+        if !case.stdout.is_empty() {
+            xml.push_str(&format!("      <system-out>{}</system-out>\n", escape(case.stdout)));
+        }
+
+        if !case.stderr.is_empty() {
+            xml.push_str(&format!("      <system-err>{}</system-err>\n", escape(case.stderr)));
+        }
+
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Renders the cases in the TAP format, with the exit code and output of failed cases as
+/// diagnostics. Cases are described by the first line of their name, `#` escaped.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use shells::ci::{self, Case};
+///
+/// let name = "echo #1\necho 2".to_string();
+/// let case = Case { name, code: 0, stdout: "", stderr: "", duration: Duration::ZERO };
+///
+/// assert_eq!(ci::tap(&[case]), "1..1\nok 1 - echo \\#1...\n");
+/// ```
+///
+pub fn tap(cases: &[Case]) -> String {
+    let mut tap = format!("1..{}\n", cases.len());
+
+    for (i, case) in cases.iter().enumerate() {
+        // A description is a single line, in which `#` would start a directive.
+        let name = title(&case.name).replace('\\', "\\\\").replace('#', "\\#");
+
+        if case.passed() {
+            tap.push_str(&format!("ok {} - {}\n", i + 1, name));
+        } else {
+            tap.push_str(&format!("not ok {} - {}\n# exit code {}\n", i + 1, name, case.code));
+
+            for line in case.stdout.lines().chain(case.stderr.lines()) {
+                tap.push_str(&format!("# {}\n", line));
+            }
+        }
+    }
+
+    tap
+}
+
+/// Escapes the text for XML content and attributes, dropping the control characters XML does
+/// not allow.
+///
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {},
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// The CI system the program runs in, which determines how log groups are marked.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    /// GitHub Actions, detected from `GITHUB_ACTIONS=true`.
+    GitHubActions,
+
+    /// GitLab CI, detected from `GITLAB_CI`.
+    GitLab,
+
+    /// Not a known CI system: groups are not marked.
+    None,
+}
+
+impl Provider {
+    /// The CI system of the environment.
+    ///
+    pub fn detect() -> Provider {
+        let var = |name| ::std::env::var(name).unwrap_or_default();
+
+        if var("GITHUB_ACTIONS") == "true" {
+            Provider::GitHubActions
+        } else if !var("GITLAB_CI").is_empty() {
+            Provider::GitLab
+        } else {
+            Provider::None
+        }
+    }
+
+    /// The line opening a group of the log, empty when groups are not marked. `id` names the
+    /// group in GitLab, which requires the line closing it to repeat it.
+    ///
+    pub fn start_marker(&self, id: &str, title: &str) -> String {
+        match *self {
+            Provider::GitHubActions => format!("::group::{}\n", title),
+            Provider::GitLab => {
+                format!("\x1b[0Ksection_start:{}:{}[collapsed=true]\r\x1b[0K{}\n",
+                        timestamp(), id, title)
+            },
+            Provider::None => String::new(),
+        }
+    }
+
+    /// The line closing the group opened with `start_marker`.
+    ///
+    pub fn end_marker(&self, id: &str) -> String {
+        match *self {
+            Provider::GitHubActions => "::endgroup::\n".to_string(),
+            Provider::GitLab => format!("\x1b[0Ksection_end:{}:{}\r\x1b[0K\n", timestamp(), id),
+            Provider::None => String::new(),
+        }
+    }
+}
+
+/// Runs `step` within a group of the CI log titled `title`, see `Provider::detect`. The markers
+/// are printed to stdout.
+///
+pub fn group<T, F: FnOnce() -> T>(title: &str, step: F) -> T {
+    let provider = Provider::detect();
+    let id = next_id();
+
+    print!("{}", provider.start_marker(&id, title));
+    let result = step();
+    print!("{}", provider.end_marker(&id));
+
+    result
+}
+
+/// Sink echoing the output of each command to stdout, both streams included, within a group of
+/// the CI log titled by the command. Commands without output get no group.
+///
+#[derive(Debug)]
+pub struct Groups {
+    provider: Provider,
+    title: String,
+    open: Option<String>,
+    last: Option<Stream>,
+    newline: bool,
+}
+
+impl Groups {
+    pub fn new(provider: Provider) -> Groups {
+        Groups { provider, title: String::new(), open: None, last: None, newline: true }
+    }
+
+    /// Creates a sink marking the groups for the CI system of the environment.
+    ///
+    pub fn detect() -> Groups {
+        Groups::new(Provider::detect())
+    }
+}
+
+impl Sink for Groups {
+    fn start(&mut self, command: &str) -> io::Result<()> {
+        self.title = title(command);
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+
+        if self.open.is_none() {
+            let id = next_id();
+            stdout.write_all(self.provider.start_marker(&id, &self.title).as_bytes())?;
+            self.open = Some(id);
+        }
+
+        // A partial line of the other stream is ended rather than continued.
+        if !self.newline && self.last != Some(stream) {
+            stdout.write_all(b"\n")?;
+        }
+
+        if let Some(&last) = bytes.last() {
+            self.newline = last == b'\n';
+            self.last = Some(stream);
+        }
+
+        stdout.write_all(bytes)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+
+        if let Some(id) = self.open.take() {
+            if !self.newline {
+                stdout.write_all(b"\n")?;
+                self.newline = true;
+            }
+
+            stdout.write_all(self.provider.end_marker(&id).as_bytes())?;
+        }
+
+        stdout.flush()
+    }
+}
+
+/// The title of the group of a command: its first line, shortened when the command is long.
+///
+fn title(command: &str) -> String {
+    let command = command.trim();
+    let first = command.lines().next().unwrap_or_default();
+
+    match first.chars().count() > 72 || first.len() < command.len() {
+        true => format!("{}...", first.chars().take(72).collect::<String>()),
+        false => first.to_string(),
+    }
+}
+
+/// A new name for a GitLab section.
+///
+fn next_id() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!("shells_{}_{}", ::std::process::id(), NEXT.fetch_add(1, Ordering::SeqCst))
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,98 @@
+//! Source of time used by the subsystems which wait, so that tests can advance time virtually
+//! instead of sleeping: lock timeouts, retry backoffs, the delays before the restarts of
+//! supervised jobs, the deadlines of probes, and the delays and timeouts of mocked commands, see
+//! `mock::Scenario::clock`. The timeouts of the commands actually executed always run on the
+//! system time, as the processes do.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use shells::clock::{Clock, FakeClock};
+//!
+//! let clock = FakeClock::new();
+//! let start = clock.now();
+//!
+//! clock.sleep(Duration::from_secs(3600));
+//!
+//! assert_eq!(clock.now() - start, Duration::from_secs(3600));
+//! ```
+//!
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of time.
+///
+pub trait Clock: Send + Sync + ::std::fmt::Debug {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Blocks for the given duration.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock, used by default.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A virtual clock which only moves when slept on or explicitly advanced. Clones share the same
+/// time.
+///
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl FakeClock {
+    pub fn new() -> FakeClock {
+        FakeClock { start: Instant::now(), elapsed: Arc::new(Mutex::new(Duration::from_secs(0))) }
+    }
+
+    /// Moves the clock forward.
+    ///
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+
+    /// Total time the clock moved forward since its creation.
+    ///
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FakeClock {
+    fn default() -> FakeClock {
+        FakeClock::new()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    /// Returns immediately after advancing the clock.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// The clock used when none is given.
+///
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
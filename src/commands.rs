@@ -0,0 +1,667 @@
+//! Registries of named commands, defined in code or, with the `commands-toml` and
+//! `commands-yaml` features, loaded from files so that operational commands can be tweaked
+//! without recompiling the program running them:
+//!
+//! ```toml
+//! [commands.backup]
+//! description = "Dump the database"
+//! command = "pg_dump shop > shop.sql"
+//! shell = "bash"
+//! cwd = "/var/backups"
+//! timeout = "10m"
+//! env = { PGHOST = "db.internal" }
+//!
+//! [commands.vacuum]
+//! command = "psql -c 'VACUUM ANALYZE' shop"
+//! ```
+//!
+//! The YAML format has the same structure. Only `command` is required, a string or an array of
+//! lines: `shell` defaults to `sh`, and `timeout` is a number of seconds or a number followed by
+//! `ms`, `s`, `m` or `h`. `target` runs the command elsewhere, see `target::parse`.
+//!
+//! ```rust,no_run
+//! use shells::commands::Commands;
+//!
+//! let commands = Commands::load("commands.toml").unwrap();
+//! let (code, stdout, stderr) = commands.run("backup").unwrap();
+//! ```
+//!
+//! `Commands::tasks` exposes the registry as `Tasks`, with its `just`-like command line.
+//!
+//! The same file can define profiles, e.g. one per environment, overriding the shell, the
+//! environment variables, the directory, the timeout and the target of every command or of
+//! some of them. `Profiles::select` returns the registry of the commands as changed by a profile:
+//!
+//! ```toml
+//! [profiles.staging]
+//! target = "ssh://deploy@staging01"
+//! env = { PGHOST = "db.staging" }
+//!
+//! [profiles.prod]
+//! target = "ssh://deploy@prod01"
+//! env = { PGHOST = "db.prod" }
+//!
+//! [profiles.prod.commands.backup]
+//! timeout = "1h"
+//! ```
+//!
+//! ```rust,no_run
+//! use shells::commands::Profiles;
+//!
+//! let profile = ::std::env::var("DEPLOY_ENV").unwrap_or_else(|_| "staging".to_string());
+//! let commands = Profiles::load("commands.toml").unwrap().select(&profile).unwrap();
+//!
+//! commands.try_run("backup").unwrap().unwrap();
+//! ```
+//!
+//! ```rust
+//! use shells::commands::{Commands, Definition};
+//!
+//! let commands = Commands::new()
+//!     .define(Definition::new("greet", "echo \"hello $NAME\"").env("NAME", "world"))
+//!     .define(Definition::new("where", "pwd").cwd("/"));
+//!
+//! assert_eq!(commands.names(), vec!["greet", "where"]);
+//! assert_eq!(commands.run("greet").unwrap().1, "hello world\n");
+//! assert_eq!(commands.run("where").unwrap().1, "/\n");
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use shell::Shell;
+use target::Target;
+use tasks::Tasks;
+
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+use document::{Invalid, Node};
+
+/// A named command and how to run it.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub description: String,
+    pub command: String,
+    pub shell: String,
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+    pub target: Option<String>,
+}
+
+impl Definition {
+    /// Defines a command run by `sh`, without description.
+    ///
+    pub fn new(name: &str, command: &str) -> Definition {
+        Definition {
+            name: name.to_string(),
+            description: String::new(),
+            command: command.to_string(),
+            shell: "sh".to_string(),
+            env: BTreeMap::new(),
+            cwd: None,
+            timeout: None,
+            target: None,
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Definition {
+        self.description = description.to_string();
+        self
+    }
+
+    /// The program of the shell running the command, e.g. `bash`.
+    ///
+    pub fn shell(mut self, program: &str) -> Definition {
+        self.shell = program.to_string();
+        self
+    }
+
+    /// Sets an environment variable of the command.
+    ///
+    pub fn env(mut self, name: &str, value: &str) -> Definition {
+        self.env.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Runs the command in the given directory.
+    ///
+    pub fn cwd<P: Into<PathBuf>>(mut self, path: P) -> Definition {
+        self.cwd = Some(path.into());
+        self
+    }
+
+    /// Kills the command once `timeout` elapsed, see `Shell::timeout`.
+    ///
+    pub fn timeout(mut self, timeout: Duration) -> Definition {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Runs the command on the given target, see `target::parse`, rather than with a local shell.
+    /// The command keeps its directory and environment variables there, but not its timeout.
+    ///
+    pub fn target(mut self, spec: &str) -> Definition {
+        self.target = Some(spec.to_string());
+        self
+    }
+
+    /// The shell configured to run the command locally, whatever its target.
+    ///
+    pub fn to_shell(&self) -> Shell {
+        let mut shell = Shell::new(&self.shell);
+
+        for (name, value) in &self.env {
+            shell = shell.env(name, value);
+        }
+
+        if let Some(ref cwd) = self.cwd {
+            shell = shell.cwd(cwd);
+        }
+
+        if let Some(timeout) = self.timeout {
+            shell = shell.timeout(timeout);
+        }
+
+        shell
+    }
+
+    /// Runs the command, like `Shell::run`. A target which cannot be parsed fails with the code
+    /// 126.
+    ///
+    pub fn run(&self) -> (i32, String, String) {
+        match self.remote() {
+            Some(Ok(target)) => target.run(&self.script()),
+            Some(Err(e)) => (126, String::new(), e.to_string()),
+            None => self.to_shell().run(&self.command),
+        }
+    }
+
+    /// Runs the command, like `Shell::try_run`.
+    ///
+    pub fn try_run(&self) -> ::Result {
+        match self.remote() {
+            Some(Ok(target)) => target.try_run(&self.script()),
+            Some(Err(e)) => {
+                Err(::Error::Failed { code: 126, stdout: String::new(), stderr: e.to_string() })
+            },
+            None => self.to_shell().try_run(&self.command),
+        }
+    }
+
+    /// The target of the command, unless it runs locally.
+    ///
+    fn remote(&self) -> Option<Result<Box<dyn Target>, ::target::Error>> {
+        match self.target.as_ref().map(|spec| &spec[..]) {
+            None | Some("") | Some("local") => None,
+            Some(spec) => Some(::target::parse(spec)),
+        }
+    }
+
+    /// The command preceded by its directory and environment variables, for its target.
+    ///
+    fn script(&self) -> String {
+        let mut script = String::new();
+
+        if let Some(ref cwd) = self.cwd {
+            let cwd = cwd.to_string_lossy();
+            script.push_str(&format!("cd {} || exit 126\n", ::quote::posix(&cwd)));
+        }
+
+        for (name, value) in &self.env {
+            script.push_str(&format!("export {}={}\n", name, ::quote::posix(value)));
+        }
+
+        script + &self.command
+    }
+}
+
+/// Changes made by a profile to the commands of a registry. Environment variables are added to
+/// those of the commands; the other settings replace theirs.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub shell: Option<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Option<Duration>,
+    pub target: Option<String>,
+}
+
+impl Overrides {
+    pub fn new() -> Overrides {
+        Overrides::default()
+    }
+
+    pub fn shell(mut self, program: &str) -> Overrides {
+        self.shell = Some(program.to_string());
+        self
+    }
+
+    pub fn env(mut self, name: &str, value: &str) -> Overrides {
+        self.env.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn cwd<P: Into<PathBuf>>(mut self, path: P) -> Overrides {
+        self.cwd = Some(path.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Overrides {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn target(mut self, spec: &str) -> Overrides {
+        self.target = Some(spec.to_string());
+        self
+    }
+
+    fn apply(&self, definition: &mut Definition) {
+        definition.env.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        if let Some(ref shell) = self.shell {
+            definition.shell = shell.clone();
+        }
+
+        if let Some(ref cwd) = self.cwd {
+            definition.cwd = Some(cwd.clone());
+        }
+
+        if let Some(timeout) = self.timeout {
+            definition.timeout = Some(timeout);
+        }
+
+        if let Some(ref target) = self.target {
+            definition.target = Some(target.clone());
+        }
+    }
+}
+
+/// A named set of overrides, applied to every command, then to each command by name.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub all: Overrides,
+    pub commands: BTreeMap<String, Overrides>,
+}
+
+impl Profile {
+    /// Creates a profile applying `all` to every command.
+    ///
+    pub fn new(name: &str, all: Overrides) -> Profile {
+        Profile { name: name.to_string(), all, commands: BTreeMap::new() }
+    }
+
+    /// Applies `overrides` to the given command, after those applying to every command.
+    ///
+    pub fn command(mut self, name: &str, overrides: Overrides) -> Profile {
+        self.commands.insert(name.to_string(), overrides);
+        self
+    }
+}
+
+/// Named commands, listed by name.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Commands {
+    definitions: BTreeMap<String, Definition>,
+}
+
+impl Commands {
+    /// Creates an empty registry.
+    ///
+    pub fn new() -> Commands {
+        Commands::default()
+    }
+
+    /// Adds a command, replacing any command with the same name.
+    ///
+    pub fn define(mut self, definition: Definition) -> Commands {
+        self.definitions.insert(definition.name.clone(), definition);
+        self
+    }
+
+    /// Loads a registry from a file, whose format is selected by its extension: `.toml`, or
+    /// `.yaml` and `.yml`, depending on the enabled features.
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Commands, Error> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "commands-toml")]
+            Some("toml") => Commands::from_toml(&read(path)?),
+
+            #[cfg(feature = "commands-yaml")]
+            Some("yaml") | Some("yml") => Commands::from_yaml(&read(path)?),
+
+            _ => Err(Error::Unsupported(path.display().to_string())),
+        }
+    }
+
+    /// Parses a registry in the TOML format.
+    ///
+    #[cfg(feature = "commands-toml")]
+    pub fn from_toml(content: &str) -> Result<Commands, Error> {
+        Commands::from_node(&parse_toml(content)?)
+    }
+
+    /// Parses a registry in the YAML format, from the first document of the content.
+    ///
+    #[cfg(feature = "commands-yaml")]
+    pub fn from_yaml(content: &str) -> Result<Commands, Error> {
+        Commands::from_node(&parse_yaml(content)?)
+    }
+
+    /// The definition of the command with this name.
+    ///
+    pub fn get(&self, name: &str) -> Option<&Definition> {
+        self.definitions.get(name)
+    }
+
+    /// Names of the commands, in alphabetical order.
+    ///
+    pub fn names(&self) -> Vec<&str> {
+        self.definitions.keys().map(|name| &name[..]).collect()
+    }
+
+    /// Runs the command with this name, like `Shell::run`.
+    ///
+    pub fn run(&self, name: &str) -> Result<(i32, String, String), Error> {
+        self.definition(name).map(Definition::run)
+    }
+
+    /// Runs the command with this name, like `Shell::try_run`.
+    ///
+    pub fn try_run(&self, name: &str) -> Result<::Result, Error> {
+        self.definition(name).map(Definition::try_run)
+    }
+
+    /// A task for each command, described by its description or else by its command. A task
+    /// fails when its command does.
+    ///
+    pub fn tasks(&self) -> Tasks {
+        self.definitions.values().fold(Tasks::new(), |tasks, definition| {
+            let description = match definition.description.is_empty() {
+                true => &definition.command,
+                false => &definition.description,
+            };
+
+            let run = definition.clone();
+
+            tasks.task(&definition.name, description, move || {
+                run.try_run()?;
+                Ok(())
+            })
+        })
+    }
+
+    fn definition(&self, name: &str) -> Result<&Definition, Error> {
+        self.get(name).ok_or_else(|| Error::Unknown(name.to_string()))
+    }
+
+    #[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+    fn from_node(root: &Node) -> Result<Commands, Error> {
+        let mut commands = Commands::new();
+
+        for (name, node) in tables(root, "commands")? {
+            let context = format!("commands.{}", name);
+
+            let command = node.get("command")
+                .ok_or_else(|| Error::Parse(format!("{}: missing `command`", context)))?;
+
+            let command = match *command {
+                Node::Array(_) => command.scalars(&context)?.join("\n"),
+                _ => command.scalar(&context)?,
+            };
+
+            let mut definition = Definition::new(name, &command);
+
+            if let Some(description) = node.get("description") {
+                definition.description = description.scalar(&context)?;
+            }
+
+            overrides(node, &context)?.apply(&mut definition);
+            commands.definitions.insert(name.clone(), definition);
+        }
+
+        Ok(commands)
+    }
+}
+
+/// Registry of commands along with the profiles changing them.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profiles {
+    commands: Commands,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn new(commands: Commands) -> Profiles {
+        Profiles { commands, profiles: BTreeMap::new() }
+    }
+
+    /// Adds a profile, replacing any profile with the same name.
+    ///
+    pub fn profile(mut self, profile: Profile) -> Profiles {
+        self.profiles.insert(profile.name.clone(), profile);
+        self
+    }
+
+    /// Loads the commands and the profiles from a file, see `Commands::load`.
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Profiles, Error> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "commands-toml")]
+            Some("toml") => Profiles::from_toml(&read(path)?),
+
+            #[cfg(feature = "commands-yaml")]
+            Some("yaml") | Some("yml") => Profiles::from_yaml(&read(path)?),
+
+            _ => Err(Error::Unsupported(path.display().to_string())),
+        }
+    }
+
+    /// Parses the commands and the profiles in the TOML format.
+    ///
+    #[cfg(feature = "commands-toml")]
+    pub fn from_toml(content: &str) -> Result<Profiles, Error> {
+        Profiles::from_node(&parse_toml(content)?)
+    }
+
+    /// Parses the commands and the profiles in the YAML format, from the first document of the
+    /// content.
+    ///
+    #[cfg(feature = "commands-yaml")]
+    pub fn from_yaml(content: &str) -> Result<Profiles, Error> {
+        Profiles::from_node(&parse_yaml(content)?)
+    }
+
+    /// The commands, without any profile applied.
+    ///
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
+
+    /// Names of the profiles, in alphabetical order.
+    ///
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.keys().map(|name| &name[..]).collect()
+    }
+
+    /// The commands as changed by the profile with this name.
+    ///
+    pub fn select(&self, name: &str) -> Result<Commands, Error> {
+        let profile = self.profiles.get(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_string()))?;
+        let mut commands = self.commands.clone();
+
+        for (name, definition) in &mut commands.definitions {
+            profile.all.apply(definition);
+
+            if let Some(overrides) = profile.commands.get(name) {
+                overrides.apply(definition);
+            }
+        }
+
+        Ok(commands)
+    }
+
+    #[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+    fn from_node(root: &Node) -> Result<Profiles, Error> {
+        let mut profiles = Profiles::new(Commands::from_node(root)?);
+
+        for (name, node) in tables(root, "profiles")? {
+            let context = format!("profiles.{}", name);
+            let mut profile = Profile::new(name, overrides(node, &context)?);
+
+            for (command, node) in tables(node, "commands")? {
+                let context = format!("{}.commands.{}", context, command);
+
+                if profiles.commands.get(command).is_none() {
+                    let reason = format!("{}: no command named `{}`", context, command);
+                    return Err(Error::Parse(reason));
+                }
+
+                profile.commands.insert(command.clone(), overrides(node, &context)?);
+            }
+
+            profiles.profiles.insert(name.clone(), profile);
+        }
+
+        Ok(profiles)
+    }
+}
+
+#[cfg(feature = "commands-toml")]
+fn parse_toml(content: &str) -> Result<Node, Error> {
+    let value = content.parse::<::toml::Value>().map_err(|e| Error::Parse(e.to_string()))?;
+    Ok(Node::from_toml(value))
+}
+
+#[cfg(feature = "commands-yaml")]
+fn parse_yaml(content: &str) -> Result<Node, Error> {
+    let documents = ::yaml_rust2::YamlLoader::load_from_str(content)
+        .map_err(|e| Error::Parse(e.to_string()))?;
+
+    Ok(documents.into_iter().next().map(Node::from_yaml).unwrap_or(Node::Table(Vec::new())))
+}
+
+/// The entries of the table under `key`, none when it is missing.
+///
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+fn tables<'a>(node: &'a Node, key: &str) -> Result<&'a [(String, Node)], Error> {
+    Ok(node.get(key).map(|table| table.entries(key)).unwrap_or(Ok(&[]))?)
+}
+
+/// The settings of a command, or of a profile.
+///
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+fn overrides(node: &Node, context: &str) -> Result<Overrides, Error> {
+    let mut overrides = Overrides::new();
+
+    if let Some(shell) = node.get("shell") {
+        overrides.shell = Some(shell.scalar(context)?);
+    }
+
+    if let Some(env) = node.get("env") {
+        overrides.env = env.vars(context)?;
+    }
+
+    if let Some(cwd) = node.get("cwd") {
+        overrides.cwd = Some(PathBuf::from(cwd.scalar(context)?));
+    }
+
+    if let Some(timeout) = node.get("timeout") {
+        let timeout = timeout.scalar(context)?;
+        let invalid = || Error::Parse(format!("{}: invalid timeout {}", context, timeout));
+        overrides.timeout = Some(parse_timeout(&timeout).ok_or_else(invalid)?);
+    }
+
+    if let Some(target) = node.get("target") {
+        let target = target.scalar(context)?;
+        ::target::parse(&target).map_err(|e| Error::Parse(format!("{}: {}", context, e)))?;
+        overrides.target = Some(target);
+    }
+
+    Ok(overrides)
+}
+
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+fn read(path: &Path) -> Result<String, Error> {
+    ::std::fs::read_to_string(path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))
+}
+
+/// Parses a number of seconds, or a number followed by one of the units `ms`, `s`, `m` and `h`.
+///
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+fn parse_timeout(timeout: &str) -> Option<Duration> {
+    let timeout = timeout.trim();
+    let split = timeout.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(timeout.len());
+    let value: f64 = timeout[..split].parse().ok()?;
+
+    let seconds = match timeout[split..].trim() {
+        "" | "s" => value,
+        "ms" => value / 1000.0,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Error returned when a registry cannot be loaded, or has no command or profile with the given
+/// name.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The file could not be read.
+    Io(String),
+
+    /// The format of the file is not supported, or its feature is not enabled.
+    Unsupported(String),
+
+    /// The file is not a valid registry.
+    Parse(String),
+
+    /// No command has this name.
+    Unknown(String),
+
+    /// No profile has this name.
+    UnknownProfile(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Invalid command registry."
+    }
+}
+
+#[cfg(any(feature = "commands-toml", feature = "commands-yaml"))]
+impl From<Invalid> for Error {
+    fn from(invalid: Invalid) -> Error {
+        Error::Parse(invalid.0)
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Io(ref reason) | Error::Parse(ref reason) => write!(f, "{}", reason),
+            Error::Unsupported(ref path) => {
+                write!(f, "unsupported command registry format: {}", path)
+            },
+            Error::Unknown(ref name) => write!(f, "no command named `{}` in the registry", name),
+            Error::UnknownProfile(ref name) => write!(f, "no profile named `{}`", name),
+        }
+    }
+}
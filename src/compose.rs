@@ -0,0 +1,177 @@
+//! Stacks of services run through `docker compose` (or `podman compose`), for integration test
+//! harnesses.
+//!
+//! Each stack runs as its own compose project, so that stacks started concurrently from the same
+//! file do not interfere. The stack is torn down, volumes included, when dropped.
+//!
+//! ```rust,no_run
+//! use shells::compose::ComposeStack;
+//!
+//! let stack = ComposeStack::up("tests/docker-compose.yml").unwrap();
+//! let address = stack.port("db", 5432).unwrap();
+//!
+//! // ... run the tests against `address` ...
+//!
+//! print!("{}", stack.logs("app").unwrap());
+//! ```
+//!
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use container::{Error, Runtime};
+use shell::Shell;
+use which::which;
+
+/// Configuration of a compose stack to start.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compose {
+    file: PathBuf,
+    project: Option<String>,
+    runtime: Option<Runtime>,
+    health_timeout: Duration,
+}
+
+impl Compose {
+    /// Creates a stack from the given compose file, waiting up to 2 minutes for its services to be
+    /// healthy.
+    ///
+    pub fn new<P: Into<PathBuf>>(file: P) -> Compose {
+        Compose {
+            file: file.into(),
+            project: None,
+            runtime: None,
+            health_timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// Names the compose project instead of generating a unique name.
+    ///
+    pub fn project(mut self, name: &str) -> Compose {
+        self.project = Some(name.to_string());
+        self
+    }
+
+    /// Forces the use of a specific runtime instead of picking the first one available.
+    ///
+    pub fn runtime(mut self, runtime: Runtime) -> Compose {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Maximum duration to wait for the services to be running, and healthy for those defining a
+    /// health check.
+    ///
+    pub fn health_timeout(mut self, timeout: Duration) -> Compose {
+        self.health_timeout = timeout;
+        self
+    }
+
+    /// Starts the services and waits for them to be healthy. On failure the stack is torn down.
+    ///
+    pub fn start(&self) -> Result<ComposeStack, Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let runtime = match self.runtime {
+            Some(runtime) if which(runtime.program()).is_some() => runtime,
+            Some(runtime) => return Err(Error::Missing(runtime)),
+            None => Runtime::detect().ok_or(Error::Unavailable)?,
+        };
+
+        let project = self.project.clone().unwrap_or_else(|| {
+            format!("shells-{}-{}", ::std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst))
+        });
+
+        let stack = ComposeStack { runtime, file: self.file.clone(), project };
+        let timeout = self.health_timeout.as_secs().max(1).to_string();
+
+        stack.compose(&["up", "--detach", "--wait", "--wait-timeout", &timeout[..]])?;
+        Ok(stack)
+    }
+}
+
+/// A started compose project, torn down when dropped.
+///
+#[derive(Debug)]
+pub struct ComposeStack {
+    runtime: Runtime,
+    file: PathBuf,
+    project: String,
+}
+
+impl ComposeStack {
+    /// Starts the stack defined by `file` with the default options, see `Compose`.
+    ///
+    pub fn up<P: Into<PathBuf>>(file: P) -> Result<ComposeStack, Error> {
+        Compose::new(file).start()
+    }
+
+    /// The name of the compose project.
+    ///
+    pub fn project(&self) -> &str {
+        &self.project[..]
+    }
+
+    /// The compose file of the stack.
+    ///
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// The names of the services of the stack.
+    ///
+    pub fn services(&self) -> Result<Vec<String>, Error> {
+        Ok(self.compose(&["config", "--services"])?.lines().map(str::to_string).collect())
+    }
+
+    /// The logs printed so far by the service, without colors nor prefixes.
+    ///
+    pub fn logs(&self, service: &str) -> Result<String, Error> {
+        self.compose(&["logs", "--no-color", "--no-log-prefix", service])
+    }
+
+    /// The logs of every service, e.g. to attach them to a failed test report.
+    ///
+    pub fn all_logs(&self) -> Result<Vec<(String, String)>, Error> {
+        self.services()?.into_iter().map(|service| {
+            let logs = self.logs(&service)?;
+            Ok((service, logs))
+        }).collect()
+    }
+
+    /// The `host:port` address the given port of the service is published on.
+    ///
+    pub fn port(&self, service: &str, port: u16) -> Result<String, Error> {
+        Ok(self.compose(&["port", service, &port.to_string()[..]])?.trim().to_string())
+    }
+
+    /// Runs a command in the running container of the service through `sh -c`.
+    ///
+    pub fn exec(&self, service: &str, cmd: &str) -> (i32, String, String) {
+        Shell::new("sh").run(&self.command_line(&["exec", "-T", service, "sh", "-c", cmd]))
+    }
+
+    fn command_line(&self, args: &[&str]) -> String {
+        let file = self.file.to_string_lossy();
+        let mut full = vec!["compose", "--file", &file[..], "--project-name", &self.project[..]];
+        full.extend_from_slice(args);
+        self.runtime.command_line(&full)
+    }
+
+    /// Runs a compose subcommand and returns its stdout.
+    ///
+    fn compose(&self, args: &[&str]) -> Result<String, Error> {
+        match Shell::new("sh").run(&self.command_line(args)) {
+            (0, stdout, _) => Ok(stdout),
+            (code, _, stderr) => Err(Error::Failed { code, stderr }),
+        }
+    }
+}
+
+impl Drop for ComposeStack {
+    fn drop(&mut self) {
+        let _ = self.compose(&["down", "--volumes", "--remove-orphans"]);
+    }
+}
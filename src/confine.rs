@@ -0,0 +1,85 @@
+//! Mandatory access control contexts commands can be confined to.
+//!
+//! The shell is started through `runcon` for SELinux and `aa-exec` for AppArmor; when the tool is
+//! not installed the command is not run at all.
+//!
+//! ```rust,no_run
+//! use shells::Shell;
+//!
+//! let shell = Shell::new("sh").apparmor_profile("restricted-backup");
+//! let (code, _, stderr) = shell.run("tar -czf /var/backups/etc.tar.gz /etc");
+//! ```
+//!
+
+use std::ffi::OsString;
+
+use which::which;
+
+/// A security context a command runs under.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confinement {
+    /// SELinux security context, e.g. `system_u:system_r:backup_t:s0`.
+    SeLinux(String),
+
+    /// Name of a loaded AppArmor profile.
+    AppArmor(String),
+}
+
+impl Confinement {
+    /// Name of the program used to enter the context.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            Confinement::SeLinux(_) => "runcon",
+            Confinement::AppArmor(_) => "aa-exec",
+        }
+    }
+
+    /// Returns `argv` prefixed by the invocation of the tool entering the context.
+    ///
+    pub(crate) fn wrap(&self, argv: Vec<OsString>) -> Result<Vec<OsString>, Error> {
+        if which(self.program()).is_none() {
+            return Err(Error::Missing(self.program()));
+        }
+
+        let mut wrapped: Vec<OsString> = vec![self.program().into()];
+
+        match *self {
+            Confinement::SeLinux(ref context) => wrapped.push(context.into()),
+
+            Confinement::AppArmor(ref profile) => {
+                wrapped.push("-p".into());
+                wrapped.push(profile.into());
+                wrapped.push("--".into());
+            },
+        }
+
+        wrapped.extend(argv);
+        Ok(wrapped)
+    }
+}
+
+/// Error returned when a confined command cannot be wrapped.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The tool entering the context could not be found.
+    Missing(&'static str),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Confinement tool unavailable."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Missing(program) => {
+                write!(f, "confinement tool `{}` not found on the PATH", program)
+            },
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Lifecycle of containers run through docker or podman, for integration tests and build
+//! sandboxes.
+//!
+//! The container is removed, even if still running, when the `Running` handle is dropped.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use shells::container::Container;
+//!
+//! let container = Container::new("postgres:16")
+//!     .env("POSTGRES_PASSWORD", "test")
+//!     .publish(5432, 5432)
+//!     .start()
+//!     .unwrap();
+//!
+//! for line in container.logs() {
+//!     if line.text().contains("ready to accept connections") {
+//!         break;
+//!     }
+//! }
+//!
+//! let (code, stdout, _) = container.exec("psql -U postgres -c 'select 1'");
+//! ```
+//!
+
+use std::time::Duration;
+
+use shell::Shell;
+use stream::Lines;
+use which::which;
+
+/// Container engines supported by `Container`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Docker,
+    Podman,
+}
+
+impl Runtime {
+    /// Name of the program implementing the runtime.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+        }
+    }
+
+    /// Returns the first runtime available on the PATH, docker being preferred.
+    ///
+    pub fn detect() -> Option<Runtime> {
+        [Runtime::Docker, Runtime::Podman].iter().cloned()
+            .find(|runtime| which(runtime.program()).is_some())
+    }
+
+    /// Shell command line invoking the runtime with the given arguments.
+    ///
+    pub(crate) fn command_line<S: AsRef<str>>(&self, args: &[S]) -> String {
+        let mut line = format!("exec {}", self.program());
+
+        for arg in args {
+            line.push(' ');
+            line.push_str(&::quote::posix(arg.as_ref()));
+        }
+
+        line
+    }
+}
+
+/// Configuration of a container to start.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Container {
+    image: String,
+    runtime: Option<Runtime>,
+    name: Option<String>,
+    args: Vec<String>,
+    command: Vec<String>,
+}
+
+impl Container {
+    /// Creates a container running the given image with its default command.
+    ///
+    pub fn new(image: &str) -> Container {
+        Container {
+            image: image.to_string(),
+            runtime: None,
+            name: None,
+            args: Vec::new(),
+            command: Vec::new(),
+        }
+    }
+
+    /// Forces the use of a specific runtime instead of picking the first one available.
+    ///
+    pub fn runtime(mut self, runtime: Runtime) -> Container {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Names the container.
+    ///
+    pub fn name(mut self, name: &str) -> Container {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets an environment variable in the container.
+    ///
+    pub fn env(self, name: &str, value: &str) -> Container {
+        self.arg("--env").arg(&format!("{}={}", name, value))
+    }
+
+    /// Publishes the port of the container on the given port of the host.
+    ///
+    pub fn publish(self, host: u16, container: u16) -> Container {
+        self.arg("--publish").arg(&format!("{}:{}", host, container))
+    }
+
+    /// Mounts the host path at the given path of the container.
+    ///
+    pub fn volume(self, host: &str, container: &str) -> Container {
+        self.arg("--volume").arg(&format!("{}:{}", host, container))
+    }
+
+    /// Adds an option of `docker run`/`podman run`.
+    ///
+    pub fn arg(mut self, arg: &str) -> Container {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Overrides the command of the image.
+    ///
+    pub fn command(mut self, command: &[&str]) -> Container {
+        self.command = command.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    /// Starts the container in the background.
+    ///
+    pub fn start(&self) -> Result<Running, Error> {
+        let runtime = match self.runtime {
+            Some(runtime) if which(runtime.program()).is_some() => runtime,
+            Some(runtime) => return Err(Error::Missing(runtime)),
+            None => Runtime::detect().ok_or(Error::Unavailable)?,
+        };
+
+        let mut args = vec!["run".to_string(), "--detach".to_string()];
+
+        if let Some(ref name) = self.name {
+            args.push("--name".to_string());
+            args.push(name.clone());
+        }
+
+        args.extend(self.args.iter().cloned());
+        args.push(self.image.clone());
+        args.extend(self.command.iter().cloned());
+
+        match Shell::new("sh").run(&runtime.command_line(&args)) {
+            (0, stdout, _) => Ok(Running { runtime, id: stdout.trim().to_string(), owned: true }),
+            (code, _, stderr) => Err(Error::Failed { code, stderr }),
+        }
+    }
+}
+
+/// A running container, removed when dropped if it was started by `Container::start`.
+///
+#[derive(Debug)]
+pub struct Running {
+    runtime: Runtime,
+    id: String,
+    owned: bool,
+}
+
+impl Running {
+    /// Handle to a running container which was not started by `Container::start`, given its
+    /// identifier or name. It is left untouched when the handle is dropped.
+    ///
+    pub fn attach(runtime: Runtime, container: &str) -> Running {
+        Running { runtime, id: container.to_string(), owned: false }
+    }
+
+    /// The identifier of the container.
+    ///
+    pub fn id(&self) -> &str {
+        &self.id[..]
+    }
+
+    /// The runtime managing the container.
+    ///
+    pub fn runtime(&self) -> Runtime {
+        self.runtime
+    }
+
+    /// Follows the logs of the container, from its start, until it exits.
+    ///
+    pub fn logs(&self) -> Lines {
+        Shell::new("sh").stream(&self.runtime.command_line(&["logs", "--follow", &self.id[..]]))
+    }
+
+    /// Runs a command in the container through `sh -c`.
+    ///
+    pub fn exec(&self, cmd: &str) -> (i32, String, String) {
+        Shell::new("sh").run(&self.runtime.command_line(&["exec", &self.id[..], "sh", "-c", cmd]))
+    }
+
+    /// Waits for the container to exit and returns its exit code.
+    ///
+    pub fn wait(&self, timeout: Duration) -> Result<i32, Error> {
+        let shell = Shell::new("sh").timeout(timeout);
+
+        match shell.run(&self.runtime.command_line(&["wait", &self.id[..]])) {
+            (0, stdout, stderr) => {
+                stdout.trim().parse().map_err(|_| Error::Failed { code: 0, stderr })
+            },
+            (124, _, _) => Err(Error::TimedOut(timeout)),
+            (code, _, stderr) => Err(Error::Failed { code, stderr }),
+        }
+    }
+
+    /// Stops the container, giving it the usual grace period to exit.
+    ///
+    pub fn stop(&self) -> Result<(), Error> {
+        match Shell::new("sh").run(&self.runtime.command_line(&["stop", &self.id[..]])) {
+            (0, _, _) => Ok(()),
+            (code, _, stderr) => Err(Error::Failed { code, stderr }),
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
+        let remove = self.runtime.command_line(&["rm", "--force", "--volumes", &self.id[..]]);
+        Shell::new("sh").run(&remove);
+    }
+}
+
+/// Error returned by container operations.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Neither docker nor podman could be found.
+    Unavailable,
+
+    /// The runtime requested with `Container::runtime` could not be found.
+    Missing(Runtime),
+
+    /// The runtime exited with a non-zero code.
+    Failed { code: i32, stderr: String },
+
+    /// The container was still running after the given duration.
+    TimedOut(Duration),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Container operation failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Unavailable => {
+                write!(f, "no container runtime available: install docker or podman")
+            },
+            Error::Missing(runtime) => {
+                write!(f, "container runtime `{}` not found on the PATH", runtime.program())
+            },
+
+            Error::Failed { code, ref stderr } => {
+                write!(f, "container runtime exited with code {}: {}", code, stderr.trim_end())
+            },
+
+            Error::TimedOut(timeout) => write!(f, "container still running after {:?}", timeout),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Reading and writing the crontab of the current user through `crontab -l` and `crontab -`.
+//!
+//! ```rust,no_run
+//! use shells::cron::Crontab;
+//!
+//! let mut crontab = Crontab::load().unwrap();
+//!
+//! if crontab.add("*/5 * * * *", "/usr/local/bin/sync-mirror") {
+//!     crontab.save().unwrap();
+//! }
+//! ```
+//!
+//! Lines are kept in order and rendered back as they were parsed, except for the spacing between
+//! the schedule and the command of jobs, so loading and saving an unmodified crontab is safe.
+//!
+
+use Error;
+use shell::Shell;
+
+/// A line of a crontab.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// A scheduled command; the schedule is either five time fields or a `@` nickname such as
+    /// `@daily`.
+    Job { schedule: String, command: String },
+
+    /// An environment variable assignment, such as `MAILTO=ops@example.com`.
+    Variable { name: String, value: String },
+
+    /// A comment, including its leading `#`.
+    Comment(String),
+
+    /// An empty line.
+    Blank,
+
+    /// A line which could not be parsed, kept verbatim.
+    Other(String),
+}
+
+impl Entry {
+    /// Parses a single crontab line.
+    ///
+    pub fn parse(line: &str) -> Entry {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            return Entry::Blank;
+        }
+
+        if trimmed.starts_with('#') {
+            return Entry::Comment(trimmed.to_string());
+        }
+
+        if let Some(eq) = trimmed.find('=') {
+            let name = trimmed[..eq].trim_end();
+            let is_name = name.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_')
+                .unwrap_or(false) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+            if is_name {
+                return Entry::Variable {
+                    name: name.to_string(),
+                    value: trimmed[eq + 1..].trim_start().to_string(),
+                };
+            }
+        }
+
+        let fields = if trimmed.starts_with('@') { 1 } else { 5 };
+        let mut rest = trimmed;
+        let mut schedule = Vec::with_capacity(fields);
+
+        for _ in 0..fields {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            schedule.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+
+        if rest.is_empty() {
+            Entry::Other(line.to_string())
+        } else {
+            Entry::Job { schedule: schedule.join(" "), command: rest.to_string() }
+        }
+    }
+}
+
+impl ::std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Entry::Job { ref schedule, ref command } => write!(f, "{} {}", schedule, command),
+            Entry::Variable { ref name, ref value } => write!(f, "{}={}", name, value),
+            Entry::Comment(ref comment) => write!(f, "{}", comment),
+            Entry::Blank => Ok(()),
+            Entry::Other(ref line) => write!(f, "{}", line),
+        }
+    }
+}
+
+/// The crontab of the current user.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Crontab {
+    entries: Vec<Entry>,
+}
+
+impl Crontab {
+    /// Loads the crontab of the current user. A user without a crontab gets an empty one.
+    ///
+    pub fn load() -> Result<Crontab, Error> {
+        match crontab("-l", None) {
+            Ok(stdout) => Ok(Crontab::parse(&stdout)),
+            Err(ref e) if e.stderr().contains("no crontab for") => Ok(Crontab::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parses the content of a crontab.
+    ///
+    pub fn parse(text: &str) -> Crontab {
+        Crontab { entries: text.lines().map(Entry::parse).collect() }
+    }
+
+    /// Every entry, in order.
+    ///
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..]
+    }
+
+    /// Iterates over the scheduled jobs as `(schedule, command)` pairs.
+    ///
+    pub fn jobs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().filter_map(|entry| match *entry {
+            Entry::Job { ref schedule, ref command } => Some((&schedule[..], &command[..])),
+            _ => None,
+        })
+    }
+
+    /// Appends a job unless an identical one already exists. Returns whether the crontab changed.
+    ///
+    pub fn add(&mut self, schedule: &str, command: &str) -> bool {
+        let entry = Entry::Job { schedule: schedule.to_string(), command: command.to_string() };
+
+        if self.entries.contains(&entry) {
+            false
+        } else {
+            self.entries.push(entry);
+            true
+        }
+    }
+
+    /// Removes every entry matching the predicate and returns how many were removed.
+    ///
+    pub fn remove_if<F: FnMut(&Entry) -> bool>(&mut self, mut predicate: F) -> usize {
+        let len = self.entries.len();
+        self.entries.retain(|entry| !predicate(entry));
+        len - self.entries.len()
+    }
+
+    /// Removes every job running exactly `command` and returns how many were removed.
+    ///
+    pub fn remove_command(&mut self, command: &str) -> usize {
+        self.remove_if(|entry| match *entry {
+            Entry::Job { command: ref c, .. } => c == command,
+            _ => false,
+        })
+    }
+
+    /// Installs this crontab for the current user, replacing the previous one.
+    ///
+    pub fn save(&self) -> Result<(), Error> {
+        crontab("-", Some(self.to_string())).map(|_| ())
+    }
+}
+
+impl ::std::fmt::Display for Crontab {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs `crontab` with a shell, so that dry runs, policies, mocks and sinks apply to it.
+///
+fn crontab(args: &str, input: Option<String>) -> Result<String, Error> {
+    let shell = match input {
+        Some(input) => Shell::new("sh").stdin(input),
+        None => Shell::new("sh"),
+    };
+
+    shell.try_run(&format!("crontab {}", args))
+}
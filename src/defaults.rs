@@ -0,0 +1,178 @@
+//! Configuration applied to every shell invocation: process-wide default flags, and the shell
+//! configurations scoped to a thread.
+//!
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Mutex;
+
+static DEFAULT_ARGS: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+
+fn key(shell: &str) -> String {
+    Path::new(shell).file_name().map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| shell.to_string())
+}
+
+/// Sets the flags passed before `-c` to every invocation of the given shell, by the macros as
+/// well as by `Shell`, e.g. `-f` for zsh to skip the rc files or `--posix` for bash. The shell
+/// is identified by its file name, so the setting also applies when it is invoked by path.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     shells::set_default_args("bash", &["--posix"]);
+///     assert_eq!(bash!("echo $POSIXLY_CORRECT").1, "y\n");
+///
+///     shells::set_default_args("bash", &[]);
+/// }
+/// ```
+///
+pub fn set_default_args(shell: &str, args: &[&str]) {
+    let mut defaults = DEFAULT_ARGS.lock().unwrap_or_else(|e| e.into_inner());
+    let key = key(shell);
+
+    defaults.retain(|(other, _)| *other != key);
+
+    if !args.is_empty() {
+        defaults.push((key, args.iter().map(|arg| arg.to_string()).collect()));
+    }
+}
+
+/// Returns the default flags of the given shell.
+///
+pub fn default_args(shell: &str) -> Vec<String> {
+    let defaults = DEFAULT_ARGS.lock().unwrap_or_else(|e| e.into_inner());
+    let key = key(shell);
+
+    defaults.iter().find(|(other, _)| *other == key).map(|(_, args)| args.clone())
+        .unwrap_or_default()
+}
+
+/// The interpreter and flags a shell is invoked with, e.g. a bash from a toolchain directory in
+/// strict mode. It applies to the shell of its name when built with `Shell::from`, or within
+/// `with_shell_config`.
+///
+/// ```rust
+/// use shells::{Shell, ShellConfig};
+///
+/// let flags = ["--noprofile", "--norc", "-e", "-u", "-o", "pipefail"];
+/// let strict = ShellConfig::new("bash").args(&flags);
+///
+/// assert_eq!(Shell::from(strict).run("false | true; echo unreachable").0, 1);
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShellConfig {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    strict: bool,
+}
+
+impl ShellConfig {
+    /// Configures the shell of the given name, e.g. `bash` for the `bash!` macros, invoked as is
+    /// without flags until told otherwise. The shell is identified by its file name like with
+    /// `set_default_args`.
+    ///
+    pub fn new(name: &str) -> ShellConfig {
+        ShellConfig { name: key(name), program: name.to_string(), args: Vec::new(), strict: false }
+    }
+
+    /// Invokes the given interpreter instead, a path or a program looked up on the PATH.
+    ///
+    pub fn program(mut self, program: &str) -> ShellConfig {
+        self.program = program.to_string();
+        self
+    }
+
+    /// Adds a flag passed to the interpreter before `-c`, after the defaults set with
+    /// `set_default_args`.
+    ///
+    pub fn arg(mut self, arg: &str) -> ShellConfig {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Adds several flags, see `arg`.
+    ///
+    pub fn args(mut self, args: &[&str]) -> ShellConfig {
+        self.args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Runs the commands in the strict mode of the shell, see `Shell::strict`.
+    ///
+    pub fn strict(mut self) -> ShellConfig {
+        self.strict = true;
+        self
+    }
+
+    /// The name of the configured shell.
+    ///
+    pub fn name(&self) -> &str {
+        &self.name[..]
+    }
+
+    /// The interpreter invoked.
+    ///
+    pub fn program_path(&self) -> &str {
+        &self.program[..]
+    }
+
+    /// The flags passed to the interpreter.
+    ///
+    pub fn flags(&self) -> &[String] {
+        &self.args[..]
+    }
+
+    /// Whether the commands run in strict mode.
+    ///
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+}
+
+thread_local! {
+    static SCOPED: RefCell<Vec<ShellConfig>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with the shell configured on the current thread: the macros as well as `Shell::new`
+/// invoke the shell of the configuration's name as it says. Within nested calls the innermost
+/// configuration of a shell applies. Commands run on other threads are not affected.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use shells::ShellConfig;
+///
+/// fn main() {
+///     let strict = ShellConfig::new("bash").args(&["-e", "-o", "pipefail"]);
+///
+///     assert_eq!(shells::with_shell_config(strict, || bash!("false | true").0), 1);
+///     assert_eq!(bash!("false | true").0, 0);
+/// }
+/// ```
+///
+pub fn with_shell_config<R, F: FnOnce() -> R>(config: ShellConfig, f: F) -> R {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SCOPED.with(|scoped| scoped.borrow_mut().pop());
+        }
+    }
+
+    SCOPED.with(|scoped| scoped.borrow_mut().push(config));
+    let _reset = Reset;
+    f()
+}
+
+/// The configuration of the given shell on the current thread, see `with_shell_config`.
+///
+pub(crate) fn scoped_config(shell: &str) -> Option<ShellConfig> {
+    let key = key(shell);
+    SCOPED.with(|scoped| scoped.borrow().iter().rev().find(|config| config.name == key).cloned())
+}
@@ -0,0 +1,259 @@
+//! Line-level diffs of command outputs, for drift detection scripts.
+//!
+//! ```rust
+//! use shells::diff;
+//!
+//! let diff = diff::lines("a\nb\nc\n", "a\nc\nd\n");
+//!
+//! assert!(!diff.is_empty());
+//! assert_eq!(diff.to_string(), " a\n-b\n c\n+d\n");
+//! ```
+//!
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use shell::Shell;
+
+/// A line of a diff.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Sequence of changes turning an old text into a new one, computed with Myers' algorithm. Its
+/// `Display` implementation prefixes each line with ` `, `+` or `-`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    /// Every line of both texts, in order.
+    ///
+    pub fn changes(&self) -> &[Change] {
+        &self.changes[..]
+    }
+
+    /// Whether both texts were identical.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.changes.iter().all(|change| matches!(*change, Change::Same(_)))
+    }
+
+    /// Lines only present in the new text.
+    ///
+    pub fn added(&self) -> impl Iterator<Item = &str> {
+        self.changes.iter().filter_map(|change| match *change {
+            Change::Added(ref line) => Some(&line[..]),
+            _ => None,
+        })
+    }
+
+    /// Lines only present in the old text.
+    ///
+    pub fn removed(&self) -> impl Iterator<Item = &str> {
+        self.changes.iter().filter_map(|change| match *change {
+            Change::Removed(ref line) => Some(&line[..]),
+            _ => None,
+        })
+    }
+
+    /// Renders the diff in the unified format of `diff -u`, with `context` unchanged lines around
+    /// each hunk. Returns an empty string when both texts are identical.
+    ///
+    /// ```rust
+    /// use shells::diff;
+    ///
+    /// let diff = diff::lines("a\nb\nc\n", "a\nB\nc\n");
+    ///
+    /// assert_eq!(diff.unified("old", "new", 1),
+    ///            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+    /// ```
+    ///
+    pub fn unified(&self, old_name: &str, new_name: &str, context: usize) -> String {
+        let edits: Vec<usize> = self.changes.iter().enumerate()
+            .filter(|(_, change)| !matches!(**change, Change::Same(_)))
+            .map(|(i, _)| i)
+            .collect();
+
+        if edits.is_empty() {
+            return String::new();
+        }
+
+        let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+        for &i in &edits {
+            let start = i.saturating_sub(context);
+            let end = (i + context + 1).min(self.changes.len());
+
+            match hunks.last_mut() {
+                Some(last) if start <= last.1 => last.1 = end,
+                _ => hunks.push((start, end)),
+            }
+        }
+
+        let mut unified = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+        for (start, end) in hunks {
+            let count = |changes: &[Change], old: bool| {
+                changes.iter().filter(|change| match **change {
+                    Change::Same(_) => true,
+                    Change::Removed(_) => old,
+                    Change::Added(_) => !old,
+                }).count()
+            };
+
+            let (before, hunk) = (&self.changes[..start], &self.changes[start..end]);
+            let (old_before, new_before) = (count(before, true), count(before, false));
+            let (old_len, new_len) = (count(hunk, true), count(hunk, false));
+            let range = |before: usize, len: usize| match len {
+                0 => format!("{},0", before),
+                1 => format!("{}", before + 1),
+                _ => format!("{},{}", before + 1, len),
+            };
+
+            unified.push_str(&format!("@@ -{} +{} @@\n", range(old_before, old_len),
+                                      range(new_before, new_len)));
+
+            for change in &self.changes[start..end] {
+                match *change {
+                    Change::Same(ref line) => unified.push_str(&format!(" {}\n", line)),
+                    Change::Added(ref line) => unified.push_str(&format!("+{}\n", line)),
+                    Change::Removed(ref line) => unified.push_str(&format!("-{}\n", line)),
+                }
+            }
+        }
+
+        unified
+    }
+}
+
+impl ::std::fmt::Display for Diff {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        for change in &self.changes {
+            match *change {
+                Change::Same(ref line) => writeln!(f, " {}", line)?,
+                Change::Added(ref line) => writeln!(f, "+{}", line)?,
+                Change::Removed(ref line) => writeln!(f, "-{}", line)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the line-level diff between two texts.
+///
+pub fn lines(old: &str, new: &str) -> Diff {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    Diff { changes: myers(&old, &new) }
+}
+
+/// Runs the command twice in a row and returns the diff between the stdout of both runs.
+///
+pub fn run_twice(shell: &Shell, cmd: &str) -> Diff {
+    let (_, first, _) = shell.run(cmd);
+    let (_, second, _) = shell.run(cmd);
+
+    lines(&first, &second)
+}
+
+/// Compares the stdout of the command with the baseline stored at `path`. When there is no
+/// baseline yet, the output is stored as the baseline and the diff is empty. The baseline is
+/// never updated otherwise, delete the file to reset it.
+///
+pub fn against_baseline<P: AsRef<Path>>(shell: &Shell, cmd: &str, path: P) -> io::Result<Diff> {
+    let (_, stdout, _) = shell.run(cmd);
+
+    match fs::read_to_string(path.as_ref()) {
+        Ok(baseline) => Ok(lines(&baseline, &stdout)),
+
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::write(path.as_ref(), &stdout)?;
+            Ok(lines(&stdout, &stdout))
+        },
+
+        Err(e) => Err(e),
+    }
+}
+
+fn myers(a: &[&str], b: &[&str]) -> Vec<Change> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let index = |k: isize| (k + offset) as usize;
+
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+
+        let mut k = -d;
+
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index(k)] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+
+            k += 2;
+        }
+    }
+
+    let mut changes = Vec::with_capacity(max as usize);
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+
+        let prev_k = match k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            changes.push(Change::Same(a[x as usize - 1].to_string()));
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                changes.push(Change::Added(b[y as usize - 1].to_string()));
+                y -= 1;
+            } else {
+                changes.push(Change::Removed(a[x as usize - 1].to_string()));
+                x -= 1;
+            }
+        }
+    }
+
+    changes.reverse();
+    changes
+}
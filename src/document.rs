@@ -0,0 +1,109 @@
+//! Format-independent tree of the configuration files, shared by the loaders of inventories,
+//! command registries and project settings.
+//!
+
+use std::collections::BTreeMap;
+
+/// Parsed document: strings, numbers and booleans are all scalars. The project settings use no
+/// array.
+///
+#[cfg_attr(not(any(feature = "inventory-toml", feature = "inventory-json",
+                   feature = "commands-toml", feature = "commands-yaml")), allow(dead_code))]
+pub(crate) enum Node {
+    Scalar(String),
+    Array(Vec<Node>),
+    Table(Vec<(String, Node)>),
+}
+
+/// A document which does not have the expected structure, with the path of the offending value.
+///
+pub(crate) struct Invalid(pub(crate) String);
+
+impl Node {
+    #[cfg(any(feature = "inventory-toml", feature = "commands-toml", feature = "project-config"))]
+    pub(crate) fn from_toml(value: ::toml::Value) -> Node {
+        match value {
+            ::toml::Value::String(s) => Node::Scalar(s),
+            ::toml::Value::Array(values) => {
+                Node::Array(values.into_iter().map(Node::from_toml).collect())
+            },
+            ::toml::Value::Table(table) => {
+                Node::Table(table.into_iter().map(|(k, v)| (k, Node::from_toml(v))).collect())
+            },
+
+            value => Node::Scalar(value.to_string()),
+        }
+    }
+
+    #[cfg(feature = "inventory-json")]
+    pub(crate) fn from_json(value: ::serde_json::Value) -> Node {
+        match value {
+            ::serde_json::Value::String(s) => Node::Scalar(s),
+            ::serde_json::Value::Array(values) => {
+                Node::Array(values.into_iter().map(Node::from_json).collect())
+            },
+
+            ::serde_json::Value::Object(map) => {
+                Node::Table(map.into_iter().map(|(k, v)| (k, Node::from_json(v))).collect())
+            },
+
+            value => Node::Scalar(value.to_string()),
+        }
+    }
+
+    /// Converts a YAML value; keys which are not scalars are dropped.
+    ///
+    #[cfg(feature = "commands-yaml")]
+    pub(crate) fn from_yaml(value: ::yaml_rust2::Yaml) -> Node {
+        use yaml_rust2::Yaml;
+
+        match value {
+            Yaml::String(s) | Yaml::Real(s) => Node::Scalar(s),
+            Yaml::Integer(i) => Node::Scalar(i.to_string()),
+            Yaml::Boolean(b) => Node::Scalar(b.to_string()),
+            Yaml::Array(values) => Node::Array(values.into_iter().map(Node::from_yaml).collect()),
+            Yaml::Hash(hash) => {
+                Node::Table(hash.into_iter().filter_map(|(k, v)| match Node::from_yaml(k) {
+                    Node::Scalar(k) => Some((k, Node::from_yaml(v))),
+                    _ => None,
+                }).collect())
+            },
+
+            Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Node::Scalar(String::new()),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Node> {
+        match *self {
+            Node::Table(ref entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn entries(&self, context: &str) -> Result<&[(String, Node)], Invalid> {
+        match *self {
+            Node::Table(ref entries) => Ok(&entries[..]),
+            _ => Err(Invalid(format!("{}: expected a table", context))),
+        }
+    }
+
+    pub(crate) fn scalar(&self, context: &str) -> Result<String, Invalid> {
+        match *self {
+            Node::Scalar(ref value) => Ok(value.clone()),
+            _ => Err(Invalid(format!("{}: expected a string or a number", context))),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "inventory-toml", feature = "inventory-json",
+                       feature = "commands-toml", feature = "commands-yaml")), allow(dead_code))]
+    pub(crate) fn scalars(&self, context: &str) -> Result<Vec<String>, Invalid> {
+        match *self {
+            Node::Array(ref values) => values.iter().map(|value| value.scalar(context)).collect(),
+            _ => Err(Invalid(format!("{}: expected an array", context))),
+        }
+    }
+
+    pub(crate) fn vars(&self, context: &str) -> Result<BTreeMap<String, String>, Invalid> {
+        self.entries(context)?.iter().map(|(k, v)| Ok((k.clone(), v.scalar(context)?))).collect()
+    }
+}
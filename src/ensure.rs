@@ -0,0 +1,190 @@
+//! Declarative helpers converging the system to a desired state. Each helper inspects the current
+//! state first and only mutates it when needed, reporting whether anything changed, so that
+//! provisioning programs can be run repeatedly.
+//!
+//! ```rust,no_run
+//! use shells::ensure;
+//!
+//! let changes = [
+//!     ensure::line_in_file("/etc/hosts", "10.0.0.2 db.internal").unwrap(),
+//!     ensure::symlink("/opt/app/releases/42", "/opt/app/current").unwrap(),
+//! ];
+//!
+//! if changes.iter().any(|state| state.is_changed()) {
+//!     println!("configuration updated");
+//! }
+//! ```
+//!
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use shell::Shell;
+
+/// Whether a helper had to modify the system.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    Changed,
+    Unchanged,
+}
+
+impl State {
+    /// Whether the system was modified.
+    ///
+    pub fn is_changed(&self) -> bool {
+        *self == State::Changed
+    }
+}
+
+/// Makes sure `path` contains `line`, appending it otherwise. The file is created when missing.
+///
+pub fn line_in_file<P: AsRef<Path>>(path: P, line: &str) -> io::Result<State> {
+    let path = path.as_ref();
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    if content.lines().any(|existing| existing == line) {
+        return Ok(State::Unchanged);
+    }
+
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        file.write_all(b"\n")?;
+    }
+
+    file.write_all(line.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(State::Changed)
+}
+
+/// Makes sure `path` does not contain `line`, removing every occurrence otherwise.
+///
+pub fn line_absent<P: AsRef<Path>>(path: P, line: &str) -> io::Result<State> {
+    let path = path.as_ref();
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::Unchanged),
+        Err(e) => return Err(e),
+    };
+
+    if !content.lines().any(|existing| existing == line) {
+        return Ok(State::Unchanged);
+    }
+
+    let kept: String = content.split_inclusive('\n')
+        .filter(|existing| existing.trim_end_matches('\n').trim_end_matches('\r') != line)
+        .collect();
+
+    fs::write(path, kept)?;
+    Ok(State::Changed)
+}
+
+/// Makes sure `link` is a symbolic link pointing to `target`. An existing file or link at `link`
+/// is replaced; a directory is not, and an error of kind `AlreadyExists` is returned instead.
+///
+pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(target: P, link: Q) -> io::Result<State> {
+    let (target, link) = (target.as_ref(), link.as_ref());
+
+    match fs::symlink_metadata(link) {
+        Ok(metadata) => {
+            if metadata.file_type().is_symlink() && fs::read_link(link)? == target {
+                return Ok(State::Unchanged);
+            }
+
+            if metadata.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                          format!("{} is a directory", link.display())));
+            }
+
+            fs::remove_file(link)?;
+        },
+
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+
+    create_symlink(target, link)?;
+    Ok(State::Changed)
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    if target.is_dir() {
+        ::std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        ::std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Makes sure `path` is a directory, creating it and its parents otherwise.
+///
+pub fn dir<P: AsRef<Path>>(path: P) -> io::Result<State> {
+    if path.as_ref().is_dir() {
+        return Ok(State::Unchanged);
+    }
+
+    fs::create_dir_all(path)?;
+    Ok(State::Changed)
+}
+
+/// Makes sure nothing exists at `path`, removing the file, link or directory tree otherwise.
+///
+pub fn absent<P: AsRef<Path>>(path: P) -> io::Result<State> {
+    let path = path.as_ref();
+
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::Unchanged),
+        Err(e) => return Err(e),
+    }
+
+    Ok(State::Changed)
+}
+
+/// Makes sure the package is installed, see the `pkg` module.
+///
+pub fn package(name: &str) -> Result<State, ::pkg::Error> {
+    let manager = ::pkg::Manager::detect().ok_or(::pkg::Error::NoManager)?;
+
+    if manager.is_installed(name)? {
+        return Ok(State::Unchanged);
+    }
+
+    manager.install(&[name])?;
+    Ok(State::Changed)
+}
+
+/// Generic form of the helpers: runs `check` and, only when it fails, `apply`. The error holds the
+/// output of `apply` when it fails too.
+///
+/// ```rust
+/// use shells::Shell;
+/// use shells::ensure::{self, State};
+///
+/// let shell = Shell::new("sh");
+/// let state = ensure::command(&shell, "true", "exit 1").unwrap();
+///
+/// assert_eq!(state, State::Unchanged);
+/// ```
+///
+pub fn command(shell: &Shell, check: &str, apply: &str) -> Result<State, ::Error> {
+    if shell.run(check).0 == 0 {
+        return Ok(State::Unchanged);
+    }
+
+    shell.try_run(apply).map(|_| State::Changed)
+}
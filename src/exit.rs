@@ -0,0 +1,37 @@
+//! Exit status passthrough for transparent wrapper binaries.
+//!
+
+use std::io::Write;
+
+use output::ExitStatus;
+
+/// Terminates the current process with the exact status of a child: its exit code, or on Unix
+/// the signal which killed it, re-raised on the current process rather than mapped to a code.
+/// Takes the raw status as well as the `ExitStatus` of an `Output`.
+///
+/// ```rust,no_run
+/// use shells::Shell;
+///
+/// let status = Shell::new("sh").status("exec make \"$@\"").unwrap();
+/// shells::propagate_exit(status);
+/// ```
+///
+pub fn propagate_exit<S: Into<ExitStatus>>(status: S) -> ! {
+    let _ = ::std::io::stdout().flush();
+    let _ = ::std::io::stderr().flush();
+
+    match status.into() {
+        ExitStatus::Exited(code) => ::std::process::exit(code),
+
+        ExitStatus::Signaled(signal) => {
+            #[cfg(unix)]
+            unsafe {
+                ::sys::signal(signal, ::sys::SIG_DFL);
+                ::sys::raise(signal);
+            }
+
+            // Only reached for signals which do not terminate the process by default.
+            ::std::process::exit(128 + signal)
+        },
+    }
+}
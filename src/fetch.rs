@@ -0,0 +1,210 @@
+//! Downloads through curl or wget, whichever is installed, with checksum verification.
+//!
+//! The file is first downloaded next to its destination with a `.part` suffix: an interrupted
+//! download is resumed by the next attempt, and the destination only ever holds a complete,
+//! verified file.
+//!
+//! ```rust,no_run
+//! let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+//!
+//! shells::fetch("https://example.com/tool.tar.gz", "/tmp/tool.tar.gz")
+//!     .sha256(hash)
+//!     .run()
+//!     .unwrap();
+//! ```
+//!
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use shell::Shell;
+use which::which;
+
+/// Download of a URL to a file, see `fetch`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fetch {
+    url: String,
+    dest: PathBuf,
+    sha256: Option<String>,
+    resume: bool,
+}
+
+/// Prepares the download of `url` to `dest`, executed by `Fetch::run`.
+///
+pub fn fetch<P: Into<PathBuf>>(url: &str, dest: P) -> Fetch {
+    Fetch { url: url.to_string(), dest: dest.into(), sha256: None, resume: true }
+}
+
+impl Fetch {
+    /// Requires the SHA-256 digest of the file to be `hex`. When the destination already exists
+    /// with this digest, nothing is downloaded.
+    ///
+    pub fn sha256(mut self, hex: &str) -> Fetch {
+        self.sha256 = Some(hex.trim().to_lowercase());
+        self
+    }
+
+    /// Always restarts the download from scratch instead of resuming a partial one.
+    ///
+    pub fn no_resume(mut self) -> Fetch {
+        self.resume = false;
+        self
+    }
+
+    /// The destination of the download.
+    ///
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    /// Downloads the file, returning once it is complete and verified. In a dry run, see
+    /// `set_trace`, the download is only printed.
+    ///
+    /// Without a digest, an existing destination is kept and nothing is downloaded: remove it to
+    /// download the file again.
+    ///
+    pub fn run(&self) -> Result<(), Error> {
+        match self.sha256 {
+            Some(ref expected) if self.dest.is_file()
+                && ::sha256::file(&self.dest)? == *expected =>
+            {
+                return Ok(());
+            },
+
+            None if self.dest.is_file() => return Ok(()),
+            _ => (),
+        }
+
+        let part = self.part();
+
+        if !self.resume {
+            match fs::remove_file(&part) {
+                Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(Error::Io(e.to_string()))
+                },
+                _ => (),
+            }
+        }
+
+        let part_arg = part.to_string_lossy().into_owned();
+
+        let argv = if which("curl").is_some() {
+            let mut argv = vec!["curl", "--fail", "--silent", "--show-error", "--location"];
+            argv.extend(["--retry", "3"]);
+
+            if self.resume && part.is_file() {
+                argv.extend(["--continue-at", "-"]);
+            }
+
+            argv.extend(["--output", &part_arg[..], &self.url[..]]);
+            argv
+        } else if which("wget").is_some() {
+            let mut argv = vec!["wget", "--quiet", "--tries=3"];
+
+            if self.resume {
+                argv.push("--continue");
+            }
+
+            argv.extend(["--output-document", &part_arg[..], &self.url[..]]);
+            argv
+        } else {
+            return Err(Error::NoTool);
+        };
+
+        let (code, _, stderr) = run(&argv);
+
+        if code != 0 {
+            return Err(Error::Failed { url: self.url.clone(), code, stderr });
+        }
+
+        // A dry run printed the download without making it.
+        if ::trace::trace() == ::Trace::DryRun {
+            return Ok(());
+        }
+
+        if let Some(ref expected) = self.sha256 {
+            let actual = ::sha256::file(&part)?;
+
+            if actual != *expected {
+                let _ = fs::remove_file(&part);
+
+                return Err(Error::ChecksumMismatch {
+                    url: self.url.clone(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        fs::rename(&part, &self.dest)?;
+        Ok(())
+    }
+
+    fn part(&self) -> PathBuf {
+        let mut name = self.dest.file_name().map(OsString::from).unwrap_or_default();
+        name.push(".part");
+        self.dest.with_file_name(name)
+    }
+}
+
+/// Runs the program with `sh`, or `cmd` on Windows, so that dry runs, policies, mocks and sinks
+/// apply to it.
+///
+fn run(argv: &[&str]) -> (i32, String, String) {
+    let shell = if cfg!(windows) { "cmd" } else { "sh" };
+    let line: Vec<String> = argv.iter()
+        .map(|arg| ::quote::for_shell(shell, arg).into_owned())
+        .collect();
+
+    Shell::new(shell).run(&line.join(" "))
+}
+
+/// Error returned when a download fails.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Neither curl nor wget is installed.
+    NoTool,
+
+    /// The download tool exited with a non-zero code.
+    Failed { url: String, code: i32, stderr: String },
+
+    /// The downloaded file does not have the expected digest; it has been deleted.
+    ChecksumMismatch { url: String, expected: String, actual: String },
+
+    /// The file could not be read or moved to its destination.
+    Io(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Download failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::NoTool => write!(f, "neither curl nor wget is installed"),
+
+            Error::Failed { ref url, code, ref stderr } => {
+                write!(f, "download of {} failed with code {}: {}", url, code, stderr.trim_end())
+            },
+
+            Error::ChecksumMismatch { ref url, ref expected, ref actual } => {
+                write!(f, "checksum mismatch for {}: expected sha256 {}, got {}", url, expected,
+                       actual)
+            },
+
+            Error::Io(ref reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e.to_string())
+    }
+}
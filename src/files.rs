@@ -0,0 +1,435 @@
+//! Generation and installation of files: template rendering and atomic writes.
+//!
+//! Files are written to a temporary file in the destination directory which is then renamed over
+//! the destination, so that readers never observe a partially written file. Writing content
+//! identical to the current one leaves the file untouched and reports `State::Unchanged`. A
+//! destination which is a symbolic link is resolved first: the file it points to is replaced, and
+//! the link is kept.
+//!
+//! Like the `--diff` and `--check` options of configuration management tools, pending changes
+//! can be reviewed first: see `Install::show_diff`, `Install::check` and `Install::confirm`.
+//!
+//! ```rust,no_run
+//! use shells::files;
+//!
+//! let template = "server {\n    listen {{ port }};\n    root {{root}};\n}\n";
+//! let vars = [("port", "8080"), ("root", "/srv/www")];
+//!
+//! files::write("/etc/nginx/conf.d/site.conf", files::render(template, &vars).unwrap())
+//!     .mode(0o644)
+//!     .apply()
+//!     .unwrap();
+//! ```
+//!
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ensure::State;
+
+/// Replaces every `{{ name }}` placeholder of the template by the value of the variable `name`.
+/// Spaces around the name are optional.
+///
+/// ```rust
+/// use shells::files;
+///
+/// let rendered = files::render("Hello {{ who }}!", &[("who", "world")]).unwrap();
+///
+/// assert_eq!(rendered, "Hello world!");
+/// ```
+///
+pub fn render<K: AsRef<str>, V: AsRef<str>>(template: &str, vars: &[(K, V)])
+    -> Result<String, Error>
+{
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+
+        let end = rest[start..].find("}}").ok_or_else(|| {
+            Error::Unterminated(template.len() - rest.len() + start)
+        })?;
+
+        let name = rest[start + 2..start + end].trim();
+
+        match vars.iter().find(|(key, _)| key.as_ref() == name) {
+            Some((_, value)) => rendered.push_str(value.as_ref()),
+            None => return Err(Error::Undefined(name.to_string())),
+        }
+
+        rest = &rest[start + end + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Renders the template and writes the result to `path`, see `render` and `write`.
+///
+pub fn render_to<P, K, V>(path: P, template: &str, vars: &[(K, V)]) -> Result<State, Error>
+    where P: Into<PathBuf>, K: AsRef<str>, V: AsRef<str>
+{
+    write(path, render(template, vars)?).apply()
+}
+
+/// Prepares the atomic replacement of the file at `path` by `content`, executed by
+/// `Install::apply`.
+///
+pub fn write<P: Into<PathBuf>, C: Into<Vec<u8>>>(path: P, content: C) -> Install {
+    Install {
+        dest: path.into(),
+        source: Source::Content(content.into()),
+        mode: None,
+        backup: None,
+        review: Review::default(),
+    }
+}
+
+/// Prepares the atomic replacement of `dest` by a copy of `src`, executed by `Install::apply`:
+/// an atomic equivalent of `install -m MODE -b SRC DEST`.
+///
+/// ```rust,no_run
+/// use shells::files;
+///
+/// files::install_file("build/app.service", "/etc/systemd/system/app.service")
+///     .mode(0o644)
+///     .backup(".bak")
+///     .apply()
+///     .unwrap();
+/// ```
+///
+pub fn install_file<P: Into<PathBuf>, Q: Into<PathBuf>>(src: P, dest: Q) -> Install {
+    Install {
+        dest: dest.into(),
+        source: Source::File(src.into()),
+        mode: None,
+        backup: None,
+        review: Review::default(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Content(Vec<u8>),
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Review {
+    show_diff: bool,
+    check: bool,
+    confirm: bool,
+}
+
+/// State of a file before and after a pending change.
+///
+struct Plan<'a> {
+    content: Cow<'a, [u8]>,
+    current: Option<Vec<u8>>,
+    current_mode: Option<u32>,
+    mode: u32,
+}
+
+impl<'a> Plan<'a> {
+    fn is_noop(&self) -> bool {
+        self.current.as_deref() == Some(&*self.content) && self.current_mode == Some(self.mode)
+    }
+}
+
+/// A pending change of a file.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Install {
+    dest: PathBuf,
+    source: Source,
+    mode: Option<u32>,
+    backup: Option<String>,
+    review: Review,
+}
+
+impl Install {
+    /// Sets the permissions of the file (Unix only, ignored elsewhere). By default an existing
+    /// file keeps its permissions and a new one gets those of the source file for
+    /// `install_file`, `0o644` otherwise.
+    ///
+    pub fn mode(mut self, mode: u32) -> Install {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Before replacing an existing file, keeps a copy of it next to it, named after it with the
+    /// given suffix appended. A previous backup is overwritten.
+    ///
+    pub fn backup(mut self, suffix: &str) -> Install {
+        self.backup = Some(suffix.to_string());
+        self
+    }
+
+    /// Prints the unified diff of the pending change to stdout before applying it.
+    ///
+    pub fn show_diff(mut self) -> Install {
+        self.review.show_diff = true;
+        self
+    }
+
+    /// Dry run: `apply` reports whether the file would change, without modifying it. Combine
+    /// with `show_diff` to review the pending change.
+    ///
+    pub fn check(mut self) -> Install {
+        self.review.check = true;
+        self
+    }
+
+    /// Prints the unified diff of the pending change and asks for confirmation on stdin before
+    /// applying it; when declined, `apply` returns `Error::Declined`.
+    ///
+    pub fn confirm(mut self) -> Install {
+        self.review.confirm = true;
+        self
+    }
+
+    /// The unified diff of the pending change, empty when there is none. Permission changes are
+    /// reported on a line of their own.
+    ///
+    pub fn diff(&self) -> Result<String, Error> {
+        self.plan().map(|plan| self.render_diff(&plan))
+    }
+
+    /// The file modified.
+    ///
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    /// Applies the change, unless the file already has the expected content and permissions.
+    ///
+    /// ```rust
+    /// # #[cfg(unix)] {
+    /// use shells::files;
+    ///
+    /// let dir = ::std::env::temp_dir().join(format!("shells-files-{}", ::std::process::id()));
+    /// ::std::fs::create_dir_all(&dir).unwrap();
+    /// ::std::fs::write(dir.join("app.conf"), "old\n").unwrap();
+    /// ::std::os::unix::fs::symlink(dir.join("app.conf"), dir.join("current.conf")).unwrap();
+    ///
+    /// files::write(dir.join("current.conf"), "new\n").apply().unwrap();
+    ///
+    /// let link = ::std::fs::symlink_metadata(dir.join("current.conf")).unwrap();
+    ///
+    /// assert!(link.file_type().is_symlink());
+    /// assert_eq!(::std::fs::read_to_string(dir.join("app.conf")).unwrap(), "new\n");
+    /// ::std::fs::remove_dir_all(&dir).unwrap();
+    /// # }
+    /// ```
+    ///
+    pub fn apply(&self) -> Result<State, Error> {
+        let plan = self.plan()?;
+
+        if plan.is_noop() {
+            return Ok(State::Unchanged);
+        }
+
+        if self.review.show_diff || self.review.confirm {
+            print!("{}", self.render_diff(&plan));
+            let _ = io::stdout().flush();
+        }
+
+        if self.review.check {
+            return Ok(State::Changed);
+        }
+
+        let question = format!("Apply changes to {}? [y/N] ", self.dest.display());
+
+        if self.review.confirm && !ask(&question) {
+            return Err(Error::Declined(self.dest.clone()));
+        }
+
+        if let (Some(suffix), Some(_)) = (self.backup.as_ref(), plan.current.as_ref()) {
+            let mut backup = self.dest.clone().into_os_string();
+            backup.push(suffix);
+            fs::copy(&self.dest, &backup).map_err(|e| self.io(e))?;
+        }
+
+        write_atomic(&self.dest, &plan.content, plan.mode).map_err(|e| self.io(e))?;
+        Ok(State::Changed)
+    }
+
+    fn plan(&self) -> Result<Plan<'_>, Error> {
+        let content = match self.source {
+            Source::Content(ref content) => Cow::Borrowed(&content[..]),
+
+            Source::File(ref src) => {
+                let content = fs::read(src)
+                    .map_err(|e| Error::Io(format!("{}: {}", src.display(), e)))?;
+                Cow::Owned(content)
+            },
+        };
+
+        let current = match fs::read(&self.dest) {
+            Ok(current) => Some(current),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(self.io(e)),
+        };
+
+        let current_mode = match current {
+            Some(_) => Some(mode_of(&self.dest).map_err(|e| self.io(e))?),
+            None => None,
+        };
+
+        let source_mode = match self.source {
+            Source::File(ref src) if current_mode.is_none() && self.mode.is_none() => {
+                mode_of(src).ok()
+            },
+            _ => None,
+        };
+
+        // Permissions are ignored where they do not exist, so that they never make a change.
+        let mode = match cfg!(unix) {
+            true => self.mode.or(current_mode).or(source_mode).unwrap_or(0o644),
+            false => current_mode.unwrap_or(0o644),
+        };
+
+        Ok(Plan { content, current, current_mode, mode })
+    }
+
+    fn render_diff(&self, plan: &Plan) -> String {
+        if plan.is_noop() {
+            return String::new();
+        }
+
+        let old = plan.current.as_ref()
+            .map(|current| String::from_utf8_lossy(current))
+            .unwrap_or_default();
+        let new = String::from_utf8_lossy(&plan.content);
+        let name = self.dest.display().to_string();
+
+        let mut rendered = match plan.current {
+            Some(_) => ::diff::lines(&old, &new).unified(&name, &name, 3),
+            None => ::diff::lines(&old, &new).unified("/dev/null", &name, 3),
+        };
+
+        match plan.current_mode {
+            Some(current) if current != plan.mode => {
+                rendered.push_str(&format!("mode {:04o} -> {:04o} {}\n", current, plan.mode, name));
+            },
+
+            _ => (),
+        }
+
+        rendered
+    }
+
+    fn io(&self, e: io::Error) -> Error {
+        Error::Io(format!("{}: {}", self.dest.display(), e))
+    }
+}
+
+/// Error returned by the file helpers.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The template uses a variable which was not given.
+    Undefined(String),
+
+    /// The placeholder starting at the given byte offset of the template is not closed.
+    Unterminated(usize),
+
+    /// The file could not be read or written.
+    Io(String),
+
+    /// The change of the file was not confirmed, see `Install::confirm`.
+    Declined(PathBuf),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "File operation failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Undefined(ref name) => write!(f, "undefined template variable `{}`", name),
+            Error::Unterminated(offset) => {
+                write!(f, "unterminated placeholder at offset {}", offset)
+            },
+            Error::Io(ref reason) => write!(f, "{}", reason),
+            Error::Declined(ref path) => write!(f, "change of {} declined", path.display()),
+        }
+    }
+}
+
+/// Prints the question to stderr and reads the answer from stdin.
+///
+pub(crate) fn ask(question: &str) -> bool {
+    eprint!("{}", question);
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(path: &Path) -> io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path)?.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_: &Path) -> io::Result<u32> {
+    Ok(0o644)
+}
+
+/// Writes `content` to a temporary file next to `path`, then renames it over `path`. A symbolic
+/// link is resolved first, so that its target is replaced rather than the link itself.
+///
+fn write_atomic(path: &Path, content: &[u8], mode: u32) -> io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = match fs::canonicalize(path) {
+        Ok(resolved) => Cow::Owned(resolved),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Cow::Borrowed(path),
+        Err(e) => return Err(e),
+    };
+
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+
+    let mut tmp_name = ::std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    let count = COUNTER.fetch_add(1, Ordering::SeqCst);
+    tmp_name.push(format!(".{}.{}.tmp", ::std::process::id(), count));
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        file.write_all(content)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        file.sync_all()?;
+        fs::rename(&tmp, &path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
@@ -0,0 +1,96 @@
+//! Execution of a command across many hosts in parallel.
+//!
+//! ```rust,no_run
+//! use shells::fleet::Fleet;
+//! use shells::ssh::Remote;
+//!
+//! let fleet = Fleet::new(vec![Remote::new("web1"), Remote::new("web2"), Remote::new("db1")])
+//!     .concurrency(2);
+//!
+//! let report = fleet.run("uptime");
+//!
+//! for result in &report.results {
+//!     print!("{}: {}", result.subject, result.stdout);
+//! }
+//!
+//! if !report.is_success() {
+//!     eprintln!("{}", report);
+//! }
+//! ```
+//!
+
+use report::{self, Outcome};
+use ssh::Remote;
+
+/// A set of hosts commands are run on together.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fleet {
+    remotes: Vec<Remote>,
+    vars: Vec<Vec<(String, String)>>,
+    concurrency: usize,
+}
+
+impl Fleet {
+    /// Creates a fleet running commands on up to 16 hosts at once.
+    ///
+    pub fn new(remotes: Vec<Remote>) -> Fleet {
+        let vars = vec![Vec::new(); remotes.len()];
+        Fleet { remotes, vars, concurrency: 16 }
+    }
+
+    /// Creates a fleet of hosts with the variables `run_template` interpolates for each of them.
+    ///
+    pub fn with_vars(hosts: Vec<(Remote, Vec<(String, String)>)>) -> Fleet {
+        let (remotes, vars) = hosts.into_iter().unzip();
+        Fleet { remotes, vars, concurrency: 16 }
+    }
+
+    /// Maximum number of hosts a command runs on at the same time.
+    ///
+    pub fn concurrency(mut self, concurrency: usize) -> Fleet {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The hosts of the fleet.
+    ///
+    pub fn remotes(&self) -> &[Remote] {
+        &self.remotes[..]
+    }
+
+    /// Runs the command on every host and waits for all of them.
+    ///
+    pub fn run(&self, cmd: &str) -> Report {
+        self.run_each(|_| Ok(cmd.to_string()))
+    }
+
+    /// Runs the command rendered for each host from `template` with the variables of the host,
+    /// see `files::render`. Hosts for which the template cannot be rendered fail with the code
+    /// 126 without running anything.
+    ///
+    pub fn run_template(&self, template: &str) -> Report {
+        self.run_each(|i| ::files::render(template, &self.vars[i]).map_err(|e| e.to_string()))
+    }
+
+    fn run_each<F>(&self, command: F) -> Report
+        where F: Fn(usize) -> ::std::result::Result<String, String> + Sync
+    {
+        let results = ::batch::parallel(self.remotes.len(), self.concurrency, |i| {
+            Outcome::timed(self.remotes[i].clone(), |remote| match command(i) {
+                Ok(cmd) => remote.run(&cmd),
+                Err(e) => (126, String::new(), e),
+            })
+        });
+
+        Report { results, total: self.remotes.len() }
+    }
+}
+
+/// Outcome of a command on one host.
+///
+pub type HostResult = Outcome<Remote>;
+
+/// Outcomes of a command on every host of a fleet, in the order of the hosts.
+///
+pub type Report = report::Report<Remote>;
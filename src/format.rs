@@ -0,0 +1,247 @@
+//! Formatting of shell scripts, for the scripts generated by programs to be readable once stored.
+//! Requires the `format` feature.
+//!
+//! `format_script` hands the script to `shfmt` when it is installed. Otherwise a simpler
+//! formatter reindents the compound commands (`if`, `while`, `for`, `case`, braces) by four spaces,
+//! leaving here-documents and multiline strings untouched.
+//!
+//! ```rust
+//! let script = "if true; then\necho yes\n      fi\n";
+//!
+//! assert_eq!(shells::format_script(script), "if true; then\n    echo yes\nfi\n");
+//! ```
+//!
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Formats the script, see the module documentation. A script `shfmt` fails to parse is returned
+/// unchanged.
+///
+pub fn format_script(script: &str) -> String {
+    match ::which::which("shfmt") {
+        Some(shfmt) => shfmt_format(&shfmt, script).unwrap_or_else(|| script.to_string()),
+        None => reindent(script),
+    }
+}
+
+fn shfmt_format(shfmt: &::std::path::Path, script: &str) -> Option<String> {
+    let mut child = Command::new(shfmt).args(["-i", "4", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let input = script.as_bytes().to_vec();
+    let mut stdin = child.stdin.take()?;
+    let writer = ::std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().ok()?;
+    let _ = writer.join();
+
+    match output.status.success() {
+        true => String::from_utf8(output.stdout).ok(),
+        false => None,
+    }
+}
+
+/// Keywords opening a level of indentation when they start a command.
+const OPENERS: &[&str] = &["then", "do", "{"];
+
+/// Keywords closing a level of indentation when they start a command.
+const CLOSERS: &[&str] = &["fi", "done", "esac", "}", "elif"];
+
+/// Keywords after which the next word still starts a command.
+const PREFIXES: &[&str] = &["if", "then", "else", "elif", "do", "while", "until", "!", "{", "time"];
+
+/// The fallback formatter: trims the lines, reindents them and collapses runs of blank lines.
+///
+fn reindent(script: &str) -> String {
+    let mut formatted = String::new();
+    let mut depth = 0usize;
+    let mut state = State::default();
+    let mut blank = false;
+
+    for line in script.lines() {
+        // Here-documents, multiline strings and continued lines are kept as they are.
+        if let Some((ref delimiter, strip_tabs)) = state.heredoc {
+            let end = if strip_tabs { line.trim_start_matches('\t') } else { line };
+
+            if end == delimiter {
+                state.heredoc = None;
+            }
+
+            formatted.push_str(line);
+            formatted.push('\n');
+            continue;
+        }
+
+        if state.quote.is_some() || state.continued {
+            formatted.push_str(line);
+            formatted.push('\n');
+            state.scan(line);
+            continue;
+        }
+
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            blank = !formatted.is_empty();
+            continue;
+        }
+
+        if blank {
+            formatted.push('\n');
+            blank = false;
+        }
+
+        let words = state.scan(trimmed);
+        let (opens, closes) = count(&words);
+
+        let dedent = match words.first() {
+            Some(word) if CLOSERS.contains(&&word[..]) || word == "else" => 1,
+            _ => 0,
+        };
+
+        for _ in 0..depth.saturating_sub(dedent) {
+            formatted.push_str("    ");
+        }
+
+        formatted.push_str(trimmed);
+        formatted.push('\n');
+
+        depth = (depth + opens).saturating_sub(closes);
+    }
+
+    formatted
+}
+
+/// The number of levels of indentation opened and closed by the words of a line.
+///
+fn count(words: &[String]) -> (usize, usize) {
+    let mut opens = 0;
+    let mut closes = 0;
+    let mut start = true;
+
+    for word in words {
+        match &word[..] {
+            ";" | "&" | "&&" | "||" | "|" | "(" | ")" | ";;" => {
+                start = true;
+                continue;
+            },
+
+            word if start && OPENERS.contains(&word) => opens += 1,
+            word if start && CLOSERS.contains(&word) => closes += 1,
+            _ => {},
+        }
+
+        if start && word == "case" {
+            opens += 1;
+        }
+
+        start = start && PREFIXES.contains(&&word[..]);
+    }
+
+    (opens, closes)
+}
+
+/// Lexer state carried from one line to the next.
+///
+#[derive(Debug, Default)]
+struct State {
+    quote: Option<char>,
+    heredoc: Option<(String, bool)>,
+    continued: bool,
+}
+
+impl State {
+    /// Splits the line in words and separators, keeping track of the quotes and here-documents it
+    /// leaves open.
+    ///
+    fn scan(&mut self, line: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut heredoc = None;
+        let mut chars = line.chars().peekable();
+
+        self.continued = false;
+
+        while let Some(c) = chars.next() {
+            match (self.quote, c) {
+                (Some('\''), '\'') => self.quote = None,
+                (Some('"'), '"') => self.quote = None,
+                (Some('"'), '\\') => { chars.next(); },
+                (Some(_), _) => {},
+
+                (None, '\'') | (None, '"') => self.quote = Some(c),
+                (None, '\\') if chars.peek().is_none() => self.continued = true,
+                (None, '\\') => { chars.next(); },
+                (None, '#') if word.is_empty() => break,
+
+                (None, c) if c.is_whitespace() => {
+                    flush(&mut words, &mut word);
+                    continue;
+                },
+
+                (None, ';') | (None, '&') | (None, '|') | (None, '(') | (None, ')') => {
+                    flush(&mut words, &mut word);
+
+                    let mut separator = c.to_string();
+
+                    if chars.peek() == Some(&c) {
+                        separator.push(c);
+                        chars.next();
+                    }
+
+                    words.push(separator);
+                    continue;
+                },
+
+                _ => {},
+            }
+
+            word.push(c);
+        }
+
+        flush(&mut words, &mut word);
+
+        for (index, word) in words.iter().enumerate() {
+            if let Some(rest) = word.strip_prefix("<<") {
+                if rest.starts_with('<') {
+                    continue;
+                }
+
+                let (rest, strip_tabs) = match rest.strip_prefix('-') {
+                    Some(rest) => (rest, true),
+                    None => (rest, false),
+                };
+
+                let delimiter = match rest {
+                    "" => words.get(index + 1).map(|word| &word[..]).unwrap_or(""),
+                    rest => rest,
+                };
+
+                let delimiter: String = delimiter.chars()
+                    .filter(|c| !"'\"\\".contains(*c))
+                    .collect();
+
+                if !delimiter.is_empty() {
+                    heredoc = Some((delimiter, strip_tabs));
+                }
+            }
+        }
+
+        if heredoc.is_some() {
+            self.heredoc = heredoc;
+        }
+
+        words
+    }
+}
+
+fn flush(words: &mut Vec<String>, word: &mut String) {
+    if !word.is_empty() {
+        words.push(::std::mem::take(word));
+    }
+}
@@ -0,0 +1,266 @@
+//! Execution of commands on the tokio runtime, see `Shell::run_async`.
+//!
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::process::{Output, Stdio};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWrite;
+use tokio::process::{ChildStdin, Command};
+use tokio::time::Sleep;
+
+use output::ExitStatus;
+use shell::{Outcome, PreparedCommand, Stream};
+
+/// Future resolving once the command exited, to the same value as its blocking counterpart:
+/// `(code, stdout, stderr)` for `Shell::run_async`, a `shells::Result` for `Shell::try_run_async`.
+/// Dropping it before completion kills the command.
+///
+pub struct Execution<T> {
+    prepared: Option<PreparedCommand>,
+    running: Option<Running>,
+    outcome: Option<Outcome>,
+    resolved: Option<T>,
+    finish: fn(Outcome) -> T,
+}
+
+impl<T> Execution<T> {
+    /// Starts the command on the current tokio runtime.
+    ///
+    pub(crate) fn start(prepared: PreparedCommand, finish: fn(Outcome) -> T) -> Execution<T> {
+        let intercepted = ::trace::intercept(&prepared).or_else(|| ::mock::intercept(&prepared));
+
+        if let Some(outcome) = intercepted {
+            return Execution::ready(prepared.filtered_outcome(outcome), finish);
+        }
+
+        if let Err(e) = prepared.setup() {
+            return Execution::ready(prepared.filtered_outcome(Outcome::failed(126, e)), finish);
+        }
+
+        match Running::spawn(&prepared) {
+            Ok(running) => {
+                let (prepared, running) = (Some(prepared), Some(running));
+                Execution { prepared, running, outcome: None, resolved: None, finish }
+            },
+
+            Err(e) => {
+                let outcome = prepared.torn_down(Outcome::failed(126, e.to_string()));
+                Execution::ready(prepared.filtered_outcome(outcome), finish)
+            },
+        }
+    }
+
+    /// An execution which already completed, e.g. because the command could not be prepared.
+    ///
+    pub(crate) fn ready(outcome: Outcome, finish: fn(Outcome) -> T) -> Execution<T> {
+        Execution { prepared: None, running: None, outcome: Some(outcome), resolved: None, finish }
+    }
+
+    /// An execution resolving to the given value, e.g. the error of a command which could not be
+    /// prepared.
+    ///
+    pub(crate) fn resolved(value: T, finish: fn(Outcome) -> T) -> Execution<T> {
+        Execution { prepared: None, running: None, outcome: None, resolved: Some(value), finish }
+    }
+}
+
+impl<T: Unpin> Future for Execution<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+
+        if let Some(value) = this.resolved.take() {
+            return Poll::Ready(value);
+        }
+
+        if let Some(ref mut running) = this.running {
+            let outcome = match running.poll(cx) {
+                Poll::Ready(outcome) => outcome,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            this.running = None;
+
+            if let Some(prepared) = this.prepared.take() {
+                this.outcome = Some(prepared.filtered_outcome(prepared.torn_down(outcome)));
+            }
+        }
+
+        let outcome = this.outcome.take().expect("execution polled after completion");
+        Poll::Ready((this.finish)(outcome))
+    }
+}
+
+/// A spawned command, along with the input still to be written to its stdin.
+///
+struct Running {
+    output: Pin<Box<dyn Future<Output = io::Result<Output>> + Send>>,
+    stdin: Option<(ChildStdin, Vec<u8>, usize)>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    timeout: Option<Duration>,
+    sinks: [Vec<::sink::Attached>; 2],
+    span: Option<::otel::Span>,
+    events: Option<::logging::Events>,
+    started: Instant,
+    metrics: Option<(::metrics::Metrics, String)>,
+
+    #[cfg(unix)]
+    pid: Option<u32>,
+    timed_out: bool,
+}
+
+impl Running {
+    fn spawn(prepared: &PreparedCommand) -> io::Result<Running> {
+        let mut command = Command::from(prepared.command());
+
+        command.stdin(if prepared.stdin().is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let span = ::otel::Span::start(prepared);
+        let events = ::logging::Events::start(prepared);
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+
+            Err(e) => {
+                span.failed(&e);
+                events.failed(&e);
+                prepared.record(126, false, Duration::default());
+                return Err(e);
+            },
+        };
+
+        let input = prepared.stdin().unwrap_or_default().to_vec();
+
+        prepared.started();
+
+        Ok(Running {
+            stdin: child.stdin.take().map(|pipe| (pipe, input, 0)),
+            deadline: prepared.timeout().map(|timeout| Box::pin(::tokio::time::sleep(timeout))),
+            timeout: prepared.timeout(),
+            sinks: [prepared.sinks(Stream::Stdout), prepared.sinks(Stream::Stderr)],
+            span: Some(span),
+            events: Some(events),
+            started: Instant::now(),
+            metrics: prepared.metrics(),
+
+            #[cfg(unix)]
+            pid: child.id(),
+
+            output: Box::pin(child.wait_with_output()),
+            timed_out: false,
+        })
+    }
+
+    fn poll(&mut self, cx: &mut Context) -> Poll<Outcome> {
+        let written = match self.stdin {
+            Some((ref mut pipe, ref input, ref mut offset)) => {
+                while *offset < input.len() {
+                    match Pin::new(&mut *pipe).poll_write(cx, &input[*offset..]) {
+                        Poll::Ready(Ok(0)) | Poll::Ready(Err(_)) => *offset = input.len(),
+                        Poll::Ready(Ok(n)) => *offset += n,
+                        Poll::Pending => break,
+                    }
+                }
+
+                *offset == input.len()
+            },
+
+            None => false,
+        };
+
+        // Closes the pipe, so that the command sees the end of its input.
+        if written {
+            self.stdin = None;
+        }
+
+        if let Some(ref mut deadline) = self.deadline {
+            if !self.timed_out && deadline.as_mut().poll(cx).is_ready() {
+                self.timed_out = true;
+                self.stdin = None;
+
+                // Elsewhere the command is killed when the output future is dropped.
+                #[cfg(unix)]
+                {
+                    if let Some(pid) = self.pid {
+                        unsafe { ::sys::kill(-(pid as i32), ::sys::SIGKILL); }
+                    }
+                }
+
+                #[cfg(not(unix))]
+                {
+                    let outcome = Outcome::failed(124, String::new());
+                    return Poll::Ready(Outcome { timed_out: self.timeout, ..outcome });
+                }
+            }
+        }
+
+        let output = match self.output.as_mut().poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        Poll::Ready(match output {
+            Ok(output) => {
+                let streams = [(Stream::Stdout, &output.stdout), (Stream::Stderr, &output.stderr)];
+                let code = match self.timed_out {
+                    true => 124,
+                    false => ExitStatus::from(output.status).code(),
+                };
+
+                if let Some(span) = self.span.take() {
+                    span.end(code, output.stdout.len(), output.stderr.len(), self.timed_out);
+                }
+
+                if let Some(events) = self.events.take() {
+                    events.end(code);
+                }
+
+                if let Some((ref metrics, ref job)) = self.metrics {
+                    metrics.record(job, code, self.timed_out, self.started.elapsed());
+                }
+
+                for (sinks, (stream, bytes)) in self.sinks.iter().zip(streams.iter()) {
+                    for sink in sinks {
+                        sink.write(*stream, bytes);
+                    }
+                }
+
+                let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+                let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+                match self.timed_out {
+                    true => {
+                        let outcome = Outcome::new(ExitStatus::Exited(124), stdout, stderr);
+                        Outcome { timed_out: self.timeout, ..outcome }
+                    },
+
+                    false => Outcome::new(output.status, stdout, stderr),
+                }
+            },
+
+            Err(e) => {
+                if let Some(span) = self.span.take() {
+                    span.failed(&e);
+                }
+
+                if let Some(events) = self.events.take() {
+                    events.failed(&e);
+                }
+
+                if let Some((ref metrics, ref job)) = self.metrics {
+                    metrics.record(job, 126, false, self.started.elapsed());
+                }
+
+                Outcome::failed(126, e.to_string())
+            },
+        })
+    }
+}
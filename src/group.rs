@@ -0,0 +1,165 @@
+//! Commands living and dying together, for development environments starting a server, a
+//! watcher and a database at once.
+//!
+//! `Group::start` starts every command in the background, each in a process group of its own
+//! like `Shell::start`. Once one of them exits, or the `Group` is dropped, the others are killed
+//! with the processes they started. Their output goes to the sinks of their shell, e.g.
+//! `sink::Tee` to show it on the terminal, and is discarded otherwise.
+//!
+//! ```rust
+//! use std::time::{Duration, Instant};
+//! use shells::group::Group;
+//! use shells::pipeline::bash;
+//!
+//! let start = Instant::now();
+//! let group = Group::start(["sleep 10", "sleep 0.1; exit 3"]).unwrap();
+//!
+//! let exit = group.wait();
+//!
+//! assert_eq!((exit.index, &exit.command[..], exit.status.code()), (1, "sleep 0.1; exit 3", 3));
+//! assert!(start.elapsed() < Duration::from_secs(5));
+//!
+//! // Dropping the group kills the commands still running.
+//! drop(Group::start([bash("sleep 10"), bash("sleep 10")]).unwrap());
+//! ```
+//!
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use child::Running;
+use output::ExitStatus;
+use pipeline::Stage;
+
+/// Commands started together, see the module documentation.
+///
+#[derive(Debug)]
+pub struct Group {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    exited: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    members: Vec<(String, Running)>,
+    exit: Option<Exit>,
+    dropped: bool,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The command of a group which exited first, ending the group.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exit {
+    /// Position of the command in the group.
+    pub index: usize,
+
+    pub command: String,
+    pub status: ExitStatus,
+}
+
+impl Group {
+    /// Starts every command, each a string run with `sh` or a `pipeline::Stage`. When one of them
+    /// cannot be started, those already started are killed and the error is returned.
+    ///
+    pub fn start<I>(commands: I) -> io::Result<Group>
+        where I: IntoIterator,
+              I::Item: Into<Stage>
+    {
+        let mut members = Vec::new();
+
+        for stage in commands.into_iter().map(Into::into) {
+            let mut running = stage.start()
+                .map_err(|e| io::Error::new(e.kind(), format!("`{}`: {}", stage.cmd(), e)))?;
+
+            running.forward_output();
+            members.push((stage.cmd().to_string(), running));
+        }
+
+        let state = State { members, ..State::default() };
+        let shared = Arc::new(Shared { state: Mutex::new(state), ..Shared::default() });
+        let watched = shared.clone();
+
+        thread::spawn(move || watch(&watched));
+        Ok(Group { shared })
+    }
+
+    /// The process identifiers of the shells running the commands, in their order.
+    ///
+    pub fn pids(&self) -> Vec<u32> {
+        self.shared.state().members.iter().map(|(_, running)| running.pid()).collect()
+    }
+
+    /// Waits for a command to exit, and for the others to be killed.
+    ///
+    pub fn wait(&self) -> Exit {
+        let mut state = self.shared.state();
+
+        loop {
+            if let Some(ref exit) = state.exit {
+                return exit.clone();
+            }
+
+            state = self.shared.exited.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// The command which exited first if a command exited already, without blocking.
+    ///
+    pub fn try_wait(&self) -> Option<Exit> {
+        self.shared.state().exit.clone()
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.dropped = true;
+
+        // Dropping the guards kills the commands still running.
+        state.members.clear();
+    }
+}
+
+/// Kills every command once one of them exited.
+///
+fn watch(shared: &Shared) {
+    loop {
+        thread::sleep(Duration::from_millis(20));
+
+        let mut state = shared.state();
+
+        if state.dropped {
+            return;
+        }
+
+        let exited = state.members.iter_mut().enumerate()
+            .find_map(|(index, &mut (_, ref mut running))| {
+                running.try_wait().ok().flatten().map(|status| (index, status))
+            });
+
+        if let Some((index, status)) = exited {
+            for &mut (_, ref mut running) in &mut state.members {
+                let _ = running.kill();
+                let _ = running.wait();
+            }
+
+            let command = state.members[index].0.clone();
+            state.exit = Some(Exit { index, command, status });
+            shared.exited.notify_all();
+            return;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! BATS-style testing of the shell functions of a `Library`.
+//!
+//! Every function whose name starts with `test_` is a test case. When the library defines
+//! `setup` and `teardown` functions, they run before and after each test; a failing `setup` fails
+//! the test without running it, `teardown` always runs. Tests run in a subshell, so a test calling
+//! `exit` does not prevent the teardown. A test passes when it returns 0.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::library::Library;
+//!
+//! let library = Library::new(Shell::new("sh")).source(r#"
+//! setup() { value=42; }
+//! test_value_is_set() { [ "$value" = 42 ]; }
+//! test_arithmetic() { [ $((1 + 1)) -eq 2 ]; }
+//! "#);
+//!
+//! let report = library.test();
+//!
+//! assert!(report.is_success());
+//! assert_eq!(report.to_string(), "1..2\nok 1 - test_value_is_set\nok 2 - test_arithmetic\n");
+//! ```
+//!
+//! The `Display` implementation of the report follows the TAP format, with the output of failed
+//! tests as diagnostics. `TestReport::junit` renders it as JUnit XML instead.
+//!
+
+use std::time::{Duration, Instant};
+
+use library::Library;
+
+/// Outcome of a single test function.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl TestCase {
+    pub fn passed(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// Outcomes of every test of a library, in order of definition.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestReport {
+    pub cases: Vec<TestCase>,
+}
+
+impl TestReport {
+    /// Whether every test passed.
+    ///
+    pub fn is_success(&self) -> bool {
+        self.cases.iter().all(TestCase::passed)
+    }
+
+    /// The tests which failed.
+    ///
+    pub fn failures(&self) -> impl Iterator<Item = &TestCase> {
+        self.cases.iter().filter(|case| !case.passed())
+    }
+
+    /// Renders the report as a JUnit XML test suite, see `ci::junit`.
+    ///
+    pub fn junit(&self, suite: &str) -> String {
+        ::ci::junit(suite, &self.ci_cases())
+    }
+
+    /// Renders the report in the TAP format, like its `Display` implementation.
+    ///
+    pub fn tap(&self) -> String {
+        ::ci::tap(&self.ci_cases())
+    }
+
+    fn ci_cases(&self) -> Vec<::ci::Case<'_>> {
+        self.cases.iter()
+            .map(|case| ::ci::Case {
+                name: case.name.clone(),
+                code: case.code,
+                stdout: &case.stdout,
+                stderr: &case.stderr,
+                duration: case.duration,
+            })
+            .collect()
+    }
+}
+
+impl ::std::fmt::Display for TestReport {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(&self.tap())
+    }
+}
+
+impl Library {
+    /// Runs every test function of the library.
+    ///
+    pub fn test(&self) -> TestReport {
+        self.test_matching("")
+    }
+
+    /// Runs the test functions whose name contains `pattern`.
+    ///
+    pub fn test_matching(&self, pattern: &str) -> TestReport {
+        let functions = self.functions();
+        let has = |name: &str| functions.iter().any(|f| f == name);
+
+        let setup = if has("setup") { "setup; status=$?" } else { "status=0" };
+        let teardown = if has("teardown") { "teardown" } else { ":" };
+
+        let cases = functions.iter()
+            .filter(|name| name.starts_with("test_") && name.contains(pattern))
+            .map(|name| {
+                let script = format!("{}\nif [ $status -eq 0 ]; then ({}); status=$?; fi\n{}\n\
+                                      exit $status", setup, name, teardown);
+
+                let start = Instant::now();
+                let (code, stdout, stderr) = self.run(&script);
+
+                TestCase { name: name.clone(), code, stdout, stderr, duration: start.elapsed() }
+            })
+            .collect();
+
+        TestReport { cases }
+    }
+}
@@ -0,0 +1,286 @@
+//! Inventories of hosts, organized in groups, with variables interpolated in the commands run on
+//! them.
+//!
+//! Inventories can be built in code or, with the `inventory-toml` and `inventory-json`
+//! features, loaded from files:
+//!
+//! ```toml
+//! [vars]
+//! app = "shop"
+//!
+//! [hosts.web1]
+//! address = "deploy@10.0.0.11"
+//! vars = { port = "8080" }
+//!
+//! [hosts.web2]
+//! address = "deploy@10.0.0.12"
+//! port = 2222
+//! vars = { port = "8081" }
+//!
+//! [groups.web]
+//! hosts = ["web1", "web2"]
+//! vars = { root = "/srv/shop" }
+//! ```
+//!
+//! The JSON format has the same structure. `address` defaults to the name of the host; `port`,
+//! `identity` (a private key) and `jump` (a bastion host) to the SSH configuration. The variables
+//! of a host are, by increasing precedence: the global ones, those of the group it is targeted
+//! through, its own, and `host`, its name.
+//!
+//! ```rust,no_run
+//! use shells::inventory::Inventory;
+//!
+//! let inventory = Inventory::load("hosts.toml").unwrap();
+//! let fleet = inventory.fleet("web").unwrap();
+//! let report = fleet.run_template("curl -sf localhost:{{ port }}/health");
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use fleet::Fleet;
+use ssh::Remote;
+
+#[cfg(any(feature = "inventory-toml", feature = "inventory-json"))]
+use document::{Invalid, Node};
+
+/// A host of an inventory.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    pub name: String,
+    pub remote: Remote,
+    pub vars: BTreeMap<String, String>,
+}
+
+/// A named set of hosts sharing variables.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    pub hosts: Vec<String>,
+    pub vars: BTreeMap<String, String>,
+}
+
+/// Hosts, groups and variables.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inventory {
+    pub hosts: BTreeMap<String, Host>,
+    pub groups: BTreeMap<String, Group>,
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Inventory {
+    /// Creates an empty inventory.
+    ///
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    /// Adds a host, replacing any host with the same name.
+    ///
+    pub fn host(mut self, name: &str, remote: Remote) -> Inventory {
+        let host = Host { name: name.to_string(), remote, vars: BTreeMap::new() };
+        self.hosts.insert(name.to_string(), host);
+        self
+    }
+
+    /// Adds a group of the given hosts.
+    ///
+    pub fn group(mut self, name: &str, hosts: &[&str]) -> Inventory {
+        let group = self.groups.entry(name.to_string()).or_default();
+        group.hosts.extend(hosts.iter().map(|host| host.to_string()));
+        self
+    }
+
+    /// Sets a global variable.
+    ///
+    pub fn var(mut self, name: &str, value: &str) -> Inventory {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Loads an inventory from a file, whose format is selected by its extension: `.toml` or
+    /// `.json`, depending on the enabled features.
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Inventory, Error> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "inventory-toml")]
+            Some("toml") => Inventory::from_toml(&read(path)?),
+
+            #[cfg(feature = "inventory-json")]
+            Some("json") => Inventory::from_json(&read(path)?),
+
+            _ => Err(Error::Unsupported(path.display().to_string())),
+        }
+    }
+
+    /// Parses an inventory in the TOML format.
+    ///
+    #[cfg(feature = "inventory-toml")]
+    pub fn from_toml(content: &str) -> Result<Inventory, Error> {
+        let value = content.parse::<::toml::Value>().map_err(|e| Error::Parse(e.to_string()))?;
+        Inventory::from_node(&Node::from_toml(value))
+    }
+
+    /// Parses an inventory in the JSON format.
+    ///
+    #[cfg(feature = "inventory-json")]
+    pub fn from_json(content: &str) -> Result<Inventory, Error> {
+        let value = ::serde_json::from_str(content).map_err(|e| Error::Parse(e.to_string()))?;
+        Inventory::from_node(&Node::from_json(value))
+    }
+
+    /// Builds a fleet of the hosts of the group, or of the single host with this name, or of
+    /// every host for `all`.
+    ///
+    pub fn fleet(&self, target: &str) -> Result<Fleet, Error> {
+        let empty = BTreeMap::new();
+
+        let (names, group_vars): (Vec<&str>, &BTreeMap<_, _>) = match self.groups.get(target) {
+            Some(group) => (group.hosts.iter().map(|name| &name[..]).collect(), &group.vars),
+            None if self.hosts.contains_key(target) => (vec![target], &empty),
+            None if target == "all" => (self.hosts.keys().map(|name| &name[..]).collect(), &empty),
+            None => return Err(Error::UnknownTarget(target.to_string())),
+        };
+
+        let mut hosts = Vec::with_capacity(names.len());
+
+        for name in names {
+            let host = self.hosts.get(name).ok_or_else(|| Error::UnknownHost(name.to_string()))?;
+
+            let mut vars = self.vars.clone();
+            vars.extend(group_vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+            vars.extend(host.vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+            vars.insert("host".to_string(), host.name.clone());
+
+            hosts.push((host.remote.clone(), vars.into_iter().collect()));
+        }
+
+        Ok(Fleet::with_vars(hosts))
+    }
+
+    #[cfg(any(feature = "inventory-toml", feature = "inventory-json"))]
+    fn from_node(root: &Node) -> Result<Inventory, Error> {
+        let mut inventory = Inventory::new();
+
+        if let Some(vars) = root.get("vars") {
+            inventory.vars = vars.vars("vars")?;
+        }
+
+        let hosts = root.get("hosts").map(|hosts| hosts.entries("hosts")).unwrap_or(Ok(&[]))?;
+
+        for (name, host) in hosts {
+            let context = format!("hosts.{}", name);
+
+            let address = match host.get("address") {
+                Some(address) => address.scalar(&context)?,
+                None => name.clone(),
+            };
+
+            let mut remote = Remote::new(&address);
+
+            if let Some(port) = host.get("port") {
+                let port = port.scalar(&context)?;
+                let invalid = |_| Error::Parse(format!("{}: invalid port {}", context, port));
+                let port = port.parse().map_err(invalid)?;
+                remote = remote.port(port);
+            }
+
+            if let Some(identity) = host.get("identity") {
+                remote = remote.identity(identity.scalar(&context)?);
+            }
+
+            if let Some(jump) = host.get("jump") {
+                remote = remote.jump(&jump.scalar(&context)?);
+            }
+
+            let vars = match host.get("vars") {
+                Some(vars) => vars.vars(&context)?,
+                None => BTreeMap::new(),
+            };
+
+            inventory.hosts.insert(name.clone(), Host { name: name.clone(), remote, vars });
+        }
+
+        let groups = root.get("groups").map(|groups| groups.entries("groups")).unwrap_or(Ok(&[]))?;
+
+        for (name, group) in groups {
+            let context = format!("groups.{}", name);
+
+            let hosts = match group.get("hosts") {
+                Some(hosts @ Node::Array(_)) => hosts.scalars(&context)?,
+                Some(_) => {
+                    return Err(Error::Parse(format!("{}: `hosts` must be an array", context)))
+                },
+                None => Vec::new(),
+            };
+
+            let vars = match group.get("vars") {
+                Some(vars) => vars.vars(&context)?,
+                None => BTreeMap::new(),
+            };
+
+            inventory.groups.insert(name.clone(), Group { hosts, vars });
+        }
+
+        Ok(inventory)
+    }
+}
+
+#[cfg(any(feature = "inventory-toml", feature = "inventory-json"))]
+fn read(path: &Path) -> Result<String, Error> {
+    ::std::fs::read_to_string(path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))
+}
+
+/// Error returned when an inventory cannot be loaded or targeted.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The file could not be read.
+    Io(String),
+
+    /// The format of the file is not supported, or its feature is not enabled.
+    Unsupported(String),
+
+    /// The file is not a valid inventory.
+    Parse(String),
+
+    /// No group or host has this name.
+    UnknownTarget(String),
+
+    /// A group refers to a host which is not defined.
+    UnknownHost(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Invalid inventory."
+    }
+}
+
+#[cfg(any(feature = "inventory-toml", feature = "inventory-json"))]
+impl From<Invalid> for Error {
+    fn from(invalid: Invalid) -> Error {
+        Error::Parse(invalid.0)
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Io(ref reason) | Error::Parse(ref reason) => write!(f, "{}", reason),
+            Error::Unsupported(ref path) => write!(f, "unsupported inventory format: {}", path),
+            Error::UnknownTarget(ref name) => {
+                write!(f, "no group or host named `{}` in the inventory", name)
+            },
+
+            Error::UnknownHost(ref name) => {
+                write!(f, "host `{}` is not defined in the inventory", name)
+            },
+        }
+    }
+}
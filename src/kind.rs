@@ -0,0 +1,177 @@
+//! The shells the crate has macros for, to select one at runtime.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::ShellKind;
+//!
+//! fn main() {
+//!     let kind: ShellKind = ::std::env::var("MY_SHELL").unwrap_or_default().parse()
+//!         .unwrap_or(ShellKind::Sh);
+//!
+//!     assert_eq!(shell!(kind, "echo {}", "hello").1, "hello\n");
+//!     assert_eq!(wrap_shell!("sh", "echo {}", "hello").unwrap(), "hello\n");
+//! }
+//! ```
+//!
+//! The shells need not all be installed: `detect` finds the shell of the user and
+//! `first_available` the first installed of a list of shells.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::ShellKind;
+//!
+//! fn main() {
+//!     let candidates = [ShellKind::Zsh, ShellKind::Bash, ShellKind::Sh];
+//!     let kind = ShellKind::first_available(&candidates).unwrap();
+//!
+//!     assert!(kind.path().unwrap().is_absolute());
+//!     assert_eq!(shell!(kind, "echo $((1 + 2))").1, "3\n");
+//! }
+//! ```
+//!
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One of the shells of the crate, invoked like its macro.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShellKind {
+    Sh,
+    Ash,
+    Csh,
+    Ksh,
+    Zsh,
+    Bash,
+    Dash,
+    Fish,
+    Mksh,
+    Tcsh,
+    Cmd,
+    PowerShell,
+}
+
+impl ShellKind {
+    /// Every kind of shell, in the order of the macros.
+    ///
+    pub const ALL: [ShellKind; 12] = [
+        ShellKind::Sh, ShellKind::Ash, ShellKind::Csh, ShellKind::Ksh, ShellKind::Zsh,
+        ShellKind::Bash, ShellKind::Dash, ShellKind::Fish, ShellKind::Mksh, ShellKind::Tcsh,
+        ShellKind::Cmd, ShellKind::PowerShell,
+    ];
+
+    /// Name of the program of the shell, looked up on the PATH. `PowerShell` is `pwsh`, PowerShell
+    /// Core, when only it is installed.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            ShellKind::Sh => "sh",
+            ShellKind::Ash => "ash",
+            ShellKind::Csh => "csh",
+            ShellKind::Ksh => "ksh",
+            ShellKind::Zsh => "zsh",
+            ShellKind::Bash => "bash",
+            ShellKind::Dash => "dash",
+            ShellKind::Fish => "fish",
+            ShellKind::Mksh => "mksh",
+            ShellKind::Tcsh => "tcsh",
+            ShellKind::Cmd => "cmd",
+            ShellKind::PowerShell => {
+                match ::which::which("powershell").is_none() && ::which::which("pwsh").is_some() {
+                    true => "pwsh",
+                    false => "powershell",
+                }
+            },
+        }
+    }
+
+    /// Location of the program of the shell on the PATH, `None` when it is not installed.
+    ///
+    pub fn path(&self) -> Option<PathBuf> {
+        ::which::which(self.program())
+    }
+
+    /// Whether the program of the shell is on the PATH.
+    ///
+    pub fn is_available(&self) -> bool {
+        self.path().is_some()
+    }
+
+    /// The first of the shells which is installed, for a script to degrade gracefully from the
+    /// shell it prefers to the ones it also supports.
+    ///
+    pub fn first_available(kinds: &[ShellKind]) -> Option<ShellKind> {
+        kinds.iter().cloned().find(ShellKind::is_available)
+    }
+
+    /// The shell of the user when it is known and installed: the one named by `$SHELL`, else the
+    /// first available of `sh` then `bash` on Unix, of `powershell` then `cmd` on Windows.
+    ///
+    pub fn detect() -> Option<ShellKind> {
+        let preferred = ::std::env::var("SHELL").ok()
+            .and_then(|shell| shell.parse::<ShellKind>().ok())
+            .filter(ShellKind::is_available);
+
+        let fallbacks = match cfg!(windows) {
+            true => [ShellKind::PowerShell, ShellKind::Cmd],
+            false => [ShellKind::Sh, ShellKind::Bash],
+        };
+
+        preferred.or_else(|| ShellKind::first_available(&fallbacks))
+    }
+}
+
+impl AsRef<str> for ShellKind {
+    fn as_ref(&self) -> &str {
+        self.program()
+    }
+}
+
+impl ::std::fmt::Display for ShellKind {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.program())
+    }
+}
+
+impl FromStr for ShellKind {
+    type Err = Error;
+
+    /// Parses the name of a program, or its path, e.g. the value of `$SHELL`. `pwsh` is parsed
+    /// as `PowerShell`.
+    ///
+    fn from_str(name: &str) -> Result<ShellKind, Error> {
+        let stem = Path::new(name).file_stem().map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match &stem[..] {
+            "powershell" | "pwsh" => Ok(ShellKind::PowerShell),
+            stem => ShellKind::ALL.iter().cloned().find(|kind| kind.program() == stem)
+                .ok_or_else(|| Error::Unknown(name.to_string())),
+        }
+    }
+}
+
+/// Error returned when a name does not designate a known shell.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Unknown(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Unknown shell."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Unknown(ref name) => write!(f, "unknown shell `{}`", name),
+        }
+    }
+}
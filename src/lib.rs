@@ -21,38 +21,368 @@
 //!
 //! A mnemotechnic to remember the ordering of the elements in the resulting tuple is the positions
 //! of stdout and stderr, they correspond to the standard streams numbers: 1 and 2 respectively.
+//! The `*_out` macros (`sh_out!`, `bash_out!`...) return an `Output` with named fields instead,
+//! which also holds the duration of the command.
+//! stdout and stderr are decoded as UTF-8, invalid sequences being replaced: the `*_bytes` macros
+//! (`sh_bytes!`...) return them untouched, as `Vec<u8>`. The `*_tee` macros (`sh_tee!`...) also
+//! print the output live on the stdout and stderr of the program, for build scripts.
 //!
 //! The implementation for all the different shells is the same: the arguments of the macro is
 //! passed directly to `format!` and the resulting string is passed to the shell using its '-c'
 //! command line option. Thus you can use `sh!` and friends the same way you would use `format!` or
 //! `println!`.
 //!
+//! Interpolated values are not quoted by these macros: the `*_safe` macros (`sh_safe!`,
+//! `fish_safe!`...) quote every argument for their shell, see the `quote` module.
+//!
+//! On Windows, the `cmd!` and `powershell!` macros (and their `wrap_*` counterparts) pass the
+//! command to `cmd /C` and `powershell -Command` instead.
+//!
+//! `shell!` and `wrap_shell!` take the shell as first argument instead, a `ShellKind` or a program
+//! name chosen at runtime.
+//!
+//! The `*_env` macros set environment variables for a single command, whatever the syntax of the
+//! shell: `sh_env!({"RUST_LOG" => "debug"}, "cargo run")`. The `*_in` macros run it in the given
+//! directory, without `cd` in the command: `sh_in!("/tmp", "ls -la")`.
+//!
+//! The `wrap_full_*` macros (`wrap_full_sh!`...) return an `Output` on success rather than only
+//! stdout, so the warnings and progress printed to stderr by successful commands are not lost.
+//!
+//! The `wrap_*_timeout` macros take a `Duration` as first argument: a command still running once
+//! it elapsed is killed, and `Error::TimedOut` holds what it printed until then. The `spawn_*`
+//! macros (`spawn_sh!`...) do not wait at all: they start the command in the background and
+//! return a `ShellChild` handle on it, see `Running` for one killing it when dropped. The `retry_*`
+//! macros take a number of attempts instead, and run a failing command again with an exponential
+//! backoff: `retry_sh!(3, "curl -f {}", url)`.
+//!
+//! `set_trace(Trace::Echo)` prints every command to stderr before it runs, like `set -x`, and
+//! `set_trace(Trace::DryRun)` prints it instead of running it, to rehearse a destructive script.
+//! `with_shell_config` makes the macros invoke a pinned interpreter or pass it flags, e.g. bash in
+//! strict mode, see `ShellConfig` and `Shell::strict`.
+//!
+//! When more control over the invocation is needed, the `Shell` builder exposes the options the
+//! macros use under the hood. The `run_on!` and `wrap_run_on!` macros run commands on any
+//! `target::Target` instead: a `Shell`, a host reached over SSH, a container or a virtual machine.
+//!
+//! `pipeline!` chains commands through OS pipes, and `batch` runs many commands concurrently on
+//! a bounded number of threads: `shells::batch(["make a", "make b"]).max_parallel(4).run()`.
+//!
+//! ## Optional features
+//!
+//! * `seccomp`: attach a seccomp filter to the spawned shell (x86_64 and aarch64 Linux only).
+//! * `inventory-toml`, `inventory-json`: load host inventories from TOML or JSON files.
+//! * `commands-toml`, `commands-yaml`: load registries of named commands from TOML or YAML files.
+//! * `tokio`: run commands from async code with `Shell::run_async` and the `async_*` macros.
+//! * `format`: format generated scripts with `format_script`, through `shfmt` when installed.
+//! * `encryption`: store captured output encrypted with `sink::Encrypted`.
+//! * `gzip`, `zstd`: store captured output compressed with `sink::Gzip` or `sink::Zstd`.
+//! * `otel`: record each blocking or async execution as an OpenTelemetry span `shells.run`, with
+//!   the command, its exit code and the size of its output as attributes.
+//! * `log`: log each blocking or async execution through the `log` crate, with the target
+//!   `shells`: the command at the `debug` level when it starts, its exit code and duration at the
+//!   `info` level when it succeeds, at the `warn` level when it fails.
+//! * `project-config`: apply the settings of the nearest `.shells.toml` to the commands, see the
+//!   `project` module.
+//! * `ndjson`: deserialize the lines of streamed output as JSON values with `Lines::json`, see the
+//!   `ndjson` module.
+//! * `pty`: run commands under a pseudo-terminal with `Shell::pty`, see the `pty` module (Linux and
+//!   macOS only).
+//! * `regex`: run actions on the lines of streamed output matching regular expressions, see the
+//!   `trigger` module.
+//!
+
+#[cfg(any(feature = "inventory-toml", feature = "commands-toml", feature = "project-config"))]
+extern crate toml;
+
+#[cfg(feature = "ndjson")]
+extern crate serde;
+
+#[cfg(any(feature = "inventory-json", feature = "ndjson"))]
+extern crate serde_json;
+
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+#[cfg(feature = "encryption")]
+extern crate chacha20poly1305;
+
+#[cfg(feature = "gzip")]
+extern crate flate2;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+#[cfg(feature = "otel")]
+extern crate opentelemetry;
+
+#[cfg(feature = "commands-yaml")]
+extern crate yaml_rust2;
+
+#[cfg(feature = "log")]
+extern crate log;
+
+#[cfg(feature = "regex")]
+extern crate regex;
+
+#[cfg(unix)]
+mod sys;
+
+#[cfg(all(feature = "seccomp", target_os = "linux"))]
+pub mod seccomp;
+
+// The filters hard-code the syscall numbers of these architectures.
+#[cfg(all(feature = "seccomp", target_os = "linux",
+          not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+compile_error!("the `seccomp` feature is only supported on x86_64 and aarch64 Linux");
+
+#[cfg(target_os = "linux")]
+mod netns;
+
+pub mod args;
+pub mod batch;
+pub mod cancel;
+pub mod chunk;
+pub mod ci;
+pub mod clock;
+pub mod commands;
+pub mod compose;
+pub mod confine;
+pub mod container;
+pub mod cron;
+pub mod diff;
+pub mod ensure;
+pub mod fetch;
+pub mod files;
+pub mod fleet;
+pub mod group;
+pub mod harness;
+pub mod inventory;
+pub mod kind;
+pub mod library;
+pub mod lock;
+pub mod memo;
+pub mod metrics;
+pub mod mock;
+
+#[cfg(feature = "ndjson")]
+pub mod ndjson;
+
+pub mod pipeline;
+pub mod pkg;
+pub mod policy;
+pub mod port;
+pub mod preflight;
+
+#[cfg(unix)]
+pub mod privilege;
+
+pub mod probe;
+
+#[cfg(feature = "project-config")]
+pub mod project;
+
+#[cfg(all(feature = "pty", any(target_os = "linux", target_os = "macos")))]
+pub mod pty;
+
+pub mod quote;
+pub mod report;
+pub mod retry;
+pub mod sandbox;
+pub mod sink;
+pub mod snapshot;
+pub mod ssh;
+pub mod supervise;
+pub mod syntax;
+pub mod target;
+pub mod tasks;
+pub mod track;
+pub mod trap;
+
+#[cfg(feature = "regex")]
+pub mod trigger;
+
+pub mod vm;
+
+mod child;
+mod defaults;
+
+#[cfg(any(feature = "inventory-toml", feature = "inventory-json", feature = "commands-toml",
+          feature = "commands-yaml", feature = "project-config"))]
+mod document;
+
+mod exit;
+
+#[cfg(feature = "format")]
+mod format;
+
+#[cfg(feature = "tokio")]
+mod future;
+
+mod logging;
+mod otel;
+mod output;
+mod platform;
+mod sha256;
+mod shell;
+mod stream;
+mod trace;
+mod which;
+
+use std::ffi::OsString;
+
+pub use batch::batch;
+pub use cancel::CancellationToken;
+pub use child::{Ready, Running, ShellChild};
+pub use defaults::{default_args, set_default_args, with_shell_config, ShellConfig};
+pub use exit::propagate_exit;
+pub use fetch::fetch;
+pub use kind::ShellKind;
+
+#[cfg(feature = "format")]
+pub use format::format_script;
+
+#[cfg(feature = "tokio")]
+pub use future::Execution;
+
+pub use output::{ExitStatus, Output};
+pub use platform::{distro, os, when, Distro, Os};
+pub use port::free_port;
+pub use shell::{PreparedCommand, Shell, Stream};
+pub use stream::{Buffer, Line, Lines};
+pub use trace::{set_trace, trace, with_trace, Trace};
 
 /// Type returned by the `wrap_*` family of macros. Will either be `Ok(stdout)` or an error
 /// containing code, stdout and stderr resulting from executing the command.
 ///
 pub type Result = ::std::result::Result<String, Error>;
 
-/// Struct holding the resulting environment after executing a failed command with the `wrap_*`
+/// Enum holding the resulting environment after executing a failed command with the `wrap_*`
 /// family of macros. It implements the Error trait and its implementation of the Display trait is
-/// identical to the implementation of the Display trait of its `stderr` field.
+/// identical to the implementation of the Display trait of its stderr.
 ///
 #[derive(Debug, Clone, PartialEq)]
-pub struct Error {
-    pub code: i32,
-    pub stdout: String,
-    pub stderr: String,
+pub enum Error {
+    /// The command exited with a non-zero code.
+    Failed {
+        code: i32,
+        stdout: String,
+        stderr: String,
+    },
+
+    /// The command was killed by a signal (Unix only).
+    Signaled {
+        signal: i32,
+        stdout: String,
+        stderr: String,
+    },
+
+    /// The command was killed once its timeout was reached, see `Shell::timeout`. Holds what it
+    /// printed until then.
+    TimedOut {
+        partial_stdout: String,
+        partial_stderr: String,
+    },
+
+    /// The command was killed once its token was cancelled, see `Shell::cancel_on`. Holds what it
+    /// printed until then.
+    Cancelled {
+        partial_stdout: String,
+        partial_stderr: String,
+    },
+
+    /// The command was not run since it violates a `policy::Policy`. Holds the command and the
+    /// description of the violation.
+    PolicyDenied {
+        command: String,
+        reason: String,
+    },
+
+    /// The command was not run since a check of `Shell::preflight` failed. Holds the failure and
+    /// its description.
+    Preflight {
+        error: preflight::Error,
+        reason: String,
+    },
+}
+
+impl Error {
+    /// Exit code of the command, 124 when it timed out, 126 when it was denied by a policy or a
+    /// preflight check failed, 130 when it was cancelled and 128 plus the signal number when it
+    /// was killed by a signal.
+    ///
+    pub fn code(&self) -> i32 {
+        match *self {
+            Error::Failed { code, .. } => code,
+            Error::Signaled { signal, .. } => 128 + signal,
+            Error::TimedOut { .. } => 124,
+            Error::Cancelled { .. } => 130,
+            Error::PolicyDenied { .. } | Error::Preflight { .. } => 126,
+        }
+    }
+
+    /// What the command printed on stdout.
+    ///
+    pub fn stdout(&self) -> &str {
+        match *self {
+            Error::Failed { ref stdout, .. } | Error::Signaled { ref stdout, .. } => stdout,
+            Error::TimedOut { ref partial_stdout, .. } => partial_stdout,
+            Error::Cancelled { ref partial_stdout, .. } => partial_stdout,
+
+            Error::PolicyDenied { .. } | Error::Preflight { .. } => "",
+        }
+    }
+
+    /// What the command printed on stderr, the description of the violation when it was denied
+    /// by a policy or of the failure when a preflight check failed.
+    ///
+    pub fn stderr(&self) -> &str {
+        match *self {
+            Error::Failed { ref stderr, .. } | Error::Signaled { ref stderr, .. } => stderr,
+            Error::TimedOut { ref partial_stderr, .. } => partial_stderr,
+            Error::Cancelled { ref partial_stderr, .. } => partial_stderr,
+
+            Error::PolicyDenied { ref reason, .. } | Error::Preflight { ref reason, .. } => reason,
+        }
+    }
+
+    /// The signal which killed the command, if any.
+    ///
+    pub fn signal(&self) -> Option<i32> {
+        match *self {
+            Error::Signaled { signal, .. } => Some(signal),
+            _ => None,
+        }
+    }
+
+    /// Whether the command was killed once its timeout was reached.
+    ///
+    pub fn is_timeout(&self) -> bool {
+        matches!(*self, Error::TimedOut { .. })
+    }
+
+    /// Whether the command was killed once its token was cancelled.
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        matches!(*self, Error::Cancelled { .. })
+    }
 }
 
 impl ::std::error::Error for Error {
     fn description(&self) -> &str {
-        "Unix command failed."
+        match *self {
+            Error::Failed { .. } => "Unix command failed.",
+            Error::Signaled { .. } => "Unix command killed by a signal.",
+            Error::TimedOut { .. } => "Unix command timed out.",
+            Error::Cancelled { .. } => "Unix command cancelled.",
+            Error::PolicyDenied { .. } => "Unix command denied by policy.",
+            Error::Preflight { .. } => "Unix command preflight check failed.",
+        }
     }
 }
 
 impl ::std::fmt::Display for Error {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "{}", self.stderr)
+        write!(f, "{}", self.stderr())
     }
 }
 
@@ -146,23 +476,31 @@ macro_rules! tcsh {
     }};
 }
 
+/// Macro to execute the given command using the Windows Command Prompt (`cmd /C`).
+///
+#[macro_export]
+macro_rules! cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell (`powershell -Command`).
+///
+#[macro_export]
+macro_rules! powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
 /// Macro to execute the given command using the Posix Shell and wraping the resulting tuple into a
 /// Result.
 ///
 #[macro_export]
 macro_rules! wrap_sh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("sh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("sh", &format!($( $cmd )*))
     }};
 }
 
@@ -172,17 +510,7 @@ macro_rules! wrap_sh {
 #[macro_export]
 macro_rules! wrap_ash {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("ash", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("ash", &format!($( $cmd )*))
     }};
 }
 
@@ -192,17 +520,7 @@ macro_rules! wrap_ash {
 #[macro_export]
 macro_rules! wrap_csh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("csh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("csh", &format!($( $cmd )*))
     }};
 }
 
@@ -212,17 +530,7 @@ macro_rules! wrap_csh {
 #[macro_export]
 macro_rules! wrap_ksh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("ksh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("ksh", &format!($( $cmd )*))
     }};
 }
 
@@ -232,17 +540,7 @@ macro_rules! wrap_ksh {
 #[macro_export]
 macro_rules! wrap_zsh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("zsh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("zsh", &format!($( $cmd )*))
     }};
 }
 
@@ -252,17 +550,7 @@ macro_rules! wrap_zsh {
 #[macro_export]
 macro_rules! wrap_bash {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("bash", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("bash", &format!($( $cmd )*))
     }};
 }
 
@@ -272,17 +560,7 @@ macro_rules! wrap_bash {
 #[macro_export]
 macro_rules! wrap_dash {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("dash", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("dash", &format!($( $cmd )*))
     }};
 }
 
@@ -292,17 +570,7 @@ macro_rules! wrap_dash {
 #[macro_export]
 macro_rules! wrap_fish {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("fish", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("fish", &format!($( $cmd )*))
     }};
 }
 
@@ -312,17 +580,7 @@ macro_rules! wrap_fish {
 #[macro_export]
 macro_rules! wrap_mksh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("mksh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
-
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+        $crate::wrap_with("mksh", &format!($( $cmd )*))
     }};
 }
 
@@ -332,35 +590,2132 @@ macro_rules! wrap_mksh {
 #[macro_export]
 macro_rules! wrap_tcsh {
     ( $( $cmd:tt )* ) => {{
-        match $crate::execute_with("tcsh", &format!($( $cmd )*)) {
-            (0, stdout, _) => Ok(stdout),
+        $crate::wrap_with("tcsh", &format!($( $cmd )*))
+    }};
+}
 
-            (code, stdout, stderr) => {
-                Err($crate::Error {
-                    code: code,
-                    stdout: stdout,
-                    stderr: stderr,
-                })
-            },
-        }
+/// Macro to execute the given command using the Windows Command Prompt and wraping the resulting
+/// tuple into a Result.
+///
+#[macro_export]
+macro_rules! wrap_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with("cmd", &format!($( $cmd )*))
     }};
 }
 
-#[doc(hidden)]
-pub fn execute_with(shell: &str, cmd: &String) -> (i32, String, String) {
-    let mut command = {
-        let mut command = ::std::process::Command::new(shell);
-        command.arg("-c").arg(cmd);
-        command
-    };
-
-    match command.output() {
-        Ok(output) => {
-            (output.status.code().unwrap_or(if output.status.success() { 0 } else { 1 }),
-             String::from_utf8_lossy(&output.stdout[..]).into_owned(),
-             String::from_utf8_lossy(&output.stderr[..]).into_owned())
-        },
-
-        Err(e) => (126, String::new(), e.to_string()),
-    }
+/// Macro to execute the given command using Windows PowerShell and wraping the resulting tuple into
+/// a Result.
+///
+#[macro_export]
+macro_rules! wrap_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, killing it once the timeout given as
+/// first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_sh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("sh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, killing it once the timeout given
+/// as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_ash_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("ash", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, killing it once the timeout given as first
+/// argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_csh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("csh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, killing it once the timeout given as
+/// first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_ksh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("ksh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, killing it once the timeout given as first
+/// argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_zsh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("zsh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, killing it once the timeout
+/// given as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_bash_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("bash", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, killing it once the timeout
+/// given as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_dash_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("dash", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, killing it once the timeout given as
+/// first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_fish_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("fish", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, killing it once the timeout
+/// given as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_mksh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("mksh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, killing it once the timeout given as
+/// first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_tcsh_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("tcsh", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, killing it once the timeout
+/// given as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_cmd_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("cmd", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, killing it once the timeout given
+/// as first argument is reached, and wraping the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_powershell_timeout {
+    ( $timeout:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with_timeout("powershell", $timeout, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_sh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_ash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell and wraping its `Output`, stderr included,
+/// into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_csh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_ksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell and wraping its `Output`, stderr included,
+/// into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_zsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_bash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell and wraping its `Output`,
+/// stderr included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_dash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_fish {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_mksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_tcsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt and wraping its `Output`,
+/// stderr included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell and wraping its `Output`, stderr
+/// included, into a Result.
+///
+#[macro_export]
+macro_rules! wrap_full_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_full_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! sh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! ash_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! csh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! ksh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! zsh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! bash_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! dash_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! fish_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! mksh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell and return its result as an `Output`.
+///
+#[macro_export]
+macro_rules! tcsh_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! cmd_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell and return its result as an
+/// `Output`.
+///
+#[macro_export]
+macro_rules! powershell_out {
+    ( $( $cmd:tt )* ) => {{
+        $crate::output_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, quoting every interpolated argument
+/// for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! sh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("sh", &$arg) )*);
+        $crate::execute_with("sh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, quoting every interpolated argument
+/// for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! ash_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("ash", &$arg) )*);
+        $crate::execute_with("ash", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, quoting every interpolated argument for it
+/// (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! csh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("csh", &$arg) )*);
+        $crate::execute_with("csh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, quoting every interpolated argument for
+/// it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! ksh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("ksh", &$arg) )*);
+        $crate::execute_with("ksh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, quoting every interpolated argument for it
+/// (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! zsh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("zsh", &$arg) )*);
+        $crate::execute_with("zsh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, quoting every interpolated
+/// argument for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! bash_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("bash", &$arg) )*);
+        $crate::execute_with("bash", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, quoting every interpolated
+/// argument for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! dash_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("dash", &$arg) )*);
+        $crate::execute_with("dash", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, quoting every interpolated argument for
+/// it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! fish_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("fish", &$arg) )*);
+        $crate::execute_with("fish", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, quoting every interpolated
+/// argument for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! mksh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("mksh", &$arg) )*);
+        $crate::execute_with("mksh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, quoting every interpolated argument
+/// for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! tcsh_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("tcsh", &$arg) )*);
+        $crate::execute_with("tcsh", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, quoting every interpolated
+/// argument for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! cmd_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        let cmd = format!($fmt $( , $crate::quote::Quoted::new("cmd", &$arg) )*);
+        $crate::execute_with("cmd", &cmd)
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, quoting every interpolated argument
+/// for it (see `quote::for_shell`).
+///
+#[macro_export]
+macro_rules! powershell_safe {
+    ( $fmt:expr $( , $arg:expr )* $(,)* ) => {{
+        $crate::execute_with("powershell",
+                             &format!($fmt $( , $crate::quote::Quoted::new("powershell", &$arg) )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, feeding the second argument (anything
+/// viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! sh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("sh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! ash_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("ash", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, feeding the second argument (anything
+/// viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! csh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("csh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, feeding the second argument (anything
+/// viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! ksh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("ksh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, feeding the second argument (anything
+/// viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! zsh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("zsh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! bash_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("bash", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! dash_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("dash", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, feeding the second argument (anything
+/// viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! fish_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("fish", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! mksh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("mksh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! tcsh_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("tcsh", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! cmd_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("cmd", &$cmd, ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, feeding the second argument
+/// (anything viewable as bytes) to its standard input.
+///
+#[macro_export]
+macro_rules! powershell_with_input {
+    ( $cmd:expr, $input:expr ) => {{
+        $crate::execute_with_input("powershell", &$cmd,
+                                   ::std::convert::AsRef::<[u8]>::as_ref(&$input))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, with the environment variables given
+/// first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! sh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("sh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, with the environment variables
+/// given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! ash_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("ash", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, with the environment variables given first
+/// as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! csh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("csh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, with the environment variables given
+/// first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! ksh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("ksh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, with the environment variables given first
+/// as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! zsh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("zsh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, with the environment variables
+/// given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! bash_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("bash", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, with the environment
+/// variables given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! dash_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("dash", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, with the environment variables given
+/// first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! fish_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("fish", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, with the environment variables
+/// given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! mksh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("mksh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, with the environment variables given
+/// first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! tcsh_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("tcsh", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, with the environment
+/// variables given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! cmd_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("cmd", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, with the environment variables
+/// given first as `{"NAME" => value, ...}` set for it alone.
+///
+#[macro_export]
+macro_rules! powershell_env {
+    ( { $( $name:expr => $value:expr ),* $(,)* }, $( $cmd:tt )* ) => {{
+        let env = [ $( (::std::convert::Into::into($name),
+                        ::std::convert::Into::into($value)) ),* ];
+        $crate::execute_with_env("powershell", &env, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! sh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("sh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! ash_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("ash", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell in the directory given as first argument.
+///
+#[macro_export]
+macro_rules! csh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("csh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! ksh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("ksh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell in the directory given as first argument.
+///
+#[macro_export]
+macro_rules! zsh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("zsh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! bash_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("bash", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell in the directory given as
+/// first argument.
+///
+#[macro_export]
+macro_rules! dash_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("dash", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! fish_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("fish", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! mksh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("mksh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! tcsh_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("tcsh", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt in the directory given as
+/// first argument.
+///
+#[macro_export]
+macro_rules! cmd_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("cmd", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell in the directory given as first
+/// argument.
+///
+#[macro_export]
+macro_rules! powershell_in {
+    ( $dir:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_in("powershell", &$dir, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell and return its stdout and stderr as raw
+/// bytes.
+///
+#[macro_export]
+macro_rules! sh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell and return its stdout and stderr as
+/// raw bytes.
+///
+#[macro_export]
+macro_rules! ash_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell and return its stdout and stderr as raw
+/// bytes.
+///
+#[macro_export]
+macro_rules! csh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell and return its stdout and stderr as raw
+/// bytes.
+///
+#[macro_export]
+macro_rules! ksh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell and return its stdout and stderr as raw
+/// bytes.
+///
+#[macro_export]
+macro_rules! zsh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell and return its stdout and stderr
+/// as raw bytes.
+///
+#[macro_export]
+macro_rules! bash_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell and return its stdout and
+/// stderr as raw bytes.
+///
+#[macro_export]
+macro_rules! dash_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell and return its stdout and stderr as raw
+/// bytes.
+///
+#[macro_export]
+macro_rules! fish_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell and return its stdout and stderr
+/// as raw bytes.
+///
+#[macro_export]
+macro_rules! mksh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell and return its stdout and stderr as
+/// raw bytes.
+///
+#[macro_export]
+macro_rules! tcsh_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt and return its stdout and
+/// stderr as raw bytes.
+///
+#[macro_export]
+macro_rules! cmd_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell and return its stdout and stderr as
+/// raw bytes.
+///
+#[macro_export]
+macro_rules! powershell_bytes {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_bytes("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Posix Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! sh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("sh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Almquist Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! ash_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("ash", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the C
+/// Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! csh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("csh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Korn Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! ksh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("ksh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the Z
+/// Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! zsh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("zsh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Bourne Again Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! bash_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("bash", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Debian Almquist Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! dash_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("dash", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Fish Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! fish_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("fish", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// MirBSD Korn Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! mksh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("mksh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// TENEX C Shell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! tcsh_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("tcsh", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using the
+/// Windows Command Prompt, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! cmd_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("cmd", $script)
+    }};
+}
+
+/// Macro to execute the given multi-line script, as is rather than as a format string, using
+/// Windows PowerShell, passing it in a temporary file, see `execute_script_with`.
+///
+#[macro_export]
+macro_rules! powershell_script {
+    ( $script:expr ) => {{
+        $crate::execute_script_with("powershell", $script)
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, printing its output live on the stdout
+/// and stderr of the current process while capturing it like `sh!`.
+///
+#[macro_export]
+macro_rules! sh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, printing its output live on the
+/// stdout and stderr of the current process while capturing it like `ash!`.
+///
+#[macro_export]
+macro_rules! ash_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, printing its output live on the stdout and
+/// stderr of the current process while capturing it like `csh!`.
+///
+#[macro_export]
+macro_rules! csh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, printing its output live on the stdout
+/// and stderr of the current process while capturing it like `ksh!`.
+///
+#[macro_export]
+macro_rules! ksh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, printing its output live on the stdout and
+/// stderr of the current process while capturing it like `zsh!`.
+///
+#[macro_export]
+macro_rules! zsh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, printing its output live on the
+/// stdout and stderr of the current process while capturing it like `bash!`.
+///
+#[macro_export]
+macro_rules! bash_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, printing its output live on
+/// the stdout and stderr of the current process while capturing it like `dash!`.
+///
+#[macro_export]
+macro_rules! dash_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, printing its output live on the stdout
+/// and stderr of the current process while capturing it like `fish!`.
+///
+#[macro_export]
+macro_rules! fish_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, printing its output live on the
+/// stdout and stderr of the current process while capturing it like `mksh!`.
+///
+#[macro_export]
+macro_rules! mksh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, printing its output live on the
+/// stdout and stderr of the current process while capturing it like `tcsh!`.
+///
+#[macro_export]
+macro_rules! tcsh_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, printing its output live on
+/// the stdout and stderr of the current process while capturing it like `cmd!`.
+///
+#[macro_export]
+macro_rules! cmd_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, printing its output live on the
+/// stdout and stderr of the current process while capturing it like `powershell!`.
+///
+#[macro_export]
+macro_rules! powershell_tee {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_tee("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_sh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_ash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell and iterate over its output lines as they
+/// are printed.
+///
+#[macro_export]
+macro_rules! stream_csh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_ksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell and iterate over its output lines as they
+/// are printed.
+///
+#[macro_export]
+macro_rules! stream_zsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell and iterate over its output
+/// lines as they are printed.
+///
+#[macro_export]
+macro_rules! stream_bash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell and iterate over its output
+/// lines as they are printed.
+///
+#[macro_export]
+macro_rules! stream_dash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_fish {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell and iterate over its output lines
+/// as they are printed.
+///
+#[macro_export]
+macro_rules! stream_mksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_tcsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt and iterate over its output
+/// lines as they are printed.
+///
+#[macro_export]
+macro_rules! stream_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell and iterate over its output lines as
+/// they are printed.
+///
+#[macro_export]
+macro_rules! stream_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::stream_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Posix Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_sh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Almquist Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_ash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the C Shell, returning a `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_csh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Korn Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_ksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Z Shell, returning a `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_zsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Bourne Again Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_bash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Debian Almquist Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_dash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Fish Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_fish {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the MirBSD Korn Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_mksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the TENEX C Shell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_tcsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using the Windows Command Prompt, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to start the given command in the background using Windows PowerShell, returning a
+/// `ShellChild`.
+///
+#[macro_export]
+macro_rules! spawn_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::spawn_with("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell, running it up to the number of times
+/// given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_sh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("sh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell, running it up to the number of
+/// times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_ash {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("ash", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell, running it up to the number of times given
+/// as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_csh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("csh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell, running it up to the number of times
+/// given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_ksh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("ksh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell, running it up to the number of times given
+/// as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_zsh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("zsh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell, running it up to the number of
+/// times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_bash {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("bash", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell, running it up to the number
+/// of times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_dash {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("dash", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell, running it up to the number of times
+/// given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_fish {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("fish", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell, running it up to the number of
+/// times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_mksh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("mksh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell, running it up to the number of times
+/// given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_tcsh {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("tcsh", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt, running it up to the number
+/// of times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_cmd {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("cmd", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell, running it up to the number of
+/// times given as first argument until it succeeds, and wraping the last result into a Result.
+///
+#[macro_export]
+macro_rules! retry_powershell {
+    ( $attempts:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with_retry("powershell", $attempts, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command on a `target::Target`, given as first argument, returning
+/// the same tuple as `sh!`.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let target = shells::Shell::new("sh");
+///     let (code, stdout, _) = run_on!(target, "echo {}", "hello");
+///
+///     assert_eq!((code, &stdout[..]), (0, "hello\n"));
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! run_on {
+    ( $target:expr, $( $cmd:tt )* ) => {{
+        $crate::target::Target::run(&$target, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command on a `target::Target`, given as first argument, and wraping
+/// the result into a Result.
+///
+#[macro_export]
+macro_rules! wrap_run_on {
+    ( $target:expr, $( $cmd:tt )* ) => {{
+        $crate::target::Target::try_run(&$target, &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to build a `pipeline::Pipeline` from its stages, see the `pipeline` module.
+///
+#[macro_export]
+macro_rules! pipeline {
+    ( $( $stage:expr ),* $(,)* ) => {{
+        $crate::pipeline::Pipeline::new() $( .stage($stage) )*
+    }};
+}
+
+/// Macro to execute the given command using the shell given as first argument, chosen at runtime:
+/// a `ShellKind`, or the name or path of the program as a `&str` or `String`.
+///
+#[macro_export]
+macro_rules! shell {
+    ( $shell:expr, $( $cmd:tt )* ) => {{
+        $crate::execute_with(::std::convert::AsRef::<str>::as_ref(&$shell), &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the shell given as first argument, see `shell!`, and
+/// wraping the resulting tuple into a `Result`.
+///
+#[macro_export]
+macro_rules! wrap_shell {
+    ( $shell:expr, $( $cmd:tt )* ) => {{
+        $crate::wrap_with(::std::convert::AsRef::<str>::as_ref(&$shell), &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute a multi-line script, given as is rather than as a format string, with the
+/// given shell. The errors the shell reports at a line of the script mention the corresponding
+/// location in the Rust sources, see `Shell::source`; the string literal must start on the line of
+/// the macro.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let (code, _, stderr) = script!("sh", "echo start
+/// if true; then
+/// fi");
+///     assert_eq!(code, 2);
+///     assert!(stderr.ends_with(&format!("({}:{})\n", file!(), line!() - 2)));
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! script {
+    ( $shell:expr, $script:expr ) => {{
+        $crate::Shell::new($shell).source(file!(), line!()).run($script)
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell from async code, returning a future
+/// resolving to the same tuple as `sh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_sh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell from async code, returning a future
+/// resolving to the same tuple as `ash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_ash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell from async code, returning a future
+/// resolving to the same tuple as `csh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_csh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell from async code, returning a future
+/// resolving to the same tuple as `ksh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_ksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell from async code, returning a future
+/// resolving to the same tuple as `zsh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_zsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell from async code, returning a
+/// future resolving to the same tuple as `bash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_bash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell from async code, returning a
+/// future resolving to the same tuple as `dash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_dash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell from async code, returning a future
+/// resolving to the same tuple as `fish!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_fish {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell from async code, returning a
+/// future resolving to the same tuple as `mksh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_mksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell from async code, returning a future
+/// resolving to the same tuple as `tcsh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_tcsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt from async code, returning a
+/// future resolving to the same tuple as `cmd!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell from async code, returning a future
+/// resolving to the same tuple as `powershell!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::execute_with_async("powershell", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Posix Shell from async code, returning a future
+/// resolving to the same Result as `wrap_sh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_sh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("sh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Almquist Shell from async code, returning a future
+/// resolving to the same Result as `wrap_ash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_ash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("ash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the C Shell from async code, returning a future
+/// resolving to the same Result as `wrap_csh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_csh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("csh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Korn Shell from async code, returning a future
+/// resolving to the same Result as `wrap_ksh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_ksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("ksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Z Shell from async code, returning a future
+/// resolving to the same Result as `wrap_zsh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_zsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("zsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Bourne Again Shell from async code, returning a
+/// future resolving to the same Result as `wrap_bash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_bash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("bash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Debian Almquist Shell from async code, returning a
+/// future resolving to the same Result as `wrap_dash!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_dash {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("dash", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Fish Shell from async code, returning a future
+/// resolving to the same Result as `wrap_fish!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_fish {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("fish", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the MirBSD Korn Shell from async code, returning a
+/// future resolving to the same Result as `wrap_mksh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_mksh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("mksh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the TENEX C Shell from async code, returning a future
+/// resolving to the same Result as `wrap_tcsh!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_tcsh {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("tcsh", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using the Windows Command Prompt from async code, returning a
+/// future resolving to the same Result as `wrap_cmd!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_cmd {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("cmd", &format!($( $cmd )*))
+    }};
+}
+
+/// Macro to execute the given command using Windows PowerShell from async code, returning a future
+/// resolving to the same Result as `wrap_powershell!`. Requires the `tokio` feature.
+///
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! async_wrap_powershell {
+    ( $( $cmd:tt )* ) => {{
+        $crate::wrap_with_async("powershell", &format!($( $cmd )*))
+    }};
+}
+
+#[doc(hidden)]
+pub fn execute_with(shell: &str, cmd: &str) -> (i32, String, String) {
+    Shell::new(shell).run(cmd)
+}
+
+#[doc(hidden)]
+pub fn output_with(shell: &str, cmd: &str) -> Output {
+    Shell::new(shell).output(cmd)
+}
+
+#[doc(hidden)]
+pub fn wrap_with(shell: &str, cmd: &str) -> Result {
+    Shell::new(shell).try_run(cmd)
+}
+
+#[doc(hidden)]
+pub fn wrap_full_with(shell: &str, cmd: &str) -> ::std::result::Result<Output, Error> {
+    Shell::new(shell).try_output(cmd)
+}
+
+#[doc(hidden)]
+pub fn wrap_with_timeout(shell: &str, timeout: ::std::time::Duration, cmd: &str) -> Result {
+    Shell::new(shell).timeout(timeout).try_run(cmd)
+}
+
+/// Executes the command with the given shell, feeding `stdin` to its standard input, and returns
+/// its exit code, stdout and stderr.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let (code, stdout, _) = shells::execute_with_input("sh", "grep foo", b"foo\nbar\nfood\n");
+///
+///     assert_eq!(code, 0);
+///     assert_eq!(&stdout[..], "foo\nfood\n");
+///     assert_eq!(sh_with_input!("tr a-z A-Z", "loud").1, "LOUD");
+/// }
+/// ```
+///
+pub fn execute_with_input(shell: &str, cmd: &str, stdin: &[u8]) -> (i32, String, String) {
+    Shell::new(shell).stdin(stdin).run(cmd)
+}
+
+/// Executes the command with the given shell and environment variables, set for it alone, and
+/// returns its exit code, stdout and stderr.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let env = [("GREETING".into(), "hello".into())];
+///
+///     assert_eq!(shells::execute_with_env("sh", &env, "echo $GREETING").1, "hello\n");
+///     assert_eq!(sh_env!({"A" => "1", "B" => 2.to_string()}, "echo $A$B").1, "12\n");
+/// }
+/// ```
+///
+pub fn execute_with_env(shell: &str, env: &[(OsString, OsString)], cmd: &str)
+    -> (i32, String, String)
+{
+    Shell::new(shell).envs(env.iter().cloned()).run(cmd)
+}
+
+/// Executes the command with the given shell in the directory `dir`, and returns its exit code,
+/// stdout and stderr.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let dir = ::std::env::temp_dir().join("shells doc");
+///     ::std::fs::create_dir_all(&dir).unwrap();
+///
+///     assert_eq!(sh_in!(dir, "basename \"$(pwd)\"").1, "shells doc\n");
+///     assert_eq!(shells::execute_in("sh", "/", "pwd").1, "/\n");
+/// }
+/// ```
+///
+pub fn execute_in<P: AsRef<::std::path::Path>>(shell: &str, dir: P, cmd: &str)
+    -> (i32, String, String)
+{
+    Shell::new(shell).cwd(dir.as_ref()).run(cmd)
+}
+
+/// Executes the command with the given shell and returns its exit code, stdout and stderr as
+/// raw bytes, for commands whose output is not UTF-8 text.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let (code, stdout, _) = shells::execute_with_bytes("sh", "printf 'caf\\351'");
+///
+///     assert_eq!(code, 0);
+///     assert_eq!(stdout, b"caf\xe9");
+///     assert_eq!(sh_bytes!("printf 'caf\\351'").1, b"caf\xe9");
+/// }
+/// ```
+///
+pub fn execute_with_bytes(shell: &str, cmd: &str) -> (i32, Vec<u8>, Vec<u8>) {
+    Shell::new(shell).run_bytes(cmd)
+}
+
+/// Executes the multi-line script with the given shell, like `execute_with` but passing the script
+/// to the shell in a temporary file rather than after `-c`, see `Shell::script_file`. Used by the
+/// `*_script` macros.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// fn main() {
+///     let (code, stdout, _) = sh_script!(r#"
+///         for word in "it's" '"quoted"' '$HOME'; do
+///             printf '%s\n' "$word"
+///         done
+///     "#);
+///
+///     assert_eq!((code, &stdout[..]), (0, "it's\n\"quoted\"\n$HOME\n"));
+/// }
+/// ```
+///
+pub fn execute_script_with(shell: &str, script: &str) -> (i32, String, String) {
+    Shell::new(shell).script_file().run(script)
+}
+
+/// Executes the command with the given shell like `execute_with`, also printing its output live
+/// on the stdout and stderr of the current process, see `Shell::tee`. Used by the `*_tee` macros.
+///
+pub fn execute_with_tee(shell: &str, cmd: &str) -> (i32, String, String) {
+    Shell::new(shell).tee().run(cmd)
+}
+
+/// Executes the command with the given shell like `wrap_with`, running it again while it fails up
+/// to `attempts` times in total, waiting 1 second before the second attempt and twice as long
+/// before each next one. Used by the `retry_*` macros; see `retry::Retry` for other policies.
+///
+pub fn execute_with_retry(shell: &str, attempts: u32, cmd: &str) -> Result {
+    let policy = ::retry::Retry::new(attempts).backoff(::std::time::Duration::from_secs(1));
+    Shell::new(shell).retry(policy).try_run(cmd)
+}
+
+#[doc(hidden)]
+pub fn stream_with(shell: &str, cmd: &str) -> Lines {
+    Shell::new(shell).stream(cmd)
+}
+
+#[doc(hidden)]
+pub fn spawn_with(shell: &str, cmd: &str) -> ::std::io::Result<ShellChild> {
+    Shell::new(shell).spawn(cmd)
+}
+
+#[cfg(feature = "tokio")]
+#[doc(hidden)]
+pub fn execute_with_async(shell: &str, cmd: &str) -> Execution<(i32, String, String)> {
+    Shell::new(shell).run_async(cmd)
+}
+
+#[cfg(feature = "tokio")]
+#[doc(hidden)]
+pub fn wrap_with_async(shell: &str, cmd: &str) -> Execution<Result> {
+    Shell::new(shell).try_run_async(cmd)
 }
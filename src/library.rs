@@ -0,0 +1,125 @@
+//! Libraries of shell functions embedded in the program and called by name.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::library::Library;
+//!
+//! // Typically `include_str!("deploy.sh")`.
+//! const FUNCTIONS: &str = r#"
+//! greet() {
+//!     echo "hello $1, you are $2"
+//! }
+//! "#;
+//!
+//! let library = Library::new(Shell::new("sh")).source(FUNCTIONS);
+//!
+//! assert_eq!(library.functions(), vec!["greet"]);
+//! assert_eq!(library.call_fn("greet", &["O'Brien", "welcome"]).1,
+//!            "hello O'Brien, you are welcome\n");
+//! ```
+//!
+//! Arguments are quoted for POSIX shells, so the library must be written for a POSIX-compatible
+//! shell (sh, bash, dash, ksh, zsh...).
+//!
+
+use std::fmt::Display;
+
+use shell::Shell;
+
+/// A set of shell functions sourced before each call.
+///
+#[derive(Debug, Clone)]
+pub struct Library {
+    shell: Shell,
+    code: String,
+}
+
+impl Library {
+    /// Creates an empty library executed with the given shell.
+    ///
+    pub fn new(shell: Shell) -> Library {
+        Library { shell, code: String::new() }
+    }
+
+    /// Adds shell code to the library, typically embedded with `include_str!`.
+    ///
+    pub fn source(mut self, code: &str) -> Library {
+        self.code.push_str(code);
+
+        if !code.ends_with('\n') {
+            self.code.push('\n');
+        }
+
+        self
+    }
+
+    /// Names of the functions defined by the library, in order of definition.
+    ///
+    pub fn functions(&self) -> Vec<String> {
+        let mut functions: Vec<String> = Vec::new();
+
+        for name in self.code.lines().filter_map(function_name) {
+            if !functions.contains(&name) {
+                functions.push(name);
+            }
+        }
+
+        functions
+    }
+
+    /// Calls the function `name` with the given arguments, each quoted so that the function
+    /// receives it verbatim. An invalid function name is rejected with the code 126.
+    ///
+    pub fn call_fn<I>(&self, name: &str, args: I) -> (i32, String, String)
+        where I: IntoIterator, I::Item: Display
+    {
+        if !is_identifier(name) {
+            return (126, String::new(), format!("invalid function name: {:?}", name));
+        }
+
+        let mut cmd = name.to_string();
+
+        for arg in args {
+            cmd.push(' ');
+            cmd.push_str(&::quote::posix(&arg.to_string()));
+        }
+
+        self.run(&cmd)
+    }
+
+    /// Runs arbitrary code with the library sourced.
+    ///
+    pub fn run(&self, cmd: &str) -> (i32, String, String) {
+        self.shell.clone().preamble(&self.code).run(cmd)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ':')
+}
+
+/// Extracts the function defined on a line, written either `name() ...` or `function name ...`.
+///
+fn function_name(line: &str) -> Option<String> {
+    let line = line.trim_start();
+
+    if let Some(rest) = line.strip_prefix("function ") {
+        let rest = rest.trim_start();
+        let end = rest.find(|c: char| c.is_whitespace() || c == '(' || c == '{')
+            .unwrap_or(rest.len());
+
+        return if is_identifier(&rest[..end]) { Some(rest[..end].to_string()) } else { None };
+    }
+
+    let end = line.find(|c: char| c.is_whitespace() || c == '(')?;
+    let (name, rest) = (&line[..end], line[end..].trim_start());
+
+    if is_identifier(name) && rest.starts_with('(') && rest[1..].trim_start().starts_with(')') {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
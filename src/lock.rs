@@ -0,0 +1,257 @@
+//! Mutual exclusion between processes through lock files.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use std::time::Duration;
+//! use shells::lock::{self, Lock};
+//!
+//! fn main() {
+//!     // Waits as long as needed.
+//!     lock::with_lock("/var/lock/backup.lock", || sh!("backup.sh")).unwrap();
+//!
+//!     // Gives up after a minute.
+//!     Lock::new("/var/lock/backup.lock")
+//!         .timeout(Duration::from_secs(60))
+//!         .run(|| sh!("backup.sh"))
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! Script-like binaries which must never run twice concurrently can use `single_instance` as the
+//! first thing in `main`:
+//!
+//! ```rust,no_run
+//! let _instance = match shells::lock::single_instance(env!("CARGO_PKG_NAME")) {
+//!     Ok(guard) => guard,
+//!     Err(e) => { eprintln!("{}", e); std::process::exit(1); },
+//! };
+//! ```
+//!
+//! The locks are advisory operating system locks (`flock` on Unix, `LockFileEx` on Windows): a
+//! lock held by a process which crashed is released by the kernel, so a leftover lock file never
+//! blocks anyone. The process holding the lock writes its pid in the file; when acquiring times
+//! out and that process is gone, the lock is reported as stale, meaning it is held by a process
+//! which inherited the descriptor (typically a daemonized child).
+//!
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clock::{self, Clock};
+
+/// Error returned when a lock cannot be acquired.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// The lock was still held by another process when the timeout expired.
+    TimedOut {
+        path: PathBuf,
+        holder: Option<u32>,
+        stale: bool,
+    },
+
+    /// Another instance of the program is running.
+    AlreadyRunning {
+        name: String,
+        pid: Option<u32>,
+    },
+
+    /// The lock file could not be opened or locked.
+    Io(io::Error),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Lock acquisition failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::TimedOut { ref path, holder, stale } => {
+                write!(f, "timed out waiting for lock {}", path.display())?;
+
+                match holder {
+                    Some(pid) if stale => {
+                        write!(f, " (holder {} is gone but a process which inherited the lock \
+                                   still holds it)", pid)
+                    },
+
+                    Some(pid) => write!(f, " (held by process {})", pid),
+                    None => Ok(()),
+                }
+            },
+
+            Error::AlreadyRunning { ref name, pid: Some(pid) } => {
+                write!(f, "{} is already running (pid {})", name, pid)
+            },
+
+            Error::AlreadyRunning { ref name, pid: None } => {
+                write!(f, "{} is already running", name)
+            },
+
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Held lock, released when dropped.
+///
+#[derive(Debug)]
+pub struct Guard {
+    file: File,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Lock file with an optional timeout.
+///
+#[derive(Debug, Clone)]
+pub struct Lock {
+    path: PathBuf,
+    timeout: Option<Duration>,
+    poll: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl Lock {
+    /// Creates a lock on the given file, created if needed. Without a timeout, acquiring it waits
+    /// for as long as another process holds it.
+    ///
+    pub fn new<P: AsRef<Path>>(path: P) -> Lock {
+        Lock {
+            path: path.as_ref().to_path_buf(),
+            timeout: None,
+            poll: Duration::from_millis(100),
+            clock: clock::system(),
+        }
+    }
+
+    /// Gives up acquiring the lock after the given duration.
+    ///
+    pub fn timeout(mut self, timeout: Duration) -> Lock {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Interval between two attempts at acquiring the lock (100ms by default).
+    ///
+    pub fn poll_interval(mut self, poll: Duration) -> Lock {
+        self.poll = poll;
+        self
+    }
+
+    /// Clock used to measure the timeout and wait between attempts.
+    ///
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Lock {
+        self.clock = clock;
+        self
+    }
+
+    /// Acquires the lock, returning a guard which releases it when dropped.
+    ///
+    pub fn acquire(&self) -> Result<Guard, Error> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+            .open(&self.path)?;
+
+        let start = self.clock.now();
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::Error(e)) => return Err(Error::Io(e)),
+
+                Err(TryLockError::WouldBlock) => {
+                    if let Some(timeout) = self.timeout {
+                        if self.clock.now() - start >= timeout {
+                            let holder = holder(&mut file);
+
+                            return Err(Error::TimedOut {
+                                path: self.path.clone(),
+                                holder,
+                                stale: holder.map(|pid| !is_alive(pid)).unwrap_or(false),
+                            });
+                        }
+                    }
+
+                    self.clock.sleep(self.poll);
+                },
+            }
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", ::std::process::id())?;
+        file.flush()?;
+
+        Ok(Guard { file })
+    }
+
+    /// Runs `f` while holding the lock.
+    ///
+    pub fn run<R, F: FnOnce() -> R>(&self, f: F) -> Result<R, Error> {
+        let _guard = self.acquire()?;
+        Ok(f())
+    }
+}
+
+/// Runs `f` while holding the lock on `path`, waiting for as long as needed to acquire it.
+///
+pub fn with_lock<P: AsRef<Path>, R, F: FnOnce() -> R>(path: P, f: F) -> Result<R, Error> {
+    Lock::new(path).run(f)
+}
+
+/// Guarantees that a single instance of the program named `name` runs at a time: returns a guard
+/// to keep alive for the whole life of the process, or `Error::AlreadyRunning` if another
+/// instance holds it. The lock file is `<name>.lock` in `$XDG_RUNTIME_DIR`, or in the temporary
+/// directory when it is not set.
+///
+pub fn single_instance(name: &str) -> Result<Guard, Error> {
+    let dir = ::std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+        .unwrap_or_else(::std::env::temp_dir);
+
+    match Lock::new(dir.join(format!("{}.lock", name))).timeout(Duration::from_secs(0)).acquire() {
+        Err(Error::TimedOut { holder, .. }) => {
+            Err(Error::AlreadyRunning { name: name.to_string(), pid: holder })
+        },
+
+        result => result,
+    }
+}
+
+fn holder(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    unsafe {
+        ::sys::kill(pid as i32, 0) == 0
+            || io::Error::last_os_error().raw_os_error() == Some(::sys::EPERM)
+    }
+}
+
+#[cfg(not(unix))]
+fn is_alive(_: u32) -> bool {
+    true
+}
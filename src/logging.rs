@@ -0,0 +1,66 @@
+//! Entries of the `log` crate for the executions, see the `log` feature. Without the feature,
+//! nothing is logged.
+//!
+//! Each blocking or async execution of a command logs, with the target `shells`:
+//!
+//! * when it starts, a `debug` entry with the program executed, usually the shell, and the
+//!   command without the preamble;
+//! * when it exits, an `info` entry with its exit code and duration, a `warn` entry when the code
+//!   is not zero, e.g. 124 when the command timed out;
+//! * a `warn` entry instead when it could not be started or waited for.
+//!
+
+use std::io;
+
+use shell::PreparedCommand;
+
+/// The log entries of an execution.
+///
+pub(crate) struct Events {
+    #[cfg(feature = "log")]
+    command: String,
+
+    #[cfg(feature = "log")]
+    start: ::std::time::Instant,
+}
+
+#[cfg(feature = "log")]
+impl Events {
+    /// Logs the start of the command, which is about to be spawned.
+    ///
+    pub(crate) fn start(prepared: &PreparedCommand) -> Events {
+        let program = prepared.argv().first().map(|program| program.to_string_lossy().into_owned());
+        let command = prepared.command_text().to_string();
+
+        let program = program.unwrap_or_default();
+        ::log::debug!(target: "shells", "running `{}` with {}", command, program);
+        Events { command, start: ::std::time::Instant::now() }
+    }
+
+    /// Logs the exit of the command.
+    ///
+    pub(crate) fn end(self, code: i32) {
+        let (command, elapsed) = (&self.command, self.start.elapsed());
+        let level = if code == 0 { ::log::Level::Info } else { ::log::Level::Warn };
+
+        ::log::log!(target: "shells", level, "`{}` exited with code {} after {:?}",
+                    command, code, elapsed);
+    }
+
+    /// Logs that the command could not be started, or waited for.
+    ///
+    pub(crate) fn failed(self, error: &io::Error) {
+        ::log::warn!(target: "shells", "`{}` failed to run: {}", self.command, error);
+    }
+}
+
+#[cfg(not(feature = "log"))]
+impl Events {
+    pub(crate) fn start(_: &PreparedCommand) -> Events {
+        Events {}
+    }
+
+    pub(crate) fn end(self, _: i32) {}
+
+    pub(crate) fn failed(self, _: &io::Error) {}
+}
@@ -0,0 +1,186 @@
+//! Memoized shell steps: skip running a command when neither its inputs nor its outputs changed
+//! since the last successful run.
+//!
+//! A step is identified by the shell configuration (program, arguments, environment, directory,
+//! stdin and preamble), the command and the content of its declared inputs. After a successful
+//! run, a stamp named after that fingerprint is written in the cache directory along with the
+//! hashes of the outputs. Subsequent runs find the stamp and, as long as every recorded output
+//! still has the same content, return `Outcome::Cached` without executing anything. A declared
+//! output the command did not produce makes the step run again.
+//!
+//! Outputs can be declared explicitly or detected with a `Tracker` watching a directory, in which
+//! case every file the command created or modified there is recorded. Inputs are never detected
+//! and must be declared.
+//!
+//! ```rust,no_run
+//! use shells::Shell;
+//! use shells::memo::{Outcome, Step};
+//!
+//! let step = Step::new(Shell::new("sh"), "cc -c main.c -o main.o")
+//!     .input("main.c")
+//!     .output("main.o");
+//!
+//! match step.run(".cache/steps").unwrap() {
+//!     Outcome::Cached => println!("main.o is up to date"),
+//!     Outcome::Ran(code, _, stderr) => assert_eq!(code, 0, "{}", stderr),
+//! }
+//! ```
+//!
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha256::{self, Sha256};
+use shell::Shell;
+use track::Tracker;
+
+/// Hash recorded for the outputs missing after a run, which no file matches.
+const MISSING: &str = "missing";
+
+/// Result of running a step.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The step was up to date and did not run.
+    Cached,
+
+    /// The step ran, with the given code, stdout and stderr.
+    Ran(i32, String, String),
+}
+
+/// A command whose execution is skipped when it is up to date.
+///
+#[derive(Debug, Clone)]
+pub struct Step {
+    shell: Shell,
+    command: String,
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    tracker: Option<Tracker>,
+}
+
+impl Step {
+    pub fn new(shell: Shell, command: &str) -> Step {
+        Step {
+            shell,
+            command: command.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            tracker: None,
+        }
+    }
+
+    /// Declares a file the command reads.
+    ///
+    pub fn input<P: AsRef<Path>>(mut self, path: P) -> Step {
+        self.inputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Declares a file the command produces.
+    ///
+    pub fn output<P: AsRef<Path>>(mut self, path: P) -> Step {
+        self.outputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Records as outputs every file the command creates or modifies below `root`. The directories
+    /// it creates are not outputs.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    /// use shells::memo::{Outcome, Step};
+    ///
+    /// let dir = ::std::env::temp_dir().join(format!("shells-memo-{}", ::std::process::id()));
+    /// let shell = Shell::new("sh").cwd(dir.join("build"));
+    /// ::std::fs::create_dir_all(dir.join("build")).unwrap();
+    ///
+    /// let step = Step::new(shell, "mkdir -p gen && echo 'int x;' > gen/x.c")
+    ///     .detect_outputs(dir.join("build"));
+    ///
+    /// let ran = Outcome::Ran(0, String::new(), String::new());
+    ///
+    /// assert_eq!(step.run(dir.join("cache")).unwrap(), ran);
+    /// assert_eq!(step.run(dir.join("cache")).unwrap(), Outcome::Cached);
+    ///
+    /// ::std::fs::remove_dir_all(dir).unwrap();
+    /// ```
+    ///
+    pub fn detect_outputs<P: AsRef<Path>>(mut self, root: P) -> Step {
+        self.tracker = Some(match self.tracker.take() {
+            Some(tracker) => tracker.root(root),
+            None => Tracker::new(root),
+        });
+
+        self
+    }
+
+    /// Fingerprint of the step, which changes whenever the command or its inputs do.
+    ///
+    pub fn fingerprint(&self) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}\0{}\0", self.shell.memo_key(), self.command).as_bytes());
+
+        for input in &self.inputs {
+            hasher.update(format!("{}\0{}\0", input.display(), sha256::file(input)?).as_bytes());
+        }
+
+        Ok(hasher.hex_digest())
+    }
+
+    /// Runs the step unless the cache directory holds an up to date stamp for it. Only successful
+    /// runs are recorded. Errors are limited to failures reading inputs or maintaining the cache.
+    ///
+    pub fn run<P: AsRef<Path>>(&self, cache: P) -> io::Result<Outcome> {
+        let stamp = cache.as_ref().join(format!("{}.stamp", self.fingerprint()?));
+
+        if is_fresh(&stamp) {
+            return Ok(Outcome::Cached);
+        }
+
+        let ((code, stdout, stderr), detected) = match self.tracker {
+            Some(ref tracker) => {
+                let (result, changes) = tracker.run(|| self.shell.run(&self.command));
+                (result, changes.touched().filter(|path| path.is_file()).cloned().collect())
+            },
+
+            None => (self.shell.run(&self.command), Vec::new()),
+        };
+
+        if code == 0 {
+            let mut content = String::new();
+
+            for output in self.outputs.iter().chain(detected.iter()) {
+                // A declared output the command did not produce keeps the stamp stale.
+                let hash = match output.is_file() {
+                    true => sha256::file(output)?,
+                    false => MISSING.to_string(),
+                };
+
+                content.push_str(&format!("{}\t{}\n", hash, output.display()));
+            }
+
+            fs::create_dir_all(cache.as_ref())?;
+            fs::write(&stamp, content)?;
+        }
+
+        Ok(Outcome::Ran(code, stdout, stderr))
+    }
+}
+
+fn is_fresh(stamp: &Path) -> bool {
+    let content = match fs::read_to_string(stamp) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+
+    content.lines().all(|line| {
+        let mut fields = line.splitn(2, '\t');
+
+        match (fields.next(), fields.next()) {
+            (Some(hash), Some(path)) => sha256::file(path).map(|h| h == hash).unwrap_or(false),
+            _ => false,
+        }
+    })
+}
@@ -0,0 +1,250 @@
+//! Execution metrics in the Prometheus text format.
+//!
+//! A shell given a `Metrics` registry with `Shell::metrics` counts its runs, failures and
+//! timeouts and records their durations, labelled by the job name given along. The registry is
+//! shared by its clones: render it with `Metrics::render` from an existing HTTP server, or let
+//! `Metrics::serve` answer the scrapes of Prometheus on a port of its own.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use shells::Shell;
+//! use shells::metrics::Metrics;
+//!
+//! let metrics = Metrics::new();
+//! let shell = Shell::new("sh").metrics(&metrics, "backup");
+//!
+//! shell.run("true");
+//! shell.run("exit 1");
+//!
+//! let text = metrics.render();
+//!
+//! assert!(text.contains("shells_runs_total{job=\"backup\"} 2\n"));
+//! assert!(text.contains("shells_failures_total{job=\"backup\"} 1\n"));
+//! assert!(text.contains("shells_run_duration_seconds_count{job=\"backup\"} 2\n"));
+//!
+//! shell.run("exit 124");
+//! shell.clone().timeout(Duration::from_millis(100)).run("sleep 5");
+//!
+//! assert!(metrics.render().contains("shells_timeouts_total{job=\"backup\"} 1\n"));
+//! ```
+//!
+//! The metrics are:
+//!
+//! * `shells_runs_total`: the commands run, counted once they exited;
+//! * `shells_failures_total`: the commands which exited with a non-zero code;
+//! * `shells_timeouts_total`: the commands killed by their timeout;
+//! * `shells_restarts_total`: the restarts recorded with `Metrics::record_restart`;
+//! * `shells_run_duration_seconds`: a histogram of the durations of the commands.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the duration histogram, in seconds.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// Names and descriptions of the counters, in the order of `Job::counters`.
+const COUNTERS: &[(&str, &str)] = &[
+    ("runs", "Commands run."),
+    ("failures", "Commands which exited with a non-zero code."),
+    ("timeouts", "Commands killed by their timeout."),
+    ("restarts", "Restarts of the jobs."),
+];
+
+/// Registry of execution metrics, see the module documentation.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    jobs: Arc<Mutex<BTreeMap<String, Job>>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Job {
+    runs: u64,
+    failures: u64,
+    timeouts: u64,
+    restarts: u64,
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+}
+
+impl Job {
+    fn counters(&self) -> [u64; 4] {
+        [self.runs, self.failures, self.timeouts, self.restarts]
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Records a run of the job which exited with the given code after `duration`, and whether it
+    /// was killed by its timeout: a command exiting with the code 124 by itself is no timeout.
+    ///
+    pub fn record(&self, job: &str, code: i32, timed_out: bool, duration: Duration) {
+        self.update(job, |metrics| {
+            let seconds = duration.as_secs_f64();
+
+            metrics.runs += 1;
+            metrics.failures += u64::from(code != 0);
+            metrics.timeouts += u64::from(timed_out);
+            metrics.sum += seconds;
+
+            for (bucket, bound) in metrics.buckets.iter_mut().zip(BUCKETS) {
+                *bucket += u64::from(seconds <= *bound);
+            }
+        });
+    }
+
+    /// Records a restart of the job, e.g. by a supervisor.
+    ///
+    pub fn record_restart(&self, job: &str) {
+        self.update(job, |metrics| metrics.restarts += 1);
+    }
+
+    fn update<F: FnOnce(&mut Job)>(&self, job: &str, f: F) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        f(jobs.entry(job.to_string()).or_default());
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    ///
+    pub fn render(&self) -> String {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut text = String::new();
+
+        for (i, &(name, help)) in COUNTERS.iter().enumerate() {
+            let _ = writeln!(text, "# HELP shells_{}_total {}", name, help);
+            let _ = writeln!(text, "# TYPE shells_{}_total counter", name);
+
+            for (job, metrics) in &jobs {
+                let value = metrics.counters()[i];
+                let job = escape(job);
+                let _ = writeln!(text, "shells_{}_total{{job=\"{}\"}} {}", name, job, value);
+            }
+        }
+
+        text.push_str("# HELP shells_run_duration_seconds Durations of the commands.\n");
+        text.push_str("# TYPE shells_run_duration_seconds histogram\n");
+
+        for (job, metrics) in &jobs {
+            let job = escape(job);
+
+            for (bucket, bound) in metrics.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(text, "shells_run_duration_seconds_bucket{{job=\"{}\",le=\"{}\"}} \
+                                        {}", job, bound, bucket);
+            }
+
+            let _ = writeln!(text, "shells_run_duration_seconds_bucket{{job=\"{}\",le=\"+Inf\"}} \
+                                    {}", job, metrics.runs);
+            let _ = writeln!(text, "shells_run_duration_seconds_sum{{job=\"{}\"}} {}",
+                             job, metrics.sum);
+            let _ = writeln!(text, "shells_run_duration_seconds_count{{job=\"{}\"}} {}",
+                             job, metrics.runs);
+        }
+
+        text
+    }
+
+    /// Serves the metrics over HTTP from a background thread, answering every request with the
+    /// rendered metrics. Use the port 0 to let the system pick a free port, see
+    /// `Endpoint::local_addr`.
+    ///
+    /// ```rust,no_run
+    /// use shells::metrics::Metrics;
+    ///
+    /// let metrics = Metrics::new();
+    /// let _endpoint = metrics.serve("0.0.0.0:9184").unwrap();
+    /// ```
+    ///
+    pub fn serve<A: ToSocketAddrs>(&self, address: A) -> io::Result<Endpoint> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let metrics = self.clone();
+        let stop = stopped.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let _ = respond(stream, &metrics.render());
+                }
+            }
+        });
+
+        Ok(Endpoint { address, stopped })
+    }
+}
+
+/// HTTP endpoint serving metrics, stopped when dropped.
+///
+#[derive(Debug)]
+pub struct Endpoint {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Endpoint {
+    /// The address the endpoint listens on.
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // Wakes the thread up, blocked until the next connection.
+        let mut address = self.address;
+
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+    }
+}
+
+/// Reads the request up to the end of its headers, and sends the metrics back.
+///
+fn respond(stream: TcpStream, body: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+
+    let mut stream = reader.into_inner();
+
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)?;
+    stream.flush()
+}
+
+/// Escapes the value of a label.
+///
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,251 @@
+//! Scripted execution for testing code built on top of this crate.
+//!
+//! While `mock::with` runs, every command executed on the current thread (through the macros,
+//! `Shell` or `PreparedCommand`) is answered by the next response of a `Scenario` instead of
+//! being executed. Responses can inject delays, partial output, failures and signal deaths, so
+//! retry and timeout logic can be exercised deterministically.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::mock::{self, Response, Scenario};
+//!
+//! fn main() {
+//!     let scenario = Scenario::new()
+//!         .respond(Response::exit(7).stderr("connection refused\n"))
+//!         .respond(Response::signal(9).stdout("half of the out"))
+//!         .respond(Response::success().stdout("done\n"));
+//!
+//!     let codes = mock::with(scenario.clone(), || {
+//!         (0..3).map(|_| sh!("curl -f https://example.com").0).collect::<Vec<_>>()
+//!     });
+//!
+//!     assert_eq!(codes, vec![7, 137, 0]);
+//!     assert_eq!(scenario.calls().len(), 3);
+//! }
+//! ```
+//!
+//! Running a command once the scenario is exhausted panics, as it means the code under test ran
+//! more commands than expected.
+//!
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clock::{self, Clock};
+use output::ExitStatus;
+use shell::{Outcome, PreparedCommand};
+
+/// Scripted outcome of a single command.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    delay: Duration,
+    status: ExitStatus,
+    stdout: String,
+    stderr: String,
+}
+
+impl Response {
+    /// A command exiting with code 0.
+    ///
+    pub fn success() -> Response {
+        Response::exit(0)
+    }
+
+    /// A command exiting with the given code.
+    ///
+    pub fn exit(code: i32) -> Response {
+        Response {
+            delay: Duration::from_secs(0),
+            status: ExitStatus::Exited(code),
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    /// A command killed by the given signal, reported the same way real signal deaths are.
+    ///
+    pub fn signal(signal: i32) -> Response {
+        Response { status: ExitStatus::Signaled(signal), ..Response::exit(0) }
+    }
+
+    /// Output printed on stdout before exiting; combined with a failure or a signal this
+    /// simulates a command interrupted halfway.
+    ///
+    pub fn stdout(mut self, stdout: &str) -> Response {
+        self.stdout = stdout.to_string();
+        self
+    }
+
+    /// Output printed on stderr before exiting.
+    ///
+    pub fn stderr(mut self, stderr: &str) -> Response {
+        self.stderr = stderr.to_string();
+        self
+    }
+
+    /// Time the command takes before exiting. When it exceeds the timeout of the command, see
+    /// `Shell::timeout`, the clock only advances by the timeout and the command times out without
+    /// output; cancelling the token of the command, see `Shell::cancel_on`, ends the delay as
+    /// well.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use shells::Shell;
+    /// use shells::clock::FakeClock;
+    /// use shells::mock::{self, Response, Scenario};
+    ///
+    /// let clock = FakeClock::new();
+    /// let scenario = Scenario::new()
+    ///     .clock(Arc::new(clock.clone()))
+    ///     .respond(Response::success().delay(Duration::from_secs(3600)));
+    ///
+    /// let shell = Shell::new("sh").timeout(Duration::from_secs(5));
+    ///
+    /// assert_eq!(mock::with(scenario, || shell.run("make")).0, 124);
+    /// assert_eq!(clock.elapsed(), Duration::from_secs(5));
+    /// ```
+    ///
+    pub fn delay(mut self, delay: Duration) -> Response {
+        self.delay = delay;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    responses: VecDeque<Response>,
+    calls: Vec<String>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Ordered list of responses. Clones share the same state, so a clone kept aside can be used to
+/// inspect the commands which ran.
+///
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    state: Rc<RefCell<State>>,
+}
+
+impl Default for Scenario {
+    fn default() -> Scenario {
+        Scenario::new()
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Scenario {
+        Scenario {
+            state: Rc::new(RefCell::new(State {
+                responses: VecDeque::new(),
+                calls: Vec::new(),
+                clock: clock::system(),
+            })),
+        }
+    }
+
+    /// Clock used to wait for the delays of the responses. With a `FakeClock`, delays advance
+    /// the virtual time instead of sleeping.
+    ///
+    pub fn clock(self, clock: Arc<dyn Clock>) -> Scenario {
+        self.state.borrow_mut().clock = clock;
+        self
+    }
+
+    /// Appends a response, used for the next command not answered yet.
+    ///
+    pub fn respond(self, response: Response) -> Scenario {
+        self.state.borrow_mut().responses.push_back(response);
+        self
+    }
+
+    /// The commands which ran so far, as rendered by `PreparedCommand`'s `Display`.
+    ///
+    pub fn calls(&self) -> Vec<String> {
+        self.state.borrow().calls.clone()
+    }
+
+    /// Whether every response has been used.
+    ///
+    pub fn is_exhausted(&self) -> bool {
+        self.state.borrow().responses.is_empty()
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Scenario>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with every command executed on the current thread answered by `scenario`. Commands
+/// executed on other threads are not affected.
+///
+pub fn with<R, F: FnOnce() -> R>(scenario: Scenario, f: F) -> R {
+    struct Reset(Option<Scenario>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            ACTIVE.with(|active| *active.borrow_mut() = previous);
+        }
+    }
+
+    let _reset = Reset(ACTIVE.with(|active| active.borrow_mut().replace(scenario)));
+    f()
+}
+
+/// Answers the command from the active scenario, if any.
+///
+pub(crate) fn intercept(command: &PreparedCommand) -> Option<Outcome> {
+    let scenario = ACTIVE.with(|active| active.borrow().clone())?;
+
+    let (response, clock) = {
+        let mut state = scenario.state.borrow_mut();
+        state.calls.push(command.to_string());
+
+        match state.responses.pop_front() {
+            Some(response) => (response, state.clock.clone()),
+            None => panic!("mock scenario exhausted, unexpected command: {}", command),
+        }
+    };
+
+    // The command is killed by its timeout, or by its token, before the end of its delay.
+    let timeout = command.timeout().filter(|&timeout| timeout < response.delay);
+    let delay = timeout.unwrap_or(response.delay);
+
+    match command.cancel_token() {
+        Some(token) => {
+            let start = clock.now();
+
+            loop {
+                if token.is_cancelled() {
+                    let outcome = Outcome::failed(130, String::new());
+                    return Some(Outcome { cancelled: true, ..outcome });
+                }
+
+                match delay.checked_sub(clock.now() - start) {
+                    Some(left) if !left.is_zero() => {
+                        clock.sleep(left.min(Duration::from_millis(10)))
+                    },
+                    _ => break,
+                }
+            }
+        },
+
+        None => clock.sleep(delay),
+    }
+
+    Some(match timeout {
+        Some(timeout) => {
+            let outcome = Outcome::new(ExitStatus::Exited(124), String::new(), String::new());
+            Outcome { timed_out: Some(timeout), ..outcome }
+        },
+
+        None => Outcome::new(response.status, response.stdout, response.stderr),
+    })
+}
@@ -0,0 +1,119 @@
+//! Values deserialized as they are printed by commands emitting a JSON document per line, like
+//! `cargo build --message-format=json` or `docker events --format json`. See the `ndjson`
+//! feature.
+//!
+//! ```rust
+//! extern crate serde_json;
+//! extern crate shells;
+//!
+//! use shells::Shell;
+//!
+//! fn main() {
+//!     let script = r#"
+//!         echo '{"reason": "compiler-artifact", "target": "shells"}'
+//!         echo 'warning: unused manifest key' >&2
+//!         echo '{"reason": "build-finished", "success": true}'
+//!     "#;
+//!
+//!     let mut values = Shell::new("sh").stream(script).json::<serde_json::Value>();
+//!
+//!     let reasons: Vec<String> = values.by_ref()
+//!         .map(|value| value.unwrap()["reason"].as_str().unwrap().to_string())
+//!         .collect();
+//!
+//!     assert_eq!(reasons, ["compiler-artifact", "build-finished"]);
+//!     assert_eq!(values.stderr(), "warning: unused manifest key\n");
+//!     assert_eq!(values.code(), Some(0));
+//! }
+//! ```
+//!
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use stream::{Line, Lines};
+
+impl Lines {
+    /// Deserializes each line printed on stdout as it is read, see the `ndjson` module.
+    ///
+    pub fn json<T: DeserializeOwned>(self) -> Values<T> {
+        Values { lines: self, stderr: String::new(), value: PhantomData }
+    }
+}
+
+/// Iterator over the values printed by a running command, one per line of stdout. Blank lines
+/// are skipped, and a line which does not hold a valid value yields an error without ending the
+/// iteration. The lines printed on stderr are kept aside.
+///
+#[derive(Debug)]
+pub struct Values<T> {
+    lines: Lines,
+    stderr: String,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> Values<T> {
+    /// The lines printed on stderr so far, each followed by a line feed.
+    ///
+    pub fn stderr(&self) -> &str {
+        &self.stderr[..]
+    }
+
+    /// The exit code of the command, once every value has been consumed, see `Lines::code`.
+    ///
+    pub fn code(&self) -> Option<i32> {
+        self.lines.code()
+    }
+
+    /// The lines underneath.
+    ///
+    pub fn into_lines(self) -> Lines {
+        self.lines
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for Values<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        loop {
+            match self.lines.next()? {
+                Line::Stdout(ref text) if text.trim().is_empty() => continue,
+
+                Line::Stdout(text) => {
+                    return Some(::serde_json::from_str(&text).map_err(|e| {
+                        Error::Invalid { line: text.clone(), reason: e.to_string() }
+                    }));
+                },
+
+                Line::Stderr(text) => {
+                    self.stderr.push_str(&text);
+                    self.stderr.push('\n');
+                },
+            }
+        }
+    }
+}
+
+/// Error returned for a line which does not hold a value.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Invalid { line: String, reason: String },
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Invalid JSON line."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Invalid { ref line, ref reason } => {
+                write!(f, "invalid JSON line `{}`: {}", line, reason)
+            },
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Network isolation through a private network namespace (Linux only).
+//!
+//! The child first tries to unshare its network namespace directly, which requires
+//! `CAP_SYS_ADMIN`. Unprivileged processes fall back to creating a user namespace at the same
+//! time, mapping the current user and group to themselves so that the command still sees its
+//! usual identity. The new namespace only contains a loopback interface which is down, hence even
+//! `localhost` is unreachable.
+//!
+
+use std::io;
+
+use sys;
+
+/// State prepared before forking so that entering the namespace does not allocate.
+///
+#[derive(Debug, Clone)]
+pub struct Isolation {
+    uid_map: String,
+    gid_map: String,
+}
+
+impl Isolation {
+    pub fn new() -> Isolation {
+        let (uid, gid) = unsafe { (sys::getuid(), sys::getgid()) };
+
+        Isolation {
+            uid_map: format!("{} {} 1", uid, uid),
+            gid_map: format!("{} {} 1", gid, gid),
+        }
+    }
+
+    /// Moves the calling process into a new network namespace. Meant to be called from
+    /// `pre_exec`.
+    ///
+    pub fn enter(&self) -> io::Result<()> {
+        unsafe {
+            if sys::unshare(sys::CLONE_NEWNET) == 0 {
+                return Ok(());
+            }
+
+            if sys::unshare(sys::CLONE_NEWUSER | sys::CLONE_NEWNET) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        write_proc(b"/proc/self/setgroups\0", b"deny")?;
+        write_proc(b"/proc/self/uid_map\0", self.uid_map.as_bytes())?;
+        write_proc(b"/proc/self/gid_map\0", self.gid_map.as_bytes())
+    }
+}
+
+fn write_proc(path: &[u8], content: &[u8]) -> io::Result<()> {
+    unsafe {
+        let fd = sys::open(path.as_ptr() as *const ::std::os::raw::c_char, sys::O_WRONLY);
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let buffer = content.as_ptr() as *const ::std::os::raw::c_void;
+        let written = sys::write(fd, buffer, content.len());
+        sys::close(fd);
+
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+//! OpenTelemetry spans for the executions, see the `otel` feature. Without the feature, spans do
+//! nothing.
+//!
+//! Each blocking or async execution of a command is a span named `shells.run`, child of the
+//! current span, with the attributes:
+//!
+//! * `process.executable.name`: the program executed, usually the shell;
+//! * `shells.command`: the command, without the preamble;
+//! * `process.exit.code`: the exit code, 124 when the command timed out;
+//! * `shells.stdout.bytes` and `shells.stderr.bytes`: the size of the captured output;
+//! * `shells.timed_out`: set when the timeout killed the command.
+//!
+//! A command which failed, or could not be started, sets the status of its span to error. Spans
+//! are recorded through the global tracer provider, registered by the application.
+//!
+
+use std::io;
+
+use shell::PreparedCommand;
+
+/// The span of an execution.
+///
+pub(crate) struct Span {
+    #[cfg(feature = "otel")]
+    span: ::opentelemetry::global::BoxedSpan,
+}
+
+#[cfg(feature = "otel")]
+impl Span {
+    /// Starts the span of the command, which is about to be spawned.
+    ///
+    pub(crate) fn start(prepared: &PreparedCommand) -> Span {
+        use opentelemetry::KeyValue;
+        use opentelemetry::trace::{Span as _, Tracer};
+
+        let mut span = ::opentelemetry::global::tracer("shells").start("shells.run");
+        let program = prepared.argv().first().map(|program| program.to_string_lossy().into_owned());
+
+        span.set_attribute(KeyValue::new("process.executable.name", program.unwrap_or_default()));
+        span.set_attribute(KeyValue::new("shells.command", prepared.command_text().to_string()));
+
+        Span { span }
+    }
+
+    /// Ends the span of a command which exited.
+    ///
+    pub(crate) fn end(mut self, code: i32, stdout: usize, stderr: usize, timed_out: bool) {
+        use opentelemetry::KeyValue;
+        use opentelemetry::trace::{Span as _, Status};
+
+        self.span.set_attribute(KeyValue::new("process.exit.code", i64::from(code)));
+        self.span.set_attribute(KeyValue::new("shells.stdout.bytes", stdout as i64));
+        self.span.set_attribute(KeyValue::new("shells.stderr.bytes", stderr as i64));
+
+        if timed_out {
+            self.span.set_attribute(KeyValue::new("shells.timed_out", true));
+        }
+
+        if code != 0 {
+            self.span.set_status(Status::error(format!("exit code {}", code)));
+        }
+
+        self.span.end();
+    }
+
+    /// Ends the span of a command which could not be started.
+    ///
+    pub(crate) fn failed(mut self, error: &io::Error) {
+        use opentelemetry::trace::{Span as _, Status};
+
+        self.span.set_status(Status::error(error.to_string()));
+        self.span.end();
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+impl Span {
+    pub(crate) fn start(_: &PreparedCommand) -> Span {
+        Span {}
+    }
+
+    pub(crate) fn end(self, _: i32, _: usize, _: usize, _: bool) {}
+
+    pub(crate) fn failed(self, _: &io::Error) {}
+}
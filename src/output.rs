@@ -0,0 +1,171 @@
+//! Result of an executed command with named fields, returned by the `*_out` family of macros.
+//!
+
+use std::process;
+use std::time::Duration;
+
+/// How a command terminated.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitStatus {
+    /// The command exited with the given code.
+    Exited(i32),
+
+    /// The command was killed by the given signal (Unix only).
+    Signaled(i32),
+}
+
+impl ExitStatus {
+    /// The exit code reported for the termination: the exit code itself, or 128 plus the signal
+    /// number when the command was killed, as shells report it in `$?`.
+    ///
+    pub fn code(&self) -> i32 {
+        match *self {
+            ExitStatus::Exited(code) => code,
+            ExitStatus::Signaled(signal) => 128 + signal,
+        }
+    }
+
+    /// The signal which killed the command, if any.
+    ///
+    pub fn signal(&self) -> Option<i32> {
+        match *self {
+            ExitStatus::Exited(_) => None,
+            ExitStatus::Signaled(signal) => Some(signal),
+        }
+    }
+
+    /// Whether the command exited with the code 0.
+    ///
+    pub fn success(&self) -> bool {
+        *self == ExitStatus::Exited(0)
+    }
+
+    /// The process status terminating the same way, for the commands answered without a process.
+    ///
+    pub(crate) fn raw(&self) -> process::ExitStatus {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            match *self {
+                ExitStatus::Exited(code) => process::ExitStatus::from_raw((code & 0xff) << 8),
+                ExitStatus::Signaled(signal) => process::ExitStatus::from_raw(signal & 0x7f),
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::ExitStatusExt;
+
+            process::ExitStatus::from_raw(self.code() as u32)
+        }
+    }
+}
+
+impl Default for ExitStatus {
+    fn default() -> ExitStatus {
+        ExitStatus::Exited(0)
+    }
+}
+
+impl From<::std::process::ExitStatus> for ExitStatus {
+    fn from(status: ::std::process::ExitStatus) -> ExitStatus {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            if let Some(signal) = status.signal() {
+                return ExitStatus::Signaled(signal);
+            }
+        }
+
+        ExitStatus::Exited(status.code().unwrap_or(if status.success() { 0 } else { 1 }))
+    }
+}
+
+/// Exit code, stdout and stderr of an executed command, the named counterpart of the tuple
+/// returned by `sh!` and friends, along with how it terminated and how long it took.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let output = sh_out!("printf 'a\nb\n'; echo oops >&2; sleep 0.1");
+///
+///     assert!(output.success());
+///     assert_eq!(output.lines().collect::<Vec<_>>(), ["a", "b"]);
+///     assert_eq!(output.stderr, "oops\n");
+///     assert!(output.duration >= Duration::from_millis(100));
+/// }
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub status: ExitStatus,
+
+    /// Time from the start of the command to its exit, retries included.
+    pub duration: Duration,
+}
+
+impl Output {
+    /// Whether the command exited with the code 0.
+    ///
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+
+    /// The stdout of the command without its leading and trailing whitespace, e.g. the final
+    /// newline.
+    ///
+    pub fn stdout_trimmed(&self) -> &str {
+        self.stdout.trim()
+    }
+
+    /// The stderr of the command without its leading and trailing whitespace.
+    ///
+    pub fn stderr_trimmed(&self) -> &str {
+        self.stderr.trim()
+    }
+
+    /// The lines of the stdout of the command, without their line terminators.
+    ///
+    pub fn lines(&self) -> ::std::str::Lines<'_> {
+        self.stdout.lines()
+    }
+}
+
+impl From<(i32, String, String)> for Output {
+    fn from((code, stdout, stderr): (i32, String, String)) -> Output {
+        let status = ExitStatus::Exited(code);
+        Output { code, stdout, stderr, status, duration: Duration::default() }
+    }
+}
+
+impl From<Output> for (i32, String, String) {
+    fn from(output: Output) -> (i32, String, String) {
+        (output.code, output.stdout, output.stderr)
+    }
+}
+
+impl From<Output> for ::Result {
+    fn from(output: Output) -> ::Result {
+        match output.status {
+            ExitStatus::Signaled(signal) => {
+                Err(::Error::Signaled { signal, stdout: output.stdout, stderr: output.stderr })
+            },
+
+            _ if output.code == 0 => Ok(output.stdout),
+            _ => {
+                let Output { code, stdout, stderr, .. } = output;
+                Err(::Error::Failed { code, stdout, stderr })
+            },
+        }
+    }
+}
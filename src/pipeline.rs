@@ -0,0 +1,390 @@
+//! Pipelines of commands, the stdout of each stage feeding the stdin of the next.
+//!
+//! Unlike a pipeline written in a single script, the stages may run with different shells, and
+//! each keeps the configuration of its `Shell`. The stages run concurrently, connected by OS
+//! pipes: the data flowing between them is never held in memory.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::pipeline::{bash, sh};
+//!
+//! fn main() {
+//!     let (code, stdout, _) = pipeline![sh("seq 100000"), bash("grep 7"), sh("wc -l")].run();
+//!
+//!     assert_eq!((code, stdout.trim()), (0, "40951"));
+//! }
+//! ```
+//!
+//! The input of the first stage is the one set with `Shell::stdin`, if any. The exit code of the
+//! pipeline is the one of its last stage, or with `pipefail` the one of the last stage which
+//! failed; a stage killed by its timeout fails the pipeline with the code 124, and the other
+//! stages are killed. Each stage has the timeout of its own shell, running from the start of the
+//! stage: the stages all start at once, not once the stages before them ended. The stderr of
+//! every stage is collected. Mocks do not intercept the stages.
+//!
+//! ```rust
+//! use std::time::{Duration, Instant};
+//! use shells::Shell;
+//! use shells::pipeline::{sh, Pipeline, Stage};
+//!
+//! let start = Instant::now();
+//! let timed = Stage::new(Shell::new("sh").timeout(Duration::from_millis(200)), "cat");
+//!
+//! assert_eq!(Pipeline::new().stage(sh("sleep 3")).stage(timed).run().0, 124);
+//! assert!(start.elapsed() < Duration::from_secs(2));
+//! ```
+//!
+
+use std::process::{Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use Error;
+use output::ExitStatus;
+use shell::{self, Killed, Outcome, PreparedCommand, Shell, Stream};
+
+/// A command along with the shell running it: a stage of a pipeline, a command of a
+/// `batch::Batch` or of a `group::Group`.
+///
+#[derive(Debug, Clone)]
+pub struct Stage {
+    shell: Shell,
+    cmd: String,
+}
+
+impl Stage {
+    pub fn new(shell: Shell, cmd: &str) -> Stage {
+        Stage { shell, cmd: cmd.to_string() }
+    }
+
+    /// The command of the stage.
+    ///
+    pub fn cmd(&self) -> &str {
+        &self.cmd[..]
+    }
+
+    /// Runs the command on its own, like `Shell::run`.
+    ///
+    pub fn run(&self) -> (i32, String, String) {
+        self.shell.run(&self.cmd)
+    }
+
+    /// Starts the command in the background, like `Shell::start`.
+    ///
+    pub fn start(&self) -> ::std::io::Result<::Running> {
+        self.shell.start(&self.cmd)
+    }
+}
+
+/// A command run using the Posix Shell.
+///
+impl<'a> From<&'a str> for Stage {
+    fn from(cmd: &'a str) -> Stage {
+        sh(cmd)
+    }
+}
+
+/// A command run using the Posix Shell.
+///
+impl From<String> for Stage {
+    fn from(cmd: String) -> Stage {
+        Stage { shell: Shell::new("sh"), cmd }
+    }
+}
+
+/// A stage running the command using the Posix Shell.
+///
+pub fn sh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("sh"), cmd)
+}
+
+/// A stage running the command using the Almquist Shell.
+///
+pub fn ash(cmd: &str) -> Stage {
+    Stage::new(Shell::new("ash"), cmd)
+}
+
+/// A stage running the command using the C Shell.
+///
+pub fn csh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("csh"), cmd)
+}
+
+/// A stage running the command using the Korn Shell.
+///
+pub fn ksh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("ksh"), cmd)
+}
+
+/// A stage running the command using the Z Shell.
+///
+pub fn zsh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("zsh"), cmd)
+}
+
+/// A stage running the command using the Bourne Again Shell.
+///
+pub fn bash(cmd: &str) -> Stage {
+    Stage::new(Shell::new("bash"), cmd)
+}
+
+/// A stage running the command using the Debian Almquist Shell.
+///
+pub fn dash(cmd: &str) -> Stage {
+    Stage::new(Shell::new("dash"), cmd)
+}
+
+/// A stage running the command using the Fish Shell.
+///
+pub fn fish(cmd: &str) -> Stage {
+    Stage::new(Shell::new("fish"), cmd)
+}
+
+/// A stage running the command using the MirBSD Korn Shell.
+///
+pub fn mksh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("mksh"), cmd)
+}
+
+/// A stage running the command using the TENEX C Shell.
+///
+pub fn tcsh(cmd: &str) -> Stage {
+    Stage::new(Shell::new("tcsh"), cmd)
+}
+
+/// A stage running the command using the Windows Command Prompt.
+///
+pub fn cmd(cmd: &str) -> Stage {
+    Stage::new(Shell::new("cmd"), cmd)
+}
+
+/// A stage running the command using Windows PowerShell.
+///
+pub fn powershell(cmd: &str) -> Stage {
+    Stage::new(Shell::new("powershell"), cmd)
+}
+
+/// Commands run with the stdout of each feeding the stdin of the next, see the module
+/// documentation.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    pipefail: bool,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Appends a stage to the pipeline.
+    ///
+    pub fn stage(mut self, stage: Stage) -> Pipeline {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Makes the pipeline fail when any of its stages fails, like `set -o pipefail`.
+    ///
+    pub fn pipefail(mut self) -> Pipeline {
+        self.pipefail = true;
+        self
+    }
+
+    /// Runs the pipeline and returns its exit code, the stdout of its last stage and the stderr of
+    /// every stage. A stage which could not be started fails the pipeline with the code 126.
+    ///
+    pub fn run(&self) -> (i32, String, String) {
+        match self.execute() {
+            Ok(outcome) => outcome.into_tuple(),
+            Err(e) => (e.code(), e.stdout().to_string(), e.stderr().to_string()),
+        }
+    }
+
+    /// Runs the pipeline and returns the stdout of its last stage when it succeeded, like
+    /// `Shell::try_run`.
+    ///
+    pub fn try_run(&self) -> ::Result {
+        self.execute()?.into_result()
+    }
+
+    fn execute(&self) -> Result<Outcome, Error> {
+        let mut prepared = self.stages.iter()
+            .map(|stage| stage.shell.approved(&stage.cmd))
+            .collect::<Result<Vec<PreparedCommand>, Error>>()?;
+
+        // The stages killed along with a stage reaching its timeout are killed with the processes
+        // they started.
+        let killable = |command: &PreparedCommand| {
+            command.timeout().is_some() || command.cancel_token().is_some()
+        };
+
+        if prepared.iter().any(killable) {
+            prepared = prepared.into_iter().map(PreparedCommand::in_own_group).collect();
+        }
+
+        // Every stage is printed when traced, and none runs in a dry run.
+        let answered = prepared.iter()
+            .filter(|command| ::trace::intercept(command).is_some())
+            .count();
+
+        if prepared.is_empty() || answered > 0 {
+            return Ok(Outcome::new(ExitStatus::Exited(0), String::new(), String::new()));
+        }
+
+        for (i, command) in prepared.iter().enumerate() {
+            if let Err(e) = command.setup() {
+                prepared[..i].iter().for_each(|command| { command.teardown(); });
+                return Ok(Outcome::failed(126, e));
+            }
+        }
+
+        let mut outcome = match spawn(&prepared) {
+            Ok(children) => wait(&prepared, children, self.pipefail),
+            Err(e) => Outcome::failed(126, e),
+        };
+
+        for command in &prepared {
+            if let Some(note) = command.teardown() {
+                shell::push_line(&mut outcome.stderr, &note);
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// A stage running along with the threads reading its output.
+///
+struct Running {
+    child: Child,
+    deadline: Option<Instant>,
+    stdout: Option<::std::thread::JoinHandle<Vec<u8>>>,
+    stderr: ::std::thread::JoinHandle<Vec<u8>>,
+}
+
+/// Starts every stage, connecting the stdout of each to the stdin of the next. The stages already
+/// started are killed when one cannot start.
+///
+fn spawn(prepared: &[PreparedCommand]) -> Result<Vec<Running>, String> {
+    let mut children: Vec<Running> = Vec::with_capacity(prepared.len());
+    let mut input = None;
+
+    for (i, command) in prepared.iter().enumerate() {
+        let last = i + 1 == prepared.len();
+        let mut process = command.command();
+
+        process.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        let spawned = match input.take() {
+            Some(stdout) => {
+                process.stdin(Stdio::from(stdout));
+                process.spawn().inspect(|_| command.started())
+            },
+
+            None => {
+                process.stdin(Stdio::null());
+                command.start(process)
+            },
+        };
+
+        let mut child = match spawned {
+            Ok(child) => child,
+
+            Err(e) => {
+                for running in &mut children {
+                    shell::kill(&mut running.child);
+                    let _ = running.child.wait();
+                }
+
+                return Err(format!("cannot start the stage {} of the pipeline: {}", i + 1, e));
+            },
+        };
+
+        let stderr = shell::read_all(child.stderr.take(), Stream::Stderr,
+                                     command.sinks(Stream::Stderr));
+        let stdout = match last {
+            true => {
+                let sinks = command.sinks(Stream::Stdout);
+                Some(shell::read_all(child.stdout.take(), Stream::Stdout, sinks))
+            },
+
+            false => {
+                input = child.stdout.take();
+                None
+            },
+        };
+
+        // Timeouts run from the start of each stage, not from the end of the stages before it.
+        let deadline = command.deadline();
+        children.push(Running { child, deadline, stdout, stderr });
+    }
+
+    Ok(children)
+}
+
+/// Waits for every stage, all at once for their timeouts and cancellation to apply while the
+/// others run, and returns the outcome of the pipeline. Once a stage is killed, so are the others.
+///
+fn wait(prepared: &[PreparedCommand], mut children: Vec<Running>, pipefail: bool) -> Outcome {
+    let mut exits: Vec<Option<_>> = children.iter().map(|_| None).collect();
+
+    while exits.iter().any(Option::is_none) {
+        for ((command, running), exit) in prepared.iter().zip(&mut children).zip(&mut exits) {
+            if exit.is_none() {
+                *exit = command.poll(&mut running.child, running.deadline).transpose();
+            }
+        }
+
+        // The pipeline fails once a stage is killed: the others are killed as well.
+        if exits.iter().any(|exit| matches!(exit, Some(Ok((_, Some(_)))))) {
+            for (running, exit) in children.iter_mut().zip(&mut exits) {
+                if exit.is_none() {
+                    shell::kill(&mut running.child);
+                    *exit = Some(running.child.wait().map(|status| (status, None)));
+                }
+            }
+        }
+
+        if exits.iter().any(Option::is_none) {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    let mut outcome = Outcome::new(ExitStatus::Exited(0), String::new(), String::new());
+
+    for ((command, running), exit) in prepared.iter().zip(children).zip(exits) {
+        let (status, timed_out) = match exit {
+            Some(Ok((status, None))) => (ExitStatus::from(status), None),
+            Some(Ok((_, Some(Killed::Timeout)))) => (ExitStatus::Exited(124), command.timeout()),
+            Some(Ok((_, Some(Killed::Cancel)))) => {
+                outcome.cancelled = true;
+                (ExitStatus::Exited(130), None)
+            },
+
+            _ => (ExitStatus::Exited(1), None),
+        };
+
+        let stderr = running.stderr.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+        outcome.stderr.push_str(&command.filtered(Stream::Stderr, stderr));
+
+        if let Some(stdout) = running.stdout {
+            let stdout = String::from_utf8_lossy(&stdout.join().unwrap_or_default()).into_owned();
+            outcome.stdout = command.filtered(Stream::Stdout, stdout);
+        }
+
+        // A stage killed by its timeout fails the pipeline, whatever the stages after it did.
+        let failed = status != ExitStatus::Exited(0);
+
+        if outcome.timed_out.is_none() && (timed_out.is_some() || !pipefail || failed) {
+            outcome.status = status;
+            outcome.timed_out = timed_out;
+        }
+    }
+
+    outcome
+}
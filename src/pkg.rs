@@ -0,0 +1,241 @@
+//! Installation of system packages through the package manager of the running system.
+//!
+//! apt, dnf, pacman, Homebrew, Chocolatey and winget are supported. Managers operating on system
+//! directories are invoked with root privileges through `privilege::Escalation` when the program
+//! does not already run as root; every invocation is non-interactive.
+//!
+//! ```rust,no_run
+//! use shells::pkg;
+//!
+//! pkg::install(&["curl", "git"]).unwrap();
+//! ```
+//!
+
+use shell::Shell;
+use which::which;
+use Os;
+
+/// The apt-get command, which must not ask questions.
+const APT_GET: &[&str] = &["env", "DEBIAN_FRONTEND=noninteractive", "apt-get"];
+
+/// Supported package managers.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Apt,
+    Dnf,
+    Pacman,
+    Brew,
+    Choco,
+    Winget,
+}
+
+impl Manager {
+    /// Name of the program implementing the manager.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            Manager::Apt => "apt-get",
+            Manager::Dnf => "dnf",
+            Manager::Pacman => "pacman",
+            Manager::Brew => "brew",
+            Manager::Choco => "choco",
+            Manager::Winget => "winget",
+        }
+    }
+
+    /// Returns the manager of the running system: the native one of the Linux distribution (or
+    /// the first one installed if the distribution is unknown), Homebrew on macOS, winget or else
+    /// Chocolatey on Windows. `None` when it is not installed.
+    ///
+    pub fn detect() -> Option<Manager> {
+        let candidates: &[Manager] = match ::os() {
+            Os::Linux => {
+                let distro = ::distro().unwrap_or_default();
+
+                if distro.is("debian") || distro.is("ubuntu") {
+                    &[Manager::Apt]
+                } else if distro.is("fedora") || distro.is("rhel") || distro.is("centos") {
+                    &[Manager::Dnf]
+                } else if distro.is("arch") {
+                    &[Manager::Pacman]
+                } else {
+                    &[Manager::Apt, Manager::Dnf, Manager::Pacman]
+                }
+            },
+
+            Os::MacOs => &[Manager::Brew],
+            Os::Windows => &[Manager::Winget, Manager::Choco],
+            _ => &[],
+        };
+
+        candidates.iter().cloned().find(|manager| which(manager.program()).is_some())
+    }
+
+    /// Whether the manager must run with root privileges.
+    ///
+    pub fn needs_root(&self) -> bool {
+        matches!(*self, Manager::Apt | Manager::Dnf | Manager::Pacman)
+    }
+
+    /// Installs the given packages; those already installed are left untouched.
+    ///
+    pub fn install(&self, packages: &[&str]) -> Result<(), Error> {
+        match *self {
+            Manager::Apt => self.execute(&[APT_GET, &["install", "-y", "-q"]].concat(), packages),
+            Manager::Dnf => self.execute(&["dnf", "install", "-y", "-q"], packages),
+            Manager::Pacman => self.execute(&["pacman", "-S", "--needed", "--noconfirm"], packages),
+            Manager::Brew => self.execute(&["brew", "install"], packages),
+            Manager::Choco => self.execute(&["choco", "install", "-y", "--no-progress"], packages),
+
+            Manager::Winget => {
+                for package in packages {
+                    let argv = ["winget", "install", "--exact", "--silent",
+                                "--accept-package-agreements", "--accept-source-agreements",
+                                "--id"];
+                    self.execute(&argv, &[package])?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Removes the given packages.
+    ///
+    pub fn remove(&self, packages: &[&str]) -> Result<(), Error> {
+        match *self {
+            Manager::Apt => self.execute(&[APT_GET, &["remove", "-y", "-q"]].concat(), packages),
+            Manager::Dnf => self.execute(&["dnf", "remove", "-y", "-q"], packages),
+            Manager::Pacman => self.execute(&["pacman", "-R", "--noconfirm"], packages),
+            Manager::Brew => self.execute(&["brew", "uninstall"], packages),
+            Manager::Choco => self.execute(&["choco", "uninstall", "-y"], packages),
+
+            Manager::Winget => {
+                for package in packages {
+                    let argv = ["winget", "uninstall", "--exact", "--silent", "--id"];
+                    self.execute(&argv, &[package])?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Whether the given package is installed. Does not require root privileges.
+    ///
+    pub fn is_installed(&self, package: &str) -> Result<bool, Error> {
+        let argv = match *self {
+            Manager::Apt => ["dpkg-query", "-W", "-f", "${Status}", package].to_vec(),
+            Manager::Dnf => ["rpm", "-q", package].to_vec(),
+            Manager::Pacman => ["pacman", "-Q", package].to_vec(),
+            Manager::Brew => ["brew", "list", "--versions", package].to_vec(),
+            Manager::Choco => ["choco", "list", "--exact", "--limit-output", package].to_vec(),
+            Manager::Winget => ["winget", "list", "--exact", "--id", package].to_vec(),
+        };
+
+        let (code, stdout, stderr) = run(&argv);
+
+        // The query itself could not run.
+        if code == 126 || code == 127 {
+            return Err(Error::Failed { manager: *self, code, stderr });
+        }
+
+        Ok(code == 0 && match *self {
+            Manager::Apt => stdout.contains("install ok installed"),
+            Manager::Brew | Manager::Choco => !stdout.trim().is_empty(),
+            _ => true,
+        })
+    }
+
+    /// Runs `command` followed by `packages`, with root privileges when needed.
+    ///
+    fn execute(&self, command: &[&str], packages: &[&str]) -> Result<(), Error> {
+        let args: Vec<&str> = command[1..].iter().chain(packages).cloned().collect();
+
+        let (code, _, stderr) = match self.needs_root() {
+            true => escalated(command[0], &args)?,
+            false => run(&command.iter().chain(packages).cloned().collect::<Vec<&str>>()),
+        };
+
+        if code == 0 {
+            Ok(())
+        } else {
+            Err(Error::Failed { manager: *self, code, stderr })
+        }
+    }
+}
+
+/// Runs the program with `sh`, or `cmd` on Windows, so that dry runs, policies, mocks and sinks
+/// apply to it.
+///
+fn run(argv: &[&str]) -> (i32, String, String) {
+    let shell = if cfg!(windows) { "cmd" } else { "sh" };
+    let line: Vec<String> = argv.iter()
+        .map(|arg| ::quote::for_shell(shell, arg).into_owned())
+        .collect();
+
+    Shell::new(shell).run(&line.join(" "))
+}
+
+#[cfg(unix)]
+fn escalated(program: &str, args: &[&str]) -> Result<(i32, String, String), Error> {
+    ::privilege::Escalation::new().run(program, args).map_err(|e| Error::Privilege(e.to_string()))
+}
+
+#[cfg(not(unix))]
+fn escalated(program: &str, _: &[&str]) -> Result<(i32, String, String), Error> {
+    Err(Error::Privilege(format!("cannot run {} with root privileges on this system", program)))
+}
+
+/// Error returned by package operations.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// No supported package manager is installed.
+    NoManager,
+
+    /// Root privileges could not be obtained.
+    Privilege(String),
+
+    /// The package manager exited with a non-zero code.
+    Failed { manager: Manager, code: i32, stderr: String },
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Package operation failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::NoManager => write!(f, "no supported package manager found"),
+            Error::Privilege(ref reason) => write!(f, "{}", reason),
+
+            Error::Failed { manager, code, ref stderr } => {
+                write!(f, "{} exited with code {}: {}", manager.program(), code, stderr.trim_end())
+            },
+        }
+    }
+}
+
+/// Installs the given packages with the package manager of the running system.
+///
+pub fn install(packages: &[&str]) -> Result<(), Error> {
+    Manager::detect().ok_or(Error::NoManager)?.install(packages)
+}
+
+/// Removes the given packages with the package manager of the running system.
+///
+pub fn remove(packages: &[&str]) -> Result<(), Error> {
+    Manager::detect().ok_or(Error::NoManager)?.remove(packages)
+}
+
+/// Whether the given package is installed, according to the package manager of the running
+/// system.
+///
+pub fn is_installed(package: &str) -> Result<bool, Error> {
+    Manager::detect().ok_or(Error::NoManager)?.is_installed(package)
+}
@@ -0,0 +1,206 @@
+//! Detection of the operating system, for scripts branching on the platform they run on.
+//!
+
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+
+/// Operating system families.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Os {
+    Linux,
+    MacOs,
+    Windows,
+    FreeBsd,
+    OpenBsd,
+    NetBsd,
+    Other,
+}
+
+impl Os {
+    /// Whether the system is a Unix, i.e. anything but Windows.
+    ///
+    pub fn is_unix(&self) -> bool {
+        *self != Os::Windows
+    }
+}
+
+/// Returns the operating system the program runs on.
+///
+pub fn os() -> Os {
+    match ::std::env::consts::OS {
+        "linux" | "android" => Os::Linux,
+        "macos" => Os::MacOs,
+        "windows" => Os::Windows,
+        "freebsd" => Os::FreeBsd,
+        "openbsd" => Os::OpenBsd,
+        "netbsd" => Os::NetBsd,
+        _ => Os::Other,
+    }
+}
+
+/// Runs `f` only when the program runs on `target`, returning its result.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use shells::Os;
+///
+/// fn main() {
+///     let kernel = shells::when(Os::Linux, || sh!("uname -s").1);
+///
+///     if cfg!(target_os = "linux") {
+///         assert_eq!(kernel.unwrap(), "Linux\n");
+///     }
+/// }
+/// ```
+///
+pub fn when<T, F: FnOnce() -> T>(target: Os, f: F) -> Option<T> {
+    if os() == target {
+        Some(f())
+    } else {
+        None
+    }
+}
+
+/// Release of the operating system. On Linux the fields come from `/etc/os-release`; elsewhere
+/// `id` is the name of the system (`macos`, `windows`, `freebsd`...) and `version_id` its version.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Distro {
+    /// Lowercase identifier such as `debian`, `fedora` or `arch`.
+    pub id: String,
+
+    /// Identifiers of the distributions this one derives from, e.g. `["debian"]` for Ubuntu.
+    pub id_like: Vec<String>,
+
+    /// Human readable name, e.g. `Debian GNU/Linux 12 (bookworm)`.
+    pub pretty_name: String,
+
+    /// Version number, absent for rolling releases.
+    pub version_id: Option<String>,
+}
+
+impl Distro {
+    /// Whether the distribution is `id` or derives from it.
+    ///
+    pub fn is(&self, id: &str) -> bool {
+        self.id == id || self.id_like.iter().any(|like| like == id)
+    }
+
+    /// Parses the content of an `os-release` file.
+    ///
+    pub fn parse_os_release(content: &str) -> Distro {
+        let mut distro = Distro { id: "linux".to_string(), ..Distro::default() };
+
+        for line in content.lines().map(str::trim).filter(|line| !line.starts_with('#')) {
+            let (key, value) = match line.find('=') {
+                Some(i) => (&line[..i], unquote(&line[i + 1..])),
+                None => continue,
+            };
+
+            match key {
+                "ID" => distro.id = value,
+
+                "ID_LIKE" => {
+                    distro.id_like = value.split_whitespace().map(str::to_string).collect()
+                },
+
+                "PRETTY_NAME" => distro.pretty_name = value,
+                "VERSION_ID" => distro.version_id = Some(value),
+                _ => (),
+            }
+        }
+
+        if distro.pretty_name.is_empty() {
+            distro.pretty_name = distro.id.clone();
+        }
+
+        distro
+    }
+}
+
+/// Removes the shell-like quoting of an `os-release` value.
+///
+fn unquote(value: &str) -> String {
+    let quote = match value.chars().next() {
+        Some(c @ '"') | Some(c @ '\'') if value.len() >= 2 && value.ends_with(c) => c,
+        _ => return value.to_string(),
+    };
+
+    let inner = &value[1..value.len() - 1];
+
+    if quote == '\'' {
+        return inner.to_string();
+    }
+
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+/// Returns the release of the operating system, or `None` when it cannot be determined.
+///
+/// ```rust
+/// if let Some(distro) = shells::distro() {
+///     println!("running on {}", distro.pretty_name);
+/// }
+/// ```
+///
+pub fn distro() -> Option<Distro> {
+    match os() {
+        Os::Linux => {
+            ["/etc/os-release", "/usr/lib/os-release"].iter()
+                .filter_map(|path| {
+                    let mut content = String::new();
+                    File::open(path).and_then(|mut file| file.read_to_string(&mut content)).ok()?;
+                    Some(Distro::parse_os_release(&content))
+                })
+                .next()
+        },
+
+        Os::MacOs => {
+            let version = output("sw_vers", &["-productVersion"])?;
+            Some(release("macos", format!("macOS {}", version), version))
+        },
+
+        Os::Windows => {
+            // "Microsoft Windows [Version 10.0.19045.3570]"
+            let banner = output("cmd", &["/C", "ver"])?;
+            let version = banner.rsplit(' ').next()?.trim_end_matches(']').to_string();
+            Some(release("windows", banner, version))
+        },
+
+        _ => {
+            let name = output("uname", &["-s"])?;
+            let version = output("uname", &["-r"])?;
+            Some(release(&name.to_lowercase(), format!("{} {}", name, version), version))
+        },
+    }
+}
+
+fn release(id: &str, pretty_name: String, version: String) -> Distro {
+    Distro { id: id.to_string(), id_like: Vec::new(), pretty_name, version_id: Some(version) }
+}
+
+/// Trimmed stdout of a successful command.
+///
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout[..]).trim().to_string())
+}
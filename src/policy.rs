@@ -0,0 +1,710 @@
+//! Restrictions on the commands which may be executed, for applications running commands built
+//! from user input.
+//!
+//! A `Policy` inspects each simple command of a script (the parts separated by `;`, `&&`, `|`,
+//! newlines, command substitutions...). Denied programs and patterns are rejected, and once a
+//! program has been allowed, every program which was not is rejected as well, including those
+//! whose name results from an expansion. Commands rejected this way are not run: `Shell::run`
+//! returns the code 126 with the reason in stderr, `Shell::try_run` returns
+//! `Error::PolicyDenied`.
+//!
+//! ```rust
+//! use shells::{Error, Shell};
+//! use shells::policy::Policy;
+//!
+//! let policy = Policy::new().allow_program("echo").allow_program("grep").deny_pattern("* -r*");
+//! let shell = Shell::new("sh").policy(policy);
+//!
+//! assert!(shell.try_run("echo hello | grep hell").is_ok());
+//! assert!(matches!(shell.try_run("echo hello; uname"), Err(Error::PolicyDenied { .. })));
+//! assert!(matches!(shell.try_run("grep -r secret /etc"), Err(Error::PolicyDenied { .. })));
+//! ```
+//!
+//! Denylists alone are a best-effort protection: a denied program can still be reached through
+//! another one, e.g. `env rm` or `sh -c 'rm'`. Allowlists should be preferred, without shells,
+//! `env`, `xargs` and the like in them.
+//!
+//! An `Approval` gate protects script authors from their own mistakes rather than from hostile
+//! input: matching commands, e.g. `rm -rf`, only run once confirmed at the terminal or when the
+//! shell was built with `Shell::force`.
+//!
+
+use std::path::Path;
+use std::sync::Mutex;
+
+static GLOBAL: Mutex<Option<Policy>> = Mutex::new(None);
+
+/// Shell keywords and reserved words which may precede the program of a simple command.
+const KEYWORDS: &[&str] = &[
+    "!", "{", "}", "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac",
+    "time",
+];
+
+/// Programs running the command given as their arguments, with their options taking a value.
+const WRAPPERS: &[(&str, &[&str])] = &[
+    ("sudo", &["-u", "-g", "-h", "-p", "-C", "-D", "-R", "-T", "-U", "-r", "-t"]),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C"]),
+    ("command", &[]),
+    ("exec", &["-a"]),
+    ("nice", &["-n"]),
+    ("nohup", &[]),
+];
+
+/// Set of rules restricting the commands which may be executed.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    patterns: Vec<String>,
+}
+
+impl Policy {
+    /// Creates a policy allowing every command.
+    ///
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Allows the program, identified by its file name. Once a program is allowed, all programs
+    /// which are not are denied.
+    ///
+    pub fn allow_program(mut self, program: &str) -> Policy {
+        self.allowed.push(program.to_string());
+        self
+    }
+
+    /// Denies the program, identified by its file name so that invoking it by path is denied as
+    /// well.
+    ///
+    pub fn deny_program(mut self, program: &str) -> Policy {
+        self.denied.push(program.to_string());
+        self
+    }
+
+    /// Denies the simple commands matching the pattern, in which `*` matches any sequence of
+    /// characters and `?` any single one. The command is matched with its words separated by a
+    /// single space and quotes removed, e.g. `rm -rf /` or `curl * | sh`... which does not match
+    /// anything since pipelines are matched command by command: use `deny_program` instead.
+    ///
+    pub fn deny_pattern(mut self, pattern: &str) -> Policy {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Checks every simple command of the script, returning the description of the first
+    /// violation.
+    ///
+    /// ```rust
+    /// use shells::policy::Policy;
+    ///
+    /// let policy = Policy::new().allow_program("cat").allow_program("echo");
+    ///
+    /// assert!(policy.check("cat <<EOF\n$(uname)\nEOF").is_err());
+    /// assert!(policy.check("cat <<'EOF'\n$(uname)\nEOF").is_ok());
+    /// assert!(policy.check("cat <(uname)").is_err());
+    /// assert!(policy.check("cat <<< $(uname)").is_err());
+    /// assert!(policy.check("cat <<< \"`uname`\"; echo done").is_err());
+    /// assert!(policy.check("echo \"$(uname)\"").is_err());
+    /// assert!(policy.check("cat <<< hello; echo done").is_ok());
+    /// assert!(policy.check("echo $(( $(uname) + 1 ))").is_err());
+    /// assert!(policy.check("echo $(( `uname` ))").is_err());
+    /// assert!(policy.check("echo $(( 1 + 2 ))").is_ok());
+    /// ```
+    ///
+    pub fn check(&self, script: &str) -> Result<(), String> {
+        for words in invocations(script) {
+            let (program, line) = (&words[0], line(&words));
+
+            if let Some(pattern) = self.patterns.iter().find(|pattern| glob(pattern, &line)) {
+                return Err(format!("`{}` matches the pattern `{}` denied by the policy",
+                                   line, pattern));
+            }
+
+            let name = file_name(&program.text);
+
+            if self.denied.contains(&name) {
+                return Err(format!("`{}` is denied by the policy", program.text));
+            }
+
+            if self.allowed.is_empty() {
+                continue;
+            }
+
+            if program.expanded {
+                return Err(format!("`{}` is not a literal program name, as required by the policy",
+                                   program.text));
+            }
+
+            if !self.allowed.contains(&name) {
+                return Err(format!("`{}` is not allowed by the policy", program.text));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Commands which only run once confirmed interactively, see `Shell::approval`.
+///
+/// ```rust,no_run
+/// use shells::Shell;
+/// use shells::policy::Approval;
+///
+/// let shell = Shell::new("sh").approval(Approval::dangerous());
+///
+/// // Asks for the confirmation of `rm -rf build` on stderr, reading the answer from stdin.
+/// let (code, _, stderr) = shell.run("make clean && rm -rf build");
+///
+/// // Runs without asking.
+/// let (code, _, stderr) = shell.force().run("rm -rf build");
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Approval {
+    patterns: Vec<String>,
+}
+
+impl Approval {
+    /// Creates a gate confirming nothing.
+    ///
+    pub fn new() -> Approval {
+        Approval::default()
+    }
+
+    /// A gate confirming commands which commonly destroy data: recursive removals, filesystem
+    /// creation, raw writes with `dd`, recursive permission changes, `shred` and `wipefs`.
+    ///
+    pub fn dangerous() -> Approval {
+        let patterns = ["rm -r*", "rm -R*", "rm -fr*", "rm -fR*", "rm -* -r*", "rm -* -R*",
+                        "rm --recursive*", "mkfs*", "dd *of=*", "chmod -R *", "chown -R *",
+                        "shred *", "wipefs *"];
+
+        patterns.iter().fold(Approval::new(), |approval, pattern| approval.pattern(pattern))
+    }
+
+    /// Requires confirmation for the simple commands matching the pattern, matched like those of
+    /// `Policy::deny_pattern`.
+    ///
+    pub fn pattern(mut self, pattern: &str) -> Approval {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// The first simple command of the script requiring confirmation, if any. Commands are also
+    /// matched with their program designated by its file name and without the wrappers running
+    /// it, such as `sudo`, `env` or `nice`.
+    ///
+    /// ```rust
+    /// use shells::policy::Approval;
+    ///
+    /// let gate = Approval::dangerous();
+    ///
+    /// assert_eq!(gate.pending("make && rm -rf build").as_deref(), Some("rm -rf build"));
+    /// assert_eq!(gate.pending("sudo -u www /bin/rm -rf /x").as_deref(),
+    ///            Some("sudo -u www /bin/rm -rf /x"));
+    /// assert!(gate.pending("env LANG=C nice -n 5 rm -rf /x").is_some());
+    /// assert!(gate.pending("command rm -rf /x").is_some());
+    /// assert!(gate.pending("sudo rm -f /x").is_none());
+    /// ```
+    ///
+    pub fn pending(&self, script: &str) -> Option<String> {
+        let matches = |line: &str| self.patterns.iter().any(|pattern| glob(pattern, line));
+
+        invocations(script).into_iter()
+            .find(|words| matches(&line(words)) || matches(&unwrapped(words)))
+            .map(|words| line(&words))
+    }
+
+    /// Asks for the confirmation of the commands of the script requiring it, on stderr, reading
+    /// the answer from stdin. Returns the description of the command declined, if any.
+    ///
+    pub(crate) fn confirm(&self, script: &str) -> Result<(), String> {
+        let line = match self.pending(script) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+
+        match ::files::ask(&format!("shells: run `{}`? [y/N] ", line)) {
+            true => Ok(()),
+            false => Err(format!("`{}` was not confirmed", line)),
+        }
+    }
+}
+
+/// Applies the policy to every command executed in the process, by the macros as well as by
+/// `Shell`, on top of the policies set with `Shell::policy`. Replaces the policy previously
+/// installed.
+///
+pub fn install(policy: Policy) {
+    *GLOBAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+/// Removes the policy installed with `install`.
+///
+pub fn uninstall() {
+    *GLOBAL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Checks the script against the policy installed with `install`, if any.
+///
+pub(crate) fn check_global(script: &str) -> Result<(), String> {
+    match *GLOBAL.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(ref policy) => policy.check(script),
+        None => Ok(()),
+    }
+}
+
+/// The words of every simple command of the script, starting with its program.
+///
+fn invocations(script: &str) -> Vec<Vec<Word>> {
+    simple_commands(script).into_iter()
+        .filter_map(|mut words| {
+            let start = words.iter()
+                .position(|word| !KEYWORDS.contains(&&word.text[..]) && !word.is_assignment())?;
+
+            words.drain(..start);
+            Some(words)
+        })
+        .collect()
+}
+
+/// The command line of the words, separated by a single space.
+///
+fn line(words: &[Word]) -> String {
+    let words: Vec<&str> = words.iter().map(|word| &word.text[..]).collect();
+    words.join(" ")
+}
+
+/// The command line run by the wrappers starting the command, if any, its program designated by
+/// its file name: `rm -rf /x` for `sudo -u www /bin/rm -rf /x`.
+///
+fn unwrapped(words: &[Word]) -> String {
+    let mut rest = words;
+
+    while let Some(&(_, options)) = rest.first().and_then(|program| {
+        WRAPPERS.iter().find(|&&(wrapper, _)| wrapper == file_name(&program.text))
+    }) {
+        rest = &rest[1..];
+
+        // The options of the wrapper and, for `env`, the variables it sets.
+        while let Some(word) = rest.first() {
+            if word.text == "--" {
+                rest = &rest[1..];
+                break;
+            } else if options.contains(&&word.text[..]) {
+                rest = rest.get(2..).unwrap_or(&[]);
+            } else if word.text.starts_with('-') || word.is_assignment() {
+                rest = &rest[1..];
+            } else {
+                break;
+            }
+        }
+    }
+
+    match rest.split_first() {
+        Some((program, args)) => {
+            let mut line = file_name(&program.text);
+            args.iter().for_each(|arg| line.push_str(&format!(" {}", arg.text)));
+            line
+        },
+
+        None => line(words),
+    }
+}
+
+/// The file name of the program, by which policies designate it.
+///
+fn file_name(program: &str) -> String {
+    Path::new(program).file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// A word of a simple command, quotes removed.
+///
+#[derive(Debug, Default)]
+struct Word {
+    text: String,
+
+    /// Whether the word contains an unquoted or double-quoted expansion.
+    expanded: bool,
+
+    /// Whether the word contains quotes or escapes.
+    quoted: bool,
+}
+
+impl Word {
+    fn is_assignment(&self) -> bool {
+        match self.text.find('=') {
+            Some(index) if index > 0 && !self.quoted => {
+                self.text[..index].chars().all(|c| c == '_' || c.is_ascii_alphanumeric())
+            },
+
+            _ => false,
+        }
+    }
+}
+
+/// Splits the script into simple commands, approximating the lexer of a POSIX shell. The
+/// content of command substitutions, process substitutions and subshells is returned as commands
+/// of their own, including the substitutions of double-quoted words and of here-documents whose
+/// delimiter is not quoted; redirection targets are left out.
+///
+fn simple_commands(script: &str) -> Vec<Vec<Word>> {
+    let mut commands = Vec::new();
+    let mut words: Vec<Word> = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut redirection = Redirection::None;
+    let mut heredocs: Vec<(String, bool)> = Vec::new();
+    let mut chars = script.chars().peekable();
+
+    macro_rules! end_word {
+        () => {
+            if let Some(word) = word.take() {
+                match ::std::mem::replace(&mut redirection, Redirection::None) {
+                    Redirection::None => words.push(word),
+                    Redirection::Target => (),
+                    Redirection::Heredoc => {
+                        heredocs.push((word.text.trim_start_matches('-').to_string(), word.quoted));
+                    },
+                }
+            }
+        };
+    }
+
+    macro_rules! end_command {
+        () => {
+            end_word!();
+
+            if words.first().is_some_and(|word| word.text == "for" || word.text == "case") {
+                words.clear();
+            }
+
+            if !words.is_empty() {
+                commands.push(::std::mem::take(&mut words));
+            }
+        };
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => end_word!(),
+
+            '\n' => {
+                end_command!();
+
+                // The body of the here-documents starts on the next line, and is expanded unless
+                // the delimiter is quoted.
+                for (delimiter, quoted) in heredocs.drain(..) {
+                    let mut body = String::new();
+                    let mut line = String::new();
+
+                    for c in chars.by_ref() {
+                        if c != '\n' {
+                            line.push(c);
+                        } else if line.trim_start_matches('\t') == delimiter {
+                            break;
+                        } else {
+                            body.push_str(&line);
+                            body.push('\n');
+                            line.clear();
+                        }
+                    }
+
+                    if !quoted {
+                        commands.extend(heredoc_commands(&body));
+                    }
+                }
+            },
+
+            ';' | '&' | '|' | '(' | ')' => { end_command!(); },
+
+            '<' | '>' => {
+                // A file descriptor number, as in `2>&1`.
+                let descriptor = |word: &Word| {
+                    !word.quoted && word.text.chars().all(|c| c.is_ascii_digit())
+                };
+
+                if word.as_ref().is_some_and(descriptor) {
+                    word = None;
+                }
+
+                end_word!();
+
+                let mut operator = c.to_string();
+
+                let continues = |&&c: &&char| matches!(c, '<' | '>' | '&' | '|' | '-');
+
+                while let Some(&next) = chars.peek().filter(continues) {
+                    operator.push(next);
+                    chars.next();
+                }
+
+                // `<<<` introduces a here-string, a word expanded like any other.
+                redirection = match operator.starts_with("<<") && !operator.starts_with("<<<") {
+                    true => Redirection::Heredoc,
+                    false => Redirection::Target,
+                };
+
+                // `>&2`, `<&-`: the target is a file descriptor, part of the operator.
+                if operator.ends_with('&') && chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        chars.next();
+                    }
+
+                    redirection = Redirection::None;
+                } else if operator.ends_with("&-") {
+                    redirection = Redirection::None;
+                }
+
+                // `<(uname)`: a process substitution, whose content is a command.
+                if (operator == "<" || operator == ">") && chars.peek() == Some(&'(') {
+                    redirection = Redirection::None;
+                }
+            },
+
+            '#' if word.is_none() => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            },
+
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                let content = substitution(&mut chars);
+
+                let current = word.get_or_insert_with(Word::default);
+                current.expanded = true;
+                current.text.push_str(&format!("$({})", content));
+                commands.extend(substitution_commands(&content));
+            },
+
+            '`' => {
+                let content = backquoted(&mut chars);
+
+                let current = word.get_or_insert_with(Word::default);
+                current.expanded = true;
+                current.text.push_str(&format!("`{}`", content));
+                commands.extend(simple_commands(&content));
+            },
+
+            '\'' => {
+                let current = word.get_or_insert_with(Word::default);
+                current.quoted = true;
+
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+
+                    current.text.push(c);
+                }
+            },
+
+            '"' => {
+                let current = word.get_or_insert_with(Word::default);
+                current.quoted = true;
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => current.text.extend(chars.next()),
+
+                        '$' if chars.peek() == Some(&'(') => {
+                            chars.next();
+                            let content = substitution(&mut chars);
+
+                            current.expanded = true;
+                            current.text.push_str(&format!("$({})", content));
+                            commands.extend(substitution_commands(&content));
+                        },
+
+                        '`' => {
+                            let content = backquoted(&mut chars);
+
+                            current.expanded = true;
+                            current.text.push_str(&format!("`{}`", content));
+                            commands.extend(simple_commands(&content));
+                        },
+
+                        '$' => {
+                            current.expanded = true;
+                            current.text.push(c);
+                        },
+
+                        _ => current.text.push(c),
+                    }
+                }
+            },
+
+            '\\' => {
+                let current = word.get_or_insert_with(Word::default);
+                current.quoted = true;
+
+                match chars.next() {
+                    Some('\n') | None => (),
+                    Some(c) => current.text.push(c),
+                }
+            },
+
+            _ => {
+                let current = word.get_or_insert_with(Word::default);
+                current.expanded |= matches!(c, '$' | '*' | '?' | '[' | '~');
+                current.text.push(c);
+            },
+        }
+    }
+
+    end_command!();
+    commands
+}
+
+/// The simple commands of the command substitutions of a text expanded like the body of a
+/// here-document.
+///
+fn heredoc_commands(text: &str) -> Vec<Vec<Word>> {
+    let mut commands = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            },
+
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                commands.extend(substitution_commands(&substitution(&mut chars)));
+            },
+
+            '`' => commands.extend(simple_commands(&backquoted(&mut chars))),
+            _ => (),
+        }
+    }
+
+    commands
+}
+
+/// The simple commands of the content of `$(...)`, or those of the command substitutions nested
+/// in an arithmetic expansion `$((...))`.
+///
+fn substitution_commands(content: &str) -> Vec<Vec<Word>> {
+    match content.len() > 1 && content.starts_with('(') && content.ends_with(')') {
+        true => heredoc_commands(&content[1..content.len() - 1]),
+        false => simple_commands(content),
+    }
+}
+
+/// Consumes a command substitution up to its closing parenthesis, `$(` being consumed already,
+/// and returns its content.
+///
+fn substitution<I: Iterator<Item = char>>(chars: &mut I) -> String {
+    let mut content = String::new();
+    let mut depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            ')' if depth == 0 => break,
+            '(' => depth += 1,
+            ')' => depth -= 1,
+
+            '\\' => {
+                content.push(c);
+                content.extend(chars.next());
+                continue;
+            },
+
+            '\'' | '"' => {
+                content.push(c);
+
+                while let Some(next) = chars.next() {
+                    content.push(next);
+
+                    if next == c {
+                        break;
+                    } else if next == '\\' && c == '"' {
+                        content.extend(chars.next());
+                    }
+                }
+
+                continue;
+            },
+
+            _ => (),
+        }
+
+        content.push(c);
+    }
+
+    content
+}
+
+/// Consumes a backquoted command substitution up to its closing backquote, the opening one being
+/// consumed already, and returns its content.
+///
+fn backquoted<I: Iterator<Item = char>>(chars: &mut I) -> String {
+    let mut content = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => break,
+
+            '\\' => match chars.next() {
+                Some(next @ ('`' | '\\' | '$')) => content.push(next),
+                Some(next) => content.extend(['\\', next]),
+                None => (),
+            },
+
+            _ => content.push(c),
+        }
+    }
+
+    content
+}
+
+/// What the next word of a simple command is.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Redirection {
+    None,
+    Target,
+    Heredoc,
+}
+
+/// Whether the text matches the pattern, in which `*` matches any sequence of characters and `?`
+/// any single one.
+///
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            },
+
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                },
+
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
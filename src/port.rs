@@ -0,0 +1,63 @@
+//! Ports of the local host for the services started by tests and fixtures.
+//!
+//! `free_port` asks the system for a port nobody listens on, and never returns the same port twice
+//! in a process, so fixtures running concurrently each get their own. `Shell::port` hands it to
+//! the commands, in place of `{port}` and as `$PORT`.
+//!
+//! ```rust
+//! use shells::Shell;
+//!
+//! let port = shells::free_port().unwrap();
+//! assert_ne!(shells::free_port().unwrap(), port);
+//!
+//! let shell = Shell::new("sh").port(port);
+//! assert_eq!(shell.run("echo {port} $PORT").1, format!("{} {}\n", port, port));
+//! ```
+//!
+
+use std::collections::BTreeSet;
+use std::io;
+use std::net::TcpListener;
+use std::sync::Mutex;
+
+static HANDED_OUT: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+/// A TCP port of the local host free at the time of the call, and not returned before by this
+/// process. It stays free until a service binds it, barring other processes picking it by chance
+/// in the meantime.
+///
+pub fn free_port() -> io::Result<u16> {
+    let mut handed_out = HANDED_OUT.lock().unwrap_or_else(|e| e.into_inner());
+
+    for _ in 0..100 {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+        if handed_out.insert(port) {
+            return Ok(port);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::AddrInUse, "shells: no free port left"))
+}
+
+/// Replaces every `{port}` of the command by the port, except in `${port}`, which expands the
+/// shell variable.
+///
+pub(crate) fn render(cmd: &str, port: u16) -> String {
+    let mut rendered = String::with_capacity(cmd.len());
+    let mut rest = cmd;
+
+    while let Some(start) = rest.find("{port}") {
+        rendered.push_str(&rest[..start]);
+
+        match rendered.ends_with('$') {
+            true => rendered.push_str("{port}"),
+            false => rendered.push_str(&port.to_string()),
+        }
+
+        rest = &rest[start + "{port}".len()..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
@@ -0,0 +1,176 @@
+//! Resource checks run before a command starts, so that a long job fails early instead of dying
+//! halfway for lack of disk space or memory.
+//!
+//! ```rust,no_run
+//! use shells::Shell;
+//! use shells::preflight::Preflight;
+//!
+//! let checks = Preflight::new().min_disk("/var/backups", 20 << 30).min_memory(2 << 30);
+//!
+//! // Fails with the code 126, without running the command, when a check does not pass.
+//! let (code, _, stderr) = Shell::new("sh").preflight(checks).run("make-backup /var/backups");
+//! ```
+//!
+//! `Shell::try_run` fails with `Error::Preflight`, holding the failure:
+//!
+//! ```rust
+//! use shells::{Error, Shell};
+//! use shells::preflight::Preflight;
+//!
+//! let shell = Shell::new("sh").preflight(Preflight::new().min_disk("/", u64::MAX));
+//!
+//! match shell.try_run("echo never") {
+//!     Err(e @ Error::Preflight { .. }) => assert_eq!(e.code(), 126),
+//!     other => panic!("unexpected result: {:?}", other),
+//! }
+//! ```
+//!
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A set of resource requirements.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preflight {
+    disk: Vec<(PathBuf, u64)>,
+    memory: Option<u64>,
+}
+
+impl Preflight {
+    /// Creates a set of checks requiring nothing.
+    ///
+    pub fn new() -> Preflight {
+        Preflight::default()
+    }
+
+    /// Requires at least `bytes` of free space on the filesystem holding `path`.
+    ///
+    pub fn min_disk<P: Into<PathBuf>>(mut self, path: P, bytes: u64) -> Preflight {
+        self.disk.push((path.into(), bytes));
+        self
+    }
+
+    /// Requires at least `bytes` of available memory. Only supported on Linux, where
+    /// `MemAvailable` from `/proc/meminfo` is used.
+    ///
+    pub fn min_memory(mut self, bytes: u64) -> Preflight {
+        self.memory = Some(bytes);
+        self
+    }
+
+    /// Runs the checks, stopping at the first one failing.
+    ///
+    pub fn check(&self) -> Result<(), Error> {
+        for (path, required) in &self.disk {
+            let available = free_disk(path)?;
+
+            if available < *required {
+                let path = path.clone();
+                return Err(Error::InsufficientDisk { path, required: *required, available });
+            }
+        }
+
+        if let Some(required) = self.memory {
+            let available = available_memory()?;
+
+            if available < required {
+                return Err(Error::InsufficientMemory { required, available });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned when a preflight check fails.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The filesystem holding `path` has less than `required` bytes free.
+    InsufficientDisk { path: PathBuf, required: u64, available: u64 },
+
+    /// Less than `required` bytes of memory are available.
+    InsufficientMemory { required: u64, available: u64 },
+
+    /// The resource could not be measured.
+    Unknown(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Preflight check failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::InsufficientDisk { ref path, required, available } => {
+                write!(f, "not enough free disk space at {}: {} bytes required, {} available",
+                       path.display(), required, available)
+            },
+
+            Error::InsufficientMemory { required, available } => {
+                write!(f, "not enough available memory: {} bytes required, {} available", required,
+                       available)
+            },
+
+            Error::Unknown(ref reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Free space available to unprivileged users on the filesystem holding `path`, as reported by
+/// `df -Pk`.
+///
+fn free_disk(path: &Path) -> Result<u64, Error> {
+    let unknown = |reason: String| {
+        Error::Unknown(format!("cannot measure free disk space at {}: {}", path.display(), reason))
+    };
+
+    let output = Command::new("df").arg("-Pk").arg(path).output()
+        .map_err(|e| unknown(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(unknown(String::from_utf8_lossy(&output.stderr[..]).trim_end().to_string()));
+    }
+
+    // Columns: `Filesystem 1024-blocks Used Available Capacity Mounted-on`. The device name and
+    // the mount point may contain spaces, the available space is thus located from the capacity
+    // percentage.
+    let stdout = String::from_utf8_lossy(&output.stdout[..]);
+    let line = stdout.lines().nth(1).ok_or_else(|| unknown("unexpected output of df".to_string()))?;
+    let columns: Vec<&str> = line.split_whitespace().collect();
+
+    columns.iter().position(|column| column.ends_with('%'))
+        .and_then(|capacity| capacity.checked_sub(1))
+        .and_then(|available| columns[available].parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .ok_or_else(|| unknown("unexpected output of df".to_string()))
+}
+
+/// Memory available for starting new applications without swapping.
+///
+fn available_memory() -> Result<u64, Error> {
+    let unknown = |reason: String| {
+        Error::Unknown(format!("cannot measure available memory: {}", reason))
+    };
+
+    if !cfg!(target_os = "linux") {
+        return Err(unknown("only supported on Linux".to_string()));
+    }
+
+    let mut meminfo = String::new();
+    File::open("/proc/meminfo").and_then(|mut file| file.read_to_string(&mut meminfo))
+        .map_err(|e| unknown(e.to_string()))?;
+
+    meminfo.lines()
+        .find(|line| line.starts_with("MemAvailable:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kib| kib.parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .ok_or_else(|| unknown("MemAvailable missing from /proc/meminfo".to_string()))
+}
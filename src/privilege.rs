@@ -0,0 +1,251 @@
+//! Privilege escalation for administration scripts (Unix only).
+//!
+//! sudo, doas and systemd's run0 are supported; by default the first one found on the PATH is
+//! used, in that order.
+//!
+//! ```rust,no_run
+//! use shells::privilege::Escalation;
+//!
+//! // Re-executes the program with root privileges unless it already runs as root. Only returns
+//! // when already root, or with an error if escalating failed or was refused.
+//! Escalation::new().keep_env("DEPLOY_TARGET").ensure_root().unwrap();
+//! ```
+//!
+
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use shell::Shell;
+use which::which;
+
+/// Whether the current process runs with an effective user id of 0.
+///
+pub fn is_root() -> bool {
+    unsafe { ::sys::geteuid() == 0 }
+}
+
+/// Programs used to gain root privileges.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tool {
+    Sudo,
+    Doas,
+    Run0,
+
+    /// Any other program invoked as `<tool> env VAR=value... <program> <args>`.
+    Other(String),
+}
+
+impl Tool {
+    /// Name of the program.
+    ///
+    pub fn program(&self) -> &str {
+        match *self {
+            Tool::Sudo => "sudo",
+            Tool::Doas => "doas",
+            Tool::Run0 => "run0",
+            Tool::Other(ref program) => &program[..],
+        }
+    }
+
+    /// Returns the first of sudo, doas and run0 available on the PATH.
+    ///
+    pub fn detect() -> Option<Tool> {
+        [Tool::Sudo, Tool::Doas, Tool::Run0].iter().find(|tool| which(tool.program()).is_some())
+            .cloned()
+    }
+}
+
+/// Error returned when privileges could not be obtained.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// None of sudo, doas and run0 is installed.
+    NoTool,
+
+    /// The escalation program refused to grant privileges (wrong password, user not allowed...).
+    Refused(Tool),
+
+    /// The escalation program could not be executed.
+    Io(io::Error),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Privilege escalation failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::NoTool => {
+                write!(f, "root privileges required but none of sudo, doas or run0 is installed")
+            },
+
+            Error::Refused(ref tool) => {
+                write!(f, "{} refused to grant root privileges", tool.program())
+            },
+
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Configuration of how root privileges are obtained.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Escalation {
+    tool: Option<Tool>,
+    keep_env: Vec<String>,
+}
+
+impl Escalation {
+    /// Escalation through the first tool available, without preserving any environment
+    /// variable.
+    ///
+    pub fn new() -> Escalation {
+        Escalation::default()
+    }
+
+    /// Forces the use of the given escalation program.
+    ///
+    pub fn tool(mut self, tool: Tool) -> Escalation {
+        self.tool = Some(tool);
+        self
+    }
+
+    /// Preserves the given environment variable, which sudo and its alternatives clear
+    /// otherwise.
+    ///
+    pub fn keep_env(mut self, name: &str) -> Escalation {
+        self.keep_env.push(name.to_string());
+        self
+    }
+
+    fn resolve(&self) -> Result<Tool, Error> {
+        match self.tool {
+            Some(ref tool) => Ok(tool.clone()),
+            None => Tool::detect().ok_or(Error::NoTool),
+        }
+    }
+
+    /// Builds the command line executing `program` with root privileges through the given tool.
+    ///
+    fn escalated<I, S>(&self, tool: &Tool, program: S, args: I) -> Vec<OsString>
+        where I: IntoIterator<Item = S>, S: Into<OsString>
+    {
+        let mut argv = vec![OsString::from(tool.program())];
+
+        match *tool {
+            Tool::Sudo => {
+                if !self.keep_env.is_empty() {
+                    argv.push(format!("--preserve-env={}", self.keep_env.join(",")).into());
+                }
+
+                argv.push("--".into());
+            },
+
+            Tool::Run0 => {
+                for name in self.keep_env.iter().filter(|name| env::var_os(name).is_some()) {
+                    argv.push(format!("--setenv={}", name).into());
+                }
+            },
+
+            Tool::Doas | Tool::Other(_) => {
+                if !self.keep_env.is_empty() {
+                    argv.push("env".into());
+                }
+
+                for name in &self.keep_env {
+                    if let Some(value) = env::var_os(name) {
+                        let mut assignment = OsString::from(format!("{}=", name));
+                        assignment.push(value);
+                        argv.push(assignment);
+                    }
+                }
+            },
+        }
+
+        argv.push(program.into());
+        argv.extend(args.into_iter().map(Into::into));
+        argv
+    }
+
+    /// Makes sure the escalation program grants privileges before relying on it, so that a
+    /// refusal is reported as `Error::Refused` instead of as a failure of the escalated command.
+    /// May prompt for a password.
+    ///
+    pub fn authenticate(&self) -> Result<Tool, Error> {
+        let tool = self.resolve()?;
+
+        let argv = match tool {
+            Tool::Sudo => vec![OsString::from("sudo"), OsString::from("-v")],
+            _ => self.escalated(&tool, "true", Vec::new()),
+        };
+
+        // The streams are inherited for the password prompt.
+        match Shell::new("sh").status(&format!("{} >/dev/null", command_line(&argv))) {
+            Ok(ref status) if status.success() => Ok(tool),
+            Ok(_) => Err(Error::Refused(tool)),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    /// Executes `program` with root privileges, directly when already root, waits for it and
+    /// returns its code, stdout and stderr. Password prompts are shown on the terminal by
+    /// `authenticate`, before the program runs.
+    ///
+    pub fn run(&self, program: &str, args: &[&str]) -> Result<(i32, String, String), Error> {
+        let argv = if is_root() {
+            ::std::iter::once(program).chain(args.iter().cloned()).map(OsString::from).collect()
+        } else {
+            let tool = self.authenticate()?;
+            self.escalated(&tool, program, args.iter().cloned())
+        };
+
+        Ok(Shell::new("sh").run(&command_line(&argv)))
+    }
+
+    /// Returns immediately when running as root; otherwise replaces the current process by
+    /// itself, with the same arguments, running with root privileges. Only returns when already
+    /// root or on error.
+    ///
+    pub fn ensure_root(&self) -> Result<(), Error> {
+        if is_root() {
+            return Ok(());
+        }
+
+        let tool = self.authenticate()?;
+        let program = env::current_exe()?;
+        let args = env::args_os().skip(1);
+
+        let argv = self.escalated(&tool, program.into_os_string(), args);
+
+        Err(Error::Io(Command::new(&argv[0]).args(&argv[1..]).exec()))
+    }
+}
+
+/// The command line executing the arguments with `sh`, quoted for it.
+///
+fn command_line(argv: &[OsString]) -> String {
+    let quoted: Vec<_> = argv.iter()
+        .map(|arg| ::quote::posix(&arg.to_string_lossy()).into_owned())
+        .collect();
+    format!("exec {}", quoted.join(" "))
+}
+
+/// Re-executes the current program with root privileges unless it already runs as root.
+///
+pub fn ensure_root() -> Result<(), Error> {
+    Escalation::new().ensure_root()
+}
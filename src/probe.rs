@@ -0,0 +1,169 @@
+//! Health checks of services: a TCP port accepting connections, a unix socket, an HTTP endpoint
+//! answering `200 OK`, or any closure.
+//!
+//! A probe tells a command started by `Shell::spawn_until` is ready, see `Ready::from`, and tells
+//! a running service is still alive once checked again later, see `ShellChild::is_alive`.
+//!
+//! ```rust
+//! use std::net::TcpListener;
+//! use std::time::Duration;
+//! use shells::probe::Probe;
+//!
+//! let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+//! let probe = Probe::port(listener.local_addr().unwrap().port());
+//!
+//! assert!(probe.check());
+//! drop(listener);
+//! assert!(!probe.wait(Duration::from_millis(100)));
+//! ```
+//!
+
+use std::fmt;
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clock::{self, Clock};
+
+/// A health check, see the module documentation.
+///
+#[derive(Clone)]
+pub struct Probe {
+    check: Check,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Clone)]
+enum Check {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    Http(String),
+    Custom(Arc<dyn Fn() -> bool + Send + Sync>),
+}
+
+impl Probe {
+    /// Passes when a TCP connection to the address succeeds.
+    ///
+    pub fn tcp(addr: SocketAddr) -> Probe {
+        Probe::new(Check::Tcp(addr))
+    }
+
+    /// Passes when a TCP connection to the given port of the local host succeeds.
+    ///
+    pub fn port(port: u16) -> Probe {
+        Probe::tcp(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    /// Passes when the unix socket exists, and accepts connections on Unix.
+    ///
+    pub fn unix_socket<P: Into<PathBuf>>(path: P) -> Probe {
+        Probe::new(Check::Unix(path.into()))
+    }
+
+    /// Passes when a `GET` of the URL answers with the status `200`, as fetched by `curl` with a
+    /// timeout of 5 seconds. Fails when `curl` is not installed.
+    ///
+    pub fn http(url: &str) -> Probe {
+        Probe::new(Check::Http(url.to_string()))
+    }
+
+    /// Passes when `check` returns `true`. It must not block for long.
+    ///
+    pub fn custom<F: Fn() -> bool + Send + Sync + 'static>(check: F) -> Probe {
+        Probe::new(Check::Custom(Arc::new(check)))
+    }
+
+    fn new(check: Check) -> Probe {
+        Probe { check, clock: clock::system() }
+    }
+
+    /// Measures the timeout of `wait`, and waits between the checks, with the given clock instead
+    /// of the system one, for tests.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use shells::clock::FakeClock;
+    /// use shells::probe::Probe;
+    ///
+    /// let clock = FakeClock::new();
+    /// let probe = Probe::custom(|| false).clock(Arc::new(clock.clone()));
+    ///
+    /// assert!(!probe.wait(Duration::from_secs(3600)));
+    /// assert!(clock.elapsed() >= Duration::from_secs(3600));
+    /// ```
+    ///
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Probe {
+        self.clock = clock;
+        self
+    }
+
+    /// Checks once whether the probe passes.
+    ///
+    pub fn check(&self) -> bool {
+        match self.check {
+            Check::Tcp(ref addr) => {
+                TcpStream::connect_timeout(addr, Duration::from_millis(100)).is_ok()
+            },
+
+            #[cfg(unix)]
+            Check::Unix(ref path) => ::std::os::unix::net::UnixStream::connect(path).is_ok(),
+
+            #[cfg(not(unix))]
+            Check::Unix(ref path) => path.exists(),
+
+            Check::Http(ref url) => {
+                let discard = if cfg!(windows) { "NUL" } else { "/dev/null" };
+
+                Command::new("curl")
+                    .args(["--silent", "--output", discard, "--max-time", "5"])
+                    .args(["--write-out", "%{http_code}", url])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output()
+                    .map(|output| output.stdout == b"200")
+                    .unwrap_or(false)
+            },
+
+            Check::Custom(ref check) => check(),
+        }
+    }
+
+    /// Checks the probe repeatedly until it passes or the timeout elapsed, returns whether it
+    /// passed.
+    ///
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = self.clock.now() + timeout;
+
+        loop {
+            if self.check() {
+                return true;
+            }
+
+            if self.clock.now() >= deadline {
+                return false;
+            }
+
+            self.clock.sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl fmt::Debug for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Probe({})", self)
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.check {
+            Check::Tcp(ref addr) => write!(f, "listening on {}", addr),
+            Check::Unix(ref path) => write!(f, "listening on {}", path.display()),
+            Check::Http(ref url) => write!(f, "answering 200 at {}", url),
+            Check::Custom(_) => write!(f, "passing its probe"),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Project-local settings, read from the nearest `.shells.toml` up the directory tree of each
+//! command, like `direnv` does with `.envrc`.
+//!
+//! ```toml
+//! # ~/src/shop/.shells.toml
+//! shell = "bash"
+//! trace = "echo"
+//!
+//! [env]
+//! RUST_LOG = "debug"
+//! DATABASE_URL = "postgres://localhost/shop_dev"
+//! ```
+//!
+//! Once `enable` was called, every command run from within the directory holding the file, or
+//! from one of its subdirectories, gets its environment variables. A variable set on the `Shell`
+//! itself takes precedence. `trace` is the trace mode of the commands, `off`, `echo` or `dry-run`,
+//! see `set_trace`: a project can make its commands more verbose than the program asks for, not
+//! less. `shell` is the shell of the local target, see `target::default_shell`.
+//!
+//! The directory of a command is its working directory, the current directory of the program by
+//! default. Only the nearest file applies, and each directory is looked up once: files created or
+//! changed afterwards are ignored until `enable` is called again.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! fn main() {
+//!     shells::project::enable();
+//!
+//!     // Prints `debug`, and the command to stderr beforehand.
+//!     sh!("echo $RUST_LOG");
+//! }
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use document::{Invalid, Node};
+use trace::Trace;
+
+/// Name of the files holding the settings of a project.
+///
+pub const FILE_NAME: &str = ".shells.toml";
+
+/// Settings of a project, see the module documentation.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// The directory holding the file.
+    pub root: PathBuf,
+
+    pub shell: Option<String>,
+    pub env: BTreeMap<String, String>,
+    pub trace: Option<Trace>,
+}
+
+impl Config {
+    /// Loads the settings from the given file.
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
+        let path = path.as_ref();
+        let content = ::std::fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        Config::parse(root, &content).map_err(|e| match e {
+            Error::Parse(reason) => Error::Parse(format!("{}: {}", path.display(), reason)),
+            e => e,
+        })
+    }
+
+    /// Parses the settings of the project rooted at `root`.
+    ///
+    pub fn parse<P: Into<PathBuf>>(root: P, content: &str) -> Result<Config, Error> {
+        let value = content.parse::<::toml::Value>().map_err(|e| Error::Parse(e.to_string()))?;
+        let node = Node::from_toml(value);
+
+        let mut config = Config { root: root.into(), ..Config::default() };
+
+        if let Some(shell) = node.get("shell") {
+            config.shell = Some(shell.scalar("shell")?);
+        }
+
+        if let Some(env) = node.get("env") {
+            config.env = env.vars("env")?;
+        }
+
+        if let Some(trace) = node.get("trace") {
+            config.trace = Some(match &trace.scalar("trace")?[..] {
+                "off" => Trace::Off,
+                "echo" => Trace::Echo,
+                "dry-run" => Trace::DryRun,
+                other => return Err(Error::Parse(format!("trace: unknown mode `{}`", other))),
+            });
+        }
+
+        Ok(config)
+    }
+}
+
+/// The settings of the nearest project of the directory: those of the first `.shells.toml`
+/// found in it or in one of its parents.
+///
+pub fn discover<P: AsRef<Path>>(dir: P) -> Result<Option<Config>, Error> {
+    for dir in dir.as_ref().ancestors() {
+        let path = dir.join(FILE_NAME);
+
+        if path.is_file() {
+            return Config::load(path).map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CACHE: Mutex<BTreeMap<PathBuf, Option<Arc<Config>>>> = Mutex::new(BTreeMap::new());
+
+/// Applies the settings of the nearest project to every command run from now on, by the macros
+/// as well as by `Shell`. Forgets the settings already read.
+///
+pub fn enable() {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Stops applying the settings of projects.
+///
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// The settings applying to the commands run from the directory when enabled, `None` for the
+/// current directory. A file which cannot be loaded is reported to stderr and ignored.
+///
+pub(crate) fn applying(dir: Option<&Path>) -> Option<Arc<Config>> {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return None;
+    }
+
+    let current = ::std::env::current_dir().ok()?;
+    let dir = dir.map(|dir| current.join(dir)).unwrap_or(current);
+
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+    cache.entry(dir).or_insert_with_key(|dir| match discover(dir) {
+        Ok(config) => config.map(Arc::new),
+        Err(e) => {
+            eprintln!("shells: ignoring the project settings: {}", e);
+            None
+        },
+    }).clone()
+}
+
+/// Error returned when the settings of a project cannot be loaded.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file could not be read.
+    Io(String),
+
+    /// The file does not hold valid settings.
+    Parse(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Invalid project settings."
+    }
+}
+
+impl From<Invalid> for Error {
+    fn from(invalid: Invalid) -> Error {
+        Error::Parse(invalid.0)
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Io(ref reason) | Error::Parse(ref reason) => write!(f, "{}", reason),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Commands run under a pseudo-terminal, for the programs which behave differently or refuse to
+//! run without one: password prompts of `ssh` and `sudo`, tools checking `isatty`... See
+//! `Shell::pty` and the `pty` feature, available on Linux and macOS.
+//!
+//! The command gets the terminal as stdin, stdout and stderr, so its output is captured as a
+//! single stream, as the terminal displays it: lines end with `\r\n`, and what is sent to the
+//! command is echoed back. `expect` waits for a text to be printed, `send` types on the keyboard.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use shells::Shell;
+//!
+//! let script = "[ -t 0 ] && echo 'on a terminal'; \
+//!               printf 'Password: '; read -r secret; echo got $secret";
+//! let mut session = Shell::new("sh").pty(script).unwrap();
+//!
+//! let printed = session.expect("Password: ", Duration::from_secs(5)).unwrap();
+//! assert_eq!(printed, "on a terminal\r\nPassword: ");
+//! session.send_line("hunter2").unwrap();
+//!
+//! let (status, output) = session.wait_output().unwrap();
+//!
+//! assert_eq!(status.code(), 0);
+//! assert!(output.ends_with("got hunter2\r\n"));
+//! ```
+//!
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use output::ExitStatus;
+use shell::PreparedCommand;
+
+/// Handle on a command running under a pseudo-terminal, see the module documentation. Like for
+/// `ShellChild`, mocks and the timeout of the shell do not apply. Dropping the handle hangs up the
+/// terminal, which usually ends the command.
+///
+#[derive(Debug)]
+pub struct Session {
+    prepared: PreparedCommand,
+    child: Child,
+    terminal: File,
+    screen: Arc<Screen>,
+    status: Option<ExitStatus>,
+}
+
+/// What the command printed and was not expected yet.
+///
+#[derive(Debug, Default)]
+struct Screen {
+    state: Mutex<(Vec<u8>, bool)>,
+    printed: Condvar,
+}
+
+impl Screen {
+    fn state(&self) -> MutexGuard<'_, (Vec<u8>, bool)> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Session {
+    /// Starts the command as the leader of a new session, with the terminal as its controlling
+    /// terminal.
+    ///
+    pub(crate) fn spawn(prepared: PreparedCommand) -> io::Result<Session> {
+        ::trace::intercept_start(&prepared)?;
+
+        // The command leads its own session, so it cannot have a process group of its own.
+        let prepared = prepared.without_process_group();
+        let (terminal, device) = open()?;
+
+        prepared.setup().map_err(io::Error::other)?;
+
+        let mut command = prepared.command();
+        command.stdin(Stdio::from(device.try_clone()?))
+            .stdout(Stdio::from(device.try_clone()?))
+            .stderr(Stdio::from(device));
+
+        unsafe {
+            command.pre_exec(|| {
+                if ::sys::setsid() < 0 || ::sys::ioctl(0, ::sys::TIOCSCTTY, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        let child = match prepared.start(command) {
+            Ok(child) => child,
+            Err(e) => {
+                prepared.teardown();
+                return Err(e);
+            },
+        };
+
+        let screen = Arc::new(Screen::default());
+        let reader = screen.clone();
+        let mut output = terminal.try_clone()?;
+
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+
+            // Reading fails with `EIO` on Linux once every process closed the terminal.
+            loop {
+                match output.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        reader.state().0.extend_from_slice(&buffer[..n]);
+                        reader.printed.notify_all();
+                    },
+                }
+            }
+
+            reader.state().1 = true;
+            reader.printed.notify_all();
+        });
+
+        Ok(Session { prepared, child, terminal, screen, status: None })
+    }
+
+    /// The process identifier of the shell running the command.
+    ///
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Types the text on the terminal.
+    ///
+    pub fn send(&mut self, text: &str) -> io::Result<()> {
+        self.terminal.write_all(text.as_bytes())?;
+        self.terminal.flush()
+    }
+
+    /// Types the text then presses enter.
+    ///
+    pub fn send_line(&mut self, text: &str) -> io::Result<()> {
+        self.send(&format!("{}\n", text))
+    }
+
+    /// Waits until the command prints `text`, and returns everything it printed up to the end of
+    /// `text` since the previous call. Fails with `ErrorKind::TimedOut` once the timeout elapsed,
+    /// and with `ErrorKind::UnexpectedEof` when the command closed the terminal before. The text
+    /// printed so far remains available to the next call in both cases.
+    ///
+    pub fn expect(&mut self, text: &str, timeout: Duration) -> io::Result<String> {
+        let deadline = Instant::now() + timeout;
+        let needle = text.as_bytes();
+        let mut state = self.screen.state();
+
+        loop {
+            let found = match needle.is_empty() {
+                true => Some(0),
+                false => state.0.windows(needle.len()).position(|window| window == needle),
+            };
+
+            if let Some(start) = found {
+                let printed: Vec<u8> = state.0.drain(..start + needle.len()).collect();
+                return Ok(String::from_utf8_lossy(&printed).into_owned());
+            }
+
+            if state.1 {
+                let reason = format!("the command closed the terminal before printing `{}`", text);
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, reason));
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                let reason = format!("`{}` was not printed", text);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, reason));
+            }
+
+            state = self.screen.printed.wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+
+    /// Everything printed and not expected yet, without waiting.
+    ///
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.screen.state().0).into_owned()
+    }
+
+    /// Waits for the command to exit and returns how it terminated.
+    ///
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self.status {
+            Some(status) => Ok(status),
+            None => self.child.wait().map(|status| self.exited(status.into())),
+        }
+    }
+
+    /// Waits for the command to exit and for the terminal to be closed, and returns how the
+    /// command terminated with everything it printed and was not expected yet.
+    ///
+    pub fn wait_output(mut self) -> io::Result<(ExitStatus, String)> {
+        let status = self.wait()?;
+        let mut state = self.screen.state();
+
+        while !state.1 {
+            state = self.screen.printed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        let output = String::from_utf8_lossy(&state.0).into_owned();
+        Ok((status, output))
+    }
+
+    /// Kills the command, with `SIGKILL`.
+    ///
+    pub fn kill(&mut self) -> io::Result<()> {
+        match self.status {
+            Some(_) => Ok(()),
+            None => self.child.kill(),
+        }
+    }
+
+    fn exited(&mut self, status: ExitStatus) -> ExitStatus {
+        self.status = Some(status);
+
+        if let Some(note) = self.prepared.teardown() {
+            eprintln!("{}", note);
+        }
+
+        status
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if self.status.is_none() {
+            unsafe { ::sys::kill(-(self.child.id() as i32), ::sys::SIGHUP); }
+        }
+    }
+}
+
+/// Opens a pseudo-terminal of 24 lines of 80 columns, returning its controlling side and the
+/// device the command uses as its terminal.
+///
+fn open() -> io::Result<(File, File)> {
+    // The commands started afterwards must not inherit the controlling side.
+    let fd = unsafe { ::sys::posix_openpt(::sys::O_RDWR | ::sys::O_NOCTTY | ::sys::O_CLOEXEC) };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let terminal = unsafe { File::from_raw_fd(fd) };
+
+    if unsafe { ::sys::grantpt(fd) } < 0 || unsafe { ::sys::unlockpt(fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let path = device_name(fd)?;
+
+    let device = File::options().read(true).write(true).custom_flags(::sys::O_NOCTTY).open(path)?;
+
+    let size = ::sys::Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+    unsafe { ::sys::ioctl(device.as_raw_fd(), ::sys::TIOCSWINSZ, &size as *const ::sys::Winsize) };
+
+    Ok((terminal, device))
+}
+
+/// Path of the device of a pseudo-terminal, from the reentrant `ptsname_r`: `ptsname` returns a
+/// static buffer, which a session opened from another thread may overwrite.
+///
+#[cfg(target_os = "linux")]
+fn device_name(fd: ::std::os::raw::c_int) -> io::Result<String> {
+    let mut name = [0 as ::std::os::raw::c_char; 128];
+
+    match unsafe { ::sys::ptsname_r(fd, name.as_mut_ptr(), name.len()) } {
+        0 => Ok(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// Path of the device of a pseudo-terminal, from `ptsname` under a lock serializing the calls of
+/// this crate.
+///
+#[cfg(not(target_os = "linux"))]
+fn device_name(fd: ::std::os::raw::c_int) -> io::Result<String> {
+    static PTSNAME: Mutex<()> = Mutex::new(());
+
+    let _guard = PTSNAME.lock().unwrap_or_else(|e| e.into_inner());
+    let name = unsafe { ::sys::ptsname(fd) };
+
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+}
@@ -0,0 +1,170 @@
+//! Quoting of values so that shells read them back verbatim.
+//!
+//! Values interpolated by `sh!` and friends go straight through `format!`: a file name containing
+//! a space or a quote breaks the command, and one coming from user input can inject commands of
+//! its own. The `*_safe` macros (`sh_safe!`, `fish_safe!`...) quote every argument according to
+//! the rules of their shell instead, while `for_shell` and `Quoted` do the same for commands built
+//! by hand.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! fn main() {
+//!     let name = "it's $HOME; uname";
+//!
+//!     assert_eq!(sh_safe!("printf '%s\n' {}", name).1, "it's $HOME; uname\n");
+//!     assert_eq!(shells::quote::posix(name), r#"'it'\''s $HOME; uname'"#);
+//!     assert_eq!(shells::quote::fish(name), r#"'it\'s $HOME; uname'"#);
+//! }
+//! ```
+//!
+//! Only the arguments following the format string are quoted: identifiers captured by the format
+//! string itself, as in `sh_safe!("ls {dir}")`, are interpolated verbatim.
+//!
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::Path;
+
+/// Quotes `value` for POSIX shells, leaving it untouched when it only contains characters which
+/// have no special meaning.
+///
+pub fn posix(value: &str) -> Cow<'_, str> {
+    if is_plain(value) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', "'\\''")))
+    }
+}
+
+/// Quotes `value` for the Fish Shell, in which backslashes and single quotes are escaped with a
+/// backslash inside single quotes.
+///
+pub fn fish(value: &str) -> Cow<'_, str> {
+    if is_plain(value) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")))
+    }
+}
+
+/// Quotes `value` for the C Shell and the TENEX C Shell, which perform history substitution on
+/// `!` and end words at newlines even inside single quotes.
+///
+pub fn csh(value: &str) -> Cow<'_, str> {
+    if is_plain(value) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut quoted = String::from("'");
+
+    for c in value.chars() {
+        match c {
+            '\'' => quoted.push_str("'\\''"),
+            '!' => quoted.push_str("\\!"),
+            '\n' => quoted.push_str("\\\n"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// Quotes `value` for the Windows Command Prompt, between double quotes. `cmd` still expands
+/// `%VARIABLE%` sequences inside them, there is no way to prevent it on the command line.
+///
+pub fn cmd(value: &str) -> Cow<'_, str> {
+    if is_made_of(value, "-_./:\\") {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    }
+}
+
+/// Quotes `value` for Windows PowerShell, between single quotes, doubling the single quotes
+/// (including the typographic ones PowerShell accepts as such) it contains.
+///
+pub fn powershell(value: &str) -> Cow<'_, str> {
+    if is_made_of(value, "_./:\\") {
+        return Cow::Borrowed(value);
+    }
+
+    let mut quoted = String::from("'");
+
+    for c in value.chars() {
+        if let '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' = c {
+            quoted.push(c);
+        }
+
+        quoted.push(c);
+    }
+
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// Quotes `value` for the given shell, identified by its file name like `set_default_args`:
+/// `fish`, `csh` and `tcsh`, `cmd`, `powershell` and `pwsh` have rules of their own, every other
+/// shell is assumed to be POSIX compliant.
+///
+/// ```rust
+/// use shells::quote;
+///
+/// assert_eq!(quote::for_shell("/usr/bin/fish", "a\\b"), r"'a\\b'");
+/// assert_eq!(quote::for_shell("bash", "a\\b"), r"'a\b'");
+/// assert_eq!(quote::for_shell("zsh", "=ls"), "'=ls'");
+/// assert_eq!(quote::for_shell("zsh", "KEY=value"), "KEY=value");
+/// ```
+///
+pub fn for_shell<'a>(shell: &str, value: &'a str) -> Cow<'a, str> {
+    let name = Path::new(shell).file_stem().map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match &name[..] {
+        "fish" => fish(value),
+        "csh" | "tcsh" => csh(value),
+        "cmd" => cmd(value),
+        "powershell" | "pwsh" => powershell(value),
+        _ => posix(value),
+    }
+}
+
+/// Displays a value quoted for the given shell, see `for_shell`. This is what the `*_safe`
+/// macros wrap their arguments in.
+///
+/// ```rust
+/// use shells::quote::Quoted;
+///
+/// assert_eq!(format!("rm -- {}", Quoted::new("sh", &"my file")), "rm -- 'my file'");
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Quoted<'a, T: ?Sized> {
+    shell: &'a str,
+    value: &'a T,
+}
+
+impl<'a, T: fmt::Display + ?Sized> Quoted<'a, T> {
+    pub fn new(shell: &'a str, value: &'a T) -> Quoted<'a, T> {
+        Quoted { shell, value }
+    }
+}
+
+impl<'a, T: fmt::Display + ?Sized> fmt::Display for Quoted<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&for_shell(self.shell, &self.value.to_string()))
+    }
+}
+
+/// Whether the value only contains characters which have no special meaning in POSIX shells and
+/// their relatives. A leading `=` expands to the path of a program in zsh, e.g. `=ls`.
+///
+fn is_plain(value: &str) -> bool {
+    !value.starts_with('=') && is_made_of(value, "-_./,:=+@%")
+}
+
+fn is_made_of(value: &str, punctuation: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || punctuation.contains(c))
+}
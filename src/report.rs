@@ -0,0 +1,151 @@
+//! Outcomes of commands run together: the commands of a `batch::Batch`, a command on the hosts of
+//! a `fleet::Fleet`, the chunks of a `chunk::Chunked` script.
+//!
+//! A `Report` holds an `Outcome` per command which ran, in order, each about a subject naming
+//! what ran: the command line, the host, the statements of the chunk. Its `Display`
+//! implementation summarizes the failures, and `junit` and `tap` render it for CI systems.
+//!
+//! ```rust
+//! let report = shells::batch(["true", "echo oops >&2; exit 3"]).run();
+//!
+//! assert!(!report.is_success());
+//! assert_eq!(report.to_string(), "1/2 succeeded\necho oops >&2; exit 3: exit code 3\n    oops\n");
+//! ```
+//!
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use ssh::Remote;
+
+/// What an outcome is about, naming its test case in CI reports and its failure in summaries.
+///
+pub trait Subject {
+    fn name(&self) -> String;
+}
+
+impl Subject for String {
+    fn name(&self) -> String {
+        self.clone()
+    }
+}
+
+impl Subject for Remote {
+    fn name(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Subject for Range<usize> {
+    fn name(&self) -> String {
+        format!("statements {} to {}", self.start + 1, self.end)
+    }
+}
+
+/// Outcome of a command.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome<T> {
+    /// What ran: the command of a batch, the host of a fleet, the statements of a chunk.
+    pub subject: T,
+
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl<T> Outcome<T> {
+    /// Runs the command and records its outcome.
+    ///
+    pub(crate) fn timed<F>(subject: T, run: F) -> Outcome<T>
+        where F: FnOnce(&T) -> (i32, String, String)
+    {
+        let start = Instant::now();
+        let (code, stdout, stderr) = run(&subject);
+        Outcome { subject, code, stdout, stderr, duration: start.elapsed() }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// Outcomes of the commands which ran, in order, see the module documentation.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report<T> {
+    pub results: Vec<Outcome<T>>,
+
+    /// Number of commands to run, including those which did not run.
+    pub total: usize,
+}
+
+impl<T> Default for Report<T> {
+    fn default() -> Report<T> {
+        Report { results: Vec::new(), total: 0 }
+    }
+}
+
+impl<T: Subject> Report<T> {
+    /// Whether every command ran and succeeded.
+    ///
+    pub fn is_success(&self) -> bool {
+        self.results.len() == self.total && self.results.iter().all(Outcome::succeeded)
+    }
+
+    /// The commands which failed.
+    ///
+    pub fn failures(&self) -> impl Iterator<Item = &Outcome<T>> {
+        self.results.iter().filter(|result| !result.succeeded())
+    }
+
+    /// Renders the report as a JUnit XML test suite with a test case per command which ran, see
+    /// `ci::junit`.
+    ///
+    pub fn junit(&self, suite: &str) -> String {
+        ::ci::junit(suite, &self.ci_cases())
+    }
+
+    /// Renders the report in the TAP format with a test case per command which ran, see
+    /// `ci::tap`.
+    ///
+    pub fn tap(&self) -> String {
+        ::ci::tap(&self.ci_cases())
+    }
+
+    fn ci_cases(&self) -> Vec<::ci::Case<'_>> {
+        self.results.iter()
+            .map(|result| ::ci::Case {
+                name: result.subject.name(),
+                code: result.code,
+                stdout: &result.stdout,
+                stderr: &result.stderr,
+                duration: result.duration,
+            })
+            .collect()
+    }
+}
+
+impl<T: Subject> ::std::fmt::Display for Report<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let failures: Vec<&Outcome<T>> = self.failures().collect();
+
+        write!(f, "{}/{} succeeded", self.results.len() - failures.len(), self.total)?;
+
+        match self.total - self.results.len() {
+            0 => writeln!(f)?,
+            skipped => writeln!(f, ", {} not run", skipped)?,
+        }
+
+        for failure in failures {
+            writeln!(f, "{}: exit code {}", failure.subject.name(), failure.code)?;
+
+            for line in failure.stderr.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
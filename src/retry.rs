@@ -0,0 +1,160 @@
+//! Re-running flaky commands, see `Shell::retry` and the `retry_*` macros.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use shells::Shell;
+//! use shells::clock::{Clock, FakeClock};
+//! use shells::mock::{self, Response, Scenario};
+//! use shells::retry::Retry;
+//!
+//! fn main() {
+//!     let clock = FakeClock::new();
+//!     let policy = Retry::new(4).backoff(Duration::from_secs(1)).clock(Arc::new(clock.clone()))
+//!         .when(|_, stderr| stderr.contains("Could not resolve host"));
+//!
+//!     let scenario = Scenario::new()
+//!         .respond(Response::exit(6).stderr("curl: (6) Could not resolve host: example.com\n"))
+//!         .respond(Response::exit(6).stderr("curl: (6) Could not resolve host: example.com\n"))
+//!         .respond(Response::success().stdout("<html>\n"));
+//!
+//!     let start = clock.now();
+//!     let page = mock::with(scenario, || {
+//!         Shell::new("sh").retry(policy).try_run("curl -f https://example.com")
+//!     });
+//!
+//!     assert_eq!(page.unwrap(), "<html>\n");
+//!     assert_eq!(clock.now() - start, Duration::from_secs(1 + 2));
+//! }
+//! ```
+//!
+//! A command which could not be prepared, e.g. denied by a policy, is not retried, nor is a
+//! command cancelled with the token of `Shell::cancel_on`.
+//!
+//! ```rust
+//! use std::thread;
+//! use std::time::{Duration, Instant};
+//! use shells::{CancellationToken, Error, Shell};
+//! use shells::retry::Retry;
+//!
+//! let token = CancellationToken::new();
+//! let shell = Shell::new("sh").cancel_on(token.clone())
+//!     .retry(Retry::new(3).backoff(Duration::from_secs(10)));
+//!
+//! let canceller = token.clone();
+//! thread::spawn(move || {
+//!     thread::sleep(Duration::from_millis(100));
+//!     canceller.cancel();
+//! });
+//!
+//! let start = Instant::now();
+//!
+//! assert!(matches!(shell.try_run("sleep 10"), Err(Error::Cancelled { .. })));
+//! assert!(start.elapsed() < Duration::from_secs(5));
+//! ```
+//!
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use clock::{self, Clock};
+
+/// Tells whether a failure must be retried, from the exit code and stderr of the command.
+type Predicate = Arc<dyn Fn(i32, &str) -> bool + Send + Sync>;
+
+/// How many times and when to re-run a failing command.
+///
+#[derive(Clone)]
+pub struct Retry {
+    attempts: u32,
+    delay: Duration,
+    factor: u32,
+    max_delay: Duration,
+    predicate: Option<Predicate>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Retry {
+    /// Runs a failing command up to `attempts` times in total, without waiting in between.
+    ///
+    pub fn new(attempts: u32) -> Retry {
+        Retry {
+            attempts: attempts.max(1),
+            delay: Duration::from_secs(0),
+            factor: 1,
+            max_delay: Duration::from_secs(60),
+            predicate: None,
+            clock: clock::system(),
+        }
+    }
+
+    /// Waits for `delay` before each new attempt.
+    ///
+    pub fn delay(mut self, delay: Duration) -> Retry {
+        self.delay = delay;
+        self.factor = 1;
+        self
+    }
+
+    /// Waits for `initial` before the second attempt, doubling the delay for each attempt after
+    /// it, up to `max_delay`.
+    ///
+    pub fn backoff(mut self, initial: Duration) -> Retry {
+        self.delay = initial;
+        self.factor = 2;
+        self
+    }
+
+    /// Maximum delay between two attempts, 1 minute by default.
+    ///
+    pub fn max_delay(mut self, max: Duration) -> Retry {
+        self.max_delay = max;
+        self
+    }
+
+    /// Only retries the failures for which `predicate` returns true, given the exit code and the
+    /// stderr of the command. Every failure is retried by default.
+    ///
+    pub fn when<F: Fn(i32, &str) -> bool + Send + Sync + 'static>(mut self, predicate: F) -> Retry {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Waits with the given clock instead of the system one, for tests.
+    ///
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Retry {
+        self.clock = clock;
+        self
+    }
+
+    /// Waits before the next attempt and returns true when the command failed on the given
+    /// attempt (starting at 1) and must run again.
+    ///
+    pub(crate) fn again(&self, attempt: u32, code: i32, stderr: &str) -> bool {
+        let retried = code != 0 && attempt < self.attempts
+            && self.predicate.as_ref().map(|predicate| predicate(code, stderr)).unwrap_or(true);
+
+        if retried {
+            let factor = self.factor.checked_pow(attempt - 1).unwrap_or(u32::MAX);
+            let delay = self.delay.checked_mul(factor).unwrap_or(self.max_delay);
+
+            self.clock.sleep(delay.min(self.max_delay));
+        }
+
+        retried
+    }
+}
+
+impl ::std::fmt::Debug for Retry {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Retry")
+            .field("attempts", &self.attempts)
+            .field("delay", &self.delay)
+            .field("factor", &self.factor)
+            .field("max_delay", &self.max_delay)
+            .finish()
+    }
+}
@@ -0,0 +1,189 @@
+//! Sandboxing of commands through bubblewrap or firejail.
+//!
+//! A `SandboxSpec` declares which paths the command may read and write and whether it may use the
+//! network. The first backend found on the PATH is used, bubblewrap being preferred; when none is
+//! available the command is not run at all.
+//!
+//! With bubblewrap, paths which are not part of the spec are hidden (the usual system directories
+//! are always mounted read-only so that the shell can start). Firejail has no such mode: the whole
+//! filesystem is made read-only instead and only the `rw` paths are writable.
+//!
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use which::which;
+
+const SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc"];
+
+/// Sandboxing tools supported by `SandboxSpec`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Bubblewrap,
+    Firejail,
+}
+
+impl Backend {
+    /// Name of the program implementing the backend.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            Backend::Bubblewrap => "bwrap",
+            Backend::Firejail => "firejail",
+        }
+    }
+
+    /// Returns the first backend available on the PATH.
+    ///
+    pub fn detect() -> Option<Backend> {
+        [Backend::Bubblewrap, Backend::Firejail].iter().cloned()
+            .find(|backend| which(backend.program()).is_some())
+    }
+}
+
+/// Error returned when a sandboxed command cannot be wrapped.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Neither bubblewrap nor firejail could be found.
+    Unavailable,
+
+    /// The backend requested with `SandboxSpec::backend` could not be found.
+    Missing(Backend),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Sandbox backend unavailable."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Unavailable => {
+                write!(f, "no sandbox backend available: install bubblewrap (bwrap) or firejail")
+            },
+
+            Error::Missing(backend) => {
+                write!(f, "sandbox backend `{}` not found on the PATH", backend.program())
+            },
+        }
+    }
+}
+
+/// Declarative description of the sandbox a command runs in.
+///
+/// ```rust,no_run
+/// use shells::Shell;
+/// use shells::sandbox::SandboxSpec;
+///
+/// let spec = SandboxSpec::new().ro("/srv/data").rw("/tmp/out").no_net();
+/// let (code, _, stderr) = Shell::new("sh").sandbox(spec).run("cp /srv/data/* /tmp/out");
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SandboxSpec {
+    ro: Vec<PathBuf>,
+    rw: Vec<PathBuf>,
+    no_net: bool,
+    backend: Option<Backend>,
+}
+
+impl SandboxSpec {
+    /// Creates a spec granting nothing beyond the system directories.
+    ///
+    pub fn new() -> SandboxSpec {
+        SandboxSpec::default()
+    }
+
+    /// Makes the given path readable inside the sandbox.
+    ///
+    pub fn ro<P: Into<PathBuf>>(mut self, path: P) -> SandboxSpec {
+        self.ro.push(path.into());
+        self
+    }
+
+    /// Makes the given path readable and writable inside the sandbox.
+    ///
+    pub fn rw<P: Into<PathBuf>>(mut self, path: P) -> SandboxSpec {
+        self.rw.push(path.into());
+        self
+    }
+
+    /// Cuts the sandbox off the network.
+    ///
+    pub fn no_net(mut self) -> SandboxSpec {
+        self.no_net = true;
+        self
+    }
+
+    /// Forces the use of a specific backend instead of picking the first one available.
+    ///
+    pub fn backend(mut self, backend: Backend) -> SandboxSpec {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Returns `argv` prefixed by the invocation of the sandboxing tool.
+    ///
+    pub(crate) fn wrap(&self, argv: Vec<OsString>) -> Result<Vec<OsString>, Error> {
+        let backend = match self.backend {
+            Some(backend) if which(backend.program()).is_some() => backend,
+            Some(backend) => return Err(Error::Missing(backend)),
+            None => Backend::detect().ok_or(Error::Unavailable)?,
+        };
+
+        let mut wrapped: Vec<OsString> = vec![backend.program().into()];
+
+        match backend {
+            Backend::Bubblewrap => {
+                wrapped.extend(["--die-with-parent", "--dev", "/dev", "--proc", "/proc", "--tmpfs",
+                                "/tmp"].iter().map(OsString::from));
+
+                for path in SYSTEM_PATHS {
+                    wrapped.extend(["--ro-bind-try", *path, *path].iter().map(OsString::from));
+                }
+
+                for path in &self.ro {
+                    wrapped.push("--ro-bind".into());
+                    wrapped.push(path.clone().into());
+                    wrapped.push(path.clone().into());
+                }
+
+                for path in &self.rw {
+                    wrapped.push("--bind".into());
+                    wrapped.push(path.clone().into());
+                    wrapped.push(path.clone().into());
+                }
+
+                if self.no_net {
+                    wrapped.push("--unshare-net".into());
+                }
+
+                wrapped.push("--".into());
+            },
+
+            Backend::Firejail => {
+                let flags = ["--quiet", "--noprofile", "--read-only=/"];
+                wrapped.extend(flags.iter().map(OsString::from));
+
+                for path in &self.rw {
+                    let mut arg = OsString::from("--read-write=");
+                    arg.push(path);
+                    wrapped.push(arg);
+                }
+
+                if self.no_net {
+                    wrapped.push("--net=none".into());
+                }
+
+                wrapped.push("--".into());
+            },
+        }
+
+        wrapped.extend(argv);
+        Ok(wrapped)
+    }
+}
@@ -0,0 +1,2187 @@
+//! Builder used to configure how a shell is invoked.
+//!
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use Error;
+use output::ExitStatus;
+
+/// The standard output streams of a command.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Output filter registered with `Shell::filter_stream`.
+///
+#[derive(Clone)]
+struct Filter {
+    stream: Option<Stream>,
+    apply: Arc<dyn Fn(&str) -> String + Send + Sync>,
+}
+
+impl ::std::fmt::Debug for Filter {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Filter").field("stream", &self.stream).finish()
+    }
+}
+
+/// Applies the filters registered for the stream to the output.
+///
+fn filtered(filters: &[Filter], stream: Stream, output: String) -> String {
+    filters.iter()
+        .filter(|filter| filter.stream.map(|s| s == stream).unwrap_or(true))
+        .fold(output, |output, filter| (filter.apply)(&output))
+}
+
+/// A configurable shell. The macros of this crate behave like `Shell::new(name).run(cmd)`; build
+/// a `Shell` yourself when the invocation needs more options than the macros expose.
+///
+/// ```rust
+/// use shells::Shell;
+///
+/// let (code, stdout, _) = Shell::new("sh").run("echo hello");
+///
+/// assert_eq!(code, 0);
+/// assert_eq!(&stdout[..], "hello\n");
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Shell {
+    program: String,
+    args: Vec<String>,
+    command_flag: Option<String>,
+    interactive: bool,
+    cwd: Option<PathBuf>,
+    env: Vec<(OsString, Option<OsString>)>,
+    env_clear: bool,
+    port: Option<u16>,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+    cancel: Option<::cancel::CancellationToken>,
+    process_group: bool,
+    session: bool,
+    preamble: String,
+    strict: bool,
+    script_file: bool,
+    source: Option<(String, u32)>,
+    exec_fallback: bool,
+    sandbox: Option<::sandbox::SandboxSpec>,
+    no_network: bool,
+    confinement: Vec<::confine::Confinement>,
+    private_tmpdir: bool,
+    keep_tmpdir: bool,
+    preflight: Option<::preflight::Preflight>,
+    policy: Option<::policy::Policy>,
+    approval: Option<::policy::Approval>,
+    force: bool,
+    filters: Vec<Filter>,
+    sinks: Vec<::sink::Attached>,
+    stream_buffer: ::stream::Buffer,
+    metrics: Option<(::metrics::Metrics, String)>,
+    retry: Option<::retry::Retry>,
+
+    #[cfg(all(feature = "seccomp", target_os = "linux"))]
+    seccomp: Option<::seccomp::Filter>,
+
+    #[cfg(feature = "regex")]
+    triggers: Vec<::trigger::Trigger>,
+}
+
+impl Shell {
+    /// Creates a shell invoking the given program, looked up on the PATH, or as configured for
+    /// the current thread, see `with_shell_config`.
+    ///
+    pub fn new(program: &str) -> Shell {
+        match ::defaults::scoped_config(program) {
+            Some(config) => Shell::from(config),
+            None => Shell::bare(program),
+        }
+    }
+
+    fn bare(program: &str) -> Shell {
+        Shell {
+            program: program.to_string(),
+            args: Vec::new(),
+            command_flag: None,
+            interactive: false,
+            cwd: None,
+            env: Vec::new(),
+            env_clear: false,
+            port: None,
+            stdin: None,
+            timeout: None,
+            cancel: None,
+            process_group: false,
+            session: false,
+            preamble: String::new(),
+            strict: false,
+            script_file: false,
+            source: None,
+            exec_fallback: false,
+            sandbox: None,
+            no_network: false,
+            confinement: Vec::new(),
+            private_tmpdir: false,
+            keep_tmpdir: false,
+            preflight: None,
+            policy: None,
+            approval: None,
+            force: false,
+            filters: Vec::new(),
+            sinks: Vec::new(),
+            stream_buffer: ::stream::Buffer::Unbounded,
+            metrics: None,
+            retry: None,
+
+            #[cfg(all(feature = "seccomp", target_os = "linux"))]
+            seccomp: None,
+
+            #[cfg(feature = "regex")]
+            triggers: Vec::new(),
+        }
+    }
+
+    /// Adds a flag passed to the shell before `-c`, after the defaults set with
+    /// `set_default_args`.
+    ///
+    pub fn arg(mut self, arg: &str) -> Shell {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Overrides the flag the command is given after: `/C` for `cmd`, `-Command` for
+    /// `powershell` and `pwsh`, `-c` for any other shell by default.
+    ///
+    pub fn command_flag(mut self, flag: &str) -> Shell {
+        self.command_flag = Some(flag.to_string());
+        self
+    }
+
+    /// Runs the shell with `-i`, so that the command sees the aliases and functions defined in
+    /// the interactive rc files. The warnings shells print about job control being unavailable
+    /// without a terminal are removed from stderr.
+    ///
+    pub fn interactive(mut self) -> Shell {
+        self.interactive = true;
+        self
+    }
+
+    /// Runs the commands in the given working directory instead of the current one.
+    ///
+    pub fn cwd<P: Into<PathBuf>>(mut self, path: P) -> Shell {
+        self.cwd = Some(path.into());
+        self
+    }
+
+    /// Sets an environment variable for the commands.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").cwd("/").env("GREETING", "hello");
+    ///
+    /// assert_eq!(shell.run("echo \"$GREETING from $(pwd)\"").1, "hello from /\n");
+    /// ```
+    ///
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(mut self, name: K, value: V) -> Shell {
+        self.env.push((name.into(), Some(value.into())));
+        self
+    }
+
+    /// Sets the environment variables for the commands, see `env`.
+    ///
+    pub fn envs<I, K, V>(mut self, vars: I) -> Shell
+        where I: IntoIterator<Item = (K, V)>, K: Into<OsString>, V: Into<OsString>
+    {
+        self.env.extend(vars.into_iter().map(|(name, value)| (name.into(), Some(value.into()))));
+        self
+    }
+
+    /// Hands a port to the commands, usually one from `free_port`: every `{port}` of a command is
+    /// replaced by it before the command runs, except in `${port}`, and `$PORT` is set to it.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let port = shells::free_port().unwrap();
+    /// let (_, stdout, _) = Shell::new("sh").port(port).run("echo {port} ${port:-unset}");
+    ///
+    /// assert_eq!(stdout, format!("{} unset\n", port));
+    /// ```
+    ///
+    pub fn port(mut self, port: u16) -> Shell {
+        self.port = Some(port);
+        self
+    }
+
+    /// Removes an environment variable inherited from the current process.
+    ///
+    pub fn env_remove<K: Into<OsString>>(mut self, name: K) -> Shell {
+        self.env.push((name.into(), None));
+        self
+    }
+
+    /// Starts the commands with an empty environment, for hermetic invocations: only the variables
+    /// set with `env` and `envs` are defined. The shell itself is still looked up on the PATH of
+    /// the current process.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").env_clear().env("ONLY", "me");
+    ///
+    /// assert_eq!(shell.run("echo \"${HOME:-unset} $ONLY\"").1, "unset me\n");
+    /// ```
+    ///
+    pub fn env_clear(mut self) -> Shell {
+        self.env_clear = true;
+        self.env.clear();
+        self
+    }
+
+    /// Feeds the given bytes to the standard input of the commands. Without input, commands read
+    /// an empty stdin.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").stdin("b\na\n");
+    ///
+    /// assert_eq!(shell.run("sort").1, "a\nb\n");
+    /// ```
+    ///
+    pub fn stdin<I: Into<Vec<u8>>>(mut self, input: I) -> Shell {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    /// Kills the commands still running after the given duration. They then return the code 124,
+    /// like the `timeout` utility, with what they printed until then, or `Error::TimedOut` with
+    /// `try_run`. On Unix, the command runs in its own process group so that the processes it
+    /// started are killed as well. Does not apply to `stream`.
+    ///
+    pub fn timeout(mut self, timeout: Duration) -> Shell {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Kills the blocking executions of the commands once the token is cancelled, with the
+    /// processes of their group like the timeout does, and the commands started by `start`. A
+    /// cancelled command fails with `Error::Cancelled` and the code 130, see the `cancel` module.
+    ///
+    pub fn cancel_on(mut self, token: ::cancel::CancellationToken) -> Shell {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Runs the commands in a process group of their own on Unix, so that `ShellChild::kill_tree`
+    /// kills the processes they started along with them: pipelines, jobs run in the background...
+    /// The timeout, `cancel_on` and `start` already imply it.
+    ///
+    pub fn process_group(mut self) -> Shell {
+        self.process_group = true;
+        self
+    }
+
+    /// Runs the commands in a session of their own on Unix, with `setsid`: they get a process group
+    /// of their own, like with `process_group`, and are detached from the controlling terminal of
+    /// the program, so that the signals of the terminal, a Ctrl-C or a hang up, do not reach them.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let (_, stdout, _) = Shell::new("sh").new_session().run("ps -o sid= -p $$; echo $$");
+    /// let ids: Vec<&str> = stdout.split_whitespace().collect();
+    ///
+    /// assert_eq!(ids[0], ids[1]);
+    /// ```
+    ///
+    pub fn new_session(mut self) -> Shell {
+        self.session = true;
+        self
+    }
+
+    /// Appends shell code (function definitions, aliases, `source` lines...) prepended to every
+    /// command run by this shell. For a preamble specific to one call, add it to a clone.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").preamble("greet() { echo \"hello $1\"; }");
+    ///
+    /// assert_eq!(shell.run("greet world").1, "hello world\n");
+    /// ```
+    ///
+    pub fn preamble(mut self, code: &str) -> Shell {
+        self.preamble.push_str(code);
+
+        if !code.ends_with('\n') {
+            self.preamble.push('\n');
+        }
+
+        self
+    }
+
+    /// Makes the commands fail at the first failing step, an unset variable or a failure in the
+    /// middle of a pipeline, which the shells ignore by default:
+    ///
+    /// * `set -euo pipefail` runs before the commands of bash, zsh, ksh, mksh and ash;
+    /// * `set -eu` before those of sh, dash and unknown shells, which may lack `pipefail`;
+    /// * csh and tcsh get the `-e` flag;
+    /// * PowerShell stops on errors, native commands included from version 7.3, and in strict
+    ///   mode.
+    ///
+    /// fish and cmd have no such mode: their commands fail with the code 126.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let (code, stdout, _) = Shell::new("bash").strict().run("false | sort; echo unreachable");
+    ///
+    /// assert_eq!((code, &stdout[..]), (1, ""));
+    /// ```
+    ///
+    pub fn strict(mut self) -> Shell {
+        self.strict = true;
+        self
+    }
+
+    /// Passes the script to the shell in a temporary file rather than as an argument after `-c`,
+    /// for long multi-line scripts which would exceed the limits of the command line. The file is
+    /// readable by the current user only, and removed once the command exited. `cmd` and
+    /// PowerShell get it with the extension they require, `.cmd` and `.ps1`.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let script = format!("cat <<'EOF' | wc -c\n{}\nEOF", "x".repeat(1 << 20));
+    /// let (code, stdout, _) = Shell::new("sh").script_file().run(&script);
+    ///
+    /// assert_eq!((code, stdout.trim()), (0, "1048577"));
+    /// ```
+    ///
+    pub fn script_file(mut self) -> Shell {
+        self.script_file = true;
+        self
+    }
+
+    /// Tells where the scripts run by this shell are written in the Rust sources: their first line
+    /// is at `line` of `file`. The errors the shell reports at a line of a script then mention the
+    /// corresponding location, e.g. `sh: 3: Syntax error: "fi" unexpected (src/main.rs:14)`. See
+    /// the `script!` macro, which passes `file!()` and `line!()`.
+    ///
+    pub fn source(mut self, file: &str, line: u32) -> Shell {
+        self.source = Some((file.to_string(), line));
+        self
+    }
+
+    /// When the shell reports that the command was not found (code 127) and the command is a
+    /// single program invocation without any shell syntax, retries by executing the program
+    /// directly. If it cannot be found either, the directories searched are listed in stderr.
+    /// The fallback is disabled for sandboxed or confined shells and shells with a preamble.
+    ///
+    pub fn exec_fallback(mut self) -> Shell {
+        self.exec_fallback = true;
+        self
+    }
+
+    /// Runs the command inside a sandbox; see the `sandbox` module for the semantics of the spec.
+    /// When no sandboxing tool is available the command is not executed and the code 126 is
+    /// returned.
+    ///
+    pub fn sandbox(mut self, spec: ::sandbox::SandboxSpec) -> Shell {
+        self.sandbox = Some(spec);
+        self
+    }
+
+    /// Runs the command in a private network namespace, guaranteeing it cannot reach the network
+    /// (not even `localhost`). Only supported on Linux: elsewhere the command is not executed and
+    /// the code 126 is returned.
+    ///
+    pub fn no_network(mut self) -> Shell {
+        self.no_network = true;
+        self
+    }
+
+    /// Runs the shell under the given SELinux security context through `runcon`. When `runcon`
+    /// is not installed the command is not executed and the code 126 is returned.
+    ///
+    pub fn selinux_context(mut self, context: &str) -> Shell {
+        self.confinement.push(::confine::Confinement::SeLinux(context.to_string()));
+        self
+    }
+
+    /// Runs the shell under the given AppArmor profile through `aa-exec`. When `aa-exec` is not
+    /// installed the command is not executed and the code 126 is returned.
+    ///
+    pub fn apparmor_profile(mut self, profile: &str) -> Shell {
+        self.confinement.push(::confine::Confinement::AppArmor(profile.to_string()));
+        self
+    }
+
+    /// Gives each command a private, empty `TMPDIR`, created right before it starts and removed
+    /// with its content once it exits, so that temporary files of concurrent commands cannot
+    /// collide and none are left behind. Sandboxed shells must be granted write access to
+    /// `std::env::temp_dir()` for the directory to be usable.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").private_tmpdir();
+    /// let (code, stdout, _) = shell.run("touch \"$TMPDIR/scratch\" && echo \"$TMPDIR\"");
+    ///
+    /// assert_eq!(code, 0);
+    /// assert!(!::std::path::Path::new(stdout.trim_end()).exists());
+    /// ```
+    ///
+    pub fn private_tmpdir(mut self) -> Shell {
+        self.private_tmpdir = true;
+        self
+    }
+
+    /// Like `private_tmpdir`, but keeps the directory once the command exits, for debugging. Its
+    /// path is reported at the end of stderr.
+    ///
+    pub fn keep_tmpdir(mut self) -> Shell {
+        self.private_tmpdir = true;
+        self.keep_tmpdir = true;
+        self
+    }
+
+    /// Checks the given resource requirements before every command; when one is not met the
+    /// command is not executed and the code 126 is returned along with the reason, or
+    /// `Error::Preflight` with `try_run`.
+    ///
+    pub fn preflight(mut self, checks: ::preflight::Preflight) -> Shell {
+        self.preflight = Some(checks);
+        self
+    }
+
+    /// Checks every command against the policy before executing it, in addition to the policy
+    /// installed process-wide with `policy::install`. See `policy::Policy`.
+    ///
+    pub fn policy(mut self, policy: ::policy::Policy) -> Shell {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Asks for an interactive confirmation, on stderr, before executing the commands matched by
+    /// the gate. A command which is not confirmed, e.g. because stdin is not a terminal, is not
+    /// executed and the code 126 is returned. See `policy::Approval`.
+    ///
+    pub fn approval(mut self, gate: ::policy::Approval) -> Shell {
+        self.approval = Some(gate);
+        self
+    }
+
+    /// Executes the commands matched by the approval gate without asking for confirmation.
+    ///
+    pub fn force(mut self) -> Shell {
+        self.force = true;
+        self
+    }
+
+    /// Registers a filter applied to the captured stdout and stderr before they are returned,
+    /// for instance to normalize temporary paths or timestamps in test assertions. Filters run in
+    /// registration order.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh").filter(|output| output.to_uppercase());
+    ///
+    /// assert_eq!(shell.run("echo hello").1, "HELLO\n");
+    /// ```
+    ///
+    pub fn filter<F: Fn(&str) -> String + Send + Sync + 'static>(mut self, f: F) -> Shell {
+        self.filters.push(Filter { stream: None, apply: Arc::new(f) });
+        self
+    }
+
+    /// Registers a filter applied to a single stream.
+    ///
+    pub fn filter_stream<F>(mut self, stream: Stream, f: F) -> Shell
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        self.filters.push(Filter { stream: Some(stream), apply: Arc::new(f) });
+        self
+    }
+
+    /// Copies the captured stdout and stderr of the commands to the sink as they are read. The
+    /// sink is shared by every command of this shell and its clones. Each call attaches one more
+    /// sink, failing independently of the others. Mocked commands, `status` and `spawn` do not
+    /// capture output and bypass the sinks. See the `sink` module.
+    ///
+    pub fn sink<S: ::sink::Sink + Send + 'static>(mut self, sink: S) -> Shell {
+        self.sinks.push(::sink::Attached::new(None, sink));
+        self
+    }
+
+    /// Copies a single captured stream to the sink, see `sink`.
+    ///
+    pub fn sink_stream<S>(mut self, stream: Stream, sink: S) -> Shell
+        where S: ::sink::Sink + Send + 'static
+    {
+        self.sinks.push(::sink::Attached::new(Some(stream), sink));
+        self
+    }
+
+    /// Bounds the buffer of the lines read by `stream` and not consumed yet, see `Buffer`.
+    ///
+    pub fn stream_buffer(mut self, buffer: ::stream::Buffer) -> Shell {
+        self.stream_buffer = buffer;
+        self
+    }
+
+    /// Runs the action of the trigger on each line of the output of `stream` matching its regular
+    /// expression, see the `trigger` module. A shell with a trigger killing the command starts it
+    /// in its own process group, on Unix.
+    ///
+    #[cfg(feature = "regex")]
+    pub fn trigger(mut self, trigger: ::trigger::Trigger) -> Shell {
+        self.triggers.push(trigger);
+        self
+    }
+
+    /// Re-runs the commands which fail according to the policy, see the `retry` module. Applies to
+    /// `run`, `try_run` and `output`.
+    ///
+    pub fn retry(mut self, policy: ::retry::Retry) -> Shell {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Records the runs of the commands in the registry, labelled with the job name. See the
+    /// `metrics` module.
+    ///
+    pub fn metrics(mut self, metrics: &::metrics::Metrics, job: &str) -> Shell {
+        self.metrics = Some((metrics.clone(), job.to_string()));
+        self
+    }
+
+    /// Prints the output of the commands live on the stdout and stderr of the current process,
+    /// while still capturing it. The output is printed as read, before the filters.
+    ///
+    pub fn tee(self) -> Shell {
+        self.sink(::sink::Tee)
+    }
+
+    /// Registers a filter replacing every occurrence of `from` by `to` in both streams.
+    ///
+    pub fn replace(self, from: &str, to: &str) -> Shell {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.filter(move |output| output.replace(&from[..], &to[..]))
+    }
+
+    /// Attaches a seccomp filter to the spawned shell. The filter is inherited by every process
+    /// the command starts.
+    ///
+    #[cfg(all(feature = "seccomp", target_os = "linux"))]
+    pub fn seccomp(mut self, filter: ::seccomp::Filter) -> Shell {
+        self.seccomp = Some(filter);
+        self
+    }
+
+    /// Prepares the command like `prepare`, once confirmed when the gate of `approval` matches it.
+    ///
+    pub(crate) fn approved(&self, cmd: &str) -> ::std::result::Result<PreparedCommand, Error> {
+        let prepared = self.prepare(cmd)?;
+
+        match self.approval {
+            Some(ref gate) if !self.force => gate.confirm(&prepared.command).map_err(|reason| {
+                Error::PolicyDenied { command: prepared.command.clone(), reason }
+            })?,
+
+            _ => (),
+        }
+
+        Ok(prepared)
+    }
+
+    /// What determines the effect of the commands run by this shell, see `memo::Step`: unlike its
+    /// `Debug` form, the key leaves out the state of the metrics, cancellation tokens and sinks.
+    ///
+    pub(crate) fn memo_key(&self) -> String {
+        format!("{:?}", (&self.program, &self.args, &self.command_flag, self.interactive,
+                         self.strict, &self.cwd, &self.env, self.env_clear, self.port, &self.stdin,
+                         &self.preamble))
+    }
+
+    /// Resolves everything needed to execute `cmd` without running it, so that the exact
+    /// invocation can be reviewed or logged first. Fails when the command could not be executed,
+    /// for instance because a required tool is missing; the error then has the code 126. The
+    /// commands matched by the gate of `approval` are not confirmed: only executing them through
+    /// the shell is.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let prepared = Shell::new("sh").prepare("echo hello").unwrap();
+    ///
+    /// assert_eq!(prepared.argv(), &["sh", "-c", "echo hello"]);
+    /// assert_eq!(prepared.to_string(), "sh -c 'echo hello'");
+    /// assert_eq!(prepared.run().1, "hello\n");
+    /// ```
+    ///
+    pub fn prepare(&self, cmd: &str) -> ::std::result::Result<PreparedCommand, Error> {
+        let rendered = self.port.map(|port| ::port::render(cmd, port));
+        let cmd = rendered.as_deref().unwrap_or(cmd);
+
+        let unavailable = |stderr: String| {
+            Error::Failed { code: 126, stdout: String::new(), stderr }
+        };
+        let denied = |reason: String| Error::PolicyDenied { command: cmd.to_string(), reason };
+
+        ::policy::check_global(cmd).map_err(denied)?;
+
+        if let Some(ref policy) = self.policy {
+            policy.check(cmd).map_err(denied)?;
+        }
+
+        if let Some(ref checks) = self.preflight {
+            checks.check().map_err(|error| Error::Preflight { reason: error.to_string(), error })?;
+        }
+
+        let mut argv: Vec<OsString> = vec![self.program.clone().into()];
+        argv.extend(::defaults::default_args(&self.program).into_iter().map(OsString::from));
+        argv.extend(self.args.iter().map(OsString::from));
+
+        if self.interactive {
+            argv.push("-i".into());
+        }
+
+        let mut preamble = String::new();
+
+        if self.strict {
+            match strict_mode(&self.program).map_err(unavailable)? {
+                Strict::Flag(flag) => argv.push(flag.into()),
+                Strict::Preamble(code) => preamble.push_str(code),
+            }
+        }
+
+        preamble.push_str(&self.preamble);
+
+        let script = format!("{}{}", preamble, cmd);
+        let flag = self.command_flag.as_deref()
+            .unwrap_or_else(|| default_command_flag(&self.program));
+        let args = argv[1..].to_vec();
+        let syntax = ::syntax::Check::new(&self.program, args, flag, &script, &preamble);
+
+        let script_file = match self.script_file {
+            true => Some(ScriptFile::new(&self.program, &script)),
+            false => None,
+        };
+
+        match script_file {
+            Some(ref file) => argv.extend(file.args(&self.program)),
+            None => argv.extend([OsString::from(flag), script.clone().into()]),
+        }
+
+        for confinement in &self.confinement {
+            argv = confinement.wrap(argv).map_err(|e| unavailable(e.to_string()))?;
+        }
+
+        if let Some(ref spec) = self.sandbox {
+            argv = spec.wrap(argv).map_err(|e| unavailable(e.to_string()))?;
+        }
+
+        let mut filters = Vec::with_capacity(self.filters.len() + 1);
+
+        if self.interactive {
+            let apply = Arc::new(strip_job_control);
+            filters.push(Filter { stream: Some(Stream::Stderr), apply });
+        }
+
+        if let Some((ref file, line)) = self.source {
+            let offset = preamble.matches('\n').count();
+            let path = script_file.as_ref().map(|file| file.path.clone());
+            let locate = ::syntax::locate(&self.program, path, offset, file, line);
+            filters.push(Filter { stream: Some(Stream::Stderr), apply: Arc::new(locate) });
+        }
+
+        filters.extend(self.filters.iter().cloned());
+
+        // The sinks receive the output filtered, line by line.
+        let applies = |stream| {
+            filters.iter().any(|filter| filter.stream.unwrap_or(stream) == stream)
+        };
+        let streams = [applies(Stream::Stdout), applies(Stream::Stderr)];
+
+        let sinks = match streams {
+            [false, false] => self.sinks.clone(),
+            _ => {
+                let all = filters.clone();
+                let apply: ::sink::Filters = Arc::new(move |stream, text| {
+                    filtered(&all, stream, text.into())
+                });
+                self.sinks.iter().map(|sink| sink.filtered(apply.clone(), streams)).collect()
+            },
+        };
+
+        let mut hooks = Hooks::default();
+
+        if self.no_network {
+            hooks.isolation = Some(isolation().map_err(unavailable)?);
+        }
+
+        #[cfg(all(feature = "seccomp", target_os = "linux"))]
+        {
+            hooks.seccomp = self.seccomp.as_ref().map(|filter| filter.program());
+        }
+
+        let (mut env, trace) = project(self.cwd.as_deref(), self.env_clear);
+        env.extend(self.port.map(|port| ("PORT".into(), Some(port.to_string().into()))));
+        env.extend(self.env.iter().cloned());
+
+        let mut tmpdir = None;
+
+        if self.private_tmpdir {
+            let path = unique_tmpdir();
+            env.push(("TMPDIR".into(), Some(path.clone().into_os_string())));
+            tmpdir = Some(TmpDir { path, keep: self.keep_tmpdir });
+        }
+
+        Ok(PreparedCommand {
+            argv,
+            env,
+            env_clear: self.env_clear,
+            cwd: self.cwd.clone(),
+            stdin: self.stdin.clone(),
+            timeout: self.timeout,
+            cancel: self.cancel.clone(),
+            own_group: self.process_group,
+            session: self.session,
+            script,
+            command: cmd.to_string(),
+            syntax,
+            hooks,
+            filters,
+            sinks,
+            stream_buffer: self.stream_buffer,
+            metrics: self.metrics.clone(),
+            tmpdir,
+            script_file,
+            trace,
+
+            #[cfg(feature = "regex")]
+            triggers: self.triggers.clone(),
+        })
+    }
+
+    /// Executes the command and returns its exit code, stdout and stderr. A command which could
+    /// not be started returns the code 126 and the reason in stderr.
+    ///
+    pub fn run(&self, cmd: &str) -> (i32, String, String) {
+        match self.execute(cmd) {
+            Ok(outcome) => outcome.into_tuple(),
+            Err(e) => (e.code(), e.stdout().to_string(), e.stderr().to_string()),
+        }
+    }
+
+    /// Executes the command and returns its stdout when it succeeded, like the `wrap_*` family of
+    /// macros. A command killed by the timeout returns `Error::TimedOut`.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use shells::{Error, Shell};
+    ///
+    /// let shell = Shell::new("sh").timeout(Duration::from_millis(200));
+    ///
+    /// assert_eq!(shell.try_run("echo done"), Ok("done\n".to_string()));
+    ///
+    /// match shell.try_run("echo started; sleep 10") {
+    ///     Err(Error::TimedOut { partial_stdout, .. }) => assert_eq!(partial_stdout, "started\n"),
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    ///
+    pub fn try_run(&self, cmd: &str) -> ::Result {
+        self.execute(cmd)?.into_result()
+    }
+
+    /// Executes the command like `try_run`, but returns its `Output` when it succeeded, for the
+    /// commands printing warnings or progress to stderr even then, like `git clone`.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let shell = Shell::new("sh");
+    /// let output = shell.try_output("echo done; echo 'warning: deprecated' >&2").unwrap();
+    ///
+    /// assert_eq!((&output.stdout[..], &output.stderr[..]), ("done\n", "warning: deprecated\n"));
+    /// ```
+    ///
+    pub fn try_output(&self, cmd: &str) -> ::std::result::Result<::Output, Error> {
+        let start = Instant::now();
+        self.execute(cmd)?.into_full_result(start.elapsed())
+    }
+
+    /// Starts the command on the current tokio runtime and returns a future resolving to its exit
+    /// code, stdout and stderr, without blocking the executor. Requires the `tokio` feature; the
+    /// runtime must have its IO driver enabled, and its time driver for `timeout`.
+    ///
+    /// ```rust,edition2018
+    /// use shells::Shell;
+    ///
+    /// let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    ///
+    /// runtime.block_on(async {
+    ///     let (code, stdout, _) = Shell::new("sh").stdin("hello").run_async("cat").await;
+    ///
+    ///     assert_eq!((code, &stdout[..]), (0, "hello"));
+    /// });
+    /// ```
+    ///
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self, cmd: &str) -> ::future::Execution<(i32, String, String)> {
+        match self.approved(cmd) {
+            Ok(prepared) => ::future::Execution::start(prepared, Outcome::into_tuple),
+            Err(e) => {
+                let result = (e.code(), e.stdout().to_string(), e.stderr().to_string());
+                ::future::Execution::resolved(result, Outcome::into_tuple)
+            },
+        }
+    }
+
+    /// Asynchronous counterpart of `try_run`, see `run_async`.
+    ///
+    /// ```rust,edition2018
+    /// use shells::{Error, Shell};
+    /// use shells::policy::Policy;
+    ///
+    /// let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// let shell = Shell::new("sh").policy(Policy::new().allow_program("echo"));
+    ///
+    /// runtime.block_on(async {
+    ///     assert_eq!(shell.try_run_async("echo hello").await.unwrap(), "hello\n");
+    ///     assert!(matches!(shell.try_run_async("uname").await, Err(Error::PolicyDenied { .. })));
+    /// });
+    /// ```
+    ///
+    #[cfg(feature = "tokio")]
+    pub fn try_run_async(&self, cmd: &str) -> ::future::Execution<::Result> {
+        match self.approved(cmd) {
+            Ok(prepared) => ::future::Execution::start(prepared, Outcome::into_result),
+            Err(e) => ::future::Execution::resolved(Err(e), Outcome::into_result),
+        }
+    }
+
+    /// Executes the command and returns its result as an `Output`, which also tells commands
+    /// killed by a signal apart from those exiting with a non-zero code, and how long the command
+    /// took.
+    ///
+    /// ```rust
+    /// use shells::{ExitStatus, Shell};
+    ///
+    /// let output = Shell::new("sh").output("kill -9 $$");
+    ///
+    /// assert_eq!(output.status, ExitStatus::Signaled(9));
+    /// assert_eq!(output.code, 137);
+    /// ```
+    ///
+    pub fn output(&self, cmd: &str) -> ::Output {
+        let start = Instant::now();
+
+        match self.execute(cmd) {
+            Ok(outcome) => outcome.into_output(start.elapsed()),
+            Err(e) => {
+                Outcome::failed(e.code(), e.stderr().to_string()).into_output(start.elapsed())
+            },
+        }
+    }
+
+    /// Executes the command and returns its exit code along with its stdout and stderr as they
+    /// were printed, for commands emitting binary data such as `tar -c`. Output filters, which
+    /// work on text, do not apply.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let (code, stdout, _) = Shell::new("sh").run_bytes("printf '\\377\\000'");
+    ///
+    /// assert_eq!((code, stdout), (0, vec![0xff, 0x00]));
+    /// ```
+    ///
+    pub fn run_bytes(&self, cmd: &str) -> (i32, Vec<u8>, Vec<u8>) {
+        match self.approved(cmd) {
+            Ok(prepared) => prepared.run_bytes(),
+            Err(e) => (e.code(), e.stdout().as_bytes().to_vec(), e.stderr().as_bytes().to_vec()),
+        }
+    }
+
+    /// Executes the command with stdout and stderr redirected to the same pipe, like `2>&1` in
+    /// any shell, and returns its exit code and their output merged in the order it was printed,
+    /// as a terminal would show it, e.g. for error reports. Only the output filters and the sinks
+    /// applying to both streams apply. `stream` tells the lines of both streams apart instead, in
+    /// the order they are read.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    ///
+    /// let script = "echo compiling; echo 'error: oops' >&2; echo done";
+    /// let (code, output) = Shell::new("sh").run_combined(script);
+    ///
+    /// assert_eq!((code, &output[..]), (0, "compiling\nerror: oops\ndone\n"));
+    /// ```
+    ///
+    pub fn run_combined(&self, cmd: &str) -> (i32, String) {
+        match self.approved(cmd) {
+            Ok(prepared) => prepared.run_combined(),
+            Err(e) => (e.code(), format!("{}{}", e.stdout(), e.stderr())),
+        }
+    }
+
+    fn execute(&self, cmd: &str) -> ::std::result::Result<Outcome, Error> {
+        let mut attempt = 1;
+        let mut prepared = self.approved(cmd)?;
+
+        loop {
+            let outcome = self.execute_once(cmd, prepared);
+
+            let code = outcome.status.code();
+
+            // A cancelled command must not run again, nor wait for the next attempt.
+            match self.retry {
+                Some(_) if outcome.cancelled => return Ok(outcome),
+                Some(ref retry) if retry.again(attempt, code, &outcome.stderr) => {
+                    attempt += 1;
+                    prepared = self.prepare(cmd)?;
+                },
+
+                _ => return Ok(outcome),
+            }
+        }
+    }
+
+    fn execute_once(&self, cmd: &str, prepared: PreparedCommand) -> Outcome {
+        let outcome = prepared.outcome();
+
+        if outcome.status != ExitStatus::Exited(127) || !self.exec_fallback
+            || self.sandbox.is_some() || !self.confinement.is_empty() || !self.preamble.is_empty()
+        {
+            return outcome;
+        }
+
+        // A program which ran and exited with 127 by itself must not run twice.
+        match simple_invocation(cmd) {
+            Some(words) if reported_not_found(&outcome.stderr, words[0]) => {
+                exec_directly(prepared, &words, outcome)
+            },
+
+            _ => outcome,
+        }
+    }
+
+    /// Starts the command and returns an iterator over its output lines as they are printed, for
+    /// long running commands whose progress must be shown or processed in real time. Filters are
+    /// applied to each line. A command which could not be started yields the reason on stderr and
+    /// the code 126.
+    ///
+    pub fn stream(&self, cmd: &str) -> ::stream::Lines {
+        match self.approved(cmd) {
+            Ok(prepared) => prepared.stream(),
+            Err(e) => ::stream::Lines::failed(e.code(), e.stderr().to_string()),
+        }
+    }
+
+    /// Starts the command in the background and returns a handle on it, see `ShellChild`.
+    ///
+    pub fn spawn(&self, cmd: &str) -> io::Result<::ShellChild> {
+        self.approved(cmd).map_err(|e| io::Error::other(e.to_string()))?.spawn()
+    }
+
+    /// Starts the command in the background in its own process group, and returns a guard killing
+    /// the group when dropped, or once the token of `cancel_on` is cancelled, see `Running`.
+    ///
+    pub fn start(&self, cmd: &str) -> io::Result<::Running> {
+        let prepared = self.approved(cmd).map_err(|e| io::Error::other(e.to_string()))?;
+        ::Running::start(prepared.in_own_group())
+    }
+
+    /// Starts the command in the background like `spawn`, and returns the handle on it once it is
+    /// ready: once it printed a line, listens on a port, or passes a probe, see `Ready`. For the
+    /// services tests depend on, a development server or a database.
+    ///
+    /// The output of the command is read until it exits, so that it never blocks on a full pipe:
+    /// it goes to the sinks of the shell, e.g. `sink::Memory`, and the handle has no stdout nor
+    /// stderr to take. A command which exits before it is ready, or is not ready within the
+    /// timeout, fails; it is killed in the latter case, with the processes it started.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use shells::{Ready, Shell};
+    ///
+    /// let script = "sleep 0.2; echo 'database system is ready to accept connections'; sleep 10";
+    /// let mut database = Shell::new("sh")
+    ///     .spawn_until(script, Ready::line("ready to accept connections"), Duration::from_secs(5))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(database.try_wait().unwrap(), None);
+    /// database.kill().unwrap();
+    ///
+    /// let early = Shell::new("sh").spawn_until("exit 3", Ready::port(1), Duration::from_secs(5));
+    /// assert!(early.unwrap_err().to_string().contains("exited with code 3"));
+    /// ```
+    ///
+    pub fn spawn_until(&self, cmd: &str, ready: ::child::Ready, timeout: Duration)
+        -> io::Result<::ShellChild>
+    {
+        let prepared = self.approved(cmd).map_err(|e| io::Error::other(e.to_string()))?;
+        ::ShellChild::spawn_until(prepared, ready, timeout)
+    }
+
+    /// Starts the command under a pseudo-terminal, for the programs which require one, and
+    /// returns a handle to converse with it, see `pty::Session`. Requires the `pty` feature.
+    ///
+    #[cfg(all(feature = "pty", any(target_os = "linux", target_os = "macos")))]
+    pub fn pty(&self, cmd: &str) -> io::Result<::pty::Session> {
+        ::pty::Session::spawn(self.approved(cmd).map_err(|e| io::Error::other(e.to_string()))?)
+    }
+
+    /// Executes the command with the standard streams inherited from the current process and
+    /// returns its raw exit status, e.g. to pass it to `propagate_exit`. The active mock scenario
+    /// answers it like the other commands.
+    ///
+    /// ```rust
+    /// use shells::Shell;
+    /// use shells::mock::{self, Response, Scenario};
+    ///
+    /// let scenario = Scenario::new().respond(Response::exit(3));
+    /// let status = mock::with(scenario, || Shell::new("sh").status("rm -rf /tmp/cache")).unwrap();
+    ///
+    /// assert_eq!(status.code(), Some(3));
+    /// ```
+    ///
+    pub fn status(&self, cmd: &str) -> io::Result<process::ExitStatus> {
+        self.approved(cmd).map_err(|e| io::Error::other(e.to_string()))?.status()
+    }
+}
+
+impl From<::defaults::ShellConfig> for Shell {
+    /// A shell invoking the configured interpreter with its flags.
+    ///
+    fn from(config: ::defaults::ShellConfig) -> Shell {
+        let mut shell = Shell::bare(config.program_path());
+        shell.args = config.flags().to_vec();
+        shell.strict = config.is_strict();
+        shell
+    }
+}
+
+/// The environment variables and the trace mode the project of the command sets, see
+/// `project::enable`. A command starting with an empty environment gets no variable.
+///
+#[cfg(feature = "project-config")]
+fn project(cwd: Option<&Path>, env_clear: bool)
+    -> (Vec<(OsString, Option<OsString>)>, ::trace::Trace)
+{
+    match ::project::applying(cwd) {
+        Some(project) => {
+            let env = match env_clear {
+                true => Vec::new(),
+                false => project.env.iter().map(|(k, v)| (k.into(), Some(v.into()))).collect(),
+            };
+
+            (env, project.trace.unwrap_or_default())
+        },
+
+        None => (Vec::new(), ::trace::Trace::Off),
+    }
+}
+
+#[cfg(not(feature = "project-config"))]
+fn project(_: Option<&Path>, _: bool) -> (Vec<(OsString, Option<OsString>)>, ::trace::Trace) {
+    (Vec::new(), ::trace::Trace::Off)
+}
+
+/// How the strict mode of a shell is enabled, see `Shell::strict`.
+///
+enum Strict {
+    Flag(&'static str),
+    Preamble(&'static str),
+}
+
+fn strict_mode(program: &str) -> ::std::result::Result<Strict, String> {
+    use kind::ShellKind;
+
+    match program.parse::<ShellKind>() {
+        Ok(ShellKind::Bash) | Ok(ShellKind::Zsh) | Ok(ShellKind::Ksh) | Ok(ShellKind::Mksh)
+        | Ok(ShellKind::Ash) => Ok(Strict::Preamble("set -euo pipefail\n")),
+
+        Ok(ShellKind::Csh) | Ok(ShellKind::Tcsh) => Ok(Strict::Flag("-e")),
+
+        Ok(ShellKind::PowerShell) => Ok(Strict::Preamble(
+            "Set-StrictMode -Version Latest; $ErrorActionPreference = 'Stop'; \
+             $PSNativeCommandUseErrorActionPreference = $true\n")),
+
+        Ok(kind @ ShellKind::Fish) | Ok(kind @ ShellKind::Cmd) => {
+            Err(format!("shells: {} has no strict mode", kind))
+        },
+
+        Ok(ShellKind::Sh) | Ok(ShellKind::Dash) | Err(_) => Ok(Strict::Preamble("set -eu\n")),
+    }
+}
+
+/// Flag introducing the command for the given shell program.
+///
+fn default_command_flag(program: &str) -> &'static str {
+    let name = Path::new(program).file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+
+    match &name.to_lowercase()[..] {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
+
+/// Splits the command in words if it is a plain program invocation, without any character having
+/// a special meaning for the shell.
+///
+fn simple_invocation(cmd: &str) -> Option<Vec<&str>> {
+    const SPECIAL: &str = "|&;<>()$`\\\"'*?[]#~{}!";
+
+    if cmd.contains(|c: char| SPECIAL.contains(c) || c == '\n') {
+        return None;
+    }
+
+    let words: Vec<&str> = cmd.split_whitespace().collect();
+
+    match words.first() {
+        Some(program) if !program.contains('=') => Some(words),
+        _ => None,
+    }
+}
+
+/// Exit code and output of an executed command, along with the timeout which killed it if any.
+///
+pub(crate) struct Outcome {
+    pub(crate) status: ExitStatus,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    pub(crate) timed_out: Option<Duration>,
+    pub(crate) cancelled: bool,
+}
+
+impl Outcome {
+    pub(crate) fn new<S: Into<ExitStatus>>(status: S, stdout: String, stderr: String) -> Outcome {
+        Outcome { status: status.into(), stdout, stderr, timed_out: None, cancelled: false }
+    }
+
+    pub(crate) fn failed(code: i32, stderr: String) -> Outcome {
+        Outcome::new(ExitStatus::Exited(code), String::new(), stderr)
+    }
+
+    pub(crate) fn into_tuple(mut self) -> (i32, String, String) {
+        if let Some(timeout) = self.timed_out {
+            push_line(&mut self.stderr, &format!("shells: timed out after {:?}", timeout));
+        }
+
+        if self.cancelled {
+            push_line(&mut self.stderr, "shells: cancelled");
+        }
+
+        (self.status.code(), self.stdout, self.stderr)
+    }
+
+    pub(crate) fn into_output(self, duration: Duration) -> ::Output {
+        let status = self.status;
+        let (code, stdout, stderr) = self.into_tuple();
+        ::Output { code, stdout, stderr, status, duration }
+    }
+
+    pub(crate) fn into_result(self) -> ::Result {
+        self.into_full_result(Duration::ZERO).map(|output| output.stdout)
+    }
+
+    pub(crate) fn into_full_result(self, duration: Duration)
+        -> ::std::result::Result<::Output, Error>
+    {
+        match self {
+            Outcome { timed_out: Some(_), stdout, stderr, .. } => {
+                Err(Error::TimedOut { partial_stdout: stdout, partial_stderr: stderr })
+            },
+
+            Outcome { cancelled: true, stdout, stderr, .. } => {
+                Err(Error::Cancelled { partial_stdout: stdout, partial_stderr: stderr })
+            },
+
+            Outcome { status: ExitStatus::Signaled(signal), stdout, stderr, .. } => {
+                Err(Error::Signaled { signal, stdout, stderr })
+            },
+
+            outcome @ Outcome { status: ExitStatus::Exited(0), .. } => {
+                Ok(outcome.into_output(duration))
+            },
+
+            Outcome { status: ExitStatus::Exited(code), stdout, stderr, .. } => {
+                Err(Error::Failed { code, stdout, stderr })
+            },
+        }
+    }
+}
+
+/// Appends the line to the output, on a line of its own.
+///
+pub(crate) fn push_line(output: &mut String, line: &str) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output.push_str(line);
+    output.push('\n');
+}
+
+/// `push_line` for raw output.
+///
+fn push_line_bytes(output: &mut Vec<u8>, line: &str) {
+    if !output.is_empty() && !output.ends_with(b"\n") {
+        output.push(b'\n');
+    }
+
+    output.extend_from_slice(line.as_bytes());
+    output.push(b'\n');
+}
+
+/// Output of a command as it was captured, before its conversion to text.
+///
+struct Captured {
+    status: process::ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    killed: Option<Killed>,
+}
+
+/// Why a command was killed before it exited.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Killed {
+    Timeout,
+    Cancel,
+}
+
+impl Killed {
+    /// The exit code reported for the command.
+    ///
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            Killed::Timeout => 124,
+            Killed::Cancel => 130,
+        }
+    }
+}
+
+/// Implements `Shell::exec_fallback` once the shell reported the command as not found.
+///
+fn exec_directly(mut prepared: PreparedCommand, words: &[&str], mut outcome: Outcome) -> Outcome {
+    let path = prepared.path_var();
+
+    match ::which::which_in(words[0], path.as_deref()) {
+        Some(path) => {
+            prepared.argv = ::std::iter::once(path.into_os_string())
+                .chain(words[1..].iter().map(OsString::from))
+                .collect();
+
+            prepared.outcome()
+        },
+
+        None => {
+            let searched: Vec<String> = path
+                .map(|paths| {
+                    ::std::env::split_paths(&paths).map(|p| p.display().to_string()).collect()
+                })
+                .unwrap_or_default();
+
+            let note = if words[0].contains('/') {
+                format!("shells: `{}` does not exist or is not executable", words[0])
+            } else {
+                format!("shells: `{}` not found, searched: {}", words[0], searched.join(", "))
+            };
+
+            push_line(&mut outcome.stderr, &note);
+
+            outcome
+        },
+    }
+}
+
+/// Whether the stderr of a shell reports that the program was not found, as opposed to the
+/// program exiting with the code 127 by itself.
+///
+fn reported_not_found(stderr: &str, program: &str) -> bool {
+    const MESSAGES: &[&str] = &["not found", "Unknown command", "No such file or directory",
+                                "is not recognized"];
+
+    stderr.lines().any(|line| {
+        line.contains(program) && MESSAGES.iter().any(|message| line.contains(message))
+    })
+}
+
+/// Removes the warnings printed by interactive shells started without a controlling terminal.
+///
+fn strip_job_control(stderr: &str) -> String {
+    const NOISE: &[&str] = &[
+        "no job control",
+        "cannot set terminal process group",
+        "can't set tty pgrp",
+        "can't access tty",
+        "no access to tty",
+        "No controlling tty",
+        "won't have full job control",
+    ];
+
+    stderr.split_inclusive('\n').filter(|line| !NOISE.iter().any(|noise| line.contains(noise)))
+        .collect()
+}
+
+/// Returns a path in the system temporary directory not used by any other command.
+///
+fn unique_tmpdir() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or(0);
+
+    ::std::env::temp_dir().join(format!("shells-{}-{}-{}", ::std::process::id(), nanos,
+                                        COUNTER.fetch_add(1, Ordering::SeqCst)))
+}
+
+/// Private temporary directory of a command, see `Shell::private_tmpdir`.
+///
+#[derive(Debug, Clone)]
+struct TmpDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TmpDir {
+    #[cfg(unix)]
+    fn create(&self) -> io::Result<()> {
+        use std::os::unix::fs::DirBuilderExt;
+        ::std::fs::DirBuilder::new().mode(0o700).create(&self.path)
+    }
+
+    #[cfg(not(unix))]
+    fn create(&self) -> io::Result<()> {
+        ::std::fs::create_dir(&self.path)
+    }
+
+    /// Removes the directory, or returns the note telling where it was kept.
+    ///
+    fn finish(&self) -> Option<String> {
+        if !self.keep {
+            let _ = ::std::fs::remove_dir_all(&self.path);
+            return None;
+        }
+
+        Some(format!("shells: TMPDIR kept at {}", self.path.display()))
+    }
+}
+
+/// Temporary file holding the script of a command, see `Shell::script_file`.
+///
+#[derive(Debug, Clone)]
+struct ScriptFile {
+    path: PathBuf,
+    script: String,
+}
+
+impl ScriptFile {
+    fn new(program: &str, script: &str) -> ScriptFile {
+        let extension = match shell_name(program).as_deref() {
+            Some("cmd") => ".cmd",
+            Some("powershell") | Some("pwsh") => ".ps1",
+            _ => "",
+        };
+
+        let mut path = unique_tmpdir().into_os_string();
+        path.push(extension);
+
+        ScriptFile { path: path.into(), script: script.to_string() }
+    }
+
+    /// The arguments running the file with the shell.
+    ///
+    fn args(&self, program: &str) -> Vec<OsString> {
+        let path = self.path.clone().into_os_string();
+
+        match shell_name(program).as_deref() {
+            Some("cmd") => vec!["/C".into(), path],
+            Some("powershell") | Some("pwsh") => {
+                vec!["-NoProfile".into(), "-ExecutionPolicy".into(), "Bypass".into(),
+                     "-File".into(), path]
+            },
+
+            _ => vec![path],
+        }
+    }
+
+    #[cfg(unix)]
+    fn create(&self) -> io::Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = ::std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600)
+            .open(&self.path)?;
+
+        file.write_all(self.script.as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    fn create(&self) -> io::Result<()> {
+        let mut file = ::std::fs::OpenOptions::new().write(true).create_new(true).open(&self.path)?;
+        file.write_all(self.script.as_bytes())
+    }
+}
+
+/// The lowercase file stem of the program, which names the shell.
+///
+fn shell_name(program: &str) -> Option<String> {
+    Path::new(program).file_stem().map(|stem| stem.to_string_lossy().to_lowercase())
+}
+
+/// Reads the pipe until it is closed, from a separate thread, copying what is read to the sinks.
+///
+pub(crate) fn read_all<R>(pipe: Option<R>, stream: Stream, sinks: Vec<::sink::Attached>)
+    -> thread::JoinHandle<Vec<u8>>
+    where R: Read + Send + 'static
+{
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let mut buffer = [0; 8192];
+
+        if let Some(mut pipe) = pipe {
+            loop {
+                match pipe.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        for sink in &sinks {
+                            sink.write(stream, &buffer[..n]);
+                        }
+
+                        bytes.extend_from_slice(&buffer[..n]);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        bytes
+    })
+}
+
+/// Kills the command and, on Unix, the processes of its process group.
+///
+#[cfg(unix)]
+pub(crate) fn kill(child: &mut Child) {
+    unsafe { ::sys::kill(-(child.id() as i32), ::sys::SIGKILL); }
+    let _ = child.kill();
+}
+
+#[cfg(not(unix))]
+pub(crate) fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(target_os = "linux")]
+type Isolation = ::netns::Isolation;
+
+#[cfg(not(target_os = "linux"))]
+type Isolation = ();
+
+#[cfg(target_os = "linux")]
+fn isolation() -> ::std::result::Result<Isolation, String> {
+    Ok(::netns::Isolation::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn isolation() -> ::std::result::Result<Isolation, String> {
+    Err("network isolation is only supported on Linux".to_string())
+}
+
+/// Work done in the child between `fork` and `exec`, prepared beforehand.
+///
+#[derive(Debug, Clone, Default)]
+struct Hooks {
+    isolation: Option<Isolation>,
+
+    #[cfg(all(feature = "seccomp", target_os = "linux"))]
+    seccomp: Option<::seccomp::Program>,
+}
+
+/// A command ready to be executed, as returned by `Shell::prepare`. Its `Display` implementation
+/// renders the invocation as a shell command line, suitable for logs.
+///
+#[derive(Debug, Clone)]
+pub struct PreparedCommand {
+    argv: Vec<OsString>,
+    env: Vec<(OsString, Option<OsString>)>,
+    env_clear: bool,
+    cwd: Option<PathBuf>,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+    cancel: Option<::cancel::CancellationToken>,
+    own_group: bool,
+
+    #[cfg_attr(not(unix), allow(dead_code))]
+    session: bool,
+
+    script: String,
+    command: String,
+    syntax: ::syntax::Check,
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    hooks: Hooks,
+
+    filters: Vec<Filter>,
+    sinks: Vec<::sink::Attached>,
+    stream_buffer: ::stream::Buffer,
+    metrics: Option<(::metrics::Metrics, String)>,
+    tmpdir: Option<TmpDir>,
+    script_file: Option<ScriptFile>,
+    trace: ::trace::Trace,
+
+    #[cfg(feature = "regex")]
+    triggers: Vec<::trigger::Trigger>,
+}
+
+impl PreparedCommand {
+    /// The program and arguments which will be executed.
+    ///
+    pub fn argv(&self) -> &[OsString] {
+        &self.argv[..]
+    }
+
+    /// The changes made to the inherited environment: variables set to a value, or removed when
+    /// the value is `None`.
+    ///
+    pub fn env(&self) -> &[(OsString, Option<OsString>)] {
+        &self.env[..]
+    }
+
+    /// Whether the command starts with an empty environment rather than the inherited one.
+    ///
+    pub fn env_cleared(&self) -> bool {
+        self.env_clear
+    }
+
+    /// The working directory of the command, `None` meaning the current one.
+    ///
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// The bytes fed to the standard input of the command.
+    ///
+    pub fn stdin(&self) -> Option<&[u8]> {
+        self.stdin.as_deref()
+    }
+
+    /// The duration after which the command is killed.
+    ///
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The script given to the shell, including the preamble.
+    ///
+    pub fn script(&self) -> &str {
+        &self.script[..]
+    }
+
+    /// Builds the `std::process::Command` executing this command, for callers needing to
+    /// configure it further.
+    ///
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.argv[0]);
+
+        match self.argv.split_last() {
+            // cmd does not follow the quoting rules of the C runtime std applies to arguments.
+            #[cfg(windows)]
+            Some((script, _)) if default_command_flag(&self.argv[0].to_string_lossy()) == "/C" => {
+                use std::os::windows::process::CommandExt;
+
+                command.args(&self.argv[1..self.argv.len() - 1]);
+                command.raw_arg(script);
+            },
+
+            _ => {
+                command.args(&self.argv[1..]);
+            },
+        }
+
+        if self.env_clear {
+            command.env_clear();
+        }
+
+        for (name, value) in &self.env {
+            match *value {
+                Some(ref value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+
+        if let Some(ref cwd) = self.cwd {
+            command.current_dir(cwd);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            let own_group = self.timeout.is_some() || self.cancel.is_some() || self.own_group;
+
+            // A session leader leads its process group as well, which it must not before `setsid`.
+            if self.session {
+                unsafe {
+                    command.pre_exec(|| match ::sys::setsid() < 0 {
+                        true => Err(io::Error::last_os_error()),
+                        false => Ok(()),
+                    });
+                }
+            } else if own_group || self.kills_on_output() {
+                command.process_group(0);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::process::CommandExt;
+
+            if let Some(ref isolation) = self.hooks.isolation {
+                let isolation = isolation.clone();
+                unsafe { command.pre_exec(move || isolation.enter()); }
+            }
+        }
+
+        #[cfg(all(feature = "seccomp", target_os = "linux"))]
+        {
+            use std::os::unix::process::CommandExt;
+
+            if let Some(ref program) = self.hooks.seccomp {
+                let program = program.clone();
+                unsafe { command.pre_exec(move || program.install()); }
+            }
+        }
+
+        command
+    }
+
+    /// Executes the command and returns its exit code, stdout and stderr.
+    ///
+    pub fn run(&self) -> (i32, String, String) {
+        self.outcome().into_tuple()
+    }
+
+    /// Executes the command and returns its stdout when it succeeded, see `Shell::try_run`.
+    ///
+    pub fn try_run(&self) -> ::Result {
+        self.outcome().into_result()
+    }
+
+    /// Executes the command and returns its exit code and its stdout and stderr merged, see
+    /// `Shell::run_combined`.
+    ///
+    pub fn run_combined(&self) -> (i32, String) {
+        let outcome = match ::trace::intercept(self).or_else(|| ::mock::intercept(self)) {
+            Some(outcome) => outcome,
+            None => match self.setup() {
+                Err(e) => Outcome::failed(126, e),
+                Ok(()) => {
+                    let outcome = self.output(true)
+                        .unwrap_or_else(|e| Outcome::failed(126, e.to_string()));
+                    self.torn_down(outcome)
+                },
+            },
+        };
+
+        let (code, mut output, notes) = outcome.into_tuple();
+        output.push_str(&notes);
+
+        let output = self.filters.iter().filter(|filter| filter.stream.is_none())
+            .fold(output, |output, filter| (filter.apply)(&output));
+
+        (code, output)
+    }
+
+    /// Executes the command and returns its exit code, stdout and stderr as raw bytes, see
+    /// `Shell::run_bytes`.
+    ///
+    pub fn run_bytes(&self) -> (i32, Vec<u8>, Vec<u8>) {
+        if let Some(outcome) = ::trace::intercept(self).or_else(|| ::mock::intercept(self)) {
+            let (code, stdout, stderr) = outcome.into_tuple();
+            return (code, stdout.into_bytes(), stderr.into_bytes());
+        }
+
+        if let Err(e) = self.setup() {
+            return (126, Vec::new(), e.into_bytes());
+        }
+
+        // Output filters, which work on text, do not apply to the sinks either.
+        let sinks = self.sinks.iter().map(::sink::Attached::raw).collect();
+        let raw = PreparedCommand { sinks, ..self.clone() };
+
+        let (code, stdout, mut stderr) = match raw.capture(false) {
+            Ok(Captured { killed: Some(Killed::Timeout), stdout, mut stderr, .. }) => {
+                if let Some(timeout) = self.timeout {
+                    push_line_bytes(&mut stderr, &format!("shells: timed out after {:?}", timeout));
+                }
+
+                (124, stdout, stderr)
+            },
+
+            Ok(Captured { killed: Some(Killed::Cancel), stdout, mut stderr, .. }) => {
+                push_line_bytes(&mut stderr, "shells: cancelled");
+                (130, stdout, stderr)
+            },
+
+            Ok(Captured { status, stdout, stderr, .. }) => {
+                (ExitStatus::from(status).code(), stdout, stderr)
+            },
+
+            Err(e) => (126, Vec::new(), e.to_string().into_bytes()),
+        };
+
+        if let Some(note) = self.teardown() {
+            push_line_bytes(&mut stderr, &note);
+        }
+
+        (code, stdout, stderr)
+    }
+
+    /// Parses the script with the shell in its no-exec mode, without running anything, and returns
+    /// the error it reported if any. See the `syntax` module.
+    ///
+    pub fn check_syntax(&self) -> ::std::result::Result<(), ::syntax::Error> {
+        self.syntax.run()
+    }
+
+    /// Starts the command and returns an iterator over its output lines, see `Shell::stream`.
+    ///
+    pub fn stream(self) -> ::stream::Lines {
+        ::stream::Lines::spawn(self)
+    }
+
+    /// Starts the command in the background, see `Shell::spawn`.
+    ///
+    pub fn spawn(self) -> io::Result<::ShellChild> {
+        ::ShellChild::spawn(self)
+    }
+
+    /// Executes the command with the standard streams inherited from the current process and
+    /// returns its raw exit status. Output filters do not apply.
+    ///
+    pub fn status(&self) -> io::Result<process::ExitStatus> {
+        // The output of a mocked command is printed to the inherited streams, as it would be.
+        if let Some(outcome) = ::trace::intercept(self).or_else(|| ::mock::intercept(self)) {
+            print!("{}", outcome.stdout);
+            eprint!("{}", outcome.stderr);
+            return Ok(outcome.status.raw());
+        }
+
+        self.setup().map_err(io::Error::other)?;
+
+        let status = self.start(self.command()).and_then(|mut child| {
+            let (status, _) = self.wait(&mut child)?;
+            Ok(status)
+        });
+
+        if let Some(note) = self.teardown() {
+            eprintln!("{}", note);
+        }
+
+        status
+    }
+
+    /// Creates what the command needs to exist before it starts.
+    ///
+    pub(crate) fn setup(&self) -> ::std::result::Result<(), String> {
+        if let Some(ref tmpdir) = self.tmpdir {
+            tmpdir.create().map_err(|e| format!("cannot create {}: {}", tmpdir.path.display(), e))?;
+        }
+
+        if let Some(ref file) = self.script_file {
+            file.create().map_err(|e| format!("cannot create {}: {}", file.path.display(), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// The command, without the preamble.
+    ///
+    #[cfg(any(feature = "otel", feature = "log"))]
+    pub(crate) fn command_text(&self) -> &str {
+        &self.command[..]
+    }
+
+    /// Cleans up after the command exited, returning a note to append to its stderr if any.
+    ///
+    pub(crate) fn teardown(&self) -> Option<String> {
+        if let Some(ref file) = self.script_file {
+            let _ = ::std::fs::remove_file(&file.path);
+        }
+
+        let notes: Vec<String> = self.sinks.iter().filter_map(::sink::Attached::finish)
+            .chain(self.tmpdir.as_ref().and_then(TmpDir::finish))
+            .collect();
+
+        if notes.is_empty() { None } else { Some(notes.join("\n")) }
+    }
+
+    /// Tells the sinks the command started.
+    ///
+    pub(crate) fn started(&self) {
+        for sink in &self.sinks {
+            sink.start(&self.command);
+        }
+    }
+
+    /// The metrics registry of the shell and the job name to record the runs with.
+    ///
+    #[cfg(feature = "tokio")]
+    pub(crate) fn metrics(&self) -> Option<(::metrics::Metrics, String)> {
+        self.metrics.clone()
+    }
+
+    /// Records the run of the command in the metrics registry of the shell, if any.
+    ///
+    pub(crate) fn record(&self, code: i32, timed_out: bool, duration: Duration) {
+        if let Some((ref metrics, ref job)) = self.metrics {
+            metrics.record(job, code, timed_out, duration);
+        }
+    }
+
+    /// Records a restart of the command in the metrics of its shell, if any.
+    ///
+    pub(crate) fn record_restart(&self) {
+        if let Some((ref metrics, ref job)) = self.metrics {
+            metrics.record_restart(job);
+        }
+    }
+
+    /// The command without its timeout nor anything else requiring a process group of its own,
+    /// for the commands leading their own session.
+    ///
+    #[cfg(all(feature = "pty", any(target_os = "linux", target_os = "macos")))]
+    pub(crate) fn without_process_group(mut self) -> PreparedCommand {
+        self.timeout = None;
+        self.cancel = None;
+        self.own_group = false;
+        self.session = false;
+
+        #[cfg(feature = "regex")]
+        self.triggers.clear();
+
+        self
+    }
+
+    /// The command started in a process group of its own, so that it can be killed with the
+    /// processes it started.
+    ///
+    pub(crate) fn in_own_group(mut self) -> PreparedCommand {
+        self.own_group = true;
+        self
+    }
+
+    /// The PATH the command runs with, given the changes made to the environment.
+    ///
+    fn path_var(&self) -> Option<OsString> {
+        match self.env.iter().rev().find(|&(name, _)| name == "PATH") {
+            Some((_, value)) => value.clone(),
+            None if self.env_clear => None,
+            None => ::std::env::var_os("PATH"),
+        }
+    }
+
+    /// The token cancelling the command, see `Shell::cancel_on`.
+    ///
+    pub(crate) fn cancel_token(&self) -> Option<&::cancel::CancellationToken> {
+        self.cancel.as_ref()
+    }
+
+    /// The triggers run on the output of `stream`.
+    ///
+    #[cfg(feature = "regex")]
+    pub(crate) fn triggers(&self) -> &[::trigger::Trigger] {
+        &self.triggers[..]
+    }
+
+    /// Whether a trigger may kill the command, with the processes of its group on Unix.
+    ///
+    #[cfg(unix)]
+    fn kills_on_output(&self) -> bool {
+        #[cfg(feature = "regex")]
+        return self.triggers.iter().any(::trigger::Trigger::kills);
+
+        #[cfg(not(feature = "regex"))]
+        false
+    }
+
+    /// The trace mode set by the project of the command, see `project::enable`.
+    ///
+    pub(crate) fn trace(&self) -> ::trace::Trace {
+        self.trace
+    }
+
+    /// The buffer policy of `stream`.
+    ///
+    pub(crate) fn buffer(&self) -> ::stream::Buffer {
+        self.stream_buffer
+    }
+
+    /// The sinks attached to both streams, for the output of `run_combined`.
+    ///
+    fn combined_sinks(&self) -> Vec<::sink::Attached> {
+        self.sinks.iter()
+            .filter(|sink| sink.receives(Stream::Stdout) && sink.receives(Stream::Stderr))
+            .cloned()
+            .collect()
+    }
+
+    /// The sinks attached to the stream.
+    ///
+    pub(crate) fn sinks(&self, stream: Stream) -> Vec<::sink::Attached> {
+        self.sinks.iter().filter(|sink| sink.receives(stream)).cloned().collect()
+    }
+
+    /// Executes the command and applies the output filters.
+    ///
+    fn outcome(&self) -> Outcome {
+        let outcome = self.execute();
+        self.filtered_outcome(outcome)
+    }
+
+    fn execute(&self) -> Outcome {
+        if let Some(outcome) = ::trace::intercept(self).or_else(|| ::mock::intercept(self)) {
+            return outcome;
+        }
+
+        if let Err(e) = self.setup() {
+            return Outcome::failed(126, e);
+        }
+
+        let outcome = match self.output(false) {
+            Ok(outcome) => outcome,
+            Err(e) => Outcome::failed(126, e.to_string()),
+        };
+
+        self.torn_down(outcome)
+    }
+
+    /// Applies the output filters to the outcome of the command.
+    ///
+    pub(crate) fn filtered_outcome(&self, mut outcome: Outcome) -> Outcome {
+        outcome.stdout = self.filtered(Stream::Stdout, outcome.stdout);
+        outcome.stderr = self.filtered(Stream::Stderr, outcome.stderr);
+        outcome
+    }
+
+    /// Tears down the command, appending the note it returned to the stderr of the outcome.
+    ///
+    pub(crate) fn torn_down(&self, mut outcome: Outcome) -> Outcome {
+        if let Some(note) = self.teardown() {
+            push_line(&mut outcome.stderr, &note);
+        }
+
+        outcome
+    }
+
+    /// Spawns the command, writing the input to its stdin from a separate thread.
+    ///
+    pub(crate) fn start(&self, mut command: Command) -> io::Result<Child> {
+        if self.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        }
+
+        let mut child = command.spawn()?;
+
+        if let (Some(input), Some(mut pipe)) = (self.stdin.clone(), child.stdin.take()) {
+            thread::spawn(move || pipe.write_all(&input));
+        }
+
+        self.started();
+        Ok(child)
+    }
+
+    /// Waits for the command to exit, killing it when the timeout is reached or the token is
+    /// cancelled. Returns why it was killed, if it was.
+    ///
+    pub(crate) fn wait(&self, child: &mut Child)
+        -> io::Result<(process::ExitStatus, Option<Killed>)>
+    {
+        if self.timeout.is_none() && self.cancel.is_none() {
+            return child.wait().map(|status| (status, None));
+        }
+
+        let deadline = self.deadline();
+
+        loop {
+            if let Some(exit) = self.poll(child, deadline)? {
+                return Ok(exit);
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// When the command started now must be killed given its timeout.
+    ///
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Checks whether the command exited without blocking, killing it when the deadline is
+    /// reached or the token is cancelled. Returns why it was killed, if it was, once it exited.
+    ///
+    pub(crate) fn poll(&self, child: &mut Child, deadline: Option<Instant>)
+        -> io::Result<Option<(process::ExitStatus, Option<Killed>)>>
+    {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some((status, None)));
+        }
+
+        let killed = match self.cancel {
+            Some(ref token) if token.is_cancelled() => Some(Killed::Cancel),
+            _ => deadline.filter(|&deadline| Instant::now() >= deadline).map(|_| Killed::Timeout),
+        };
+
+        match killed {
+            Some(killed) => {
+                kill(child);
+                child.wait().map(|status| Some((status, Some(killed))))
+            },
+
+            None => Ok(None),
+        }
+    }
+
+    /// Runs the command to completion, capturing its output. When `combined`, stdout and stderr
+    /// are written to the same pipe and captured together as stdout.
+    ///
+    fn capture(&self, combined: bool) -> io::Result<Captured> {
+        let mut command = self.command();
+        command.stdin(Stdio::null());
+
+        let combined = match combined {
+            true => {
+                let (reader, writer) = io::pipe()?;
+                command.stdout(writer.try_clone()?).stderr(writer);
+                Some(reader)
+            },
+
+            false => {
+                command.stdout(Stdio::piped()).stderr(Stdio::piped());
+                None
+            },
+        };
+
+        let span = ::otel::Span::start(self);
+        let events = ::logging::Events::start(self);
+        let start = Instant::now();
+
+        let mut child = match self.start(command) {
+            Ok(child) => child,
+
+            Err(e) => {
+                span.failed(&e);
+                events.failed(&e);
+                self.record(126, false, start.elapsed());
+                return Err(e);
+            },
+        };
+
+        let stdout = match combined {
+            Some(reader) => read_all(Some(reader), Stream::Stdout, self.combined_sinks()),
+            None => read_all(child.stdout.take(), Stream::Stdout, self.sinks(Stream::Stdout)),
+        };
+
+        let stderr = read_all(child.stderr.take(), Stream::Stderr, self.sinks(Stream::Stderr));
+
+        let (status, killed) = match self.wait(&mut child) {
+            Ok(waited) => waited,
+
+            Err(e) => {
+                span.failed(&e);
+                events.failed(&e);
+                self.record(126, false, start.elapsed());
+                return Err(e);
+            },
+        };
+
+        let captured = Captured {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+            killed,
+        };
+
+        let code = killed.map(Killed::code).unwrap_or_else(|| ExitStatus::from(status).code());
+        let timed_out = killed == Some(Killed::Timeout);
+        span.end(code, captured.stdout.len(), captured.stderr.len(), timed_out);
+        events.end(code);
+        self.record(code, timed_out, start.elapsed());
+
+        Ok(captured)
+    }
+
+    /// Runs the command to completion, capturing its output as text.
+    ///
+    fn output(&self, combined: bool) -> io::Result<Outcome> {
+        let Captured { status, stdout, stderr, killed } = self.capture(combined)?;
+
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+
+        let killed = match killed {
+            Some(killed) => killed,
+            None => return Ok(Outcome::new(status, stdout, stderr)),
+        };
+
+        let outcome = Outcome::new(ExitStatus::Exited(killed.code()), stdout, stderr);
+
+        Ok(match killed {
+            Killed::Timeout => Outcome { timed_out: self.timeout, ..outcome },
+            Killed::Cancel => Outcome { cancelled: true, ..outcome },
+        })
+    }
+
+    /// Applies the output filters registered for the stream.
+    ///
+    pub(crate) fn filtered(&self, stream: Stream, output: String) -> String {
+        filtered(&self.filters, stream, output)
+    }
+}
+
+impl ::std::fmt::Display for PreparedCommand {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let quote = |value: &OsStr| ::quote::posix(&value.to_string_lossy()).into_owned();
+
+        if let Some(ref cwd) = self.cwd {
+            write!(f, "cd {} && ", quote(cwd.as_os_str()))?;
+        }
+
+        if self.env_clear {
+            write!(f, "env -i ")?;
+        }
+
+        for (name, value) in &self.env {
+            match *value {
+                Some(ref value) => write!(f, "{}={} ", name.to_string_lossy(), quote(value))?,
+                None => write!(f, "env -u {} ", quote(name))?,
+            }
+        }
+
+        let argv: Vec<String> = self.argv.iter().map(|arg| quote(arg)).collect();
+        write!(f, "{}", argv.join(" "))
+    }
+}
@@ -0,0 +1,611 @@
+//! Destinations the captured output of commands is copied to as it is read, see `Shell::sink`.
+//!
+//! Sinks receive the bytes of the streams they are attached to as they are read, and are finished
+//! once the command exited. A failing sink does not fail the command: it stops receiving data and
+//! the error is noted in the stderr of the command.
+//!
+//! The output filters of the shell apply to the streams they filter line by line, so that stored
+//! logs hold the redacted text which is returned: such a stream reaches the sinks one complete
+//! line at a time, converted to text. Otherwise, and for `run_bytes`, sinks receive the bytes as
+//! is.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::sink::Memory;
+//!
+//! let log = Memory::new();
+//! let shell = Shell::new("sh").filter(|output| output.replace("s3cr3t", "***")).sink(log.clone());
+//!
+//! assert_eq!(shell.run("echo token=s3cr3t").1, "token=***\n");
+//! assert_eq!(log.text(), "token=***\n");
+//! ```
+//!
+//! `Tee` copies the output to the terminal, `Writer` to a file or any other writer as is,
+//! `Memory` to a buffer and `Callback` to a closure. Behind their features, `Encrypted` stores it
+//! encrypted, `Gzip` and `Zstd` compressed.
+//!
+//! ```rust
+//! use std::fs::{self, File};
+//! use shells::{Shell, Stream};
+//! use shells::sink::Writer;
+//!
+//! let path = std::env::temp_dir().join(format!("shells-sink-doc-{}.log", std::process::id()));
+//! let log = Writer::new(File::create(&path).unwrap());
+//! let shell = Shell::new("sh").sink_stream(Stream::Stdout, log);
+//!
+//! assert_eq!(shell.run("echo hello; echo oops >&2").1, "hello\n");
+//! assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+//!
+//! fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! A shell can have any number of sinks, each attached to both streams or to one of them, and
+//! each receiving its own copy of the output: the failure of one of them leaves the others and
+//! the capture untouched.
+//!
+//! ```rust
+//! use std::io;
+//! use std::sync::{Arc, Mutex};
+//! use shells::{Shell, Stream};
+//! use shells::sink::{Callback, Memory};
+//!
+//! let everything = Memory::new();
+//! let lines = Arc::new(Mutex::new(0));
+//! let counted = lines.clone();
+//!
+//! let shell = Shell::new("sh")
+//!     .sink(everything.clone())
+//!     .sink_stream(Stream::Stdout, Callback::new(move |_, bytes| {
+//!         *counted.lock().unwrap() += bytes.iter().filter(|&&b| b == b'\n').count();
+//!         Ok(())
+//!     }))
+//!     .sink(Callback::new(|_, _| Err(io::Error::other("disk full"))));
+//!
+//! let (code, stdout, stderr) = shell.run("echo a; echo b; echo oops >&2");
+//!
+//! assert_eq!((code, &stdout[..]), (0, "a\nb\n"));
+//! assert!(stderr.ends_with("shells: output sink failed: disk full\n"));
+//! assert_eq!(everything.text().lines().count(), 3);
+//! assert_eq!(*lines.lock().unwrap(), 2);
+//! ```
+//!
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use shell::Stream;
+
+/// A destination of captured output.
+///
+pub trait Sink {
+    /// Called when the command started, with its script.
+    ///
+    fn start(&mut self, command: &str) -> io::Result<()> {
+        let _ = command;
+        Ok(())
+    }
+
+    /// Receives the next bytes read from the stream.
+    ///
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()>;
+
+    /// Called once the command exited, after every byte of its output was written.
+    ///
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink writing the output to anything implementing `io::Write`, e.g. a log file.
+///
+#[derive(Debug)]
+pub struct Writer<W> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Writer<W> {
+        Writer { inner }
+    }
+
+    /// Returns the wrapped writer.
+    ///
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    fn write(&mut self, _: Stream, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Sink copying each stream to the same stream of the current process, see `Shell::tee`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tee;
+
+impl Sink for Tee {
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        match stream {
+            Stream::Stdout => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(bytes).and_then(|_| stdout.flush())
+            },
+
+            Stream::Stderr => io::stderr().lock().write_all(bytes),
+        }
+    }
+}
+
+/// Sink keeping the output in memory, readable through any of its clones while and after the
+/// commands run. Both streams are appended to the same buffer, in the order they were read.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory::default()
+    }
+
+    /// The output received so far.
+    ///
+    pub fn contents(&self) -> Vec<u8> {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The output received so far, decoded as UTF-8 with invalid sequences replaced.
+    ///
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.contents()).into_owned()
+    }
+
+    /// Discards the output received so far.
+    ///
+    pub fn clear(&self) {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl Sink for Memory {
+    fn write(&mut self, _: Stream, bytes: &[u8]) -> io::Result<()> {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Sink handing the output to a closure, along with the stream it was read from.
+///
+pub struct Callback<F> {
+    f: F,
+}
+
+impl<F: FnMut(Stream, &[u8]) -> io::Result<()>> Callback<F> {
+    pub fn new(f: F) -> Callback<F> {
+        Callback { f }
+    }
+}
+
+impl<F: FnMut(Stream, &[u8]) -> io::Result<()>> Sink for Callback<F> {
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        (self.f)(stream, bytes)
+    }
+}
+
+impl<F> ::std::fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Callback").finish()
+    }
+}
+
+/// The output filters of a command, given the stream and the text to filter.
+///
+pub(crate) type Filters = Arc<dyn Fn(Stream, &str) -> String + Send + Sync>;
+
+/// A sink attached to a shell, shared by the commands it runs.
+///
+#[derive(Clone)]
+pub(crate) struct Attached {
+    pub(crate) stream: Option<Stream>,
+    state: Arc<Mutex<State>>,
+    filtering: Option<Arc<Filtering>>,
+}
+
+struct State {
+    sink: Box<dyn Sink + Send>,
+    error: Option<io::Error>,
+}
+
+/// The output filters the bytes written by a command go through, line by line, with the last
+/// incomplete line of each stream.
+///
+struct Filtering {
+    filters: Filters,
+    streams: [bool; 2],
+    pending: Mutex<[Vec<u8>; 2]>,
+}
+
+impl Attached {
+    pub(crate) fn new<S: Sink + Send + 'static>(stream: Option<Stream>, sink: S) -> Attached {
+        let state = Arc::new(Mutex::new(State { sink: Box::new(sink), error: None }));
+        Attached { stream, state, filtering: None }
+    }
+
+    /// The sink receiving the output of a command once filtered, for the streams which have
+    /// filters: `streams` tells whether stdout and stderr do.
+    ///
+    pub(crate) fn filtered(&self, filters: Filters, streams: [bool; 2]) -> Attached {
+        let pending = Mutex::new([Vec::new(), Vec::new()]);
+        let filtering = Filtering { filters, streams, pending };
+        Attached { filtering: Some(Arc::new(filtering)), ..self.clone() }
+    }
+
+    /// The sink receiving the output as is, for binary output.
+    ///
+    pub(crate) fn raw(&self) -> Attached {
+        Attached { filtering: None, ..self.clone() }
+    }
+
+    /// Whether the sink receives the given stream.
+    ///
+    pub(crate) fn receives(&self, stream: Stream) -> bool {
+        self.stream.map(|s| s == stream).unwrap_or(true)
+    }
+
+    pub(crate) fn start(&self, command: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.error.is_none() {
+            if let Err(e) = state.sink.start(command) {
+                state.error = Some(e);
+            }
+        }
+    }
+
+    pub(crate) fn write(&self, stream: Stream, bytes: &[u8]) {
+        let index = stream as usize;
+
+        let filtering = match self.filtering {
+            Some(ref filtering) if filtering.streams[index] => filtering,
+            _ => return self.write_raw(stream, bytes),
+        };
+
+        let mut pending = filtering.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending[index].extend_from_slice(bytes);
+
+        while let Some(end) = pending[index].iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending[index].drain(..=end).collect();
+            let text = (filtering.filters)(stream, &String::from_utf8_lossy(&line));
+            self.write_raw(stream, text.as_bytes());
+        }
+    }
+
+    fn write_raw(&self, stream: Stream, bytes: &[u8]) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.error.is_none() {
+            if let Err(e) = state.sink.write(stream, bytes) {
+                state.error = Some(e);
+            }
+        }
+    }
+
+    /// Finishes the sink, returning the note to append to the stderr of the command when it
+    /// failed.
+    ///
+    pub(crate) fn finish(&self) -> Option<String> {
+        // The output of the command ended with an incomplete line.
+        if let Some(ref filtering) = self.filtering {
+            let mut pending = filtering.pending.lock().unwrap_or_else(|e| e.into_inner());
+
+            for &stream in &[Stream::Stdout, Stream::Stderr] {
+                let line = ::std::mem::take(&mut pending[stream as usize]);
+
+                if !line.is_empty() {
+                    let text = (filtering.filters)(stream, &String::from_utf8_lossy(&line));
+                    self.write_raw(stream, text.as_bytes());
+                }
+            }
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let error = match state.error.take() {
+            Some(e) => Some(e),
+            None => state.sink.finish().err(),
+        };
+
+        error.map(|e| format!("shells: output sink failed: {}", e))
+    }
+}
+
+impl ::std::fmt::Debug for Attached {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Attached").field("stream", &self.stream).finish()
+    }
+}
+
+/// Sink encrypting the output with XChaCha20-Poly1305 before writing it, for commands whose
+/// output holds secrets such as dumped credentials. Requires the `encryption` feature.
+///
+/// The output of each command is encrypted as a whole once it exited, as one frame: a random
+/// 24 bytes nonce, the length of the ciphertext as 4 big-endian bytes, and the ciphertext. Both
+/// streams are interleaved in the order they were read. `decrypt` reads the frames back.
+///
+/// The commands of a shell, and of its clones, share its sinks. The sink must not receive the
+/// output of commands running concurrently, e.g. from threads or `Batch`: a frame would then
+/// hold the output of every command received since the previous frame, and not that of a
+/// single command. Give each concurrent command a shell with a sink of its own instead.
+///
+/// ```rust
+/// use std::fs::{self, File};
+/// use shells::Shell;
+/// use shells::sink::{self, Encrypted};
+///
+/// let key = [7; 32];
+/// let path = std::env::temp_dir().join(format!("shells-encrypted-doc-{}", std::process::id()));
+/// let shell = Shell::new("sh").sink(Encrypted::new(File::create(&path).unwrap(), key));
+///
+/// shell.run("echo s3cr3t");
+/// let stored = fs::read(&path).unwrap();
+///
+/// assert!(!stored.windows(6).any(|window| window == b"s3cr3t"));
+/// assert_eq!(sink::decrypt(&key, &stored).unwrap(), b"s3cr3t\n");
+///
+/// fs::remove_file(&path).unwrap();
+/// ```
+///
+#[cfg(feature = "encryption")]
+pub struct Encrypted<W> {
+    inner: W,
+    key: [u8; 32],
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "encryption")]
+impl<W: Write> Encrypted<W> {
+    /// Creates a sink writing the frames to `inner`, encrypted with the given 256 bits key.
+    ///
+    pub fn new(inner: W, key: [u8; 32]) -> Encrypted<W> {
+        Encrypted { inner, key, buffer: Vec::new() }
+    }
+
+    /// Returns the wrapped writer.
+    ///
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl<W: Write> Sink for Encrypted<W> {
+    fn write(&mut self, _: Stream, bytes: &[u8]) -> io::Result<()> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+        use chacha20poly1305::XChaCha20Poly1305;
+
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let ciphertext = cipher.encrypt(&nonce, &self.buffer[..])
+            .map_err(|_| io::Error::other("cannot encrypt the output"))?;
+
+        self.buffer.clear();
+
+        self.inner.write_all(&nonce)?;
+        self.inner.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&ciphertext)?;
+        self.inner.flush()
+    }
+}
+
+/// Decrypts the frames written by `Encrypted` with the given key, and returns their content
+/// concatenated. Requires the `encryption` feature.
+///
+#[cfg(feature = "encryption")]
+pub fn decrypt(key: &[u8; 32], mut data: &[u8]) -> io::Result<Vec<u8>> {
+    use chacha20poly1305::aead::{Aead, KeyInit};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    let mut plaintext = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < 28 {
+            return Err(invalid("truncated frame header"));
+        }
+
+        let (nonce, rest) = data.split_at(24);
+        let (len, rest) = rest.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+        if rest.len() < len {
+            return Err(invalid("truncated frame"));
+        }
+
+        let (ciphertext, rest) = rest.split_at(len);
+
+        let decrypted = cipher.decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid("wrong key or corrupted frame"))?;
+
+        plaintext.extend_from_slice(&decrypted);
+        data = rest;
+    }
+
+    Ok(plaintext)
+}
+
+/// Sink compressing the output with gzip before writing it, for build logs which get huge.
+/// Requires the `gzip` feature.
+///
+/// The output of each command is compressed as it is read, into a gzip member of its own
+/// completed once the command exited. A file holding several members is still a valid gzip file,
+/// which `zcat` or `flate2::read::MultiGzDecoder` decompress as a whole.
+///
+/// ```rust
+/// extern crate flate2;
+/// extern crate shells;
+///
+/// use std::fs::{self, File};
+/// use std::io::Read;
+/// use shells::Shell;
+/// use shells::sink::Gzip;
+///
+/// fn main() {
+///     let path = std::env::temp_dir().join(format!("shells-gzip-doc-{}.gz", std::process::id()));
+///     let shell = Shell::new("sh").sink(Gzip::new(File::create(&path).unwrap()));
+///
+///     shell.run("seq 1000");
+///     shell.run("echo done");
+///
+///     let mut log = String::new();
+///     let mut decoder = flate2::read::MultiGzDecoder::new(File::open(&path).unwrap());
+///     decoder.read_to_string(&mut log).unwrap();
+///
+///     assert!(log.starts_with("1\n2\n3\n") && log.ends_with("1000\ndone\n"));
+///     fs::remove_file(&path).unwrap();
+/// }
+/// ```
+///
+#[cfg(feature = "gzip")]
+pub struct Gzip<W: Write> {
+    writer: Option<W>,
+    encoder: Option<::flate2::write::GzEncoder<W>>,
+    level: u32,
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Gzip<W> {
+    /// Creates a sink writing the compressed output to `inner`, at the default level (6).
+    ///
+    pub fn new(inner: W) -> Gzip<W> {
+        Gzip { writer: Some(inner), encoder: None, level: 6 }
+    }
+
+    /// Compression level, from 0 (none) to 9 (best).
+    ///
+    pub fn level(mut self, level: u32) -> Gzip<W> {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Completes the current member and returns the wrapped writer.
+    ///
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        self.writer.take().ok_or_else(lost)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Sink for Gzip<W> {
+    fn write(&mut self, _: Stream, bytes: &[u8]) -> io::Result<()> {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        if self.encoder.is_none() {
+            let writer = self.writer.take().ok_or_else(lost)?;
+            self.encoder = Some(GzEncoder::new(writer, Compression::new(self.level)));
+        }
+
+        match self.encoder {
+            Some(ref mut encoder) => encoder.write_all(bytes),
+            None => Err(lost()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let mut writer = encoder.finish()?;
+            writer.flush()?;
+            self.writer = Some(writer);
+        }
+
+        Ok(())
+    }
+}
+
+/// Sink compressing the output with zstd before writing it, like `Gzip` but faster and denser.
+/// Requires the `zstd` feature.
+///
+/// The output of each command is compressed into a zstd frame of its own. Concatenated frames
+/// decompress as a whole, with `zstdcat` or `zstd::stream::decode_all`.
+///
+#[cfg(feature = "zstd")]
+pub struct Zstd<W: Write> {
+    writer: Option<W>,
+    encoder: Option<::zstd::stream::write::Encoder<'static, W>>,
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Zstd<W> {
+    /// Creates a sink writing the compressed output to `inner`, at the default level (3).
+    ///
+    pub fn new(inner: W) -> Zstd<W> {
+        Zstd { writer: Some(inner), encoder: None, level: 3 }
+    }
+
+    /// Compression level, from 1 (fastest) to 22 (best).
+    ///
+    pub fn level(mut self, level: i32) -> Zstd<W> {
+        self.level = level.clamp(1, 22);
+        self
+    }
+
+    /// Completes the current frame and returns the wrapped writer.
+    ///
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        self.writer.take().ok_or_else(lost)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Sink for Zstd<W> {
+    fn write(&mut self, _: Stream, bytes: &[u8]) -> io::Result<()> {
+        if self.encoder.is_none() {
+            let writer = self.writer.take().ok_or_else(lost)?;
+            self.encoder = Some(::zstd::stream::write::Encoder::new(writer, self.level)?);
+        }
+
+        match self.encoder {
+            Some(ref mut encoder) => encoder.write_all(bytes),
+            None => Err(lost()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let mut writer = encoder.finish()?;
+            writer.flush()?;
+            self.writer = Some(writer);
+        }
+
+        Ok(())
+    }
+}
+
+/// Error of a compressing sink whose writer was lost when completing a member failed.
+///
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn lost() -> io::Error {
+    io::Error::other("the compressed output was lost after a previous failure")
+}
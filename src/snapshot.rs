@@ -0,0 +1,72 @@
+//! Snapshot testing of command outputs, for CLI integration tests.
+//!
+//! `assert_sh_snapshot!` runs a command with the POSIX shell and compares its stdout with the
+//! snapshot stored in `tests/snapshots/<name>.snap` of the crate being tested. A missing snapshot
+//! is created from the current output. When the output changed, the assertion fails with a diff;
+//! run the tests with `SHELLS_UPDATE_SNAPSHOTS=1` to accept the new outputs.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! fn main() {
+//!     assert_sh_snapshot!(name = "help", "cargo run -q -- --help");
+//! }
+//! ```
+//!
+//! Without an explicit name, the snapshot is named after the module path and line of the
+//! assertion, which changes whenever lines are added above it.
+//!
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Environment variable enabling the update mode.
+///
+pub const UPDATE_VAR: &str = "SHELLS_UPDATE_SNAPSHOTS";
+
+/// Compares `actual` with the snapshot `name` stored in `dir`, creating or updating it as
+/// described in the module documentation. Panics when they differ.
+///
+pub fn assert_snapshot<P: AsRef<Path>>(dir: P, name: &str, actual: &str) {
+    let path = dir.as_ref().join(format!("{}.snap", name));
+    let update = env::var_os(UPDATE_VAR).map(|value| value != "0").unwrap_or(false);
+
+    match fs::read_to_string(&path) {
+        Ok(ref expected) if expected == actual => (),
+
+        Ok(ref expected) if !update => {
+            panic!("snapshot `{}` does not match the output (set {}=1 to update it):\n{}",
+                   name, UPDATE_VAR, ::diff::lines(expected, actual));
+        },
+
+        _ => {
+            let written = fs::create_dir_all(dir.as_ref()).and_then(|_| fs::write(&path, actual));
+
+            if let Err(e) = written {
+                panic!("cannot write snapshot {}: {}", path.display(), e);
+            }
+        },
+    }
+}
+
+/// Macro running the given command with the Posix Shell and comparing its stdout with a stored
+/// snapshot. See the `snapshot` module.
+///
+#[macro_export]
+macro_rules! assert_sh_snapshot {
+    ( name = $name:expr, $( $cmd:tt )* ) => {{
+        let (_, stdout, _) = $crate::execute_with("sh", &format!($( $cmd )*));
+        let dir = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("snapshots");
+
+        $crate::snapshot::assert_snapshot(dir, &$name, &stdout);
+    }};
+
+    ( $( $cmd:tt )* ) => {{
+        let name = format!("{}__{}", module_path!().replace("::", "__"), line!());
+        $crate::assert_sh_snapshot!(name = name, $( $cmd )*);
+    }};
+}
@@ -0,0 +1,342 @@
+//! Execution of commands on remote hosts and file transfers through the OpenSSH client.
+//!
+//! Commands are given to `ssh` as is and interpreted by the login shell of the remote user. The
+//! client runs in batch mode: hosts must be reachable without password prompts (keys, agent),
+//! failures to connect are reported with the code 255 like `ssh` does.
+//!
+//! ```rust,no_run
+//! use shells::ssh::Remote;
+//!
+//! let remote = Remote::new("deploy@web1.example.com").port(2222);
+//!
+//! remote.put("target/release/app", "/opt/app/bin/app").unwrap();
+//! let (code, stdout, stderr) = remote.run("systemctl restart app && systemctl is-active app");
+//! ```
+//!
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use shell::Shell;
+
+/// Verification of the keys of the hosts connected to.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Only connect to hosts whose key is already known.
+    Strict,
+
+    /// Record the keys of new hosts, refuse changed keys.
+    AcceptNew,
+
+    /// Accept any key without recording it, for throwaway hosts such as test VMs.
+    Off,
+}
+
+/// A host commands are executed on.
+///
+/// ```rust
+/// use shells::ssh::{HostKeyPolicy, Remote};
+///
+/// let remote = Remote::new("deploy@10.0.1.5")
+///     .jump("ops@bastion.example.com")
+///     .host_key_policy(HostKeyPolicy::AcceptNew);
+///
+/// assert_eq!(remote.argv("uptime"), ["ssh", "-o", "BatchMode=yes", "-J",
+///                                    "ops@bastion.example.com", "-o",
+///                                    "StrictHostKeyChecking=accept-new", "--", "deploy@10.0.1.5",
+///                                    "uptime"]);
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    destination: String,
+    port: Option<u16>,
+    identity: Option<PathBuf>,
+    jumps: Vec<String>,
+    host_key_policy: Option<HostKeyPolicy>,
+    connect_timeout: Option<Duration>,
+    options: Vec<(String, String)>,
+}
+
+impl Remote {
+    /// Creates a remote for the given destination, `host` or `user@host`, which may also be an
+    /// alias defined in `~/.ssh/config`.
+    ///
+    pub fn new(destination: &str) -> Remote {
+        Remote {
+            destination: destination.to_string(),
+            port: None,
+            identity: None,
+            jumps: Vec::new(),
+            host_key_policy: None,
+            connect_timeout: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Port of the SSH server, 22 or the one configured in `~/.ssh/config` by default.
+    ///
+    pub fn port(mut self, port: u16) -> Remote {
+        self.port = Some(port);
+        self
+    }
+
+    /// Private key used to authenticate.
+    ///
+    pub fn identity<P: Into<PathBuf>>(mut self, path: P) -> Remote {
+        self.identity = Some(path.into());
+        self
+    }
+
+    /// Connects through the given bastion host, `[user@]host[:port]`, like `ProxyJump`. Calling it
+    /// several times chains the jumps in order.
+    ///
+    pub fn jump(mut self, destination: &str) -> Remote {
+        self.jumps.push(destination.to_string());
+        self
+    }
+
+    /// Sets how host keys are verified, instead of the policy of `~/.ssh/config`.
+    ///
+    pub fn host_key_policy(mut self, policy: HostKeyPolicy) -> Remote {
+        self.host_key_policy = Some(policy);
+        self
+    }
+
+    /// Gives up connecting after the given duration, rounded up to the second.
+    ///
+    pub fn connect_timeout(mut self, timeout: Duration) -> Remote {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Passes `-o name=value` to the client, e.g. `("StrictHostKeyChecking", "accept-new")`.
+    ///
+    pub fn option(mut self, name: &str, value: &str) -> Remote {
+        self.options.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The destination given to `new`.
+    ///
+    pub fn destination(&self) -> &str {
+        &self.destination[..]
+    }
+
+    /// The client options connecting to the host, without the destination. `ssh` and `scp` only
+    /// differ by the flag setting the port.
+    ///
+    fn client_args(&self, port_flag: &str) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-o".into(), "BatchMode=yes".into()];
+
+        if let Some(port) = self.port {
+            args.push(port_flag.into());
+            args.push(port.to_string().into());
+        }
+
+        if let Some(ref identity) = self.identity {
+            args.push("-i".into());
+            args.push(identity.clone().into());
+        }
+
+        if !self.jumps.is_empty() {
+            args.push("-J".into());
+            args.push(self.jumps.join(",").into());
+        }
+
+        let policy: &[&str] = match self.host_key_policy {
+            Some(HostKeyPolicy::Strict) => &["-o", "StrictHostKeyChecking=yes"],
+            Some(HostKeyPolicy::AcceptNew) => &["-o", "StrictHostKeyChecking=accept-new"],
+            Some(HostKeyPolicy::Off) => {
+                &["-o", "StrictHostKeyChecking=no", "-o", "UserKnownHostsFile=/dev/null"]
+            },
+            None => &[],
+        };
+
+        args.extend(policy.iter().map(OsString::from));
+
+        if let Some(timeout) = self.connect_timeout {
+            let seconds = timeout.as_secs() + if timeout.subsec_nanos() > 0 { 1 } else { 0 };
+            args.push("-o".into());
+            args.push(format!("ConnectTimeout={}", seconds.max(1)).into());
+        }
+
+        for (name, value) in &self.options {
+            args.push("-o".into());
+            args.push(format!("{}={}", name, value).into());
+        }
+
+        args
+    }
+
+    /// The program and arguments executing `cmd` on the host.
+    ///
+    pub fn argv(&self, cmd: &str) -> Vec<OsString> {
+        let mut argv: Vec<OsString> = vec!["ssh".into()];
+        argv.extend(self.client_args("-p"));
+        argv.push("--".into());
+        argv.push(self.destination.clone().into());
+        argv.push(cmd.into());
+        argv
+    }
+
+    /// Executes the command on the host and returns its exit code, stdout and stderr. stdin is
+    /// not forwarded.
+    ///
+    pub fn run(&self, cmd: &str) -> (i32, String, String) {
+        execute(&self.argv(cmd))
+    }
+
+    /// Copies the local file or directory to `remote` on the host, through `scp`.
+    ///
+    pub fn put<P: AsRef<Path>>(&self, local: P, remote: &str) -> Result<(), ::Error> {
+        let mut argv = self.scp_argv();
+        argv.push(local.as_ref().into());
+        argv.push(format!("{}:{}", self.destination, remote).into());
+        checked(execute(&argv))
+    }
+
+    /// Copies the file or directory `remote` of the host to `local`, through `scp`.
+    ///
+    pub fn get<P: AsRef<Path>>(&self, remote: &str, local: P) -> Result<(), ::Error> {
+        let mut argv = self.scp_argv();
+        argv.push(format!("{}:{}", self.destination, remote).into());
+        argv.push(local.as_ref().into());
+        checked(execute(&argv))
+    }
+
+    /// Opens an `ssh -L` tunnel forwarding connections to the local port to `target`, a
+    /// `host:port` reached from the remote host. Returns once the local port accepts connections;
+    /// the tunnel is closed when the returned guard is dropped.
+    ///
+    /// ```rust,no_run
+    /// use shells::ssh::Remote;
+    ///
+    /// let remote = Remote::new("deploy@db1.example.com");
+    /// let _tunnel = remote.forward_local(5433, "localhost:5432").unwrap();
+    ///
+    /// let shell = shells::Shell::new("sh");
+    /// let (code, _, _) = shell.run("pg_dump -h localhost -p 5433 shop > shop.sql");
+    /// ```
+    ///
+    pub fn forward_local(&self, port: u16, target: &str) -> Result<Tunnel, ::Error> {
+        const TIMEOUT: Duration = Duration::from_secs(15);
+
+        let failed = |code: i32, stderr: String| {
+            ::Error::Failed { code, stdout: String::new(), stderr }
+        };
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+        // Otherwise a service already listening on the port would be mistaken for the tunnel.
+        TcpListener::bind(address)
+            .map_err(|e| failed(255, format!("cannot forward port {}: {}", port, e)))?;
+
+        let mut child = Command::new("ssh")
+            .args(self.client_args("-p"))
+            .args(["-N", "-o", "ExitOnForwardFailure=yes", "-L"])
+            .arg(format!("{}:{}", port, target))
+            .arg("--")
+            .arg(&self.destination)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| failed(126, format!("cannot execute ssh: {}", e)))?;
+
+        // Drained for as long as ssh runs, so that it never blocks on a full pipe; only the end is
+        // kept, to report why it exited.
+        let mut pipe = child.stderr.take();
+        let stderr = ::std::thread::spawn(move || {
+            let (mut stderr, mut chunk) = (Vec::new(), [0; 4096]);
+
+            while let Some(Ok(read @ 1..)) = pipe.as_mut().map(|pipe| pipe.read(&mut chunk)) {
+                stderr.extend_from_slice(&chunk[..read]);
+                let excess = stderr.len().saturating_sub(64 << 10);
+                stderr.drain(..excess);
+            }
+
+            String::from_utf8_lossy(&stderr).into_owned()
+        });
+
+        let start = Instant::now();
+
+        loop {
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(failed(status.code().unwrap_or(255), stderr.join().unwrap_or_default()));
+            }
+
+            if TcpStream::connect_timeout(&address, Duration::from_millis(100)).is_ok() {
+                return Ok(Tunnel { child, port });
+            }
+
+            if start.elapsed() > TIMEOUT {
+                let _ = child.kill();
+                let _ = child.wait();
+
+                let reason = format!("tunnel to {} through {} not ready after {}s", target,
+                                     self.destination, TIMEOUT.as_secs());
+                return Err(failed(255, reason));
+            }
+
+            ::std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn scp_argv(&self) -> Vec<OsString> {
+        let mut argv: Vec<OsString> = vec!["scp".into(), "-q".into(), "-r".into()];
+        argv.extend(self.client_args("-P"));
+        argv.push("--".into());
+        argv
+    }
+}
+
+/// An open port forwarding, see `Remote::forward_local`. Dropping it closes the tunnel.
+///
+#[derive(Debug)]
+pub struct Tunnel {
+    child: Child,
+    port: u16,
+}
+
+impl Tunnel {
+    /// The local port forwarded.
+    ///
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Executes the client through `sh`, so that the execution is traced, mocked and checked against
+/// the policies like any other command.
+///
+fn execute(argv: &[OsString]) -> (i32, String, String) {
+    let quoted: Vec<_> = argv.iter()
+        .map(|arg| ::quote::posix(&arg.to_string_lossy()).into_owned())
+        .collect();
+    Shell::new("sh").run(&format!("exec {}", quoted.join(" ")))
+}
+
+fn checked((code, stdout, stderr): (i32, String, String)) -> Result<(), ::Error> {
+    match code {
+        0 => Ok(()),
+        _ => Err(::Error::Failed { code, stdout, stderr }),
+    }
+}
+
+impl ::std::fmt::Display for Remote {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}", self.destination)
+    }
+}
@@ -0,0 +1,606 @@
+//! Line by line consumption of the output of a running command.
+//!
+//! The lines read from the command wait in a buffer until they are consumed. By default the
+//! buffer grows as needed; a `Buffer` policy set with `Shell::stream_buffer` bounds it, for the
+//! consumers which may fall behind a verbose command.
+//!
+//! ```rust
+//! use shells::{Buffer, Shell};
+//!
+//! let mut lines = Shell::new("sh").stream_buffer(Buffer::DropOldest(10)).stream("seq 1 1000");
+//!
+//! // The command runs to completion while the consumer sleeps, losing all but the last lines.
+//! ::std::thread::sleep(::std::time::Duration::from_millis(500));
+//!
+//! assert_eq!(lines.next().map(|line| line.text().to_string()), Some("991".to_string()));
+//! assert_eq!(lines.by_ref().count(), 9);
+//! assert_eq!(lines.dropped(), 990);
+//! ```
+//!
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use output::ExitStatus;
+use shell::{PreparedCommand, Stream};
+
+#[cfg(feature = "regex")]
+use trigger::Armed;
+
+/// A line printed by a command, without its line terminator.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Line {
+    Stdout(String),
+    Stderr(String),
+}
+
+impl Line {
+    /// The stream the line was printed on.
+    ///
+    pub fn stream(&self) -> Stream {
+        match *self {
+            Line::Stdout(_) => Stream::Stdout,
+            Line::Stderr(_) => Stream::Stderr,
+        }
+    }
+
+    /// The content of the line.
+    ///
+    pub fn text(&self) -> &str {
+        match *self {
+            Line::Stdout(ref text) | Line::Stderr(ref text) => &text[..],
+        }
+    }
+}
+
+/// How the lines not consumed yet are buffered, see `Shell::stream_buffer`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Buffer {
+    /// The buffer grows as needed.
+    #[default]
+    Unbounded,
+
+    /// At most this many lines are buffered: once the buffer is full the output of the command
+    /// is no longer read, and the command blocks on its next write until lines are consumed.
+    Block(usize),
+
+    /// At most this many lines are buffered: once the buffer is full each new line evicts the
+    /// oldest one, counted by `Lines::dropped`. The command is never slowed down.
+    DropOldest(usize),
+
+    /// At most this many lines are kept in memory, the following ones are written to a temporary
+    /// file until they are consumed. No line is lost and the command is never slowed down.
+    Spill(usize),
+}
+
+/// Iterator over the lines of a running command, in the order they are produced. Once it returns
+/// `None` the command has exited and `code` returns its exit code. Dropping the iterator before
+/// the end kills the command.
+///
+/// The lines are buffered according to the policy of the shell, without bound by default, see
+/// `Buffer`.
+///
+/// ```rust
+/// use shells::{Line, Shell};
+///
+/// let mut lines = Shell::new("sh").stream("echo building; echo warning >&2; echo done");
+///
+/// for line in &mut lines {
+///     match line {
+///         Line::Stdout(text) => println!("[out] {}", text),
+///         Line::Stderr(text) => println!("[err] {}", text),
+///     }
+/// }
+///
+/// assert_eq!(lines.code(), Some(0));
+/// ```
+///
+#[derive(Debug)]
+pub struct Lines {
+    prepared: Option<PreparedCommand>,
+    receiver: Receiving,
+    child: Option<Child>,
+    armed: Option<Armed>,
+    code: Option<i32>,
+}
+
+impl Lines {
+    /// Starts the command, in a process group of its own so that dropping the iterator kills the
+    /// processes it started as well.
+    ///
+    pub(crate) fn spawn(prepared: PreparedCommand) -> Lines {
+        let prepared = prepared.in_own_group();
+
+        let intercepted = ::trace::intercept(&prepared).or_else(|| ::mock::intercept(&prepared));
+
+        if let Some(outcome) = intercepted {
+            let (sender, receiver) = mpsc::channel();
+            send_all(&sender, Line::Stdout, &outcome.stdout);
+            send_all(&sender, Line::Stderr, &outcome.stderr);
+
+            let (receiver, code) = (Receiving::Channel(receiver), Some(outcome.status.code()));
+            return Lines { prepared: Some(prepared), receiver, child: None, armed: None, code };
+        }
+
+        if let Err(e) = prepared.setup() {
+            return Lines::failed(126, e);
+        }
+
+        let mut command = prepared.command();
+        command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        let spawned = prepared.start(command);
+
+        match spawned {
+            Ok(mut child) => {
+                let (sender, receiver) = match buffer(prepared.buffer()) {
+                    Ok(buffer) => buffer,
+                    Err(e) => {
+                        ::shell::kill(&mut child);
+                        let _ = child.wait();
+                        let reason = format!("shells: cannot buffer the output: {}", e);
+                        return Lines::failed(126, reason);
+                    },
+                };
+
+                let armed = Armed::new(&prepared, &child);
+
+                if let Some(stdout) = child.stdout.take() {
+                    let sinks = prepared.sinks(Stream::Stdout);
+                    forward(stdout, sender.clone(), Stream::Stdout, sinks, armed.clone());
+                }
+
+                if let Some(stderr) = child.stderr.take() {
+                    let sinks = prepared.sinks(Stream::Stderr);
+                    forward(stderr, sender, Stream::Stderr, sinks, armed.clone());
+                }
+
+                let (child, armed) = (Some(child), Some(armed));
+                Lines { prepared: Some(prepared), receiver, child, armed, code: None }
+            },
+
+            Err(e) => Lines::failed(126, e.to_string()),
+        }
+    }
+
+    /// A command which could not be started.
+    ///
+    pub(crate) fn failed(code: i32, stderr: String) -> Lines {
+        let (sender, receiver) = mpsc::channel();
+        send_all(&sender, Line::Stderr, &stderr);
+        let receiver = Receiving::Channel(receiver);
+        Lines { prepared: None, receiver, child: None, armed: None, code: Some(code) }
+    }
+
+    /// The number of lines lost so far because the consumer fell behind, always 0 unless the
+    /// buffer policy is `Buffer::DropOldest`.
+    ///
+    pub fn dropped(&self) -> u64 {
+        match self.receiver {
+            Receiving::Queue(ref queue) => queue.state().dropped,
+            Receiving::Channel(_) => 0,
+        }
+    }
+
+    /// The exit code of the command, once every line has been consumed.
+    ///
+    pub fn code(&self) -> Option<i32> {
+        self.code
+    }
+
+    /// Discards the remaining lines and returns the exit code of the command.
+    ///
+    pub fn wait(mut self) -> i32 {
+        for _ in &mut self {}
+        self.code.unwrap_or(1)
+    }
+
+    fn reap(&mut self) {
+        if let Some(ref armed) = self.armed {
+            armed.disarm();
+        }
+
+        if let Some(mut child) = self.child.take() {
+            self.code = Some(match child.wait() {
+                Ok(status) => ExitStatus::from(status).code(),
+                Err(_) => 1,
+            });
+        }
+    }
+}
+
+impl Iterator for Lines {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        #[cfg(not(unix))]
+        if let (Some(armed), Some(child)) = (self.armed.as_ref(), self.child.as_mut()) {
+            if armed.killed() {
+                ::shell::kill(child);
+            }
+        }
+
+        let line = match self.receiver.recv() {
+            Some(line) => line,
+
+            None => {
+                self.child.as_ref()?;
+                self.reap();
+                return self.prepared.as_ref().and_then(PreparedCommand::teardown).map(Line::Stderr);
+            },
+        };
+
+        Some(match (line, self.prepared.as_ref()) {
+            (Line::Stdout(text), Some(prepared)) => {
+                Line::Stdout(prepared.filtered(Stream::Stdout, text))
+            },
+
+            (Line::Stderr(text), Some(prepared)) => {
+                Line::Stderr(prepared.filtered(Stream::Stderr, text))
+            },
+
+            (line, None) => line,
+        })
+    }
+}
+
+impl Drop for Lines {
+    fn drop(&mut self) {
+        if let Some(ref mut child) = self.child {
+            ::shell::kill(child);
+            self.reap();
+
+            if let Some(ref prepared) = self.prepared {
+                prepared.teardown();
+            }
+        }
+    }
+}
+
+/// Sends every line read from `pipe` until it is closed.
+///
+fn forward<R>(pipe: R, sender: Sending, stream: Stream, sinks: Vec<::sink::Attached>, armed: Armed)
+    where R: Read + Send + 'static
+{
+    let line = match stream {
+        Stream::Stdout => Line::Stdout,
+        Stream::Stderr => Line::Stderr,
+    };
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buffer = Vec::new();
+
+        loop {
+            buffer.clear();
+
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+
+                Ok(_) => {
+                    for sink in &sinks {
+                        sink.write(stream, &buffer);
+                    }
+
+                    if buffer.ends_with(b"\n") {
+                        buffer.pop();
+                    }
+
+                    let text = String::from_utf8_lossy(&buffer).into_owned();
+                    armed.fire(stream, &text);
+
+                    if !sender.send(line(text)) {
+                        break;
+                    }
+                },
+            }
+        }
+    });
+}
+
+fn send_all(sender: &Sender<Line>, line: fn(String) -> Line, output: &str) {
+    for text in output.lines() {
+        let _ = sender.send(line(text.to_string()));
+    }
+}
+
+/// Without the `regex` feature, commands have no trigger.
+///
+#[cfg(not(feature = "regex"))]
+#[derive(Debug, Clone)]
+struct Armed;
+
+#[cfg(not(feature = "regex"))]
+impl Armed {
+    fn new(_: &PreparedCommand, _: &Child) -> Armed {
+        Armed
+    }
+
+    fn fire(&self, _: Stream, _: &str) {}
+
+    #[cfg(not(unix))]
+    fn killed(&self) -> bool {
+        false
+    }
+
+    fn disarm(&self) {}
+}
+
+/// Creates the buffer of the lines read from a command.
+///
+fn buffer(policy: Buffer) -> ::std::io::Result<(Sending, Receiving)> {
+    let spill = match policy {
+        Buffer::Unbounded => {
+            let (sender, receiver) = mpsc::channel();
+            return Ok((Sending::Unbounded(sender), Receiving::Channel(receiver)));
+        },
+
+        Buffer::Block(capacity) => {
+            let (sender, receiver) = mpsc::sync_channel(capacity);
+            return Ok((Sending::Bounded(sender), Receiving::Channel(receiver)));
+        },
+
+        Buffer::DropOldest(_) => None,
+        Buffer::Spill(_) => Some(Spill::create()?),
+    };
+
+    let state = State { lines: VecDeque::new(), spill, dropped: 0, writers: 0 };
+    let queue = Arc::new(Queue { policy, state: Mutex::new(state), ready: Condvar::new() });
+
+    Ok((Sending::Queue(Writer::new(queue.clone())), Receiving::Queue(queue)))
+}
+
+/// The producing side of the buffer, held by the thread reading a pipe.
+///
+enum Sending {
+    Unbounded(Sender<Line>),
+    Bounded(SyncSender<Line>),
+    Queue(Writer),
+}
+
+impl Sending {
+    /// Buffers the line, returns `false` once the lines are no longer consumed.
+    ///
+    fn send(&self, line: Line) -> bool {
+        match *self {
+            Sending::Unbounded(ref sender) => sender.send(line).is_ok(),
+            Sending::Bounded(ref sender) => sender.send(line).is_ok(),
+            Sending::Queue(ref writer) => writer.send(line),
+        }
+    }
+}
+
+impl Clone for Sending {
+    fn clone(&self) -> Sending {
+        match *self {
+            Sending::Unbounded(ref sender) => Sending::Unbounded(sender.clone()),
+            Sending::Bounded(ref sender) => Sending::Bounded(sender.clone()),
+            Sending::Queue(ref writer) => Sending::Queue(Writer::new(writer.0.clone())),
+        }
+    }
+}
+
+/// The consuming side of the buffer.
+///
+#[derive(Debug)]
+enum Receiving {
+    Channel(Receiver<Line>),
+    Queue(Arc<Queue>),
+}
+
+impl Receiving {
+    /// Waits for the next line, `None` once every producer is gone.
+    ///
+    fn recv(&self) -> Option<Line> {
+        match *self {
+            Receiving::Channel(ref receiver) => receiver.recv().ok(),
+            Receiving::Queue(ref queue) => queue.recv(),
+        }
+    }
+}
+
+/// Buffer of the `DropOldest` and `Spill` policies.
+///
+#[derive(Debug)]
+struct Queue {
+    policy: Buffer,
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    lines: VecDeque<Line>,
+    spill: Option<Spill>,
+    dropped: u64,
+    writers: usize,
+}
+
+impl Queue {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, line: Line) {
+        let mut state = self.state();
+
+        match self.policy {
+            Buffer::DropOldest(capacity) => {
+                if state.lines.len() >= capacity {
+                    state.dropped += 1;
+
+                    if state.lines.pop_front().is_none() {
+                        return;
+                    }
+                }
+
+                state.lines.push_back(line);
+            },
+
+            Buffer::Spill(capacity) => {
+                let full = state.lines.len() >= capacity;
+
+                match state.spill {
+                    // Once lines were spilled, the next ones follow them to keep the order.
+                    Some(ref mut spill) if full || spill.pending > 0 => spill.write(line),
+                    _ => state.lines.push_back(line),
+                }
+            },
+
+            Buffer::Unbounded | Buffer::Block(_) => state.lines.push_back(line),
+        }
+
+        self.ready.notify_one();
+    }
+
+    fn recv(&self) -> Option<Line> {
+        let mut state = self.state();
+
+        loop {
+            if let Some(line) = state.lines.pop_front() {
+                return Some(line);
+            }
+
+            if let Some(line) = state.spill.as_mut().and_then(Spill::read) {
+                return Some(line);
+            }
+
+            if state.writers == 0 {
+                return None;
+            }
+
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// A producer of a `Queue`, which is closed once all of them are dropped.
+///
+struct Writer(Arc<Queue>);
+
+impl Writer {
+    fn new(queue: Arc<Queue>) -> Writer {
+        queue.state().writers += 1;
+        Writer(queue)
+    }
+
+    fn send(&self, line: Line) -> bool {
+        // The `Lines` holds the other reference: nobody consumes the lines once it is dropped.
+        if Arc::strong_count(&self.0) <= self.0.state().writers {
+            return false;
+        }
+
+        self.0.push(line);
+        true
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.0.state().writers -= 1;
+        self.0.ready.notify_all();
+    }
+}
+
+/// The temporary file holding the lines spilled out of memory, removed once the buffer is gone.
+///
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    pending: usize,
+}
+
+impl Spill {
+    fn create() -> ::std::io::Result<Spill> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = ::std::env::temp_dir().join(format!("shells-stream-{}-{}", ::std::process::id(),
+                                                       COUNTER.fetch_add(1, Ordering::SeqCst)));
+
+        let writer = Spill::open(&path)?;
+        let reader = BufReader::new(File::open(&path)?);
+
+        // Once both ends are open, the file is only reachable through them.
+        if cfg!(unix) {
+            let _ = fs::remove_file(&path);
+        }
+
+        Ok(Spill { path, writer, reader, pending: 0 })
+    }
+
+    /// Creates the file, only readable by the current user.
+    ///
+    #[cfg(unix)]
+    fn open(path: &Path) -> ::std::io::Result<File> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        File::options().write(true).create_new(true).mode(0o600).open(path)
+    }
+
+    #[cfg(not(unix))]
+    fn open(path: &Path) -> ::std::io::Result<File> {
+        File::options().write(true).create_new(true).open(path)
+    }
+
+    /// Appends the line to the file, each on its own line after a character telling its stream.
+    /// A line which cannot be written is lost.
+    ///
+    fn write(&mut self, line: Line) {
+        let (tag, text) = match line {
+            Line::Stdout(text) => ('o', text),
+            Line::Stderr(text) => ('e', text),
+        };
+
+        if writeln!(self.writer, "{}{}", tag, text).is_ok() {
+            self.pending += 1;
+        }
+    }
+
+    fn read(&mut self) -> Option<Line> {
+        if self.pending == 0 {
+            return None;
+        }
+
+        self.pending -= 1;
+
+        let mut record = String::new();
+        let read = self.reader.read_line(&mut record);
+
+        // The file is emptied once every spilled line was consumed.
+        if self.pending == 0 {
+            let _ = self.writer.set_len(0);
+            let _ = self.writer.seek(SeekFrom::Start(0));
+            let _ = self.reader.seek(SeekFrom::Start(0));
+        }
+
+        read.ok()?;
+
+        if record.ends_with('\n') {
+            record.pop();
+        }
+
+        let text = record.get(1..).unwrap_or_default().to_string();
+
+        Some(match record.starts_with('e') {
+            true => Line::Stderr(text),
+            false => Line::Stdout(text),
+        })
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
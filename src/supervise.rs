@@ -0,0 +1,305 @@
+//! Commands kept running in the background, restarted when they exit, for the helper processes
+//! of small daemons: a tunnel, a queue worker, a file watcher...
+//!
+//! `Job::start` starts the command like `Shell::start`, and a supervisor thread restarts it as
+//! its `Restart` policy says, after a delay doubling at each restart up to 1 minute. Its output
+//! goes to the sinks of its shell, and each exit and restart is recorded in the metrics of the
+//! shell, see `Shell::metrics`. Dropping the `Job` kills the command and stops restarting it.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use shells::ExitStatus;
+//! use shells::supervise::{Job, Restart, State};
+//!
+//! let policy = Restart::OnFailure { max: 2, backoff: Duration::from_millis(10) };
+//! let job = Job::start("echo starting; exit 3", policy).unwrap();
+//!
+//! assert_eq!(job.wait(), ExitStatus::Exited(3));
+//! assert_eq!((job.state(), job.restarts()), (State::Done(ExitStatus::Exited(3)), 2));
+//!
+//! let always = Restart::Always { backoff: Duration::from_secs(1) };
+//! let daemon = Job::start("sleep 10", always).unwrap();
+//!
+//! assert_eq!((daemon.state(), daemon.last_exit()), (State::Running, None));
+//! assert!(daemon.pid().is_some());
+//! ```
+//!
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use child::Running;
+use clock::{self, Clock, SystemClock};
+use output::ExitStatus;
+use pipeline::Stage;
+
+/// Longest delay before a restart.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// When a supervised command is restarted once it exited.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Restart {
+    /// The command is never restarted.
+    #[default]
+    Never,
+
+    /// The command is restarted when it exits with a non-zero code or is killed by a signal, up
+    /// to `max` times, waiting `backoff` before the first restart.
+    OnFailure {
+        max: u32,
+        backoff: Duration,
+    },
+
+    /// The command is restarted whenever it exits, waiting `backoff` before the first restart.
+    Always {
+        backoff: Duration,
+    },
+}
+
+impl Restart {
+    /// The delay before the restart following the given number of restarts, or `None` when the
+    /// command must not be restarted.
+    ///
+    fn delay(&self, status: ExitStatus, restarts: u32) -> Option<Duration> {
+        let failed = status != ExitStatus::Exited(0);
+
+        let backoff = match *self {
+            Restart::Never => return None,
+            Restart::OnFailure { max, backoff } if failed && restarts < max => backoff,
+            Restart::OnFailure { .. } => return None,
+            Restart::Always { backoff } => backoff,
+        };
+
+        let factor = 2u32.checked_pow(restarts).unwrap_or(u32::MAX);
+        Some(backoff.checked_mul(factor).unwrap_or(MAX_BACKOFF).min(MAX_BACKOFF))
+    }
+}
+
+/// Where a supervised command stands.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// The command is running.
+    Running,
+
+    /// The command exited, and is restarted once its delay elapsed.
+    Restarting,
+
+    /// The command exited, and is not restarted given its policy.
+    Done(ExitStatus),
+}
+
+/// A command supervised in the background, see the module documentation.
+///
+#[derive(Debug)]
+pub struct Job {
+    shared: Arc<Shared>,
+    supervisor: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    status: Mutex<Status>,
+    changed: Condvar,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug)]
+struct Status {
+    state: State,
+    pid: Option<u32>,
+    restarts: u32,
+    last_exit: Option<ExitStatus>,
+    dropped: bool,
+}
+
+impl Shared {
+    fn status(&self) -> MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until `timeout` elapsed on the given clock or the job was dropped, returns whether
+    /// it was. The clock is slept on by steps, so that a drop is noticed within 20ms.
+    ///
+    fn dropped_within(&self, clock: &dyn Clock, timeout: Duration) -> bool {
+        let deadline = clock.now() + timeout;
+
+        loop {
+            let now = clock.now();
+
+            if self.status().dropped || now >= deadline {
+                return self.status().dropped;
+            }
+
+            clock.sleep((deadline - now).min(Duration::from_millis(20)));
+        }
+    }
+}
+
+impl Job {
+    /// Starts the command, a string run with `sh` or a `pipeline::Stage`, and supervises it. Fails
+    /// when the command cannot be started the first time; later, a command which cannot be
+    /// restarted counts as having exited with the code 126.
+    ///
+    pub fn start<S: Into<Stage>>(command: S, restart: Restart) -> io::Result<Job> {
+        Job::start_with_clock(command, restart, clock::system())
+    }
+
+    /// Starts the command like `start`, waiting the delays before the restarts with the given
+    /// clock instead of the system one, for tests.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use shells::clock::FakeClock;
+    /// use shells::supervise::{Job, Restart};
+    ///
+    /// let clock = FakeClock::new();
+    /// let policy = Restart::OnFailure { max: 2, backoff: Duration::from_secs(10) };
+    /// let job = Job::start_with_clock("exit 3", policy, Arc::new(clock.clone())).unwrap();
+    ///
+    /// job.wait();
+    ///
+    /// assert_eq!(job.restarts(), 2);
+    /// assert!(clock.elapsed() >= Duration::from_secs(10 + 20));
+    /// ```
+    ///
+    pub fn start_with_clock<S: Into<Stage>>(command: S, restart: Restart, clock: Arc<dyn Clock>)
+        -> io::Result<Job>
+    {
+        let stage = command.into();
+        let mut running = stage.start()?;
+        running.forward_output();
+
+        let status = Status {
+            state: State::Running,
+            pid: Some(running.pid()),
+            restarts: 0,
+            last_exit: None,
+            dropped: false,
+        };
+
+        let (status, changed) = (Mutex::new(status), Condvar::new());
+        let shared = Arc::new(Shared { status, changed, clock });
+        let supervised = shared.clone();
+        let supervisor = thread::spawn(move || {
+            supervise(&supervised, &stage, restart, Some(running))
+        });
+
+        Ok(Job { shared, supervisor: Some(supervisor) })
+    }
+
+    /// Where the command stands.
+    ///
+    pub fn state(&self) -> State {
+        self.shared.status().state
+    }
+
+    /// The process identifier of the shell running the command, `None` while it is not running.
+    ///
+    pub fn pid(&self) -> Option<u32> {
+        self.shared.status().pid
+    }
+
+    /// How many times the command was restarted.
+    ///
+    pub fn restarts(&self) -> u32 {
+        self.shared.status().restarts
+    }
+
+    /// How the command terminated the last time it exited, if it did.
+    ///
+    pub fn last_exit(&self) -> Option<ExitStatus> {
+        self.shared.status().last_exit
+    }
+
+    /// Waits until the command exited for good, given its policy, and returns how it terminated
+    /// the last time. Never returns for a command restarted `Always`.
+    ///
+    pub fn wait(&self) -> ExitStatus {
+        let mut status = self.shared.status();
+
+        loop {
+            if let State::Done(exit) = status.state {
+                return exit;
+            }
+
+            status = self.shared.changed.wait(status).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.shared.status().dropped = true;
+        self.shared.changed.notify_all();
+
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+/// Waits for the command to exit and restarts it, until the policy says otherwise or the job is
+/// dropped, which kills the command. The command runs on the system time, only the delays before
+/// the restarts are waited on the clock of the job.
+///
+fn supervise(shared: &Shared, stage: &Stage, restart: Restart, mut running: Option<Running>) {
+    let system = SystemClock;
+
+    loop {
+        let start = system.now();
+
+        let exit = match running {
+            Some(ref mut running) => loop {
+                if let Ok(Some(exit)) = running.try_wait() {
+                    running.prepared().record(exit.code(), false, system.now() - start);
+                    break exit;
+                }
+
+                if shared.dropped_within(&system, Duration::from_millis(20)) {
+                    return;
+                }
+            },
+
+            None => ExitStatus::Exited(126),
+        };
+
+        let delay = {
+            let mut status = shared.status();
+            let delay = restart.delay(exit, status.restarts);
+
+            status.pid = None;
+            status.last_exit = Some(exit);
+            status.state = delay.map(|_| State::Restarting).unwrap_or(State::Done(exit));
+            shared.changed.notify_all();
+
+            delay
+        };
+
+        match delay {
+            Some(delay) if !shared.dropped_within(&*shared.clock, delay) => (),
+            _ => return,
+        }
+
+        if let Some(ref running) = running {
+            running.prepared().record_restart();
+        }
+
+        running = stage.start().ok();
+
+        let mut status = shared.status();
+        status.restarts += 1;
+
+        if let Some(ref mut running) = running {
+            running.forward_output();
+            status.pid = Some(running.pid());
+            status.state = State::Running;
+        }
+
+        shared.changed.notify_all();
+    }
+}
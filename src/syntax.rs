@@ -0,0 +1,185 @@
+//! Syntax checking of scripts before they run, see `PreparedCommand::check_syntax`.
+//!
+//! The shell parses the script in its no-exec mode (`-n`): nothing is executed, so that a
+//! user-supplied script with a syntax error in its last line is rejected before its first lines
+//! had any side effect.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::syntax::Error;
+//!
+//! let script = "echo start\nif true; then\nfi";
+//! let prepared = Shell::new("sh").prepare(script).unwrap();
+//!
+//! match prepared.check_syntax() {
+//!     Err(Error::Invalid { line, .. }) => assert_eq!(line, Some(3)),
+//!     other => panic!("unexpected result: {:?}", other),
+//! }
+//! ```
+//!
+//! Errors reported while the script runs can be traced back to the Rust sources as well, see
+//! `Shell::source` and the `script!` macro.
+//!
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Error returned when a script cannot be checked or is invalid.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The shell rejected the script. `line` is the line of the script the error is reported at,
+    /// when the shell tells it.
+    Invalid { line: Option<usize>, message: String },
+
+    /// The shell has no mode parsing scripts without executing them, e.g. `cmd`.
+    Unsupported(String),
+
+    /// The shell could not be started.
+    Unavailable(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Invalid { .. } => "Invalid script.",
+            Error::Unsupported(_) => "Syntax checking not supported by the shell.",
+            Error::Unavailable(_) => "Shell not available.",
+        }
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Invalid { ref message, .. } => write!(f, "{}", message),
+            Error::Unsupported(ref shell) => {
+                write!(f, "`{}` cannot check scripts without running them", shell)
+            },
+
+            Error::Unavailable(ref reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// How to run the shell of a prepared command in its no-exec mode.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Check {
+    program: String,
+    argv: Option<Vec<OsString>>,
+    offset: usize,
+}
+
+impl Check {
+    /// The check of `script` by the shell invoked as `program args flag script`, `preamble` being
+    /// the code prepended to the script by `Shell::preamble`.
+    ///
+    pub(crate) fn new(program: &str, args: Vec<OsString>, flag: &str, script: &str, preamble: &str)
+        -> Check
+    {
+        let name = Path::new(program).file_stem().map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let argv = match &name[..] {
+            "cmd" | "powershell" | "pwsh" => None,
+
+            _ => {
+                let mut argv: Vec<OsString> = vec![program.into()];
+                argv.extend(args);
+                argv.extend(["-n", flag, script].iter().map(OsString::from));
+                Some(argv)
+            },
+        };
+
+        Check { program: program.to_string(), argv, offset: preamble.matches('\n').count() }
+    }
+
+    pub(crate) fn run(&self) -> Result<(), Error> {
+        let argv = match self.argv {
+            Some(ref argv) => argv,
+            None => return Err(Error::Unsupported(self.program.clone())),
+        };
+
+        let output = Command::new(&argv[0]).args(&argv[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| Error::Unavailable(format!("cannot execute `{}`: {}", self.program, e)))?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let message = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+        let line = line_number(&message).and_then(|line| line.checked_sub(self.offset))
+            .filter(|&line| line > 0);
+
+        Err(Error::Invalid { line, message })
+    }
+}
+
+/// Filter appending the location in the Rust sources to the messages the shell reports at a
+/// line of the script, see `Shell::source`. `offset` is the number of lines of the preamble, and
+/// `script` the path of the file holding the script, see `Shell::script_file`.
+///
+pub(crate) fn locate(program: &str, script: Option<PathBuf>, offset: usize, file: &str, line: u32)
+    -> impl Fn(&str) -> String + Send + Sync + 'static
+{
+    let program = program.to_string();
+    let file = file.to_string();
+
+    let name = Path::new(&program).file_stem().map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // The shells running a script file report its path instead of their name.
+    let is_shell = move |prefix: &str| {
+        prefix == program
+            || Path::new(prefix).file_stem().map(|stem| stem == &name[..]).unwrap_or(false)
+            || script.as_ref().is_some_and(|script| Path::new(prefix) == script)
+    };
+
+    move |stderr: &str| {
+        stderr.split_inclusive('\n').map(|message| {
+            let text = message.trim_end_matches('\n');
+
+            let located = match text.split(':').next() {
+                Some(prefix) if is_shell(prefix) => {
+                    line_number(text).and_then(|number| number.checked_sub(offset))
+                        .filter(|&number| number > 0)
+                },
+
+                _ => None,
+            };
+
+            match located {
+                Some(number) => {
+                    let newline = &message[text.len()..];
+                    format!("{} ({}:{}){}", text, file, line as usize + number - 1, newline)
+                },
+
+                None => message.to_string(),
+            }
+        }).collect()
+    }
+}
+
+/// The line number of the first error message, as reported by the common shells: `line 3` (bash,
+/// ksh) or a `:3:` field (dash, zsh).
+///
+fn line_number(message: &str) -> Option<usize> {
+    let first = message.lines().next().unwrap_or("");
+
+    let after_line = first.split("line ").skip(1)
+        .filter_map(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .find(|digits| !digits.is_empty());
+
+    let field = || {
+        first.split(':').map(str::trim)
+            .find(|field| !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit()))
+    };
+
+    after_line.or_else(field).and_then(|digits| digits.parse().ok())
+}
@@ -0,0 +1,128 @@
+//! Raw bindings to the few libc functions used by the platform-specific features of this crate.
+//!
+//! The dependencies of the crate are all optional and none of them is `libc`, so the handful of
+//! symbols needed are declared here by hand rather than pulled from it.
+//!
+
+#[cfg(all(feature = "seccomp", target_os = "linux"))]
+pub const PR_SET_SECCOMP: ::std::os::raw::c_int = 22;
+
+#[cfg(all(feature = "seccomp", target_os = "linux"))]
+pub const PR_SET_NO_NEW_PRIVS: ::std::os::raw::c_int = 38;
+
+#[cfg(all(feature = "seccomp", target_os = "linux"))]
+extern "C" {
+    pub fn prctl(option: ::std::os::raw::c_int, ...) -> ::std::os::raw::c_int;
+}
+
+#[cfg(target_os = "linux")]
+pub const CLONE_NEWUSER: ::std::os::raw::c_int = 0x1000_0000;
+
+#[cfg(target_os = "linux")]
+pub const CLONE_NEWNET: ::std::os::raw::c_int = 0x4000_0000;
+
+pub const O_WRONLY: ::std::os::raw::c_int = 1;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    pub fn unshare(flags: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+
+extern "C" {
+    pub fn getuid() -> u32;
+    pub fn getgid() -> u32;
+    pub fn open(path: *const ::std::os::raw::c_char, flags: ::std::os::raw::c_int, ...)
+        -> ::std::os::raw::c_int;
+    pub fn write(fd: ::std::os::raw::c_int, buf: *const ::std::os::raw::c_void, count: usize)
+        -> isize;
+    pub fn close(fd: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+
+extern "C" {
+    pub fn kill(pid: i32, sig: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+}
+
+pub const EPERM: i32 = 1;
+
+#[cfg(feature = "pty")]
+pub const SIGHUP: ::std::os::raw::c_int = 1;
+
+pub const SIGINT: ::std::os::raw::c_int = 2;
+pub const SIGKILL: ::std::os::raw::c_int = 9;
+pub const SIGTERM: ::std::os::raw::c_int = 15;
+pub const SIG_DFL: usize = 0;
+
+pub const EINTR: i32 = 4;
+
+pub const F_SETFD: ::std::os::raw::c_int = 2;
+pub const FD_CLOEXEC: ::std::os::raw::c_int = 1;
+
+extern "C" {
+    pub fn fcntl(fd: ::std::os::raw::c_int, cmd: ::std::os::raw::c_int, ...)
+        -> ::std::os::raw::c_int;
+    pub fn signal(signum: ::std::os::raw::c_int, handler: usize) -> usize;
+    pub fn raise(sig: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    pub fn pipe(fds: *mut ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    pub fn read(fd: ::std::os::raw::c_int, buf: *mut ::std::os::raw::c_void, count: usize) -> isize;
+}
+
+extern "C" {
+    pub fn geteuid() -> u32;
+    pub fn setsid() -> i32;
+}
+
+#[cfg(feature = "pty")]
+pub const O_RDWR: ::std::os::raw::c_int = 2;
+
+#[cfg(all(feature = "pty", target_os = "linux"))]
+pub const O_NOCTTY: ::std::os::raw::c_int = 0o400;
+
+#[cfg(all(feature = "pty", target_os = "macos"))]
+pub const O_NOCTTY: ::std::os::raw::c_int = 0x20000;
+
+#[cfg(all(feature = "pty", target_os = "linux"))]
+pub const O_CLOEXEC: ::std::os::raw::c_int = 0o2000000;
+
+#[cfg(all(feature = "pty", target_os = "macos"))]
+pub const O_CLOEXEC: ::std::os::raw::c_int = 0x0100_0000;
+
+#[cfg(all(feature = "pty", target_os = "linux"))]
+pub const TIOCSCTTY: ::std::os::raw::c_ulong = 0x540E;
+
+#[cfg(all(feature = "pty", target_os = "macos"))]
+pub const TIOCSCTTY: ::std::os::raw::c_ulong = 0x2000_7461;
+
+#[cfg(all(feature = "pty", target_os = "linux"))]
+pub const TIOCSWINSZ: ::std::os::raw::c_ulong = 0x5414;
+
+#[cfg(all(feature = "pty", target_os = "macos"))]
+pub const TIOCSWINSZ: ::std::os::raw::c_ulong = 0x8008_7467;
+
+#[cfg(feature = "pty")]
+#[repr(C)]
+pub struct Winsize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+#[cfg(feature = "pty")]
+extern "C" {
+    pub fn posix_openpt(flags: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    pub fn grantpt(fd: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    pub fn unlockpt(fd: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    pub fn ioctl(fd: ::std::os::raw::c_int, request: ::std::os::raw::c_ulong, ...)
+        -> ::std::os::raw::c_int;
+}
+
+#[cfg(all(feature = "pty", target_os = "linux"))]
+extern "C" {
+    pub fn ptsname_r(fd: ::std::os::raw::c_int, buf: *mut ::std::os::raw::c_char, len: usize)
+        -> ::std::os::raw::c_int;
+}
+
+#[cfg(all(feature = "pty", target_os = "macos"))]
+extern "C" {
+    pub fn ptsname(fd: ::std::os::raw::c_int) -> *mut ::std::os::raw::c_char;
+}
@@ -0,0 +1,320 @@
+//! Execution targets: where commands run, behind a common interface.
+//!
+//! Scripts written against `Target` rather than a concrete type can be pointed at the local
+//! machine, a host reached over SSH, a container, a virtual machine or a WSL distribution at
+//! runtime, e.g. `Box<dyn Target>` chosen from a command line flag.
+//!
+//! `from_env` picks the target from the `SHELLS_TARGET` environment variable, so that the same
+//! binary can be redirected without code changes: `SHELLS_TARGET=ssh://build@ci01 ./deploy`. See
+//! `parse` for the accepted forms. Local commands run with the shell named by `SHELLS_SHELL`,
+//! `sh` by default.
+//!
+//! ```rust
+//! use shells::Shell;
+//! use shells::ssh::Remote;
+//! use shells::target::Target;
+//!
+//! fn uptime(target: &dyn Target) -> String {
+//!     target.try_run("uptime").unwrap_or_default()
+//! }
+//!
+//! let remote = ::std::env::args().nth(1);
+//!
+//! let target: Box<dyn Target> = match remote {
+//!     Some(destination) => Box::new(Remote::new(&destination)),
+//!     None => Box::new(Shell::new("sh")),
+//! };
+//!
+//! println!("{}", uptime(&*target));
+//! ```
+//!
+
+use std::env;
+use std::ffi::OsString;
+
+use container::{Running, Runtime};
+use shell::Shell;
+use ssh::Remote;
+use vm::Vm;
+
+/// A place commands can be executed in. Each target hands the command to a shell of its own:
+/// the configured one for `Shell`, the login shell of the remote user for `ssh::Remote`, `sh -c`
+/// for containers and WSL.
+///
+pub trait Target {
+    /// Executes the command and returns its exit code, stdout and stderr.
+    ///
+    fn run(&self, cmd: &str) -> (i32, String, String);
+
+    /// Executes the command and returns its stdout when it succeeded, like the `wrap_*` family of
+    /// macros.
+    ///
+    fn try_run(&self, cmd: &str) -> ::Result {
+        match self.run(cmd) {
+            (0, stdout, _) => Ok(stdout),
+            (code, stdout, stderr) => Err(::Error::Failed { code, stdout, stderr }),
+        }
+    }
+}
+
+impl<T: Target + ?Sized> Target for &T {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        (**self).run(cmd)
+    }
+
+    fn try_run(&self, cmd: &str) -> ::Result {
+        (**self).try_run(cmd)
+    }
+}
+
+impl<T: Target + ?Sized> Target for Box<T> {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        (**self).run(cmd)
+    }
+
+    fn try_run(&self, cmd: &str) -> ::Result {
+        (**self).try_run(cmd)
+    }
+}
+
+impl Target for Shell {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        Shell::run(self, cmd)
+    }
+
+    fn try_run(&self, cmd: &str) -> ::Result {
+        Shell::try_run(self, cmd)
+    }
+}
+
+impl Target for Remote {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        Remote::run(self, cmd)
+    }
+}
+
+impl Target for Running {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        self.exec(cmd)
+    }
+}
+
+impl Target for Vm {
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        Vm::run(self, cmd)
+    }
+}
+
+/// A distribution of the Windows Subsystem for Linux, commands being run through `wsl --exec sh
+/// -c`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Wsl {
+    distribution: Option<String>,
+    user: Option<String>,
+}
+
+impl Wsl {
+    /// Targets the default distribution, as the default user.
+    ///
+    pub fn new() -> Wsl {
+        Wsl::default()
+    }
+
+    /// Targets the distribution with the given name, as listed by `wsl --list`.
+    ///
+    pub fn distribution(mut self, name: &str) -> Wsl {
+        self.distribution = Some(name.to_string());
+        self
+    }
+
+    /// Runs the commands as the given user of the distribution.
+    ///
+    pub fn user(mut self, name: &str) -> Wsl {
+        self.user = Some(name.to_string());
+        self
+    }
+
+    /// The program and arguments executing `cmd` in the distribution.
+    ///
+    pub fn argv(&self, cmd: &str) -> Vec<OsString> {
+        let mut argv: Vec<OsString> = vec!["wsl".into()];
+
+        if let Some(ref distribution) = self.distribution {
+            argv.push("--distribution".into());
+            argv.push(distribution.into());
+        }
+
+        if let Some(ref user) = self.user {
+            argv.push("--user".into());
+            argv.push(user.into());
+        }
+
+        argv.extend(["--exec", "sh", "-c", cmd].iter().map(OsString::from));
+        argv
+    }
+}
+
+impl Target for Wsl {
+    /// Runs `wsl` through `cmd`, or `sh` outside Windows, so that dry runs, policies, mocks and
+    /// sinks apply to it.
+    ///
+    fn run(&self, cmd: &str) -> (i32, String, String) {
+        let shell = if cfg!(windows) { "cmd" } else { "sh" };
+        let line: Vec<String> = self.argv(cmd).iter()
+            .map(|arg| ::quote::for_shell(shell, &arg.to_string_lossy()).into_owned())
+            .collect();
+
+        Shell::new(shell).run(&line.join(" "))
+    }
+}
+
+/// Error returned when a target specification cannot be understood.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The scheme of the specification is not one of those supported by `parse`.
+    UnknownScheme(String),
+
+    /// The specification lacks the name of the host, container or machine.
+    MissingName(String),
+
+    /// The port of an `ssh://` specification is not a number.
+    InvalidPort(String),
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        "Invalid target specification."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::UnknownScheme(ref spec) => write!(f, "unknown scheme in target `{}`", spec),
+            Error::MissingName(ref spec) => {
+                write!(f, "target `{}` does not name what to run on", spec)
+            },
+
+            Error::InvalidPort(ref spec) => write!(f, "invalid port in target `{}`", spec),
+        }
+    }
+}
+
+/// The shell local commands run with: the value of `SHELLS_SHELL`, else the shell of the
+/// project of the current directory with the `project-config` feature, `sh` by default.
+///
+pub fn default_shell() -> String {
+    match env::var("SHELLS_SHELL") {
+        Ok(ref shell) if !shell.is_empty() => shell.clone(),
+
+        #[cfg(feature = "project-config")]
+        _ => {
+            let project = ::project::applying(None);
+            project.and_then(|project| project.shell.clone()).unwrap_or_else(|| "sh".to_string())
+        },
+
+        #[cfg(not(feature = "project-config"))]
+        _ => "sh".to_string(),
+    }
+}
+
+/// The target named by the `SHELLS_TARGET` environment variable, see `parse`. The local machine
+/// when unset or empty.
+///
+pub fn from_env() -> Result<Box<dyn Target>, Error> {
+    parse(&env::var("SHELLS_TARGET").unwrap_or_default())
+}
+
+/// Parses a target specification:
+///
+/// * `local`, or an empty string: the local machine, with the shell given by `default_shell`;
+/// * `ssh://[user@]host[:port]`: a host reached through the OpenSSH client;
+/// * `docker://container`, `podman://container`: a running container, see `Running::attach`;
+/// * `vagrant://[machine]`: a machine of the Vagrant project of the current directory,
+///   `default` when omitted;
+/// * `multipass://instance`: a Multipass instance;
+/// * `wsl://[user@][distribution]`: a WSL distribution, the default one when omitted.
+///
+/// ```rust
+/// use shells::target;
+///
+/// assert!(target::parse("ssh://build@ci01:2222").is_ok());
+/// assert!(target::parse("ftp://ci01").is_err());
+/// ```
+///
+pub fn parse(spec: &str) -> Result<Box<dyn Target>, Error> {
+    let (scheme, rest) = match spec.find("://") {
+        Some(index) => (&spec[..index], &spec[index + 3..]),
+        None => (spec, ""),
+    };
+
+    let named = |name: &str| match name {
+        "" => Err(Error::MissingName(spec.to_string())),
+        name => Ok(name.to_string()),
+    };
+
+    Ok(match scheme {
+        "" | "local" if rest.is_empty() => Box::new(Shell::new(&default_shell())),
+        "ssh" => Box::new(remote(spec, rest)?),
+        "docker" => Box::new(Running::attach(Runtime::Docker, &named(rest)?)),
+        "podman" => Box::new(Running::attach(Runtime::Podman, &named(rest)?)),
+        "vagrant" => Box::new(Vm::vagrant(if rest.is_empty() { "default" } else { rest })),
+        "multipass" => Box::new(Vm::multipass(&named(rest)?)),
+
+        "wsl" => {
+            let (user, distribution) = match rest.find('@') {
+                Some(index) => (Some(&rest[..index]), &rest[index + 1..]),
+                None => (None, rest),
+            };
+
+            let mut wsl = Wsl::new();
+
+            if let Some(user) = user {
+                wsl = wsl.user(user);
+            }
+
+            if !distribution.is_empty() {
+                wsl = wsl.distribution(distribution);
+            }
+
+            Box::new(wsl)
+        },
+
+        _ => return Err(Error::UnknownScheme(spec.to_string())),
+    })
+}
+
+/// Parses the `[user@]host[:port]` part of an `ssh://` specification, IPv6 addresses being
+/// enclosed in brackets.
+///
+fn remote(spec: &str, rest: &str) -> Result<Remote, Error> {
+    let (user, address) = match rest.rfind('@') {
+        Some(index) => (&rest[..index + 1], &rest[index + 1..]),
+        None => ("", rest),
+    };
+
+    let (host, port) = match address.strip_prefix('[').and_then(|address| address.split_once(']')) {
+        Some((host, port)) => (host, port.strip_prefix(':')),
+        None => match address.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        },
+    };
+
+    if host.is_empty() {
+        return Err(Error::MissingName(spec.to_string()));
+    }
+
+    let remote = Remote::new(&format!("{}{}", user, host));
+
+    match port {
+        Some(port) => {
+            let port = port.parse().map_err(|_| Error::InvalidPort(spec.to_string()))?;
+            Ok(remote.port(port))
+        },
+
+        None => Ok(remote),
+    }
+}
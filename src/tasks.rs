@@ -0,0 +1,184 @@
+//! Named tasks for automation binaries, with a `just`-like command line.
+//!
+//! ```rust,no_run
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::tasks::Tasks;
+//!
+//! fn main() {
+//!     Tasks::new()
+//!         .task("build", "Compile the project", || {
+//!             wrap_sh!("cargo build --release")?;
+//!             Ok(())
+//!         })
+//!         .task("deploy", "Upload the release build", || {
+//!             wrap_sh!("rsync -a target/release/app deploy@example.com:")?;
+//!             Ok(())
+//!         })
+//!         .main()
+//! }
+//! ```
+//!
+//! `Tasks::main` understands the following arguments:
+//!
+//! * no argument or `--list`: lists the tasks with their descriptions;
+//! * `--describe <task>`: prints the description of a task;
+//! * `<task>...`: runs the given tasks in order, stopping at the first failure.
+//!
+
+use std::env;
+use std::error::Error as StdError;
+
+/// Result returned by task actions.
+///
+pub type TaskResult = ::std::result::Result<(), Box<dyn StdError>>;
+
+struct Task {
+    name: String,
+    description: String,
+    action: Box<dyn Fn() -> TaskResult>,
+}
+
+/// Error returned when running a task.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// No task has this name.
+    Unknown(String),
+
+    /// The task returned an error.
+    Failed { task: String, error: Box<dyn StdError> },
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        "Task failed."
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::Unknown(ref name) => write!(f, "unknown task `{}`", name),
+            Error::Failed { ref task, ref error } => write!(f, "task `{}` failed: {}", task, error),
+        }
+    }
+}
+
+/// Registry of named tasks, listed in registration order.
+///
+#[derive(Default)]
+pub struct Tasks {
+    tasks: Vec<Task>,
+}
+
+impl ::std::fmt::Debug for Tasks {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_list().entries(self.tasks.iter().map(|task| &task.name)).finish()
+    }
+}
+
+impl Tasks {
+    pub fn new() -> Tasks {
+        Tasks::default()
+    }
+
+    /// Registers a task. A task registered with the name of an existing one replaces it.
+    ///
+    pub fn task<F>(mut self, name: &str, description: &str, action: F) -> Tasks
+        where F: Fn() -> TaskResult + 'static
+    {
+        self.tasks.retain(|task| task.name != name);
+
+        self.tasks.push(Task {
+            name: name.to_string(),
+            description: description.to_string(),
+            action: Box::new(action),
+        });
+
+        self
+    }
+
+    /// Names and descriptions of the registered tasks.
+    ///
+    pub fn list_tasks(&self) -> Vec<(&str, &str)> {
+        self.tasks.iter().map(|task| (&task.name[..], &task.description[..])).collect()
+    }
+
+    /// Description of the given task.
+    ///
+    pub fn describe(&self, name: &str) -> Option<&str> {
+        self.tasks.iter().find(|task| task.name == name).map(|task| &task.description[..])
+    }
+
+    /// Runs the given task.
+    ///
+    pub fn run(&self, name: &str) -> ::std::result::Result<(), Error> {
+        let task = self.tasks.iter().find(|task| task.name == name)
+            .ok_or_else(|| Error::Unknown(name.to_string()))?;
+
+        (task.action)().map_err(|error| Error::Failed { task: name.to_string(), error })
+    }
+
+    /// Renders the list of tasks as printed by `--list`.
+    ///
+    pub fn usage(&self) -> String {
+        let width = self.tasks.iter().map(|task| task.name.len()).max().unwrap_or(0);
+        let mut usage = String::from("Available tasks:\n");
+
+        for task in &self.tasks {
+            let line = format!("    {:width$}  {}\n", task.name, task.description, width = width);
+            usage.push_str(&line);
+        }
+
+        usage
+    }
+
+    /// Handles the given command line arguments (without the program name) as described in the
+    /// module documentation and returns the exit code of the program.
+    ///
+    pub fn dispatch<I: IntoIterator<Item = String>>(&self, args: I) -> i32 {
+        let args: Vec<String> = args.into_iter().collect();
+
+        match args.first().map(|arg| &arg[..]) {
+            None | Some("--list") | Some("-l") => {
+                print!("{}", self.usage());
+                0
+            },
+
+            Some("--describe") => match args.get(1).and_then(|name| self.describe(name)) {
+                Some(description) => {
+                    println!("{}", description);
+                    0
+                },
+
+                None => {
+                    eprintln!("{}", Error::Unknown(args.get(1).cloned().unwrap_or_default()));
+                    2
+                },
+            },
+
+            Some(_) => {
+                for name in &args {
+                    if let Err(e) = self.run(name) {
+                        eprintln!("{}", e);
+
+                        return match e {
+                            Error::Unknown(_) => 2,
+                            Error::Failed { .. } => 1,
+                        };
+                    }
+                }
+
+                0
+            },
+        }
+    }
+
+    /// Dispatches the arguments of the process and exits with the resulting code.
+    ///
+    pub fn main(&self) -> ! {
+        ::std::process::exit(self.dispatch(env::args().skip(1)))
+    }
+}
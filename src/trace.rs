@@ -0,0 +1,134 @@
+//! Tracing of the executed commands, like `set -x`, and dry runs.
+//!
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use shell::{Outcome, PreparedCommand};
+
+/// What happens to the commands before they are executed, see `set_trace`. The modes are
+/// ordered from the least to the most cautious.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Trace {
+    /// Commands are executed silently.
+    #[default]
+    Off,
+
+    /// Each command is printed to stderr, prefixed by `+ `, then executed.
+    Echo,
+
+    /// Each command is printed like with `Echo`, and not executed: it succeeds without output.
+    DryRun,
+}
+
+impl Trace {
+    fn from_u8(value: u8) -> Trace {
+        match value {
+            1 => Trace::Echo,
+            2 => Trace::DryRun,
+            _ => Trace::Off,
+        }
+    }
+}
+
+static GLOBAL: AtomicU8 = AtomicU8::new(0);
+
+thread_local! {
+    static SCOPED: Cell<Option<Trace>> = const { Cell::new(None) };
+}
+
+/// Sets the trace mode of every command executed by the process, by the macros as well as by
+/// `Shell`, `Pipeline` and the other builders, `Remote`, `Fleet` and the targets included. The
+/// printed line holds the shell, its arguments and the command, quoted for a POSIX shell, after
+/// the directory and the environment variables set for it.
+///
+/// The programs the crate runs for its own needs are not traced: the `ssh` client of
+/// `Remote::forward_local`, the `curl` of the HTTP readiness probes, the re-execution of
+/// `privilege::ensure_root`, and the tools inspecting the system or the scripts, such as `df`,
+/// `shfmt` and the syntax checkers.
+///
+/// In dry-run mode, blocking, async and streamed commands succeed without output, but the
+/// commands started in the background by `spawn_*` and `Shell::spawn` fail to start, as no
+/// process stands behind their handle.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use shells::Trace;
+///
+/// fn main() {
+///     shells::set_trace(Trace::DryRun);
+///
+///     // Prints `+ sh -c 'rm -rf /srv/shop/releases/*'` to stderr.
+///     assert_eq!(sh!("rm -rf /srv/shop/releases/*"), (0, String::new(), String::new()));
+///
+///     shells::set_trace(Trace::Off);
+/// }
+/// ```
+///
+pub fn set_trace(trace: Trace) {
+    GLOBAL.store(trace as u8, Ordering::SeqCst);
+}
+
+/// The trace mode of the commands executed on the current thread.
+///
+pub fn trace() -> Trace {
+    SCOPED.with(Cell::get).unwrap_or_else(|| Trace::from_u8(GLOBAL.load(Ordering::SeqCst)))
+}
+
+/// Runs `f` with the given trace mode for the commands executed on the current thread, whatever
+/// the mode set by `set_trace`. Commands executed on other threads are not affected.
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shells;
+///
+/// use shells::Trace;
+///
+/// fn main() {
+///     let (code, stdout, _) = shells::with_trace(Trace::Echo, || sh!("echo deployed"));
+///     assert_eq!((code, &stdout[..]), (0, "deployed\n"));
+/// }
+/// ```
+///
+pub fn with_trace<R, F: FnOnce() -> R>(trace: Trace, f: F) -> R {
+    struct Reset(Option<Trace>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SCOPED.with(|scoped| scoped.set(self.0));
+        }
+    }
+
+    let _reset = Reset(SCOPED.with(|scoped| scoped.replace(Some(trace))));
+    f()
+}
+
+/// Prints the command when traced, and answers it when it must not be executed.
+///
+pub(crate) fn intercept(command: &PreparedCommand) -> Option<Outcome> {
+    match trace().max(command.trace()) {
+        Trace::Off => None,
+        Trace::Echo => {
+            eprintln!("+ {}", command);
+            None
+        },
+
+        Trace::DryRun => {
+            eprintln!("+ {}", command);
+            Some(Outcome::new(::ExitStatus::Exited(0), String::new(), String::new()))
+        },
+    }
+}
+
+/// Prints the command when traced, failing when it must not be started, for the commands which
+/// cannot be answered without a process.
+///
+pub(crate) fn intercept_start(command: &PreparedCommand) -> ::std::io::Result<()> {
+    match intercept(command) {
+        Some(_) => Err(::std::io::Error::other("shells: dry run, the command was not started")),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,164 @@
+//! Tracking of the files a command creates, modifies or removes.
+//!
+//! The tracker snapshots the size and modification time of everything below its roots before and
+//! after running a closure, and reports the difference. It works with any of the macros:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! use shells::track::Tracker;
+//!
+//! fn main() {
+//!     let dir = std::env::temp_dir().join(format!("shells-track-doc-{}", std::process::id()));
+//!     std::fs::create_dir_all(&dir).unwrap();
+//!
+//!     let ((code, _, _), changes) = Tracker::new(&dir).run(|| {
+//!         sh!("touch {}/new-file", dir.display())
+//!     });
+//!
+//!     assert_eq!(code, 0);
+//!     assert_eq!(changes.created, vec![dir.join("new-file")]);
+//!
+//!     std::fs::remove_dir_all(&dir).unwrap();
+//! }
+//! ```
+//!
+//! Since the detection relies on modification times, a file rewritten with identical size within
+//! the timestamp granularity of the filesystem can go unnoticed.
+//!
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    is_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// State of the tracked trees at a point in time.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    entries: BTreeMap<PathBuf, Stamp>,
+}
+
+impl Snapshot {
+    /// Returns the changes which happened between `earlier` and this snapshot.
+    ///
+    pub fn changes_since(&self, earlier: &Snapshot) -> Changes {
+        let mut changes = Changes::default();
+
+        for (path, stamp) in &self.entries {
+            match earlier.entries.get(path) {
+                None => changes.created.push(path.clone()),
+                Some(old) if !stamp.is_dir && old != stamp => changes.modified.push(path.clone()),
+                Some(_) => (),
+            }
+        }
+
+        for path in earlier.entries.keys() {
+            if !self.entries.contains_key(path) {
+                changes.removed.push(path.clone());
+            }
+        }
+
+        changes
+    }
+}
+
+/// Files and directories which appeared, changed or disappeared, sorted by path. Directories are
+/// only reported when created or removed.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Changes {
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl Changes {
+    /// Whether nothing changed.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// Iterates over every created or modified path.
+    ///
+    pub fn touched(&self) -> impl Iterator<Item = &PathBuf> {
+        self.created.iter().chain(self.modified.iter())
+    }
+}
+
+/// Watches a set of directory trees.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tracker {
+    roots: Vec<PathBuf>,
+}
+
+impl Tracker {
+    /// Creates a tracker watching everything below `root`.
+    ///
+    pub fn new<P: AsRef<Path>>(root: P) -> Tracker {
+        Tracker { roots: vec![root.as_ref().to_path_buf()] }
+    }
+
+    /// Watches an additional tree.
+    ///
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> Tracker {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Records the current state of the watched trees. Entries which cannot be read are skipped.
+    ///
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+
+        for root in &self.roots {
+            walk(root, &mut snapshot.entries);
+        }
+
+        snapshot
+    }
+
+    /// Runs `f` and returns its result along with the changes it made to the watched trees.
+    ///
+    pub fn run<R, F: FnOnce() -> R>(&self, f: F) -> (R, Changes) {
+        let before = self.snapshot();
+        let result = f();
+        (result, self.snapshot().changes_since(&before))
+    }
+}
+
+fn walk(dir: &Path, entries: &mut BTreeMap<PathBuf, Stamp>) {
+    let children = match fs::read_dir(dir) {
+        Ok(children) => children,
+        Err(_) => return,
+    };
+
+    for child in children.filter_map(|child| child.ok()) {
+        let path = child.path();
+
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        entries.insert(path.clone(), Stamp {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+
+        if metadata.is_dir() {
+            walk(&path, entries);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Cleanup actions run when a scope exits, the equivalent of `trap '...' EXIT` in shell scripts.
+//!
+//! A `Trap` runs its action when dropped, which covers returning normally, early returns and
+//! panics (unless panics abort). Traps armed with `on_signals` additionally run when the process
+//! receives SIGINT or SIGTERM, after which the signal is re-raised so that the process dies with
+//! the expected status. Nothing runs when the process calls `std::process::exit`.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate shells;
+//!
+//! fn main() {
+//!     let dir = std::env::temp_dir().join(format!("shells-trap-doc-{}", std::process::id()));
+//!
+//!     {
+//!         std::fs::create_dir(&dir).unwrap();
+//!         let _cleanup = shells::trap::trap_command("sh", &format!("rm -r '{}'", dir.display()));
+//!
+//!         defer! { println!("leaving the scope"); }
+//!     }
+//!
+//!     assert!(!dir.exists());
+//! }
+//! ```
+//!
+//! Traps run in reverse order of creation when triggered by a signal, like nested scopes do.
+//!
+
+use std::sync::{Arc, Mutex};
+
+type Action = Arc<Mutex<Option<Box<dyn FnOnce() + Send>>>>;
+
+/// A cleanup action armed until it runs or is disarmed.
+///
+pub struct Trap {
+    action: Action,
+
+    #[cfg(unix)]
+    registration: Option<u64>,
+}
+
+impl ::std::fmt::Debug for Trap {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let armed = self.action.lock().map(|action| action.is_some()).unwrap_or(false);
+        f.debug_struct("Trap").field("armed", &armed).finish()
+    }
+}
+
+/// Arms a trap running `f` when the returned guard is dropped.
+///
+pub fn trap<F: FnOnce() + Send + 'static>(f: F) -> Trap {
+    Trap {
+        action: Arc::new(Mutex::new(Some(Box::new(f)))),
+
+        #[cfg(unix)]
+        registration: None,
+    }
+}
+
+/// Arms a trap executing `cmd` with the given shell when the returned guard is dropped. The
+/// outcome of the command is ignored.
+///
+pub fn trap_command(shell: &str, cmd: &str) -> Trap {
+    let shell = ::Shell::new(shell);
+    let cmd = cmd.to_string();
+
+    trap(move || { shell.run(&cmd); })
+}
+
+impl Trap {
+    /// Also runs the action when the process receives SIGINT or SIGTERM.
+    ///
+    #[cfg(unix)]
+    pub fn on_signals(mut self) -> Trap {
+        if self.registration.is_none() {
+            self.registration = Some(signals::register(self.action.clone()));
+        }
+
+        self
+    }
+
+    /// Cancels the trap without running its action.
+    ///
+    pub fn disarm(self) {
+        if let Ok(mut action) = self.action.lock() {
+            action.take();
+        }
+    }
+}
+
+impl Drop for Trap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Some(id) = self.registration.take() {
+                signals::unregister(id);
+            }
+        }
+
+        run(&self.action);
+    }
+}
+
+fn run(action: &Action) {
+    let action = action.lock().ok().and_then(|mut action| action.take());
+
+    if let Some(action) = action {
+        action();
+    }
+}
+
+/// Macro arming a trap which runs the given block when the enclosing scope exits.
+///
+#[macro_export]
+macro_rules! defer {
+    ( $( $body:tt )* ) => {
+        let _defer = $crate::trap::trap(move || { $( $body )* });
+    };
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::io;
+    use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+    use std::sync::{Mutex, Once};
+    use std::thread;
+
+    use sys;
+
+    use super::{run, Action};
+
+    static INSTALL: Once = Once::new();
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+    static REGISTRY: Mutex<Vec<(u64, Action)>> = Mutex::new(Vec::new());
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    pub fn register(action: Action) -> u64 {
+        INSTALL.call_once(install);
+
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).push((id, action));
+        id
+    }
+
+    pub fn unregister(id: u64) {
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).retain(|&(other, _)| other != id);
+    }
+
+    extern "C" fn handle(sig: ::std::os::raw::c_int) {
+        let byte = sig as u8;
+        unsafe { sys::write(WRITE_FD.load(Ordering::SeqCst), &byte as *const u8 as *const _, 1); }
+    }
+
+    fn install() {
+        let mut fds = [-1; 2];
+
+        if unsafe { sys::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+
+        // The commands started afterwards must not inherit the pipe.
+        for &fd in &fds {
+            unsafe { sys::fcntl(fd, sys::F_SETFD, sys::FD_CLOEXEC); }
+        }
+
+        WRITE_FD.store(fds[1], Ordering::SeqCst);
+        let read_fd = fds[0];
+
+        thread::spawn(move || {
+            let mut byte = 0u8;
+
+            loop {
+                match unsafe { sys::read(read_fd, &mut byte as *mut u8 as *mut _, 1) } {
+                    1 => break,
+                    -1 if io::Error::last_os_error().raw_os_error() == Some(sys::EINTR) => (),
+                    _ => return,
+                }
+            }
+
+            let actions = {
+                let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+                ::std::mem::take(&mut *registry)
+            };
+
+            for (_, action) in actions.iter().rev() {
+                run(action);
+            }
+
+            let sig = ::std::os::raw::c_int::from(byte);
+
+            unsafe {
+                sys::signal(sig, sys::SIG_DFL);
+                sys::raise(sig);
+            }
+
+            ::std::process::exit(128 + sig);
+        });
+
+        for &sig in &[sys::SIGINT, sys::SIGTERM] {
+            unsafe { sys::signal(sig, handle as extern "C" fn(::std::os::raw::c_int) as usize); }
+        }
+    }
+}
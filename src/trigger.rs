@@ -0,0 +1,202 @@
+//! Actions run when a line printed by a streamed command matches a regular expression, see
+//! `Shell::trigger` and the `regex` feature.
+//!
+//! The lines are matched as they are read, whether or not they are consumed yet: a trigger fires
+//! even while the caller is busy elsewhere. `kill` stops the command, `capture` hands what was
+//! matched to the caller, and `call` runs a closure.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use shells::{Shell, Stream};
+//! use shells::trigger::{Regex, Trigger};
+//!
+//! let (port, captured) = Trigger::capture(Regex::new(r"listening on port (\d+)").unwrap());
+//!
+//! let lines = Shell::new("sh")
+//!     .trigger(port)
+//!     .trigger(Trigger::kill(Regex::new("FATAL").unwrap()).stream(Stream::Stderr))
+//!     .stream("echo 'listening on port 8080'; sleep 0.2; echo 'FATAL: disk full' >&2; sleep 10");
+//!
+//! // The server is up, its port is known while it keeps running.
+//! assert_eq!(captured.wait(Duration::from_secs(5)), Some("8080".to_string()));
+//!
+//! // Until it reports a fatal error.
+//! assert_eq!(lines.wait(), 137);
+//! ```
+//!
+
+use std::fmt;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use regex::{Captures, Regex};
+
+use shell::{PreparedCommand, Stream};
+
+/// A regular expression and what to do with the lines it matches.
+///
+#[derive(Clone)]
+pub struct Trigger {
+    regex: Regex,
+    stream: Option<Stream>,
+    action: Action,
+}
+
+#[derive(Clone)]
+enum Action {
+    Kill,
+    Capture(Sender<String>),
+    Call(Arc<dyn Fn(&Captures) + Send + Sync>),
+}
+
+impl Trigger {
+    /// Kills the command, and on Unix the processes it started, once a line matches.
+    ///
+    pub fn kill(regex: Regex) -> Trigger {
+        Trigger { regex, stream: None, action: Action::Kill }
+    }
+
+    /// Sends each match to the returned `Captured`: the first capture group when the expression
+    /// has one, the whole match otherwise.
+    ///
+    pub fn capture(regex: Regex) -> (Trigger, Captured) {
+        let (sender, receiver) = mpsc::channel();
+        (Trigger { regex, stream: None, action: Action::Capture(sender) }, Captured { receiver })
+    }
+
+    /// Calls `f` with the capture groups of each line matching, from the thread reading the
+    /// output: a slow closure holds the output back.
+    ///
+    pub fn call<F>(regex: Regex, f: F) -> Trigger
+        where F: Fn(&Captures) + Send + Sync + 'static
+    {
+        Trigger { regex, stream: None, action: Action::Call(Arc::new(f)) }
+    }
+
+    /// Only matches the lines printed on the given stream, both by default.
+    ///
+    pub fn stream(mut self, stream: Stream) -> Trigger {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Whether the trigger kills the command.
+    ///
+    #[cfg(unix)]
+    pub(crate) fn kills(&self) -> bool {
+        matches!(self.action, Action::Kill)
+    }
+}
+
+impl fmt::Debug for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            Action::Kill => "kill",
+            Action::Capture(_) => "capture",
+            Action::Call(_) => "call",
+        };
+
+        f.debug_struct("Trigger")
+            .field("regex", &self.regex.as_str())
+            .field("stream", &self.stream)
+            .field("action", &action)
+            .finish()
+    }
+}
+
+/// The matches of a `capture` trigger, in the order they were printed.
+///
+#[derive(Debug)]
+pub struct Captured {
+    receiver: Receiver<String>,
+}
+
+impl Captured {
+    /// Waits for the next match, `None` once the timeout elapsed or the command exited without
+    /// printing one.
+    ///
+    pub fn wait(&self, timeout: Duration) -> Option<String> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(text) => Some(text),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// The next match if one was printed already.
+    ///
+    pub fn try_get(&self) -> Option<String> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// The triggers of a running command.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Armed {
+    triggers: Vec<Trigger>,
+
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pid: u32,
+    killed: Arc<AtomicBool>,
+}
+
+impl Armed {
+    pub(crate) fn new(prepared: &PreparedCommand, child: &Child) -> Armed {
+        let killed = Arc::new(AtomicBool::new(false));
+        Armed { triggers: prepared.triggers().to_vec(), pid: child.id(), killed }
+    }
+
+    /// Runs the actions of the triggers matching the line.
+    ///
+    pub(crate) fn fire(&self, stream: Stream, line: &str) {
+        for trigger in &self.triggers {
+            if trigger.stream.is_some_and(|only| only != stream) {
+                continue;
+            }
+
+            let captures = match trigger.regex.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+
+            match trigger.action {
+                Action::Kill => self.kill(),
+
+                Action::Capture(ref sender) => {
+                    let matched = captures.get(1).or_else(|| captures.get(0));
+                    let text = matched.map(|m| m.as_str().to_string()).unwrap_or_default();
+                    let _ = sender.send(text);
+                },
+
+                Action::Call(ref f) => f(&captures),
+            }
+        }
+    }
+
+    /// Whether a trigger killed the command, or must have it killed on platforms where it cannot
+    /// be from the threads reading its output.
+    ///
+    #[cfg(not(unix))]
+    pub(crate) fn killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    /// Keeps the triggers from killing the command, before it is reaped.
+    ///
+    pub(crate) fn disarm(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+    }
+
+    fn kill(&self) {
+        if self.killed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // The command is not reaped until disarmed: its process group is still its own.
+        #[cfg(unix)]
+        unsafe { ::sys::kill(-(self.pid as i32), ::sys::SIGKILL); }
+    }
+}
@@ -0,0 +1,176 @@
+//! Execution of commands in local virtual machines managed by Vagrant or Multipass.
+//!
+//! A `Vm` exposes the same interface as `ssh::Remote`, so that scripts can target a local machine
+//! during development and the real hosts in production. Commands are interpreted by a shell of
+//! the machine: `vagrant ssh -c` hands them to the login shell of the `vagrant` user, `multipass
+//! exec` to `sh -c`. The machine must already be running.
+//!
+//! ```rust,no_run
+//! use shells::vm::Vm;
+//!
+//! let vm = Vm::vagrant("default").project_dir("infra/dev");
+//!
+//! vm.put("target/release/app", "/tmp/app").unwrap();
+//! let script = "sudo install /tmp/app /usr/local/bin/app && app --version";
+//! let (code, stdout, stderr) = vm.run(script);
+//! ```
+//!
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use shell::Shell;
+
+/// Virtual machine managers supported by `Vm`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Vagrant,
+    Multipass,
+}
+
+impl Provider {
+    /// Name of the program managing the machines.
+    ///
+    pub fn program(&self) -> &'static str {
+        match *self {
+            Provider::Vagrant => "vagrant",
+            Provider::Multipass => "multipass",
+        }
+    }
+}
+
+/// A local virtual machine commands are executed in.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vm {
+    provider: Provider,
+    name: String,
+    project_dir: Option<PathBuf>,
+}
+
+impl Vm {
+    /// Targets the machine of the Vagrant project, `default` unless the `Vagrantfile` names its
+    /// machines.
+    ///
+    pub fn vagrant(machine: &str) -> Vm {
+        Vm { provider: Provider::Vagrant, name: machine.to_string(), project_dir: None }
+    }
+
+    /// Targets the Multipass instance with the given name.
+    ///
+    pub fn multipass(instance: &str) -> Vm {
+        Vm { provider: Provider::Multipass, name: instance.to_string(), project_dir: None }
+    }
+
+    /// Runs the manager from the given directory, the one holding the `Vagrantfile` for Vagrant.
+    /// Defaults to the current directory.
+    ///
+    pub fn project_dir<P: Into<PathBuf>>(mut self, path: P) -> Vm {
+        self.project_dir = Some(path.into());
+        self
+    }
+
+    /// The manager of the machine.
+    ///
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    /// The name of the machine.
+    ///
+    pub fn name(&self) -> &str {
+        &self.name[..]
+    }
+
+    /// The program and arguments executing `cmd` in the machine.
+    ///
+    pub fn argv(&self, cmd: &str) -> Vec<OsString> {
+        let args: &[&str] = match self.provider {
+            Provider::Vagrant => &["ssh", "--no-tty", &self.name, "-c", cmd],
+            Provider::Multipass => &["exec", &self.name, "--", "sh", "-c", cmd],
+        };
+
+        ::std::iter::once(self.provider.program())
+            .chain(args.iter().cloned())
+            .map(OsString::from)
+            .collect()
+    }
+
+    /// Executes the command in the machine and returns its exit code, stdout and stderr. stdin is
+    /// not forwarded.
+    ///
+    pub fn run(&self, cmd: &str) -> (i32, String, String) {
+        let argv: Vec<String> = self.argv(cmd).iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        self.execute(&command_line(&argv))
+    }
+
+    /// Copies the local file or directory to `remote` in the machine.
+    ///
+    pub fn put<P: AsRef<Path>>(&self, local: P, remote: &str) -> Result<(), ::Error> {
+        let local = local.as_ref().to_string_lossy();
+
+        let argv = match self.provider {
+            Provider::Vagrant => vec!["vagrant", "upload", &local, remote, &self.name],
+            Provider::Multipass => {
+                return self.transfer(&local, &format!("{}:{}", self.name, remote));
+            },
+        };
+
+        checked(self.execute(&command_line(&argv)))
+    }
+
+    /// Copies the file `remote` of the machine to `local`. Directories can only be copied from
+    /// Multipass instances.
+    ///
+    pub fn get<P: AsRef<Path>>(&self, remote: &str, local: P) -> Result<(), ::Error> {
+        let local = local.as_ref().to_string_lossy();
+
+        match self.provider {
+            Provider::Vagrant => {
+                let cat = format!("cat -- {}", ::quote::posix(remote));
+                let argv = ["vagrant", "ssh", "--no-tty", &self.name, "-c", &cat];
+                let line = format!("{} > {}", command_line(&argv), ::quote::posix(&local));
+                checked(self.execute(&line))
+            },
+
+            Provider::Multipass => self.transfer(&format!("{}:{}", self.name, remote), &local),
+        }
+    }
+
+    fn transfer(&self, source: &str, dest: &str) -> Result<(), ::Error> {
+        let argv = ["multipass", "transfer", "--recursive", source, dest];
+        checked(self.execute(&command_line(&argv)))
+    }
+
+    fn execute(&self, line: &str) -> (i32, String, String) {
+        let shell = Shell::new("sh");
+
+        match self.project_dir {
+            Some(ref dir) => shell.cwd(dir.clone()).run(line),
+            None => shell.run(line),
+        }
+    }
+}
+
+impl ::std::fmt::Display for Vm {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}:{}", self.provider.program(), self.name)
+    }
+}
+
+/// Shell command line executing the program with the given arguments.
+///
+fn command_line<S: AsRef<str>>(argv: &[S]) -> String {
+    let quoted: Vec<_> = argv.iter().map(|arg| ::quote::posix(arg.as_ref()).into_owned()).collect();
+    format!("exec {}", quoted.join(" "))
+}
+
+fn checked((code, stdout, stderr): (i32, String, String)) -> Result<(), ::Error> {
+    match code {
+        0 => Ok(()),
+        _ => Err(::Error::Failed { code, stdout, stderr }),
+    }
+}
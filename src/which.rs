@@ -0,0 +1,52 @@
+//! Lookup of programs on the PATH.
+//!
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Returns the location of the first executable named `program` found on the PATH. A program
+/// given as a path (containing a separator) is returned as is if it exists.
+///
+pub fn which(program: &str) -> Option<PathBuf> {
+    which_in(program, env::var_os("PATH").as_deref())
+}
+
+/// Like `which`, searching the directories of the given PATH instead of the PATH of the process.
+///
+pub(crate) fn which_in(program: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    if program.contains('/') || program.contains(::std::path::MAIN_SEPARATOR) {
+        let path = Path::new(program);
+        return if is_executable(path) { Some(path.to_path_buf()) } else { None };
+    }
+
+    path.and_then(|paths| env::split_paths(paths).filter_map(|dir| candidate(&dir, program)).next())
+}
+
+#[cfg(unix)]
+fn candidate(dir: &Path, program: &str) -> Option<PathBuf> {
+    let path = dir.join(program);
+    if is_executable(&path) { Some(path) } else { None }
+}
+
+#[cfg(windows)]
+fn candidate(dir: &Path, program: &str) -> Option<PathBuf> {
+    let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+
+    ::std::iter::once(String::new())
+        .chain(extensions.split(';').map(|ext| ext.to_string()))
+        .map(|ext| dir.join(format!("{}{}", program, ext)))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata().map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}